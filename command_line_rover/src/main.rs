@@ -1,11 +1,13 @@
+use std::{fs, path::{Path, PathBuf}, thread, time::{Duration, Instant}};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use structopt::StructOpt;
 use comms_if::{
-    tc::{Tc, TcResponse},
-    net::{zmq, MonitoredSocket, SocketOptions}, 
+    tc::{path::PathChunk, Tc, TcPacket, TcRejectReason, TcResponse},
+    net::{chunk::ChunkSender, transport, zmq, SocketOptions, Transport, TransportError, TransportExt, TransportKind},
 };
-use color_eyre::{Result, eyre::WrapErr};
+use color_eyre::{Result, eyre::{eyre, WrapErr}};
+use chrono::Utc;
 
 // const str ascii_art = """
 //  ____  _   _  ___  ____   ___  ____
@@ -18,15 +20,79 @@ use color_eyre::{Result, eyre::WrapErr};
 const PROMPT: &str = "[Phobos] $ ";
 const HISTORY_PATH: &str = "clr_history.txt";
 
+/// Command line options for the console.
+///
+/// With no options the console runs interactively. Passing `--file` or `--oneshot` runs the
+/// console in batch mode, where the TCs are sent without a prompt and the exit code reflects the
+/// rover's responses.
+#[derive(StructOpt)]
+#[structopt(name = "command_line_rover", about = "Command line telecommand console for Phobos")]
+struct Opts {
+    /// Send each TC in the given file, one per line, then exit.
+    ///
+    /// Lines use the same syntax as the interactive prompt. Blank lines and lines starting with
+    /// `#` are ignored.
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["oneshot", "upload-path"])]
+    file: Option<PathBuf>,
 
-fn main() -> Result<()> {
-    // Rustline input
-    let mut rl = Editor::<()>::new();
+    /// Send a single TC, such as "mnvr stop", then exit.
+    #[structopt(long, conflicts_with = "upload-path")]
+    oneshot: Option<String>,
 
-    // Load history if some exists
-    if rl.load_history(HISTORY_PATH).is_err() {
-        println!("No history detected");
-    }
+    /// Uplink the given JSON or CSV path file to the rover as a series of `path chunk` TCs, then
+    /// exit. The file is stored on the rover under the same file name.
+    #[structopt(long, parse(from_os_str))]
+    upload_path: Option<PathBuf>,
+
+    /// Size of each chunk when uplinking a path file, in bytes.
+    #[structopt(long, default_value = "512")]
+    chunk_bytes: usize,
+
+    /// Delay between sending consecutive TCs in batch mode, in milliseconds.
+    #[structopt(long, default_value = "500")]
+    delay_ms: u64,
+
+    /// Time to wait for the rover to respond to each TC, in milliseconds. Increase this when the
+    /// link has a long latency, for example when running through link_sim.
+    #[structopt(long, default_value = "200")]
+    response_timeout_ms: i32,
+
+    /// Time to wait for the rover to connect in batch mode, in seconds.
+    #[structopt(long, default_value = "10")]
+    connect_timeout_s: u64,
+
+    /// Continue sending the remaining TCs in batch mode after a TC is rejected.
+    #[structopt(long)]
+    keep_going: bool,
+
+    /// ID of the vehicle to address TCs to. If not given any connected vehicle will execute them.
+    #[structopt(long)]
+    vehicle_id: Option<String>,
+
+    /// Endpoint to bind the TcServer to. Use a different endpoint for each vehicle when running
+    /// more than one rover.
+    #[structopt(long, default_value = "tcp://*:5020")]
+    endpoint: String,
+
+    /// Transport to use, either zmq or grpc. Must match ground_transport in the rover's net.toml.
+    #[structopt(long, default_value = "zmq", possible_values = &["zmq", "grpc"])]
+    transport: TransportKind,
+}
+
+/// Result of sending a single TC to the rover.
+enum SendOutcome {
+    /// The TC could not be parsed by the console.
+    ParseError,
+
+    /// The rover was not connected so the TC was not sent.
+    NotSent,
+
+    /// The rover responded to the TC.
+    Response(TcResponse),
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::from_args();
 
     // Create the zmq context
     let ctx = zmq::Context::new();
@@ -35,20 +101,55 @@ fn main() -> Result<()> {
     let socket_options = SocketOptions {
         bind: true,
         block_on_first_connect: false,
-        recv_timeout: 200,
+        recv_timeout: opts.response_timeout_ms,
         send_timeout: 10,
         ..Default::default()
     };
 
     // Bind the server
-    let socket = MonitoredSocket::new(
+    let socket = transport::open(
+        opts.transport,
         &ctx,
         zmq::REQ,
         socket_options,
-        "tcp://*:5020"
+        &opts.endpoint
     ).wrap_err("Failed to create the TcServer")?;
 
-    println!("TcServer started");
+    match opts.vehicle_id {
+        Some(ref id) => println!("TcServer started on {}, addressing {}", opts.endpoint, id),
+        None => println!("TcServer started on {}", opts.endpoint)
+    }
+
+    // Collect the TCs to send in batch mode, if there are any
+    let batch = match (&opts.file, &opts.oneshot, &opts.upload_path) {
+        (Some(path), _, _) => Some(
+            fs::read_to_string(path)
+                .wrap_err_with(|| format!("Could not read the TC file {:?}", path))?
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .collect::<Vec<String>>()
+        ),
+        (None, Some(tc), _) => Some(vec![tc.trim().to_string()]),
+        (None, None, Some(path)) => Some(path_chunk_tcs(path, opts.chunk_bytes)?),
+        (None, None, None) => None
+    };
+
+    match batch {
+        Some(lines) => run_batch(&*socket, &opts, &lines),
+        None => run_interactive(&*socket, &opts)
+    }
+}
+
+/// Run the console interactively, reading TCs from the prompt until interrupted.
+fn run_interactive(socket: &dyn Transport, opts: &Opts) -> Result<()> {
+    // Rustline input
+    let mut rl = Editor::<()>::new();
+
+    // Load history if some exists
+    if rl.load_history(HISTORY_PATH).is_err() {
+        println!("No history detected");
+    }
 
     // Main loop
     loop {
@@ -70,57 +171,12 @@ fn main() -> Result<()> {
                 if line.is_empty() {
                     continue
                 }
-                
-                // Split on spaces to parse with structopt
-                let cmd: Vec<&str> = line.split(' ').collect();
-
-                // Get the clap matches for this TC
-                let tc = match Tc::from_iter_safe(cmd) {
-                    Ok(m) => m,
-                    Err(e) => {
-                        println!("\n{:#}\n", e.message);
-                        continue;
-                    }
-                };
-
-                // Serialize the TC
-                let tc_str = serde_json::to_string(&tc)
-                    .wrap_err("Failed to serialize the TC")?;
-
-                // Send the TC
-                match socket.send(&tc_str, 0) {
-                    Ok(_) => (),
-                    Err(zmq::Error::EAGAIN) => {
-                        println!("Client not connected, TC not sent");
-                        continue;
-                    },
-                    Err(e) => return Err(e).wrap_err("Could not send TC")
-                }
-                
-
-                // Recieve response from client
-                let response = serde_json::from_str(match socket.recv_string(0){
-                    Ok(Ok(ref s)) => s,
-                    Ok(Err(_)) => {
-                        println!("Client responed with invalid UTF-8 message");
-                        continue;
-                    }
-                    Err(e) => {
-                        return Err(e).wrap_err("Could not deserialise client's response")
-                    }
-                }).wrap_err("Could not deserialise response from client")?;
-
-                // Print response message
-                match response {
-                    TcResponse::Ok => (),
-                    TcResponse::Invalid => 
-                        println!("Client responded that the send TC was invalid"),
-                    TcResponse::CannotExecute => 
-                        println!("Client responded that the sent TC could not be executed")
-                }
+
+                // Send the TC, the outcome has already been printed
+                send_tc(socket, opts, line)?;
             }
             Err(ReadlineError::Interrupted) => {
-                
+
                 break
             }
             Err(err) => {
@@ -133,4 +189,134 @@ fn main() -> Result<()> {
     rl.save_history(HISTORY_PATH).unwrap();
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Send each of the given TCs in turn, without a prompt.
+///
+/// Returns an error if any of the TCs could not be sent or were not accepted by the rover, so that
+/// the process exits with a non-zero code.
+fn run_batch(socket: &dyn Transport, opts: &Opts, lines: &[String]) -> Result<()> {
+    // Wait for the rover to connect, there's no point sending before this as the TC would just be
+    // dropped.
+    let connect_start = Instant::now();
+    while !socket.connected() {
+        if connect_start.elapsed() > Duration::from_secs(opts.connect_timeout_s) {
+            return Err(eyre!(
+                "Rover did not connect within {} s",
+                opts.connect_timeout_s
+            ))
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let mut num_failed = 0;
+
+    for (i, line) in lines.iter().enumerate() {
+        // Wait between commands, but not before the first one
+        if i > 0 {
+            thread::sleep(Duration::from_millis(opts.delay_ms));
+        }
+
+        println!("{}{}", PROMPT, line);
+
+        match send_tc(socket, opts, line)? {
+            SendOutcome::Response(TcResponse::Ok) | SendOutcome::Response(TcResponse::Queued) => (),
+            _ => {
+                num_failed += 1;
+
+                if !opts.keep_going {
+                    break
+                }
+            }
+        }
+    }
+
+    match num_failed {
+        0 => Ok(()),
+        n => Err(eyre!("{} of {} TCs were not accepted by the rover", n, lines.len()))
+    }
+}
+
+/// Build the `path chunk` TCs which uplink the given path file.
+fn path_chunk_tcs(path: &Path, chunk_bytes: usize) -> Result<Vec<String>> {
+    let data = fs::read(path)
+        .wrap_err_with(|| format!("Could not read the path file {:?}", path))?;
+
+    let name = path.file_name()
+        .ok_or_else(|| eyre!("{:?} is not a file", path))?
+        .to_string_lossy();
+
+    // TCs are split on spaces so the name can't contain any
+    if name.contains(' ') {
+        return Err(eyre!("Path file names cannot contain spaces"));
+    }
+
+    // There's no channel for the rover to ask for chunks again, so none are kept for resending
+    let mut sender = ChunkSender::new(chunk_bytes, 0);
+
+    Ok(PathChunk::split_file(&mut sender, &name, &data)
+        .iter()
+        .map(|c| c.to_tc_string())
+        .collect())
+}
+
+/// Parse and send a single TC, printing the rover's response.
+fn send_tc(socket: &dyn Transport, opts: &Opts, line: &str) -> Result<SendOutcome> {
+    // Split on spaces to parse with structopt
+    let cmd: Vec<&str> = line.split(' ').collect();
+
+    // Get the clap matches for this TC
+    let tc = match Tc::from_iter_safe(cmd) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("\n{:#}\n", e.message);
+            return Ok(SendOutcome::ParseError);
+        }
+    };
+
+    // Address and serialize the TC
+    let packet = TcPacket {
+        vehicle_id: opts.vehicle_id.clone(),
+        tc,
+        sent: Some(Utc::now())
+    };
+
+    // Send the TC
+    match socket.send_json(&packet) {
+        Ok(_) => (),
+        Err(TransportError::NotSent) => {
+            println!("Client not connected, TC not sent");
+            return Ok(SendOutcome::NotSent);
+        },
+        Err(e) => return Err(e).wrap_err("Could not send TC")
+    }
+
+
+    // Recieve response from client
+    let response = match socket.recv_json() {
+        Ok(Some(r)) => r,
+        Ok(None) => return Err(eyre!("The client did not respond to the TC")),
+        Err(TransportError::DeserializationError(e)) => {
+            println!("Client responded with an invalid message: {}", e);
+            return Ok(SendOutcome::Response(TcResponse::Invalid));
+        }
+        Err(e) => {
+            return Err(e).wrap_err("Could not recieve the client's response")
+        }
+    };
+
+    // Print response message
+    match response {
+        TcResponse::Ok => (),
+        TcResponse::Invalid =>
+            println!("Client responded that the send TC was invalid"),
+        TcResponse::CannotExecute(TcRejectReason::ModeNotAllowed) =>
+            println!("Client responded that the sent TC is not allowed in the rover's current mode"),
+        TcResponse::WrongVehicle =>
+            println!("Client responded that the sent TC was addressed to a different vehicle"),
+        TcResponse::Queued =>
+            println!("TC queued by the relay, it will be delivered when the rover is in contact")
+    }
+
+    Ok(SendOutcome::Response(response))
+}
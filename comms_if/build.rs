@@ -0,0 +1,32 @@
+//! Build script for the comms interface.
+//!
+//! When the `grpc` feature is enabled this generates the gRPC link service. The service is defined
+//! here rather than in a `.proto` file so that `protoc` isn't needed to build the software.
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        let exchange = tonic_build::manual::Method::builder()
+            .name("exchange")
+            .route_name("Exchange")
+            .input_type("crate::net::grpc::Frame")
+            .output_type("crate::net::grpc::Frame")
+            .codec_path("tonic::codec::ProstCodec")
+            .client_streaming()
+            .server_streaming()
+            .build();
+
+        let link = tonic_build::manual::Service::builder()
+            .name("Link")
+            .package("phobos.net")
+            .comment("A message link between the rover and the ground")
+            .method(exchange)
+            .build();
+
+        // The transport constructors use `TryInto` from the 2021 prelude, so aren't generated.
+        // Clients are created from a connected channel instead.
+        tonic_build::manual::Builder::new()
+            .build_transport(false)
+            .compile(&[link]);
+    }
+}
@@ -0,0 +1,164 @@
+//! # TC Lint
+//!
+//! Checks telecommands without sending them to the rover. Each TC is parsed exactly as the rover
+//! would parse it, including the checks the rover makes before executing it, and the resulting
+//! command or error is printed.
+//!
+//! TCs can be given on the command line or in a file, using either the console syntax (such as
+//! `mnvr ack 0.1 0 0`) or JSON. Rover scripts (`.prs` files) can also be checked. The exit code is
+//! non-zero if any TC is invalid, so this can be used to check command sequences before a pass.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::tc::{script, Tc, TcPacket};
+use std::{fs, path::PathBuf, process};
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Command line options.
+#[derive(StructOpt)]
+#[structopt(name = "tc_lint", about = "Check telecommands as the rover would parse them")]
+struct Opts {
+    /// TCs to check, each in the console syntax or as JSON. Quote each TC, for example
+    /// "mnvr ack 0.1 0 0".
+    tcs: Vec<String>,
+
+    /// Check each TC in the given file, one per line, in the same format as `command_line_rover
+    /// --file`. Blank lines and lines starting with `#` are ignored.
+    #[structopt(long, parse(from_os_str))]
+    file: Option<PathBuf>,
+
+    /// Check the rover script (`.prs` file) at the given path.
+    #[structopt(long, parse(from_os_str))]
+    script: Option<PathBuf>,
+
+    /// Print valid TCs as JSON, as sent to the rover, rather than as structs.
+    #[structopt(long)]
+    json: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// MAIN
+// ------------------------------------------------------------------------------------------------
+
+fn main() {
+    let opts = Opts::from_args();
+
+    let mut num_invalid = 0;
+    let mut num_checked = 0;
+
+    let mut report = |source: String, result: Result<Tc, String>| {
+        num_checked += 1;
+        match result {
+            Ok(tc) => {
+                let tc_str = match opts.json {
+                    true => serde_json::to_string(&tc).unwrap_or_else(|e| e.to_string()),
+                    false => format!("{:?}", tc),
+                };
+                println!("OK    {}: {}", source, tc_str);
+            }
+            Err(e) => {
+                num_invalid += 1;
+                println!("ERROR {}: {}", source, e);
+            }
+        }
+    };
+
+    for tc in opts.tcs.iter() {
+        report(format!("{:?}", tc), check_line(tc));
+    }
+
+    if let Some(ref path) = opts.file {
+        match fs::read_to_string(path) {
+            Ok(s) => {
+                for (i, line) in s.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+
+                    report(format!("{}:{}", path.display(), i + 1), check_line(line));
+                }
+            }
+            Err(e) => report(path.display().to_string(), Err(e.to_string())),
+        }
+    }
+
+    if let Some(ref path) = opts.script {
+        let parsed = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| script::parse(&s).map_err(|e| e.to_string()));
+
+        match parsed {
+            Ok(tcs) => {
+                let mut last_time_s = 0.0;
+
+                for s in tcs {
+                    let exec_time_s = s.exec_time_s;
+                    let source = format!("{} at {} s", path.display(), exec_time_s);
+
+                    // The rover runs the script in order, so an earlier time would run late
+                    let result = match exec_time_s < last_time_s {
+                        true => Err(format!(
+                            "TC is scheduled before the previous one at {} s",
+                            last_time_s
+                        )),
+                        false => s.tc.validate().map(|_| s.tc).map_err(|e| e.to_string()),
+                    };
+                    last_time_s = exec_time_s.max(last_time_s);
+
+                    report(source, result);
+                }
+            }
+            Err(e) => report(path.display().to_string(), Err(e)),
+        }
+    }
+
+    if num_checked == 0 {
+        eprintln!("No TCs to check, give TCs on the command line, --file, or --script");
+        process::exit(2);
+    }
+
+    println!("\n{} of {} TCs valid", num_checked - num_invalid, num_checked);
+
+    if num_invalid > 0 {
+        process::exit(1);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Check a TC in the console syntax or as JSON.
+///
+/// TCs in the console syntax are parsed as the console does and then sent through the rover's
+/// parser, so that what's checked is what the rover would receive.
+fn check_line(line: &str) -> Result<Tc, String> {
+    let line = line.trim();
+
+    let json = match line.starts_with('{') || line.starts_with('"') {
+        true => String::from(line),
+        false => {
+            let cmd: Vec<&str> = line.split(' ').collect();
+            let tc = Tc::from_iter_safe(cmd).map_err(|e| e.message)?;
+
+            serde_json::to_string(&TcPacket {
+                vehicle_id: None,
+                tc,
+                sent: None,
+            })
+            .map_err(|e| e.to_string())?
+        }
+    };
+
+    let tc = TcPacket::from_json(&json).map_err(|e| e.to_string())?.tc;
+    tc.validate().map_err(|e| e.to_string())?;
+
+    Ok(tc)
+}
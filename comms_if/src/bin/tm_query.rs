@@ -0,0 +1,128 @@
+//! # TM Query
+//!
+//! Prints the latest value of one or more telemetry channels, queried from the rover's TM query
+//! endpoint, without subscribing to the TM stream. For example `tm_query safe mode` prints whether
+//! the rover is in safe mode and its current mode.
+//!
+//! The exit code is non-zero if any channel could not be queried, so this can be used in scripts
+//! to check the rover's state before sending TCs.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::{
+    net::{zmq, MonitoredSocket, SocketOptions},
+    tm::query::{TmQuery, TmQueryResponse},
+};
+use std::process;
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Command line options.
+#[derive(StructOpt)]
+#[structopt(name = "tm_query", about = "Query the latest value of telemetry channels")]
+struct Opts {
+    /// Channels to query, such as `safe` or `last_tc_stamps.executed`. Give an empty string to get
+    /// the whole packet.
+    channels: Vec<String>,
+
+    /// The rover's TM query endpoint, `tm_query_endpoint` in net.toml.
+    #[structopt(long, default_value = "tcp://localhost:5032")]
+    endpoint: String,
+
+    /// Time to wait for each response in milliseconds.
+    #[structopt(long, default_value = "2000")]
+    timeout_ms: i32,
+}
+
+// ------------------------------------------------------------------------------------------------
+// MAIN
+// ------------------------------------------------------------------------------------------------
+
+fn main() {
+    let opts = Opts::from_args();
+
+    if opts.channels.is_empty() {
+        eprintln!("No channels to query");
+        process::exit(2);
+    }
+
+    let ctx = zmq::Context::new();
+
+    let socket = MonitoredSocket::new(
+        &ctx,
+        zmq::REQ,
+        SocketOptions {
+            block_on_first_connect: false,
+            connect_timeout: opts.timeout_ms,
+            recv_timeout: opts.timeout_ms,
+            send_timeout: opts.timeout_ms,
+            linger: 0,
+            // Allows the next query to be sent even if the last one timed out
+            req_correlate: true,
+            req_relaxed: true,
+            ..Default::default()
+        },
+        &opts.endpoint,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("Could not connect to {}: {}", opts.endpoint, e);
+        process::exit(2);
+    });
+
+    let mut num_failed = 0;
+
+    for channel in opts.channels.iter() {
+        match query(&socket, channel) {
+            Ok(TmQueryResponse::Value { sim_time_s, value }) => {
+                println!("{} = {} (at {:.3} s)", channel, value, sim_time_s)
+            }
+            Ok(TmQueryResponse::UnknownChannel(c)) => {
+                num_failed += 1;
+                println!("{}: unknown channel {:?}", channel, c);
+            }
+            Ok(TmQueryResponse::NoData) => {
+                num_failed += 1;
+                println!("{}: the rover hasn't produced any telemetry yet", channel);
+            }
+            Ok(TmQueryResponse::Invalid) => {
+                num_failed += 1;
+                println!("{}: the rover could not parse the query", channel);
+            }
+            Err(e) => {
+                num_failed += 1;
+                println!("{}: {}", channel, e);
+            }
+        }
+    }
+
+    if num_failed > 0 {
+        process::exit(1);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Send a single query and wait for the response.
+fn query(socket: &MonitoredSocket, channel: &str) -> Result<TmQueryResponse, String> {
+    let request = serde_json::to_vec(&TmQuery {
+        channel: String::from(channel),
+    })
+    .map_err(|e| e.to_string())?;
+
+    socket
+        .send(request, 0)
+        .map_err(|e| format!("Could not send the query: {}", e))?;
+
+    match socket.recv_bytes(0) {
+        Ok(msg) => serde_json::from_slice(&msg).map_err(|e| format!("Invalid response: {}", e)),
+        Err(zmq::Error::EAGAIN) => Err(String::from("No response from the rover")),
+        Err(e) => Err(format!("Could not recieve the response: {}", e)),
+    }
+}
@@ -5,9 +5,10 @@
 // ------------------------------------------------------------------------------------------------
 
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
+use structopt::StructOpt;
 use chrono::{DateTime, Utc, serde::ts_milliseconds};
-use image::{DynamicImage, ImageResult};
+use image::{DynamicImage, GenericImageView, ImageResult, Rgba, RgbaImage};
 
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
@@ -35,6 +36,36 @@ pub struct StreamSettings {
     pub target_addr: (String, String)
 }
 
+/// Imaging settings to apply to a camera.
+///
+/// Settings which are not given are left unchanged. Manual exposure and white balance values are
+/// only used by the camera when the matching automatic mode is disabled.
+#[derive(Debug, Serialize, Deserialize, Clone, StructOpt)]
+pub struct CameraControl {
+    /// The camera to apply the settings to, either `left_nav` or `right_nav`.
+    pub camera: CamId,
+
+    /// Enable or disable automatic exposure.
+    #[structopt(long)]
+    pub auto_exposure: Option<bool>,
+
+    /// Manual exposure time in microseconds.
+    #[structopt(long)]
+    pub exposure_us: Option<u32>,
+
+    /// Analogue gain, in the camera driver's units.
+    #[structopt(long)]
+    pub gain: Option<u32>,
+
+    /// Enable or disable automatic white balance.
+    #[structopt(long)]
+    pub auto_white_balance: Option<bool>,
+
+    /// Manual white balance colour temperature in Kelvin.
+    #[structopt(long)]
+    pub white_balance_k: Option<u32>
+}
+
 /// An individual frame from a camera
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CamFrame {
@@ -50,6 +81,45 @@ pub struct CamFrame {
     pub b64_data: String
 }
 
+/// Calibration parameters for all cameras, loaded from `cam.toml`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CamCalibParams {
+    /// If true images are undistorted as soon as they are recieved
+    pub undistort: bool,
+
+    /// Calibration of the left navigation camera
+    pub left_nav: CamCalibration,
+
+    /// Calibration of the right navigation camera
+    pub right_nav: CamCalibration
+}
+
+/// Intrinsic and extrinsic calibration of a single camera.
+///
+/// Intrinsics follow the pinhole model with Brown-Conrady distortion, so values from OpenCV's
+/// `calibrateCamera` can be copied in directly.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CamCalibration {
+    /// Size of the image the calibration was made with, as [width, height]
+    pub image_size_px: [u32; 2],
+
+    /// Focal length as [fx, fy]
+    pub focal_length_px: [f64; 2],
+
+    /// Principal point as [cx, cy]
+    pub principal_point_px: [f64; 2],
+
+    /// Distortion coefficients as [k1, k2, p1, p2, k3]
+    pub distortion: [f64; 5],
+
+    /// Position of the camera's optical centre in the Rover Body (RB) frame
+    pub position_m_rb: [f64; 3],
+
+    /// Attitude of the camera in the RB frame. This is a quaternion that will rotate an object
+    /// from the RB frame into the camera frame.
+    pub attitude_q_rb: [f64; 4]
+}
+
 #[derive(Clone)]
 pub struct CamImage {
     /// UTC timestamp at which the frame was acquired
@@ -72,6 +142,9 @@ pub enum CamRequest {
 
     /// Request to setup camera stream
     StreamSettingsRequest(StreamSettings),
+
+    /// Request to change a camera's imaging settings
+    CameraControl(CameraControl),
 }
 
 /// Possible responses from the camera server to the client
@@ -84,7 +157,14 @@ pub enum CamResponse {
     StreamSettingsAccepted,
 
     /// Indicates that a StreamSettings request was rejected.
-    StreamSettingsRejected
+    StreamSettingsRejected,
+
+    /// Indicates that a CameraControl request was applied.
+    CameraControlAccepted,
+
+    /// Indicates that a CameraControl request was rejected, for example because the camera does
+    /// not support one of the settings.
+    CameraControlRejected
 }
 
 /// Cameras available on the rover
@@ -181,6 +261,65 @@ impl CamImage {
             b64_data: base64::encode(data)
         })
     }
+
+    /// Remove lens distortion from this image using the given calibration.
+    ///
+    /// The output has the same size as the input. If the image is a different size to the one the
+    /// camera was calibrated at (for example it was downscaled) the intrinsics are scaled to match.
+    pub fn undistort(&self, calib: &CamCalibration) -> CamImage {
+        let (width, height) = self.image.dimensions();
+        let src = self.image.to_rgba8();
+
+        // Scale the intrinsics to the size of this image
+        let sx = width as f64 / calib.image_size_px[0] as f64;
+        let sy = height as f64 / calib.image_size_px[1] as f64;
+        let fx = calib.focal_length_px[0] * sx;
+        let fy = calib.focal_length_px[1] * sy;
+        let cx = calib.principal_point_px[0] * sx;
+        let cy = calib.principal_point_px[1] * sy;
+        let [k1, k2, p1, p2, k3] = calib.distortion;
+
+        // For each pixel in the undistorted image find where it came from in the distorted one
+        let dst = RgbaImage::from_fn(width, height, |u, v| {
+            let x = (u as f64 - cx) / fx;
+            let y = (v as f64 - cy) / fy;
+
+            let r2 = x * x + y * y;
+            let radial = 1.0 + k1 * r2 + k2 * r2 * r2 + k3 * r2 * r2 * r2;
+
+            let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+            let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+
+            sample_bilinear(&src, fx * xd + cx, fy * yd + cy)
+        });
+
+        CamImage {
+            timestamp: self.timestamp,
+            image: DynamicImage::ImageRgba8(dst)
+        }
+    }
+}
+
+impl CamCalibParams {
+    /// Get the calibration for the given camera
+    pub fn get(&self, cam_id: CamId) -> &CamCalibration {
+        match cam_id {
+            CamId::LeftNav => &self.left_nav,
+            CamId::RightNav => &self.right_nav
+        }
+    }
+}
+
+impl FromStr for CamId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left_nav" => Ok(CamId::LeftNav),
+            "right_nav" => Ok(CamId::RightNav),
+            _ => Err(format!("Unknown camera \"{}\", expected left_nav or right_nav", s))
+        }
+    }
 }
 
 impl Default for StreamSettings {
@@ -190,4 +329,38 @@ impl Default for StreamSettings {
             target_addr: ("127.0.0.1".into(), "5011".into())
         }
     }
-}
\ No newline at end of file
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Sample the image at a non-integer pixel position, returning transparent black outside the image.
+fn sample_bilinear(image: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    let (width, height) = image.dimensions();
+
+    if x < 0.0 || y < 0.0 || x > (width - 1) as f64 || y > (height - 1) as f64 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let x0 = x.floor() as u32;
+    let y0 = y.floor() as u32;
+    let x1 = (x0 + 1).min(width - 1);
+    let y1 = (y0 + 1).min(height - 1);
+    let fx = x - x0 as f64;
+    let fy = y - y0 as f64;
+
+    let p00 = image.get_pixel(x0, y0);
+    let p10 = image.get_pixel(x1, y0);
+    let p01 = image.get_pixel(x0, y1);
+    let p11 = image.get_pixel(x1, y1);
+
+    let mut out = [0u8; 4];
+    for (i, o) in out.iter_mut().enumerate() {
+        let top = p00[i] as f64 * (1.0 - fx) + p10[i] as f64 * fx;
+        let bottom = p01[i] as f64 * (1.0 - fx) + p11[i] as f64 * fx;
+        *o = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+
+    Rgba(out)
+}
@@ -8,6 +8,8 @@ use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, str::FromStr};
 use structopt::StructOpt;
 
+use crate::tm::TmMeta;
+
 // ------------------------------------------------------------------------------------------------
 // CONSTANTS
 // ------------------------------------------------------------------------------------------------
@@ -20,24 +22,157 @@ const ARM_IDS: [ActId; 5] = [
     ActId::ArmGrabber,
 ];
 
+const MAST_IDS: [ActId; 2] = [ActId::MastPan, ActId::MastTilt];
+
+const DRV_IDS: [ActId; 6] = [
+    ActId::DrvFL,
+    ActId::DrvML,
+    ActId::DrvRL,
+    ActId::DrvFR,
+    ActId::DrvMR,
+    ActId::DrvRR,
+];
+
+const STR_IDS: [ActId; 6] = [
+    ActId::StrFL,
+    ActId::StrML,
+    ActId::StrRL,
+    ActId::StrFR,
+    ActId::StrMR,
+    ActId::StrRR,
+];
+
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
 // ------------------------------------------------------------------------------------------------
 
 /// Demands that are sent from the MechClient to the MechServer
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, TmMeta)]
 pub struct MechDems {
     /// The demanded position of an actuator in radians.
+    #[tm(unit = "rad")]
     pub pos_rad: HashMap<ActId, f64>,
 
     /// The demanded speed of an actuator in radians
+    #[tm(unit = "rad/s")]
     pub speed_rads: HashMap<ActId, f64>,
 }
 
 /// Sensor data returned by the MechServer to the MechClient
-/// TODO
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MechSensData;
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MechSensData {
+    /// The measured position of an actuator in radians.
+    pub pos_rad: HashMap<ActId, f64>,
+
+    /// The measured speed of an actuator in radians/second.
+    pub speed_rads: HashMap<ActId, f64>,
+
+    /// The measured current drawn by an actuator in amps, for actuators which can measure it.
+    #[serde(default)]
+    pub current_a: HashMap<ActId, f64>,
+
+    /// Time since the MechServer received the demands it is actuating, or `None` if it has none.
+    ///
+    /// Units: seconds
+    #[serde(default)]
+    pub dems_age_s: Option<f64>,
+
+    /// True if the demands have expired, so the MechServer is ramping the drive speeds down to
+    /// zero or holding the actuators stopped.
+    #[serde(default)]
+    pub dems_expired: bool,
+}
+
+/// Demands as sent over the demands stream.
+///
+/// The sequence number increases by one with each set of demands, so that the server can discard
+/// demands which arrive late and count those which are lost. Sequence numbers restart when the
+/// client does, so each client picks a new session ID and the server starts counting again when
+/// the session changes.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MechDemsPacket {
+    /// ID of the client session which sent these demands
+    #[serde(default)]
+    pub session_id: u64,
+
+    /// Sequence number of these demands within the session
+    pub seq: u64,
+
+    /// The demands themselves
+    pub dems: MechDems,
+
+    /// Flags sent along with the demands
+    #[serde(default)]
+    pub flags: MechDemsFlags,
+}
+
+/// Flags sent to the MechServer along with each set of demands.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct MechDemsFlags {
+    /// True if the rover is executing an autonomous traverse, shown on the status LEDs
+    pub autonomy_active: bool,
+
+    /// If true the server takes the steer positions in these demands as the mechanical zero of
+    /// each steer axis, and updates its calibration to match. See `calibrate steer`.
+    pub capture_steer_zero: bool,
+
+    /// If true the server ignores these demands and stops all actuators, as if the E-stop had been
+    /// pressed. Sent by the rover executable when it crashes.
+    #[serde(default)]
+    pub estop: bool,
+}
+
+/// Sensor data as published by the MechServer.
+///
+/// Packets are published at a fixed rate whether or not demands are arriving, so the client can
+/// also use them to check that the server is alive.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MechSensPacket {
+    /// Session ID of the latest demands accepted by the server, if any
+    #[serde(default)]
+    pub last_dems_session_id: Option<u64>,
+
+    /// Sequence number of the latest demands accepted by the server, if any
+    pub last_dems_seq: Option<u64>,
+
+    /// The server's response to the latest demands, if any
+    pub dems_response: Option<MechDemsResponse>,
+
+    /// The sensor data
+    pub sens: MechSensData,
+
+    /// The active arm fault, if any. While set the arm is held still.
+    #[serde(default)]
+    pub arm_fault: Option<ArmFault>,
+}
+
+/// Fault raised when an arm joint stalls, for example against an obstacle.
+///
+/// The arm is held at its position when the fault was raised. The fault clears once a demand
+/// moves the stalled joint back the way it came.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TmMeta)]
+pub struct ArmFault {
+    /// The joint which stalled
+    pub act_id: ActId,
+
+    /// The current the joint was drawing when the fault was raised
+    ///
+    /// Units: amps
+    #[tm(unit = "A")]
+    pub current_a: f64,
+
+    /// The joint's current limit
+    ///
+    /// Units: amps
+    #[tm(unit = "A")]
+    pub limit_a: f64,
+
+    /// The measured position of the joint when the fault was raised
+    ///
+    /// Units: radians
+    #[tm(unit = "rad")]
+    pub pos_rad: f64,
+}
 
 // ------------------------------------------------------------------------------------------------
 // ENUMS
@@ -63,10 +198,12 @@ pub enum ActId {
     ArmElbow,
     ArmWrist,
     ArmGrabber,
+    MastPan,
+    MastTilt,
 }
 
 /// Response from the mechanisms server based on the demands sent by the client.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MechDemsResponse {
     /// Demands were valid and will be executed
     DemsOk,
@@ -83,9 +220,53 @@ pub enum MechDemsResponse {
 // -----------------------------------------------------------------------------------------------
 
 impl ActId {
+    pub fn drv_ids() -> &'static [Self] {
+        &DRV_IDS
+    }
+
+    pub fn str_ids() -> &'static [Self] {
+        &STR_IDS
+    }
+
     pub fn arm_ids() -> &'static [Self] {
         &ARM_IDS
     }
+
+    pub fn mast_ids() -> &'static [Self] {
+        &MAST_IDS
+    }
+}
+
+impl FromStr for ActId {
+    type Err = String;
+
+    /// Parse an actuator ID from its name, ignoring case, e.g. `StrFL` or `strfl`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DRV_IDS
+            .iter()
+            .chain(STR_IDS.iter())
+            .chain(ARM_IDS.iter())
+            .chain(MAST_IDS.iter())
+            .find(|id| format!("{:?}", id).eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("Unknown actuator \"{}\"", s))
+    }
+}
+
+/// `clone_from` reuses the maps' allocations, so demands can be copied each cycle without
+/// allocating.
+impl Clone for MechDems {
+    fn clone(&self) -> Self {
+        Self {
+            pos_rad: self.pos_rad.clone(),
+            speed_rads: self.speed_rads.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.pos_rad.clone_from(&source.pos_rad);
+        self.speed_rads.clone_from(&source.speed_rads);
+    }
 }
 
 impl MechDems {
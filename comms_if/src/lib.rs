@@ -2,14 +2,27 @@
 //!
 //! Provides all common communications interfaces for the software.
 
+// Allows the TmMeta derive to be used inside this crate
+extern crate self as comms_if;
+
 // ------------------------------------------------------------------------------------------------
 // MODULES
 // ------------------------------------------------------------------------------------------------
 
 pub mod tc;
 
+/// Telemetry definitions shared between the rover and ground
+pub mod tm;
+
 /// Command and response definitions for equipment (like mechanisms)
 pub mod eqpt;
 
 /// Network module
-pub mod net;
\ No newline at end of file
+pub mod net;
+
+/// Newtypes for physical quantities
+pub mod units;
+
+/// Serialisation round-trip tests of the shared types
+#[cfg(test)]
+mod serde_tests;
@@ -0,0 +1,340 @@
+//! # Traffic Capture
+//!
+//! Records every message sent or recieved over a transport, exactly as it crossed the wire, so
+//! that disagreements between the ground tools and the rover (such as whether a TC was ever
+//! recieved) can be settled from the recorded traffic rather than from each side's logs.
+//!
+//! Messages are written as JSON lines to rotating files, `traffic_<n>.jsonl`, in the given
+//! directory. Each line holds the time, direction, channel and endpoint of the message along with
+//! the message itself, as text if it's valid UTF-8 or as base64 otherwise. Once a file reaches
+//! `max_file_bytes` a new one is started, and the oldest files are deleted so that at most
+//! `max_files` are kept.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::{LinkStats, Transport, TransportError};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Parameters of the traffic capture.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CaptureParams {
+    /// If true all traffic on the ground links is recorded
+    pub enabled: bool,
+
+    /// Also record the image TM channel, which is large compared to the rest of the traffic
+    pub include_images: bool,
+
+    /// Size in bytes after which a new capture file is started
+    pub max_file_bytes: u64,
+
+    /// Maximum number of capture files to keep, the oldest are deleted first. 0 keeps all files.
+    pub max_files: usize,
+}
+
+/// A traffic capture, which can be shared between any number of transports.
+#[derive(Clone)]
+pub struct TrafficCapture {
+    writer: Arc<Mutex<CaptureWriter>>,
+}
+
+/// A transport which records all the messages passing through it into a [`TrafficCapture`].
+pub struct CaptureTransport {
+    inner: Box<dyn Transport>,
+    capture: TrafficCapture,
+    channel: String,
+    endpoint: String,
+}
+
+/// A single captured message, one of which is written on each line of the capture files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedFrame {
+    /// Time the message was sent or recieved
+    pub time: DateTime<Utc>,
+
+    /// Whether the message was sent or recieved
+    pub direction: Direction,
+
+    /// Name of the channel, such as `tc` or `tm`
+    pub channel: String,
+
+    /// Endpoint of the transport the message passed through
+    pub endpoint: String,
+
+    /// Encoding of `frame`
+    pub encoding: FrameEncoding,
+
+    /// The message itself
+    pub frame: String,
+
+    /// Size of the message in bytes
+    pub len: usize,
+
+    /// Error which prevented the message being sent, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Writes captured frames into the rotating files.
+struct CaptureWriter {
+    dir: PathBuf,
+    params: CaptureParams,
+    file: Option<BufWriter<File>>,
+    file_bytes: u64,
+    file_index: usize,
+
+    /// Set once a write has failed, so that a failure is only reported once until writing succeeds
+    /// again
+    failed: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Direction of a captured message, relative to this end of the link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// How a captured message is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameEncoding {
+    /// The message was valid UTF-8 and is stored as is
+    Utf8,
+
+    /// The message was binary and is stored as base64
+    Base64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CaptureError {
+    #[error("Could not create the capture directory: {0}")]
+    CreateDirError(std::io::Error),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Default for CaptureParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            include_images: false,
+            max_file_bytes: 10_000_000,
+            max_files: 20,
+        }
+    }
+}
+
+impl TrafficCapture {
+    /// Create a new capture writing into the given directory.
+    ///
+    /// No file is created until the first message is captured.
+    pub fn new<P: AsRef<Path>>(dir: P, params: &CaptureParams) -> Result<Self, CaptureError> {
+        fs::create_dir_all(dir.as_ref()).map_err(CaptureError::CreateDirError)?;
+
+        Ok(Self {
+            writer: Arc::new(Mutex::new(CaptureWriter {
+                dir: dir.as_ref().to_path_buf(),
+                params: params.clone(),
+                file: None,
+                file_bytes: 0,
+                file_index: 0,
+                failed: false,
+            })),
+        })
+    }
+
+    /// Wrap the transport so that all its messages are captured under the given channel name.
+    pub fn wrap(
+        &self,
+        inner: Box<dyn Transport>,
+        channel: &str,
+        endpoint: &str,
+    ) -> Box<dyn Transport> {
+        Box::new(CaptureTransport {
+            inner,
+            capture: self.clone(),
+            channel: String::from(channel),
+            endpoint: String::from(endpoint),
+        })
+    }
+
+    /// Record a single message.
+    ///
+    /// Failures to write are logged once and otherwise ignored, the capture must never stop the
+    /// link itself from working.
+    pub fn record(
+        &self,
+        direction: Direction,
+        channel: &str,
+        endpoint: &str,
+        msg: &[u8],
+        error: Option<&TransportError>,
+    ) {
+        let (encoding, frame) = match std::str::from_utf8(msg) {
+            Ok(s) => (FrameEncoding::Utf8, String::from(s)),
+            Err(_) => (FrameEncoding::Base64, base64::encode(msg)),
+        };
+
+        let captured = CapturedFrame {
+            time: Utc::now(),
+            direction,
+            channel: String::from(channel),
+            endpoint: String::from(endpoint),
+            encoding,
+            frame,
+            len: msg.len(),
+            error: error.map(|e| e.to_string()),
+        };
+
+        // A poisoned lock only means another thread panicked mid-write, the capture can carry on
+        let mut writer = match self.writer.lock() {
+            Ok(w) => w,
+            Err(e) => e.into_inner(),
+        };
+        writer.write(&captured);
+    }
+}
+
+impl Transport for CaptureTransport {
+    fn send_bytes(&self, msg: &[u8]) -> Result<(), TransportError> {
+        let result = self.inner.send_bytes(msg);
+
+        self.capture.record(
+            Direction::Out,
+            &self.channel,
+            &self.endpoint,
+            msg,
+            result.as_ref().err(),
+        );
+
+        result
+    }
+
+    fn recv_bytes(&self) -> Result<Option<Vec<u8>>, TransportError> {
+        let result = self.inner.recv_bytes();
+
+        if let Ok(Some(ref msg)) = result {
+            self.capture.record(Direction::In, &self.channel, &self.endpoint, msg, None);
+        }
+
+        result
+    }
+
+    fn connected(&self) -> bool {
+        self.inner.connected()
+    }
+
+    fn link_stats(&self) -> Option<LinkStats> {
+        self.inner.link_stats()
+    }
+}
+
+impl CaptureWriter {
+    fn write(&mut self, frame: &CapturedFrame) {
+        if let Err(e) = self.try_write(frame) {
+            if !self.failed {
+                warn!(
+                    "Could not write to the traffic capture, traffic is not being recorded: {}",
+                    e
+                );
+                self.failed = true;
+            }
+
+            // Start a new file next time in case the old one is the problem
+            self.file = None;
+        }
+    }
+
+    fn try_write(&mut self, frame: &CapturedFrame) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(frame)?;
+        line.push(b'\n');
+
+        if self.file.is_some() && self.file_bytes + line.len() as u64 > self.params.max_file_bytes {
+            self.rotate()?;
+        }
+
+        let file = match self.file {
+            Some(ref mut f) => f,
+            None => self.open_next()?,
+        };
+
+        file.write_all(&line)?;
+
+        // Flushed on every message so that nothing is lost if the executable crashes, which is
+        // when the capture is most likely to be needed
+        file.flush()?;
+
+        self.file_bytes += line.len() as u64;
+        self.failed = false;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if let Some(mut f) = self.file.take() {
+            f.flush()?;
+        }
+
+        // Delete the oldest file if there would be too many
+        if self.params.max_files > 0 && self.file_index >= self.params.max_files {
+            let oldest = self.file_index - self.params.max_files;
+            fs::remove_file(self.file_path(oldest)).ok();
+        }
+
+        Ok(())
+    }
+
+    fn open_next(&mut self) -> std::io::Result<&mut BufWriter<File>> {
+        let file = File::create(self.file_path(self.file_index))?;
+
+        self.file_index += 1;
+        self.file_bytes = 0;
+
+        Ok(self.file.insert(BufWriter::new(file)))
+    }
+
+    fn file_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("traffic_{:03}.jsonl", index))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Wraps an optional capture around a transport, so callers don't have to check if the capture is
+/// enabled.
+pub fn maybe_wrap(
+    capture: Option<&TrafficCapture>,
+    inner: Box<dyn Transport>,
+    channel: &str,
+    endpoint: &str,
+) -> Box<dyn Transport> {
+    match capture {
+        Some(c) => c.wrap(inner, channel, endpoint),
+        None => inner,
+    }
+}
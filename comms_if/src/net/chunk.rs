@@ -0,0 +1,645 @@
+//! # Chunked Transfers
+//!
+//! Splits large payloads, such as maps, paths or reports, into small chunks so that no single
+//! message holds up a link, and reassembles them at the other end. Each chunk carries its sequence
+//! number within the transfer, a CRC-32 of its data and a CRC-32 of the whole payload, so corrupt
+//! or incomplete transfers are detected rather than delivered.
+//!
+//! Transfer IDs count from zero for each sender, so every chunk also carries the sender's session
+//! ID. A restarted sender is a new session, and its transfers are never mixed up with the ones
+//! sent before the restart.
+//!
+//! The reciever can ask for missing chunks with a [`ResendRequest`], which the sender answers from
+//! the transfers it still holds in its [`ChunkSender`]. Both chunks and resend requests are sent as
+//! [`ChunkMsg`]s, so a single channel can carry both.
+//!
+//! Images use their own chunking, see [`crate::tm::img`], since they are recompressed to fit the
+//! link rather than resent.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+use super::{new_session_id, Transport, TransportError, TransportExt};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single chunk of a transfer.
+#[derive(Debug, Clone, Serialize, Deserialize, StructOpt)]
+pub struct Chunk {
+    /// ID of the sender's session, picked when the sender is created
+    pub session_id: u64,
+
+    /// ID of the transfer this chunk belongs to, unique within the sender's session
+    pub transfer_id: u64,
+
+    /// Kind of payload being transferred, such as `path` or `map`, so the reciever knows how to
+    /// deserialise it
+    pub kind: String,
+
+    /// Sequence number of this chunk in the transfer
+    pub seq: u32,
+
+    /// Total number of chunks in the transfer
+    pub num_chunks: u32,
+
+    /// Length of the whole payload in bytes
+    pub payload_len: u64,
+
+    /// CRC-32 of the whole payload
+    pub payload_crc: u32,
+
+    /// CRC-32 of this chunk's data, before base64 encoding
+    pub crc: u32,
+
+    /// This chunk's part of the payload, in base64
+    pub b64_data: String,
+}
+
+/// A request for the sender to resend the given chunks of a transfer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResendRequest {
+    pub session_id: u64,
+    pub transfer_id: u64,
+
+    /// Sequence numbers of the missing chunks
+    pub missing: Vec<u32>,
+}
+
+/// A complete, checked transfer.
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub session_id: u64,
+    pub transfer_id: u64,
+    pub kind: String,
+    pub payload: Vec<u8>,
+}
+
+/// Splits payloads into chunks, and keeps the most recent transfers so that lost chunks can be
+/// resent.
+pub struct ChunkSender {
+    /// Maximum size of the data in each chunk in bytes (before base64 encoding)
+    chunk_bytes: usize,
+
+    /// Number of recent transfers to keep for resending
+    max_kept: usize,
+
+    /// ID of this sender's session
+    session_id: u64,
+
+    /// ID of the next transfer
+    next_id: u64,
+
+    /// Recently sent transfers, oldest first
+    kept: VecDeque<(u64, Vec<Chunk>)>,
+}
+
+/// Reassembles transfers from their chunks.
+///
+/// Chunks can arrive in any order. Once more than `max_pending` transfers are incomplete the oldest
+/// is discarded. Chunks come from the link so aren't trusted, transfers claiming more than
+/// `max_chunks` chunks are rejected before any space is allocated for them.
+pub struct ChunkReassembler {
+    /// Transfers which are still missing chunks, keyed by session and transfer ID
+    pending: BTreeMap<(u64, u64), PendingTransfer>,
+
+    /// Maximum number of incomplete transfers to keep
+    max_pending: usize,
+
+    /// Maximum number of chunks in a transfer
+    max_chunks: u32,
+
+    /// Session and transfer IDs of recently completed transfers, so that duplicate chunks don't
+    /// start them again
+    completed: VecDeque<(u64, u64)>,
+
+    /// Number of transfers discarded because they were never completed
+    num_dropped: u64,
+
+    /// Number of chunks rejected because their data didn't match their CRC
+    num_corrupt: u64,
+}
+
+/// A transfer which is being reassembled.
+struct PendingTransfer {
+    kind: String,
+    payload_len: u64,
+    payload_crc: u32,
+    chunks: Vec<Option<Vec<u8>>>,
+
+    /// Time the last chunk of this transfer was recieved
+    last_recv: Instant,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A message on a chunked channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChunkMsg {
+    Chunk(Chunk),
+    Resend(ResendRequest),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkError {
+    #[error("Chunk {0} of transfer {1} could not be decoded from base64: {2}")]
+    DecodeError(u32, u64, base64::DecodeError),
+
+    #[error("Chunk {0} of transfer {1} is outside the expected {2} chunks")]
+    InvalidSeq(u32, u64, u32),
+
+    #[error("Transfer {0} has {1} chunks, more than the maximum of {2}")]
+    TooManyChunks(u64, u32, u32),
+
+    #[error("Chunk {0} of transfer {1} is corrupt, its data does not match its CRC")]
+    CorruptChunk(u32, u64),
+
+    #[error("Transfer {0} is corrupt, the reassembled payload does not match its length or CRC")]
+    CorruptTransfer(u64),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl ChunkSender {
+    /// Create a new sender making chunks of at most `chunk_bytes`, which keeps the last `max_kept`
+    /// transfers for resending.
+    pub fn new(chunk_bytes: usize, max_kept: usize) -> Self {
+        Self {
+            // Zero sized chunks would never finish
+            chunk_bytes: chunk_bytes.max(1),
+            max_kept,
+            session_id: new_session_id(),
+            next_id: 0,
+            kept: VecDeque::with_capacity(max_kept),
+        }
+    }
+
+    /// Split the payload into chunks, keeping them for resending.
+    pub fn split(&mut self, kind: &str, payload: &[u8]) -> Vec<Chunk> {
+        let transfer_id = self.next_id;
+        self.next_id += 1;
+
+        let num_chunks = payload.len().div_ceil(self.chunk_bytes).max(1) as u32;
+        let payload_crc = crc32(payload);
+
+        let session_id = self.session_id;
+        let make_chunk = |seq: usize, data: &[u8]| Chunk {
+            session_id,
+            transfer_id,
+            kind: String::from(kind),
+            seq: seq as u32,
+            num_chunks,
+            payload_len: payload.len() as u64,
+            payload_crc,
+            crc: crc32(data),
+            b64_data: base64::encode(data),
+        };
+
+        // An empty payload still needs one (empty) chunk so the reciever sees it
+        let chunks: Vec<Chunk> = match payload.is_empty() {
+            true => vec![make_chunk(0, &[])],
+            false => payload
+                .chunks(self.chunk_bytes)
+                .enumerate()
+                .map(|(i, d)| make_chunk(i, d))
+                .collect(),
+        };
+
+        if self.max_kept > 0 {
+            if self.kept.len() >= self.max_kept {
+                self.kept.pop_front();
+            }
+            self.kept.push_back((transfer_id, chunks.clone()));
+        }
+
+        chunks
+    }
+
+    /// Get the chunks asked for by a resend request.
+    ///
+    /// Chunks of transfers which are no longer kept, which don't exist, or which were sent by
+    /// another session of the sender are skipped.
+    pub fn resend(&self, request: &ResendRequest) -> Vec<Chunk> {
+        if request.session_id != self.session_id {
+            return Vec::new();
+        }
+
+        match self.kept.iter().find(|(id, _)| *id == request.transfer_id) {
+            Some((_, chunks)) => request
+                .missing
+                .iter()
+                .filter_map(|&seq| chunks.get(seq as usize).cloned())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Split the payload and send each chunk over the transport.
+    pub fn send(
+        &mut self,
+        transport: &dyn Transport,
+        kind: &str,
+        payload: &[u8],
+    ) -> Result<u64, TransportError> {
+        let chunks = self.split(kind, payload);
+        let transfer_id = chunks[0].transfer_id;
+
+        for chunk in chunks {
+            transport.send_json(&ChunkMsg::Chunk(chunk))?;
+        }
+
+        Ok(transfer_id)
+    }
+}
+
+impl ChunkReassembler {
+    /// Create a new reassembler which keeps at most `max_pending` incomplete transfers, each of at
+    /// most `max_chunks` chunks.
+    pub fn new(max_pending: usize, max_chunks: u32) -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            max_pending: max_pending.max(1),
+            max_chunks: max_chunks.max(1),
+            completed: VecDeque::new(),
+            num_dropped: 0,
+            num_corrupt: 0,
+        }
+    }
+
+    /// Number of transfers which were discarded before all their chunks arrived.
+    pub fn num_dropped(&self) -> u64 {
+        self.num_dropped
+    }
+
+    /// Number of chunks which were rejected as corrupt.
+    pub fn num_corrupt(&self) -> u64 {
+        self.num_corrupt
+    }
+
+    /// Add a chunk to the reassembler.
+    ///
+    /// If this chunk completes a transfer the transfer is returned. Corrupt chunks are rejected and
+    /// can be asked for again using `resend_requests`.
+    pub fn push(&mut self, chunk: Chunk) -> Result<Option<Transfer>, ChunkError> {
+        if chunk.seq >= chunk.num_chunks {
+            return Err(ChunkError::InvalidSeq(chunk.seq, chunk.transfer_id, chunk.num_chunks));
+        }
+
+        if chunk.num_chunks > self.max_chunks {
+            return Err(ChunkError::TooManyChunks(
+                chunk.transfer_id,
+                chunk.num_chunks,
+                self.max_chunks,
+            ));
+        }
+
+        let key = (chunk.session_id, chunk.transfer_id);
+
+        // Ignore duplicates of chunks which have already been delivered
+        if self.completed.contains(&key) {
+            return Ok(None);
+        }
+
+        let data = base64::decode(&chunk.b64_data)
+            .map_err(|e| ChunkError::DecodeError(chunk.seq, chunk.transfer_id, e))?;
+
+        if crc32(&data) != chunk.crc {
+            self.num_corrupt += 1;
+            return Err(ChunkError::CorruptChunk(chunk.seq, chunk.transfer_id));
+        }
+
+        let pending = self
+            .pending
+            .entry(key)
+            .or_insert_with(|| PendingTransfer {
+                kind: chunk.kind.clone(),
+                payload_len: chunk.payload_len,
+                payload_crc: chunk.payload_crc,
+                chunks: vec![None; chunk.num_chunks as usize],
+                last_recv: Instant::now(),
+            });
+
+        // Reject chunks which disagree with the first one about the size of the transfer
+        if pending.chunks.len() != chunk.num_chunks as usize {
+            return Err(ChunkError::InvalidSeq(
+                chunk.seq,
+                chunk.transfer_id,
+                pending.chunks.len() as u32,
+            ));
+        }
+
+        pending.chunks[chunk.seq as usize] = Some(data);
+        pending.last_recv = Instant::now();
+
+        // If complete remove and check the payload
+        if pending.chunks.iter().all(|c| c.is_some()) {
+            let pending = self
+                .pending
+                .remove(&key)
+                .expect("Pending transfer missing");
+
+            if self.completed.len() >= self.max_pending {
+                self.completed.pop_front();
+            }
+            self.completed.push_back(key);
+
+            let payload: Vec<u8> = pending.chunks.into_iter().flatten().flatten().collect();
+
+            if payload.len() as u64 != pending.payload_len || crc32(&payload) != pending.payload_crc
+            {
+                return Err(ChunkError::CorruptTransfer(chunk.transfer_id));
+            }
+
+            return Ok(Some(Transfer {
+                session_id: chunk.session_id,
+                transfer_id: chunk.transfer_id,
+                kind: pending.kind,
+                payload,
+            }));
+        }
+
+        // Drop the least recently recieved incomplete transfers if there are too many. Session IDs
+        // aren't ordered, so the keys can't be used to find the oldest.
+        while self.pending.len() > self.max_pending {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, p)| p.last_recv)
+                .map(|(&k, _)| k)
+                .expect("No pending transfers");
+            self.pending.remove(&oldest);
+            self.num_dropped += 1;
+        }
+
+        Ok(None)
+    }
+
+    /// Get resend requests for the incomplete transfers which haven't recieved a chunk for at least
+    /// `timeout`.
+    ///
+    /// Chunks missing from the end of a transfer can't be told apart from chunks still on their
+    /// way, so the caller should only send the requests once the sender is expected to have
+    /// finished.
+    pub fn resend_requests(&self, timeout: Duration) -> Vec<ResendRequest> {
+        self.pending
+            .iter()
+            .filter(|(_, p)| p.last_recv.elapsed() >= timeout)
+            .map(|(&(session_id, transfer_id), p)| ResendRequest {
+                session_id,
+                transfer_id,
+                missing: p
+                    .chunks
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.is_none())
+                    .map(|(i, _)| i as u32)
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Calculate the CRC-32 (IEEE 802.3) of the data.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+
+    !crc
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A payload which isn't a whole number of chunks long.
+    fn payload() -> Vec<u8> {
+        (0..1000u32).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut sender = ChunkSender::new(64, 4);
+        let mut reassembler = ChunkReassembler::new(4, 100);
+
+        let chunks = sender.split("path", &payload());
+        assert_eq!(chunks.len(), 16);
+        assert!(chunks.iter().all(|c| c.num_chunks == 16 && c.kind == "path"));
+
+        let last = chunks.len() - 1;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let result = reassembler.push(chunk).unwrap();
+            match i == last {
+                true => {
+                    let transfer = result.expect("Transfer not complete");
+                    assert_eq!(transfer.kind, "path");
+                    assert_eq!(transfer.payload, payload());
+                }
+                false => assert!(result.is_none()),
+            }
+        }
+
+        // An empty payload is still delivered
+        let chunks = sender.split("path", &[]);
+        assert_eq!(chunks.len(), 1);
+        let transfer = reassembler.push(chunks[0].clone()).unwrap().unwrap();
+        assert!(transfer.payload.is_empty());
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut sender = ChunkSender::new(64, 4);
+        let mut reassembler = ChunkReassembler::new(4, 100);
+
+        let mut chunks = sender.split("map", &payload());
+        chunks.reverse();
+        chunks.swap(3, 9);
+
+        let mut transfers: Vec<Transfer> = chunks
+            .into_iter()
+            .filter_map(|c| reassembler.push(c).unwrap())
+            .collect();
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers.pop().unwrap().payload, payload());
+    }
+
+    #[test]
+    fn test_duplicates_ignored() {
+        let mut sender = ChunkSender::new(64, 4);
+        let mut reassembler = ChunkReassembler::new(4, 100);
+
+        let chunks = sender.split("map", &payload());
+        for chunk in chunks.iter() {
+            reassembler.push(chunk.clone()).unwrap();
+        }
+
+        // Late duplicates of a delivered transfer don't start it again
+        for chunk in chunks.iter().cloned() {
+            assert!(reassembler.push(chunk).unwrap().is_none());
+        }
+        assert!(reassembler.resend_requests(Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_chunk_and_resend() {
+        let mut sender = ChunkSender::new(64, 4);
+        let mut reassembler = ChunkReassembler::new(4, 100);
+
+        let mut chunks = sender.split("map", &payload());
+
+        // Corrupt chunk 5 without changing its CRC
+        let mut data = base64::decode(&chunks[5].b64_data).unwrap();
+        data[0] ^= 0xff;
+        chunks[5].b64_data = base64::encode(&data);
+
+        // And lose chunk 9
+        chunks.remove(9);
+
+        for chunk in chunks {
+            match reassembler.push(chunk) {
+                Ok(t) => assert!(t.is_none()),
+                Err(ChunkError::CorruptChunk(5, _)) => (),
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+        }
+        assert_eq!(reassembler.num_corrupt(), 1);
+
+        // Both missing chunks are asked for, and resent by the sender
+        let requests = reassembler.resend_requests(Duration::ZERO);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].missing, vec![5, 9]);
+
+        let resent = sender.resend(&requests[0]);
+        assert_eq!(resent.len(), 2);
+
+        let mut transfers: Vec<Transfer> = resent
+            .into_iter()
+            .filter_map(|c| reassembler.push(c).unwrap())
+            .collect();
+        assert_eq!(transfers.pop().unwrap().payload, payload());
+    }
+
+    #[test]
+    fn test_corrupt_transfer() {
+        let mut sender = ChunkSender::new(64, 4);
+        let mut reassembler = ChunkReassembler::new(4, 100);
+
+        // Each chunk is intact, but the payload CRC is wrong
+        let mut chunks = sender.split("map", &payload());
+        for chunk in chunks.iter_mut() {
+            chunk.payload_crc ^= 1;
+        }
+
+        let last = chunks.pop().unwrap();
+        for chunk in chunks {
+            reassembler.push(chunk).unwrap();
+        }
+        assert!(matches!(
+            reassembler.push(last),
+            Err(ChunkError::CorruptTransfer(_))
+        ));
+    }
+
+    #[test]
+    fn test_resend_of_other_session() {
+        let mut sender = ChunkSender::new(64, 4);
+        let chunks = sender.split("map", &payload());
+
+        let mut request = ResendRequest {
+            session_id: chunks[0].session_id,
+            transfer_id: chunks[0].transfer_id,
+            missing: vec![0, 1, 100],
+        };
+        assert_eq!(sender.resend(&request).len(), 2);
+
+        // A request from before the sender restarted must not be answered with the new transfer
+        request.session_id ^= 1;
+        assert!(sender.resend(&request).is_empty());
+    }
+
+    #[test]
+    fn test_restarted_sender() {
+        let mut reassembler = ChunkReassembler::new(4, 100);
+
+        // Both senders start their transfer IDs from zero
+        let old = ChunkSender::new(64, 4).split("map", &payload());
+        let new = ChunkSender::new(64, 4).split("map", &[1u8; 100]);
+        assert_eq!(old[0].transfer_id, new[0].transfer_id);
+        assert_ne!(old[0].session_id, new[0].session_id);
+
+        // The old sender's first chunk arrives, then the restarted sender's transfer
+        assert!(reassembler.push(old[0].clone()).unwrap().is_none());
+        assert!(reassembler.push(new[0].clone()).unwrap().is_none());
+        let transfer = reassembler.push(new[1].clone()).unwrap().unwrap();
+        assert_eq!(transfer.payload, vec![1u8; 100]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut sender = ChunkSender::new(64, 4);
+        let mut reassembler = ChunkReassembler::new(1, 8);
+
+        // Too many chunks is rejected before anything is allocated
+        let mut chunks = sender.split("map", &payload());
+        assert!(matches!(
+            reassembler.push(chunks[0].clone()),
+            Err(ChunkError::TooManyChunks(_, 16, 8))
+        ));
+
+        chunks[0].num_chunks = u32::MAX;
+        chunks[0].seq = 0;
+        assert!(reassembler.push(chunks[0].clone()).is_err());
+
+        let mut chunk = sender.split("map", &[0u8; 100]).remove(0);
+        chunk.seq = 2;
+        assert!(matches!(
+            reassembler.push(chunk),
+            Err(ChunkError::InvalidSeq(2, _, 2))
+        ));
+
+        // A second incomplete transfer drops the first
+        let first = sender.split("map", &[0u8; 100]);
+        let second = sender.split("map", &[0u8; 100]);
+        reassembler.push(first[0].clone()).unwrap();
+        reassembler.push(second[0].clone()).unwrap();
+        assert_eq!(reassembler.num_dropped(), 1);
+        assert!(reassembler.push(second[1].clone()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_crc32() {
+        // Check value for CRC-32/ISO-HDLC
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+}
@@ -0,0 +1,311 @@
+//! # gRPC Transport
+//!
+//! A [`Transport`] carried over a single bidirectional gRPC stream, for networks which block raw
+//! zmq ports but allow HTTP/2 through.
+//!
+//! The binding end of the link runs the gRPC server and the connecting end opens a stream to it,
+//! reconnecting whenever the stream is lost. Messages sent by the server go to every connected
+//! client, in the same way as a zmq PUB socket, so only one client should be connected to a
+//! request/reply link such as the TC link.
+
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+/// Generated by the build script from the service definition in `build.rs`
+#[allow(clippy::all)]
+mod link {
+    include!(concat!(env!("OUT_DIR"), "/phobos.net.Link.rs"));
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use log::debug;
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+use tokio::{net::TcpListener, runtime::Runtime, sync::broadcast};
+use tokio_stream::{
+    wrappers::{BroadcastStream, TcpListenerStream},
+    Stream, StreamExt,
+};
+use tonic::{
+    transport::{Endpoint, Server},
+    Request, Response, Status, Streaming,
+};
+
+use super::{SocketOptions, Transport, TransportError};
+use link::{
+    link_client::LinkClient,
+    link_server::{Link, LinkServer},
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Number of messages which can wait to be sent to a peer before the oldest are dropped.
+const SEND_QUEUE_LEN: usize = 64;
+
+/// Interval between reconnection attempts if `reconnect_ivl` is not set.
+const DEFAULT_RECONNECT_IVL_MS: u64 = 100;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A single message on the link.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Frame {
+    #[prost(bytes = "vec", tag = "1")]
+    pub payload: Vec<u8>,
+}
+
+/// A transport over a gRPC stream.
+///
+/// The stream is run by a background runtime, the transport itself is used synchronously like a
+/// [`MonitoredSocket`](super::MonitoredSocket).
+pub struct GrpcTransport {
+    /// Runtime running the server or client in the background
+    _runtime: Runtime,
+
+    /// Messages to send to every connected peer
+    out_tx: broadcast::Sender<Vec<u8>>,
+
+    /// Messages recieved from any peer
+    in_rx: mpsc::Receiver<Vec<u8>>,
+
+    /// `ZMQ_RCVTIMEO` style recieve timeout, -1 to block and 0 to return immediately
+    recv_timeout: i32,
+
+    /// Number of peers with an open stream
+    num_peers: Arc<AtomicUsize>,
+}
+
+/// The server side of the link.
+struct LinkService {
+    out_tx: broadcast::Sender<Vec<u8>>,
+
+    in_tx: mpsc::Sender<Vec<u8>>,
+
+    num_peers: Arc<AtomicUsize>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl GrpcTransport {
+    /// Create a new gRPC transport.
+    ///
+    /// If `socket_options.bind` is set a server is started on the endpoint, otherwise a client
+    /// connects to it in the background. Only the `bind`, `recv_timeout`, `connect_timeout` and
+    /// `reconnect_ivl` options are used.
+    ///
+    /// The endpoint is in zmq form, such as `"tcp://*:5030"` or `"tcp://localhost:5030"`.
+    pub fn new(socket_options: &SocketOptions, endpoint: &str) -> Result<Self, TransportError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(TransportError::StartError)?;
+
+        let (out_tx, _) = broadcast::channel(SEND_QUEUE_LEN);
+        let (in_tx, in_rx) = mpsc::channel();
+        let num_peers = Arc::new(AtomicUsize::new(0));
+
+        let service = LinkService {
+            out_tx: out_tx.clone(),
+            in_tx,
+            num_peers: num_peers.clone(),
+        };
+
+        match socket_options.bind {
+            true => service.serve(&runtime, bind_addr(endpoint)?)?,
+            false => service.connect(&runtime, connect_endpoint(socket_options, endpoint)?, {
+                match socket_options.reconnect_ivl {
+                    ivl if ivl > 0 => Duration::from_millis(ivl as u64),
+                    _ => Duration::from_millis(DEFAULT_RECONNECT_IVL_MS),
+                }
+            }),
+        }
+
+        Ok(Self {
+            _runtime: runtime,
+            out_tx,
+            in_rx,
+            recv_timeout: socket_options.recv_timeout,
+            num_peers,
+        })
+    }
+}
+
+impl Transport for GrpcTransport {
+    fn send_bytes(&self, msg: &[u8]) -> Result<(), TransportError> {
+        // Fails only if there are no peers to send to
+        self.out_tx
+            .send(msg.to_vec())
+            .map(|_| ())
+            .map_err(|_| TransportError::NotSent)
+    }
+
+    fn recv_bytes(&self) -> Result<Option<Vec<u8>>, TransportError> {
+        let result = match self.recv_timeout {
+            t if t < 0 => self.in_rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+            0 => self.in_rx.try_recv().map_err(|e| match e {
+                mpsc::TryRecvError::Empty => mpsc::RecvTimeoutError::Timeout,
+                mpsc::TryRecvError::Disconnected => mpsc::RecvTimeoutError::Disconnected,
+            }),
+            t => self.in_rx.recv_timeout(Duration::from_millis(t as u64)),
+        };
+
+        match result {
+            Ok(msg) => Ok(Some(msg)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(TransportError::Closed),
+        }
+    }
+
+    fn connected(&self) -> bool {
+        self.num_peers.load(Ordering::Relaxed) > 0
+    }
+}
+
+impl LinkService {
+    /// Run the gRPC server on the given address.
+    fn serve(self, runtime: &Runtime, addr: SocketAddr) -> Result<(), TransportError> {
+        // Bind here rather than in the server task so that bind errors are reported to the caller
+        let listener = runtime
+            .block_on(TcpListener::bind(addr))
+            .map_err(TransportError::StartError)?;
+
+        runtime.spawn(async move {
+            if let Err(e) = Server::builder()
+                .add_service(LinkServer::new(self))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+            {
+                debug!("gRPC link server on {} stopped: {}", addr, e);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Connect to the server in the background, reconnecting whenever the stream is lost.
+    fn connect(self, runtime: &Runtime, endpoint: Endpoint, reconnect_ivl: Duration) {
+        runtime.spawn(async move {
+            loop {
+                if let Ok(channel) = endpoint.connect().await {
+                    let outgoing = frames(&self.out_tx);
+
+                    match LinkClient::new(channel).exchange(outgoing).await {
+                        Ok(response) => {
+                            if !self.forward(response.into_inner()).await {
+                                return;
+                            }
+                        }
+                        Err(e) => debug!("Could not open the gRPC link stream: {}", e),
+                    }
+                }
+
+                tokio::time::sleep(reconnect_ivl).await;
+            }
+        });
+    }
+
+    /// Forward messages from a peer's stream until it closes.
+    ///
+    /// Returns false if the transport has been dropped and the link should shut down.
+    async fn forward(&self, mut incoming: Streaming<Frame>) -> bool {
+        self.num_peers.fetch_add(1, Ordering::Relaxed);
+
+        let mut open = true;
+        while let Ok(Some(frame)) = incoming.message().await {
+            if self.in_tx.send(frame.payload).is_err() {
+                open = false;
+                break;
+            }
+        }
+
+        self.num_peers.fetch_sub(1, Ordering::Relaxed);
+
+        open
+    }
+}
+
+#[tonic::async_trait]
+impl Link for LinkService {
+    type ExchangeStream = Pin<Box<dyn Stream<Item = Result<Frame, Status>> + Send>>;
+
+    async fn exchange(
+        &self,
+        request: Request<Streaming<Frame>>,
+    ) -> Result<Response<Self::ExchangeStream>, Status> {
+        let peer = LinkService {
+            out_tx: self.out_tx.clone(),
+            in_tx: self.in_tx.clone(),
+            num_peers: self.num_peers.clone(),
+        };
+        let incoming = request.into_inner();
+
+        tokio::spawn(async move { peer.forward(incoming).await });
+
+        Ok(Response::new(Box::pin(frames(&self.out_tx).map(Ok))))
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Stream of the messages sent after this call, skipping any dropped because the peer fell behind.
+fn frames(out_tx: &broadcast::Sender<Vec<u8>>) -> impl Stream<Item = Frame> {
+    BroadcastStream::new(out_tx.subscribe()).filter_map(|m| m.ok().map(|payload| Frame { payload }))
+}
+
+/// Strip the scheme from a zmq or HTTP endpoint, leaving `<host>:<port>`.
+fn host_port(endpoint: &str) -> Result<&str, TransportError> {
+    endpoint
+        .strip_prefix("tcp://")
+        .or_else(|| endpoint.strip_prefix("http://"))
+        .ok_or_else(|| TransportError::InvalidEndpoint(endpoint.to_string()))
+}
+
+/// Get the address for the server to bind to, where a `*` host binds to all interfaces.
+fn bind_addr(endpoint: &str) -> Result<SocketAddr, TransportError> {
+    let host_port = host_port(endpoint)?;
+    let host_port = match host_port.strip_prefix("*:") {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => host_port.to_string(),
+    };
+
+    host_port
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| TransportError::InvalidEndpoint(endpoint.to_string()))
+}
+
+/// Get the endpoint for the client to connect to.
+fn connect_endpoint(
+    socket_options: &SocketOptions,
+    endpoint: &str,
+) -> Result<Endpoint, TransportError> {
+    let uri = format!("http://{}", host_port(endpoint)?);
+    let ep = Endpoint::from_shared(uri)
+        .map_err(|_| TransportError::InvalidEndpoint(endpoint.to_string()))?;
+
+    Ok(match socket_options.connect_timeout {
+        t if t > 0 => ep.connect_timeout(Duration::from_millis(t as u64)),
+        _ => ep,
+    })
+}
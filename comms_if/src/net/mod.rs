@@ -3,17 +3,45 @@
 //! This module provides networking abstractions over ZMQ, the networking library chosen for the 
 //! software.
 
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+/// Transport abstraction for the ground links
+pub mod transport;
+
+/// gRPC transport for the ground links
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+/// UDP transport with forward error correction for the TM channel
+pub mod udp;
+
+/// Recording of the raw traffic passing through a transport
+pub mod capture;
+
+/// Chunking and reassembly of large payloads
+pub mod chunk;
+
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
-use std::{sync::{Arc, atomic::{AtomicBool, AtomicUsize}, atomic::Ordering}, thread};
+use std::{
+    process,
+    sync::{Arc, atomic::{AtomicBool, AtomicU64, AtomicUsize}, atomic::Ordering},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use zmq::{Socket, Context, SocketType, SocketEvent};
 use serde::Deserialize;
 
 // Export zmq
 pub use zmq;
 
+pub use capture::{CaptureParams, TrafficCapture};
+pub use transport::{LinkStats, Transport, TransportError, TransportExt, TransportKind};
+
 // ------------------------------------------------------------------------------------------------
 // MACROS
 // ------------------------------------------------------------------------------------------------
@@ -34,6 +62,9 @@ macro_rules! set_sockopts {
 /// Number of monitors that are registered. Used to provide unique IDs for each mointor endpoint.
 static NUM_MONITORS: AtomicUsize = AtomicUsize::new(0);
 
+/// Number of session IDs picked by this process, so that IDs picked at the same time differ.
+static NUM_SESSIONS: AtomicU64 = AtomicU64::new(0);
+
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
 // ------------------------------------------------------------------------------------------------
@@ -51,13 +82,17 @@ pub struct MonitoredSocket {
 
     shutdown: Arc<AtomicBool>,
     
-    connected: Arc<AtomicBool>
+    connected: Arc<AtomicBool>,
+
+    /// Topics a SUB socket is subscribed to
+    subscriptions: Vec<String>
 }
 
 /// Represents options which can be set on a monitored socket.
 ///
 /// Most options here correspond to those found in the 
 /// [`zmq_setsockopt`](http://api.zeromq.org/2-1:zmq-setsockopt) documentation.
+#[derive(Clone)]
 pub struct SocketOptions {
 
     /// Indicates if the socket should bind itself to the endpoint. Servers should have this value
@@ -106,13 +141,52 @@ pub struct SocketOptions {
     /// `ZMQ_HEARTBEAT_TTL`: Set the TTL (time to live) value for ZMTP heartbeats
     pub heartbeat_ttl: i32,
 
-    /// `ZMQ_SUBSCRIBE`: Set the subscription topic filter for a SUB port.
+    /// `ZMQ_SUBSCRIBE`: Set the subscription topic filter for a SUB port. More topics can be added
+    /// later with `MonitoredSocket::subscribe`.
     pub subscribe: String,
+
+    /// `ZMQ_MAXMSGSIZE`: Limit the size of inbound messages in bytes, peers which send larger
+    /// messages are disconnected. -1 for no limit.
+    pub max_msg_size: i64,
+
+    /// `ZMQ_CONFLATE`: Keep only the last message recieved, for latest-value channels such as
+    /// sensor data where older messages are of no use. Multi-part messages are not supported.
+    pub conflate: bool,
+
+    /// Maximum number of message bytes in each datagram of the UDP transport
+    pub udp_frag_bytes: usize,
+
+    /// Number of datagrams covered by each FEC parity datagram of the UDP transport, 0 to disable
+    /// FEC
+    pub udp_fec_group_size: usize,
 }
 
 /// Network related parameters for the whole system.
 #[derive(Debug, Deserialize)]
 pub struct NetParams {
+    /// Unique ID of this vehicle, used to address telecommands and tag telemetry when more than
+    /// one rover is on the same network
+    pub vehicle_id: String,
+
+    /// Transport used for the ground links (TC, TM and image TM)
+    #[serde(default)]
+    pub ground_transport: TransportKind,
+
+    /// Transport used for the TM channel only, overriding `ground_transport`
+    #[serde(default)]
+    pub tm_transport: Option<TransportKind>,
+
+    /// Maximum number of telemetry bytes in each datagram when the TM channel uses UDP
+    pub tm_udp_frag_bytes: usize,
+
+    /// Number of datagrams covered by each parity datagram when the TM channel uses UDP, 0 to
+    /// disable forward error correction
+    pub tm_udp_fec_group_size: usize,
+
+    /// Maximum number of TM packets to store while out of contact with the ground, which are sent
+    /// once contact is regained. One packet is stored per second, 0 disables the backfill.
+    pub tm_backfill_max_packets: usize,
+
     /// Network endpoint for the mechanisms demands socket
     pub mech_dems_endpoint: String,
 
@@ -125,11 +199,36 @@ pub struct NetParams {
     /// Network endpoint for the telecommand client
     pub tc_endpoint: String,
 
+    /// Maximum number of TCs handled in a single control cycle, any more are left queued until the
+    /// next cycle so that a misbehaving ground tool can't starve the control loop
+    pub tc_max_per_cycle: usize,
+
+    /// Maximum size in bytes of a TC message, larger messages are rejected without being parsed
+    pub tc_max_bytes: usize,
+
     /// Network endpoint for the telecommand server
     pub tm_endpoint: String,
 
+    /// Network endpoint for the image telemetry channel
+    pub img_tm_endpoint: String,
+
+    /// Network endpoint for telemetry queries, see `tm::query`. Queries are always made over zmq,
+    /// and are disabled if no endpoint is given.
+    #[serde(default)]
+    pub tm_query_endpoint: Option<String>,
+
+    /// Maximum size in bytes of an image sent over the image telemetry channel
+    pub img_downlink_max_bytes: usize,
+
+    /// Maximum size in bytes of each chunk sent over the image telemetry channel
+    pub img_downlink_chunk_bytes: usize,
+
     /// Network endpoint for the simulation client
-    pub sim_endpoint: String
+    pub sim_endpoint: String,
+
+    /// Recording of the raw traffic on the ground links, disabled if not given
+    #[serde(default)]
+    pub traffic_capture: CaptureParams
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -154,7 +253,10 @@ pub enum MonitoredSocketError {
     EventReadError(zmq::Error),
 
     #[error("Could not set the {0} socket option: {1}")]
-    SocketOptionError(String, zmq::Error)
+    SocketOptionError(String, zmq::Error),
+
+    #[error("Only SUB sockets can subscribe to topics")]
+    NotSubSocket
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -176,6 +278,9 @@ impl MonitoredSocket {
     ///    established or the `connect_timeout` expires. Servers should set this value to `false`.
     ///    the default value is `true`.
     ///
+    /// The socket is considered connected while it has at least one peer, so bound sockets, such
+    /// as a PUB server, are connected once a subscriber has connected to them.
+    ///
     /// ## Arguments
     /// - `ctx`: the zmq context which will be used to create the socket
     /// - `socket_type`: the type of zmq socket to create
@@ -237,17 +342,30 @@ impl MonitoredSocket {
             connected.store(true, Ordering::Relaxed);
         }
 
+        // Record the initial subscription so it can be reported and removed later
+        let subscriptions = match socket.get_socket_type() {
+            Ok(SocketType::SUB) => vec![socket_options.subscribe.clone()],
+            _ => Vec::new()
+        };
+
         // Create clones for use by the monitor thread
         let shutdown_clone = shutdown.clone();
         let connected_clone = connected.clone();
         let monitor_endpoint_clone = monitor_endpoint.clone();
 
+        // Peers connected before the monitor thread started, which it won't see the event for
+        let initial_peers = match connected.load(Ordering::Relaxed) {
+            true => 1,
+            false => 0
+        };
+
         // Spawn the monitor thread
         let join_handle = thread::spawn(move || monitor_socket(
             monitor, 
             monitor_endpoint_clone,
             shutdown_clone, 
-            connected_clone
+            connected_clone,
+            initial_peers
         ));
 
         // Create self
@@ -256,7 +374,8 @@ impl MonitoredSocket {
             join_handle: Some(join_handle),
             _monitor_endpoint: monitor_endpoint,
             shutdown,
-            connected
+            connected,
+            subscriptions
         })
     }
 
@@ -264,6 +383,46 @@ impl MonitoredSocket {
     pub fn connected(&self) -> bool {
         self.connected.load(Ordering::Relaxed)
     }
+
+    /// Subscribe a SUB socket to messages starting with the given topic. An empty topic subscribes
+    /// to all messages.
+    pub fn subscribe(&mut self, topic: &str) -> Result<(), MonitoredSocketError> {
+        self.check_sub()?;
+
+        if self.subscriptions.iter().any(|t| t == topic) {
+            return Ok(())
+        }
+
+        set_sockopts!(self.socket, (set_subscribe, topic.as_bytes()));
+        self.subscriptions.push(topic.into());
+
+        Ok(())
+    }
+
+    /// Unsubscribe a SUB socket from the given topic. Unsubscribing from a topic which wasn't
+    /// subscribed to does nothing.
+    pub fn unsubscribe(&mut self, topic: &str) -> Result<(), MonitoredSocketError> {
+        self.check_sub()?;
+
+        if let Some(i) = self.subscriptions.iter().position(|t| t == topic) {
+            set_sockopts!(self.socket, (set_unsubscribe, topic.as_bytes()));
+            self.subscriptions.remove(i);
+        }
+
+        Ok(())
+    }
+
+    /// Get the topics a SUB socket is subscribed to.
+    pub fn subscriptions(&self) -> &[String] {
+        &self.subscriptions
+    }
+
+    fn check_sub(&self) -> Result<(), MonitoredSocketError> {
+        match self.socket.get_socket_type() {
+            Ok(SocketType::SUB) => Ok(()),
+            _ => Err(MonitoredSocketError::NotSubSocket)
+        }
+    }
 }
 
 impl Drop for MonitoredSocket {
@@ -311,9 +470,15 @@ impl SocketOptions {
             (set_reconnect_ivl, self.reconnect_ivl),
             (set_reconnect_ivl_max, self.reconnect_ivl_max),
             (set_rcvtimeo, self.recv_timeout),
-            (set_sndtimeo, self.send_timeout)
+            (set_sndtimeo, self.send_timeout),
+            (set_maxmsgsize, self.max_msg_size)
         );
 
+        // Conflate must be set before connecting, which is why it isn't a MonitoredSocket method
+        if self.conflate {
+            set_sockopts!(socket, (set_conflate, true));
+        }
+
         // If the socket is a req type set the req-specific options
         if let Ok(SocketType::REQ) = socket.get_socket_type() {
             set_sockopts!(
@@ -352,11 +517,34 @@ impl Default for SocketOptions {
             req_correlate: false,
             req_relaxed: false,
             send_timeout: 0,
-            subscribe: "".into()
+            subscribe: "".into(),
+            max_msg_size: -1,
+            conflate: false,
+            udp_frag_bytes: 1024,
+            udp_fec_group_size: 4
         }
     }
 }
 
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Pick a session ID which is very unlikely to match one picked by a previous run of the process,
+/// or by another session in this one.
+///
+/// Used to tell the messages of a restarted sender apart from those it sent before restarting,
+/// since sequence numbers and IDs start again from zero.
+pub fn new_session_id() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+
+    (nanos ^ ((process::id() as u64) << 32))
+        .wrapping_add(NUM_SESSIONS.fetch_add(1, Ordering::Relaxed))
+}
+
 // ------------------------------------------------------------------------------------------------
 // PRIVATE FUNCTIONS
 // ------------------------------------------------------------------------------------------------
@@ -380,12 +568,20 @@ fn read_event(socket: &Socket) -> Result<SocketEvent, zmq::Error> {
     Ok(SocketEvent::from_raw(event))
 }
 
+/// Monitor the events of a socket, counting its peers so that `connected` is true while it has at
+/// least one.
+///
+/// Clients see `CONNECTED` events and servers `ACCEPTED` ones, both see `DISCONNECTED` when a peer
+/// is lost, including when the heartbeat times out.
 fn monitor_socket(
     monitor: Socket,
     monitor_endpoint: String,
     shutdown: Arc<AtomicBool>,
-    connected: Arc<AtomicBool>
+    connected: Arc<AtomicBool>,
+    initial_peers: usize
 ) {
+    let mut num_peers = initial_peers;
+
     // So long as the shutdown isn't requested
     while !shutdown.load(Ordering::Relaxed) {
         // Read the next event from the monitor
@@ -395,11 +591,13 @@ fn monitor_socket(
                 monitor_endpoint
             ));
 
-        // Raise any flags required by the event
+        // Update the number of peers
         match event {
-            SocketEvent::CONNECTED => connected.store(true, Ordering::Relaxed),
-            SocketEvent::DISCONNECTED => connected.store(false, Ordering::Relaxed),
-            _ => ()
+            SocketEvent::CONNECTED | SocketEvent::ACCEPTED => num_peers += 1,
+            SocketEvent::DISCONNECTED => num_peers = num_peers.saturating_sub(1),
+            _ => continue
         }
+
+        connected.store(num_peers > 0, Ordering::Relaxed);
     }
 }
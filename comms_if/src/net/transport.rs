@@ -0,0 +1,231 @@
+//! # Transport
+//!
+//! Abstraction over the way messages are carried between the rover and the ground, so that the
+//! ground links can run over zmq or, where a network blocks raw zmq ports, over gRPC. The TM
+//! channel can also run over UDP to evaluate lossy radio links.
+//!
+//! On-rover links (mechanisms, cameras, simulation) always use zmq directly.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::str::FromStr;
+use zmq::{Context, SocketType};
+
+use super::{udp::UdpTransport, MonitoredSocket, MonitoredSocketError, SocketOptions};
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// A link which sends and recieves whole messages.
+///
+/// Messages sent while no peer is connected may be dropped, as with a zmq PUB socket.
+pub trait Transport: Send {
+    /// Send a single message.
+    ///
+    /// Returns `TransportError::NotSent` if the message could not be queued, usually because no
+    /// peer is connected.
+    fn send_bytes(&self, msg: &[u8]) -> Result<(), TransportError>;
+
+    /// Recieve a single message, or `Ok(None)` if none arrived within the recieve timeout.
+    fn recv_bytes(&self) -> Result<Option<Vec<u8>>, TransportError>;
+
+    /// Return if the transport is connected to a peer or not.
+    fn connected(&self) -> bool;
+
+    /// Get statistics of the messages lost by the link, if the transport can lose messages.
+    fn link_stats(&self) -> Option<LinkStats> {
+        None
+    }
+}
+
+/// Sending and recieving typed messages, which are serialised as JSON.
+///
+/// Implemented for all transports.
+pub trait TransportExt: Transport {
+    /// Serialise and send a single message.
+    fn send_json<T: Serialize>(&self, msg: &T) -> Result<(), TransportError> {
+        let msg = serde_json::to_vec(msg).map_err(TransportError::SerializationError)?;
+        self.send_bytes(&msg)
+    }
+
+    /// Recieve and deserialise a single message, or `Ok(None)` if none arrived within the recieve
+    /// timeout.
+    fn recv_json<T: DeserializeOwned>(&self) -> Result<Option<T>, TransportError> {
+        match self.recv_bytes()? {
+            Some(msg) => serde_json::from_slice(&msg)
+                .map(Some)
+                .map_err(TransportError::DeserializationError),
+            None => Ok(None),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Statistics of the messages recieved over a lossy link.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LinkStats {
+    /// Number of datagrams recieved
+    pub datagrams_recvd: u64,
+
+    /// Number of datagrams of partially recieved messages which never arrived. Datagrams of
+    /// messages which were lost entirely aren't counted, as their number isn't known.
+    pub datagrams_lost: u64,
+
+    /// Number of messages delivered
+    pub msgs_recvd: u64,
+
+    /// Number of the delivered messages which needed FEC to rebuild a lost datagram
+    pub msgs_recovered: u64,
+
+    /// Number of messages which could not be delivered
+    pub msgs_lost: u64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// The kind of transport to use for a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    /// zmq sockets over TCP
+    #[default]
+    Zmq,
+
+    /// A gRPC stream over HTTP/2, requires the `grpc` feature
+    Grpc,
+
+    /// UDP datagrams with forward error correction, for one-way links such as telemetry
+    Udp,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransportError {
+    #[error("Socket error: {0}")]
+    SocketError(MonitoredSocketError),
+
+    #[error("The message could not be sent, no peer is connected")]
+    NotSent,
+
+    #[error("Could not send the message: {0}")]
+    SendError(zmq::Error),
+
+    #[error("Could not recieve a message: {0}")]
+    RecvError(zmq::Error),
+
+    #[error("Could not serialize the message: {0}")]
+    SerializationError(serde_json::Error),
+
+    #[error("Could not deserialize the message: {0}")]
+    DeserializationError(serde_json::Error),
+
+    #[error("The {0:?} transport is not available, the software must be built with the grpc feature")]
+    Unsupported(TransportKind),
+
+    #[error("Invalid endpoint {0:?}, expected tcp://<host>:<port>, http://<host>:<port> or udp://<host>:<port>")]
+    InvalidEndpoint(String),
+
+    #[error("Invalid transport option: {0}")]
+    InvalidOption(String),
+
+    #[error("The {0:?} transport only supports PUB and SUB sockets")]
+    UnsupportedSocketType(TransportKind),
+
+    #[error("Could not send or recieve a datagram: {0}")]
+    IoError(std::io::Error),
+
+    #[error("Could not start the transport: {0}")]
+    StartError(std::io::Error),
+
+    #[error("The transport has shut down")]
+    Closed,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<T: Transport + ?Sized> TransportExt for T {}
+
+impl Transport for MonitoredSocket {
+    fn send_bytes(&self, msg: &[u8]) -> Result<(), TransportError> {
+        match self.socket.send(msg, 0) {
+            Ok(()) => Ok(()),
+            Err(zmq::Error::EAGAIN) => Err(TransportError::NotSent),
+            Err(e) => Err(TransportError::SendError(e)),
+        }
+    }
+
+    fn recv_bytes(&self) -> Result<Option<Vec<u8>>, TransportError> {
+        match self.socket.recv_bytes(0) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(zmq::Error::EAGAIN) => Ok(None),
+            Err(e) => Err(TransportError::RecvError(e)),
+        }
+    }
+
+    fn connected(&self) -> bool {
+        MonitoredSocket::connected(self)
+    }
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zmq" => Ok(TransportKind::Zmq),
+            "grpc" => Ok(TransportKind::Grpc),
+            "udp" => Ok(TransportKind::Udp),
+            _ => Err(format!("Unknown transport {:?}, expected zmq, grpc or udp", s)),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Open a transport of the given kind on the endpoint.
+///
+/// The endpoint is given in zmq form, such as `"tcp://*:5030"` or `"tcp://localhost:5030"`, for
+/// either kind of transport. `socket_options.bind` determines which end of the link acts as the
+/// server. The `socket_type` and zmq specific options are ignored by the gRPC transport, which
+/// uses only `bind`, `recv_timeout`, `connect_timeout` and `reconnect_ivl`.
+///
+/// UDP endpoints are given as `"udp://<host>:<port>"`. A UDP PUB transport sends to the endpoint
+/// and a SUB transport binds to it, other socket types aren't supported.
+pub fn open(
+    kind: TransportKind,
+    ctx: &Context,
+    socket_type: SocketType,
+    socket_options: SocketOptions,
+    endpoint: &str,
+) -> Result<Box<dyn Transport>, TransportError> {
+    match kind {
+        TransportKind::Zmq => Ok(Box::new(
+            MonitoredSocket::new(ctx, socket_type, socket_options, endpoint)
+                .map_err(TransportError::SocketError)?,
+        )),
+        #[cfg(feature = "grpc")]
+        TransportKind::Grpc => Ok(Box::new(super::grpc::GrpcTransport::new(
+            &socket_options,
+            endpoint,
+        )?)),
+        #[cfg(not(feature = "grpc"))]
+        TransportKind::Grpc => Err(TransportError::Unsupported(kind)),
+        TransportKind::Udp => match socket_type {
+            SocketType::PUB => Ok(Box::new(UdpTransport::new(true, &socket_options, endpoint)?)),
+            SocketType::SUB => Ok(Box::new(UdpTransport::new(false, &socket_options, endpoint)?)),
+            _ => Err(TransportError::UnsupportedSocketType(kind)),
+        },
+    }
+}
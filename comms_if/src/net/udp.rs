@@ -0,0 +1,755 @@
+//! # UDP Transport
+//!
+//! A one-way [`Transport`] over UDP for the high rate telemetry channel, used to evaluate the rover
+//! over lossy radio links where retransmission isn't practical.
+//!
+//! Each message is split into fragments which are sent as separate datagrams, tagged with the
+//! message's sequence number. The fragments are assigned to groups in turn, so fragment `i` is in
+//! group `i % num_groups`, and an XOR parity datagram is sent for each group after the data. Any
+//! one lost fragment in a group can be rebuilt from the parity, and because consecutive fragments
+//! are in different groups a burst of up to `num_groups` lost datagrams can be recovered.
+//!
+//! Every datagram also carries a stream ID chosen when the sender is created, so the reciever can
+//! tell when the sender has restarted its sequence numbers.
+//!
+//! A PUB transport sends to the endpoint, and a SUB transport binds to it and recieves.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    io::ErrorKind,
+    net::UdpSocket,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{LinkStats, SocketOptions, Transport, TransportError};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Length of the header at the start of each datagram, in bytes.
+const HEADER_LEN: usize = 20;
+
+/// Largest datagram which can be recieved.
+const MAX_DATAGRAM_LEN: usize = 65_507;
+
+/// Number of partially recieved messages to keep while waiting for their remaining datagrams.
+const MAX_PENDING_MSGS: usize = 8;
+
+/// Time without a datagram after which the recieving end is considered disconnected.
+const CONNECTED_TIMEOUT: Duration = Duration::from_secs(2);
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A transport over UDP with forward error correction.
+pub struct UdpTransport {
+    socket: UdpSocket,
+
+    /// True if this end sends, false if it recieves
+    sender: bool,
+
+    /// Maximum number of message bytes in each datagram
+    frag_bytes: usize,
+
+    /// Number of data fragments covered by each parity datagram, 0 to disable FEC
+    fec_group_size: usize,
+
+    /// ID of the stream of messages sent by this transport
+    stream_id: u32,
+
+    /// Sequence number of the next message to send
+    next_seq: Cell<u32>,
+
+    /// `ZMQ_RCVTIMEO` style recieve timeout, -1 to block and 0 to return immediately
+    recv_timeout: i32,
+
+    /// Reassembly state of the recieving end
+    rx: RefCell<Reassembler>,
+}
+
+/// The header at the start of each datagram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+    /// ID of the sender's stream
+    stream_id: u32,
+
+    /// Sequence number of the message
+    seq: u32,
+
+    /// Index of this fragment, the parity datagrams follow the data with indexes from `num_frags`
+    index: u16,
+
+    /// Number of data fragments in the message
+    num_frags: u16,
+
+    /// Number of FEC groups, and therefore parity datagrams, in the message
+    num_groups: u16,
+
+    /// Length of every fragment except the last, which may be shorter
+    frag_bytes: u16,
+
+    /// Length of the whole message
+    msg_len: u32,
+}
+
+/// Rebuilds messages from the recieved datagrams.
+#[derive(Default)]
+struct Reassembler {
+    /// ID of the stream being recieved
+    stream_id: Option<u32>,
+
+    /// Messages still being recieved, or recently delivered, by sequence number
+    pending: BTreeMap<u32, PendingMsg>,
+
+    /// Messages older than this have been dropped, so their datagrams are ignored
+    min_seq: u32,
+
+    /// Sequence number of the first message recieved since the sender started
+    first_seq: Option<u32>,
+
+    /// Highest sequence number recieved since the sender started
+    highest_seq: u32,
+
+    /// Number of messages recieved before the sender last restarted
+    msgs_recvd_before_restart: u64,
+
+    /// Time the last datagram was recieved
+    last_recv: Option<Instant>,
+
+    stats: LinkStats,
+}
+
+/// A message which is being reassembled.
+///
+/// Messages are kept after delivery so that late datagrams aren't counted as lost.
+struct PendingMsg {
+    header: Header,
+
+    frags: Vec<Option<Vec<u8>>>,
+
+    parity: Vec<Option<Vec<u8>>>,
+
+    num_recvd: usize,
+
+    /// True if any fragment had to be rebuilt from the parity
+    rebuilt: bool,
+
+    delivered: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl UdpTransport {
+    /// Create a new UDP transport.
+    ///
+    /// `sender` determines the direction of the transport. A sender sends datagrams to the
+    /// endpoint, a reciever binds to it. The `recv_timeout`, `udp_frag_bytes` and
+    /// `udp_fec_group_size` options are used.
+    ///
+    /// The endpoint is in the form `"udp://<host>:<port>"`, with `*` as the host to bind to all
+    /// interfaces.
+    pub fn new(
+        sender: bool,
+        socket_options: &SocketOptions,
+        endpoint: &str,
+    ) -> Result<Self, TransportError> {
+        let addr = endpoint
+            .strip_prefix("udp://")
+            .ok_or_else(|| TransportError::InvalidEndpoint(endpoint.to_string()))?;
+        let addr = match addr.strip_prefix("*:") {
+            Some(port) => format!("0.0.0.0:{}", port),
+            None => addr.to_string(),
+        };
+
+        // The fragment length must fit in the header, and the datagram within the UDP limit
+        if socket_options.udp_frag_bytes == 0
+            || socket_options.udp_frag_bytes > MAX_DATAGRAM_LEN - HEADER_LEN
+        {
+            return Err(TransportError::InvalidOption(format!(
+                "udp_frag_bytes must be between 1 and {}",
+                MAX_DATAGRAM_LEN - HEADER_LEN
+            )));
+        }
+
+        let socket = match sender {
+            true => {
+                let s = UdpSocket::bind("0.0.0.0:0").map_err(TransportError::StartError)?;
+                s.connect(&addr).map_err(TransportError::StartError)?;
+                s
+            }
+            false => UdpSocket::bind(&addr).map_err(TransportError::StartError)?,
+        };
+
+        Ok(Self {
+            socket,
+            sender,
+            frag_bytes: socket_options.udp_frag_bytes,
+            fec_group_size: socket_options.udp_fec_group_size,
+            // Only needs to differ between restarts of the sender
+            stream_id: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|t| t.subsec_nanos() ^ t.as_secs() as u32)
+                .unwrap_or_default(),
+            next_seq: Cell::new(0),
+            recv_timeout: socket_options.recv_timeout,
+            rx: RefCell::new(Reassembler::default()),
+        })
+    }
+
+    /// Recieve a single datagram, returning `Ok(None)` if none arrived before the deadline.
+    fn recv_datagram(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> Result<Option<usize>, TransportError> {
+        let timeout = match deadline {
+            Some(d) => match d.checked_duration_since(Instant::now()) {
+                Some(t) if t > Duration::ZERO => Some(t),
+                _ => return Ok(None),
+            },
+            None => None,
+        };
+
+        // Zero timeouts are rejected, so immediate reads are non-blocking instead
+        match self.recv_timeout {
+            0 => self.socket.set_nonblocking(true),
+            _ => self.socket.set_read_timeout(timeout),
+        }
+        .map_err(TransportError::IoError)?;
+
+        match self.socket.recv(buf) {
+            Ok(len) => Ok(Some(len)),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(TransportError::IoError(e)),
+        }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_bytes(&self, msg: &[u8]) -> Result<(), TransportError> {
+        if !self.sender {
+            return Err(TransportError::NotSent);
+        }
+
+        let seq = self.next_seq.get();
+        self.next_seq.set(seq.wrapping_add(1));
+
+        for datagram in encode(self.stream_id, seq, msg, self.frag_bytes, self.fec_group_size)? {
+            match self.socket.send(&datagram) {
+                Ok(_) => (),
+                // Nothing is listening on the other end
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                    return Err(TransportError::NotSent)
+                }
+                Err(e) => return Err(TransportError::IoError(e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn recv_bytes(&self) -> Result<Option<Vec<u8>>, TransportError> {
+        if self.sender {
+            return Ok(None);
+        }
+
+        let deadline = match self.recv_timeout {
+            t if t > 0 => Some(Instant::now() + Duration::from_millis(t as u64)),
+            _ => None,
+        };
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+
+        // Keep reading datagrams until one of them completes a message
+        loop {
+            let len = match self.recv_datagram(&mut buf, deadline)? {
+                Some(len) => len,
+                None => return Ok(None),
+            };
+
+            if let Some(msg) = self.rx.borrow_mut().push(&buf[..len]) {
+                return Ok(Some(msg));
+            }
+        }
+    }
+
+    fn connected(&self) -> bool {
+        match self.sender {
+            true => true,
+            false => self
+                .rx
+                .borrow()
+                .last_recv
+                .map(|t| t.elapsed() < CONNECTED_TIMEOUT)
+                .unwrap_or(false),
+        }
+    }
+
+    fn link_stats(&self) -> Option<LinkStats> {
+        match self.sender {
+            true => None,
+            false => Some(self.rx.borrow().stats()),
+        }
+    }
+}
+
+impl Header {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.stream_id.to_be_bytes());
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.index.to_be_bytes());
+        buf.extend_from_slice(&self.num_frags.to_be_bytes());
+        buf.extend_from_slice(&self.num_groups.to_be_bytes());
+        buf.extend_from_slice(&self.frag_bytes.to_be_bytes());
+        buf.extend_from_slice(&self.msg_len.to_be_bytes());
+    }
+
+    fn read(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+
+        let u16_at = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+
+        Some(Self {
+            stream_id: u32_at(0),
+            seq: u32_at(4),
+            index: u16_at(8),
+            num_frags: u16_at(10),
+            num_groups: u16_at(12),
+            frag_bytes: u16_at(14),
+            msg_len: u32_at(16),
+        })
+    }
+
+    /// Length of the given data fragment.
+    fn frag_len(&self, index: usize) -> usize {
+        let start = index * self.frag_bytes as usize;
+        (self.msg_len as usize - start).min(self.frag_bytes as usize)
+    }
+
+    /// True if the header describes a message which could have been sent.
+    fn is_valid(&self) -> bool {
+        self.num_frags > 0
+            && self.frag_bytes > 0
+            && self.num_groups <= self.num_frags
+            && self.index < self.num_frags + self.num_groups
+            && (self.num_frags as usize - 1) * (self.frag_bytes as usize) <= self.msg_len as usize
+            && self.msg_len as usize <= self.num_frags as usize * self.frag_bytes as usize
+    }
+}
+
+impl Reassembler {
+    /// Add a datagram, returning the message if it is now complete.
+    fn push(&mut self, datagram: &[u8]) -> Option<Vec<u8>> {
+        self.stats.datagrams_recvd += 1;
+        self.last_recv = Some(Instant::now());
+
+        let header = Header::read(datagram).filter(Header::is_valid)?;
+        let data = &datagram[HEADER_LEN..];
+
+        if self.stream_id != Some(header.stream_id) {
+            if self.stream_id.is_some() {
+                self.restart();
+            }
+            self.stream_id = Some(header.stream_id);
+        }
+
+        // Part of a message which has already been dropped
+        if header.seq < self.min_seq {
+            return None;
+        }
+
+        self.first_seq.get_or_insert(header.seq);
+        self.highest_seq = self.highest_seq.max(header.seq);
+
+        let msg = self
+            .pending
+            .entry(header.seq)
+            .or_insert_with(|| PendingMsg::new(header));
+
+        // Datagrams which don't match the rest of the message are corrupt
+        if msg.header.num_frags != header.num_frags
+            || msg.header.num_groups != header.num_groups
+            || msg.header.frag_bytes != header.frag_bytes
+            || msg.header.msg_len != header.msg_len
+        {
+            return None;
+        }
+
+        let result = msg.insert(header.index as usize, data);
+        if result.is_some() {
+            self.stats.msgs_recvd += 1;
+            if msg.rebuilt {
+                self.stats.msgs_recovered += 1;
+            }
+        }
+
+        // Drop the oldest messages once there are too many
+        while self.pending.len() > MAX_PENDING_MSGS {
+            if let Some((seq, old)) = self.pending.pop_first() {
+                self.stats.datagrams_lost +=
+                    old.num_expected().saturating_sub(old.num_recvd) as u64;
+                self.min_seq = seq + 1;
+            }
+        }
+
+        result
+    }
+
+    /// Start counting sequence numbers again after the sender restarted.
+    fn restart(&mut self) {
+        self.stats = self.stats();
+        self.msgs_recvd_before_restart = self.stats.msgs_recvd;
+
+        for (_, old) in std::mem::take(&mut self.pending) {
+            self.stats.datagrams_lost += old.num_expected().saturating_sub(old.num_recvd) as u64;
+            if !old.delivered {
+                self.stats.msgs_lost += 1;
+            }
+        }
+
+        self.min_seq = 0;
+        self.first_seq = None;
+        self.highest_seq = 0;
+    }
+
+    fn stats(&self) -> LinkStats {
+        let mut stats = self.stats;
+
+        // Every message between the first and the highest should have been recieved, unless it's
+        // still pending
+        if let Some(first) = self.first_seq {
+            let num_pending = self.pending.values().filter(|m| !m.delivered).count() as u64;
+            let num_expected = (self.highest_seq - first) as u64 + 1;
+            let num_recvd = stats.msgs_recvd - self.msgs_recvd_before_restart;
+            stats.msgs_lost += num_expected.saturating_sub(num_recvd + num_pending);
+        }
+
+        stats
+    }
+}
+
+impl PendingMsg {
+    fn new(header: Header) -> Self {
+        Self {
+            header,
+            frags: vec![None; header.num_frags as usize],
+            parity: vec![None; header.num_groups as usize],
+            num_recvd: 0,
+            rebuilt: false,
+            delivered: false,
+        }
+    }
+
+    fn num_expected(&self) -> usize {
+        self.frags.len() + self.parity.len()
+    }
+
+    /// Insert a fragment, returning the message if it has just been completed.
+    fn insert(&mut self, index: usize, data: &[u8]) -> Option<Vec<u8>> {
+        let num_frags = self.frags.len();
+        let slot = match index < num_frags {
+            true => &mut self.frags[index],
+            false => &mut self.parity[index - num_frags],
+        };
+
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(data.to_vec());
+        self.num_recvd += 1;
+
+        if self.delivered {
+            return None;
+        }
+
+        // Rebuild any fragment which is the only one missing from its group
+        let num_groups = self.parity.len();
+        for group in 0..num_groups {
+            let missing: Vec<usize> = (group..num_frags)
+                .step_by(num_groups)
+                .filter(|&i| self.frags[i].is_none())
+                .collect();
+
+            if let ([lost], Some(parity)) = (missing.as_slice(), &self.parity[group]) {
+                let mut rebuilt = parity.clone();
+                for i in (group..num_frags).step_by(num_groups).filter(|i| i != lost) {
+                    xor_into(&mut rebuilt, self.frags[i].as_ref().unwrap());
+                }
+                rebuilt.truncate(self.header.frag_len(*lost));
+                self.frags[*lost] = Some(rebuilt);
+                self.rebuilt = true;
+            }
+        }
+
+        if self.frags.iter().any(|f| f.is_none()) {
+            return None;
+        }
+
+        // Corrupt datagrams may have the wrong length, in which case the message is dropped
+        let msg: Vec<u8> = self.frags.iter().flatten().flatten().copied().collect();
+        self.delivered = msg.len() == self.header.msg_len as usize;
+        match self.delivered {
+            true => Some(msg),
+            false => None,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Split a message into datagrams, with the data fragments followed by the parity datagrams.
+fn encode(
+    stream_id: u32,
+    seq: u32,
+    msg: &[u8],
+    frag_bytes: usize,
+    fec_group_size: usize,
+) -> Result<Vec<Vec<u8>>, TransportError> {
+    let frags: Vec<&[u8]> = match msg.is_empty() {
+        true => vec![msg],
+        false => msg.chunks(frag_bytes).collect(),
+    };
+
+    let num_groups = match fec_group_size {
+        0 => 0,
+        k => frags.len().div_ceil(k),
+    };
+
+    if frags.len() + num_groups > u16::MAX as usize || frag_bytes > u16::MAX as usize {
+        return Err(TransportError::InvalidOption(format!(
+            "a {} byte message needs too many {} byte fragments",
+            msg.len(),
+            frag_bytes
+        )));
+    }
+
+    let header = |index: usize| Header {
+        stream_id,
+        seq,
+        index: index as u16,
+        num_frags: frags.len() as u16,
+        num_groups: num_groups as u16,
+        frag_bytes: frag_bytes.min(msg.len().max(1)) as u16,
+        msg_len: msg.len() as u32,
+    };
+
+    let mut datagrams = Vec::with_capacity(frags.len() + num_groups);
+
+    for (i, frag) in frags.iter().enumerate() {
+        let mut d = Vec::with_capacity(HEADER_LEN + frag.len());
+        header(i).write(&mut d);
+        d.extend_from_slice(frag);
+        datagrams.push(d);
+    }
+
+    for group in 0..num_groups {
+        let mut parity = Vec::new();
+        for frag in frags.iter().skip(group).step_by(num_groups) {
+            xor_into(&mut parity, frag);
+        }
+
+        let mut d = Vec::with_capacity(HEADER_LEN + parity.len());
+        header(frags.len() + group).write(&mut d);
+        d.extend_from_slice(&parity);
+        datagrams.push(d);
+    }
+
+    Ok(datagrams)
+}
+
+/// XOR `src` into `dst`, extending `dst` with zeros if it's shorter.
+fn xor_into(dst: &mut Vec<u8>, src: &[u8]) {
+    if dst.len() < src.len() {
+        dst.resize(src.len(), 0);
+    }
+
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= s;
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A message of the given length with bytes which differ between fragments.
+    fn test_msg(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    /// Push the datagrams into the reassembler, returning every message it delivers.
+    fn deliver<'a, I: IntoIterator<Item = &'a Vec<u8>>>(
+        rx: &mut Reassembler,
+        datagrams: I,
+    ) -> Vec<Vec<u8>> {
+        datagrams.into_iter().filter_map(|d| rx.push(d)).collect()
+    }
+
+    /// Get the datagrams except those with the given indexes.
+    fn without<'a>(
+        datagrams: &'a [Vec<u8>],
+        lost: &'a [usize],
+    ) -> impl Iterator<Item = &'a Vec<u8>> {
+        datagrams
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| !lost.contains(i))
+            .map(|(_, d)| d)
+    }
+
+    #[test]
+    fn test_encode_layout() {
+        let msg = test_msg(10);
+        let datagrams = encode(1, 2, &msg, 4, 2).unwrap();
+
+        // 3 data fragments in 2 groups, so 2 parity datagrams
+        assert_eq!(datagrams.len(), 5);
+        let lens: Vec<usize> = datagrams.iter().map(|d| d.len() - HEADER_LEN).collect();
+        assert_eq!(lens, vec![4, 4, 2, 4, 4]);
+
+        let header = Header::read(&datagrams[4]).unwrap();
+        assert!(header.is_valid());
+        assert_eq!((header.stream_id, header.seq, header.index), (1, 2, 4));
+        assert_eq!(
+            (header.num_frags, header.num_groups, header.msg_len),
+            (3, 2, 10)
+        );
+
+        // Group 0 is fragments 0 and 2, the shorter one padded with zeros
+        let mut parity = msg[0..4].to_vec();
+        xor_into(&mut parity, &msg[8..10]);
+        assert_eq!(&datagrams[3][HEADER_LEN..], &parity[..]);
+        assert_eq!(&datagrams[4][HEADER_LEN..], &msg[4..8]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (len, fec_group_size) in [(100, 4), (100, 0), (3, 4), (0, 4)] {
+            let msg = test_msg(len);
+            let mut rx = Reassembler::default();
+
+            let datagrams = encode(1, 0, &msg, 16, fec_group_size).unwrap();
+            assert_eq!(deliver(&mut rx, &datagrams), vec![msg]);
+            assert_eq!(rx.stats().msgs_recvd, 1);
+            assert_eq!(rx.stats().msgs_recovered, 0);
+        }
+    }
+
+    #[test]
+    fn test_single_loss_recovered() {
+        let msg = test_msg(100);
+        let datagrams = encode(1, 0, &msg, 16, 4).unwrap();
+        let num_frags = 7;
+
+        // Losing any one datagram, data or parity, still delivers the message
+        for lost in 0..datagrams.len() {
+            let mut rx = Reassembler::default();
+            let recvd = deliver(&mut rx, without(&datagrams, &[lost]));
+
+            assert_eq!(recvd, vec![msg.clone()], "Lost datagram {}", lost);
+            assert_eq!(rx.stats().msgs_recovered, (lost < num_frags) as u64);
+        }
+    }
+
+    #[test]
+    fn test_double_loss_in_group_not_recovered() {
+        let msg = test_msg(100);
+        let datagrams = encode(1, 0, &msg, 16, 4).unwrap();
+
+        // 7 fragments in 2 groups, fragments 0 and 2 are both in group 0
+        let mut rx = Reassembler::default();
+        let recvd = deliver(&mut rx, without(&datagrams, &[0, 2]));
+        assert!(recvd.is_empty());
+        assert_eq!(rx.stats().msgs_recvd, 0);
+
+        // Without FEC any loss is fatal
+        let datagrams = encode(1, 0, &msg, 16, 0).unwrap();
+        let mut rx = Reassembler::default();
+        assert!(deliver(&mut rx, &datagrams[1..]).is_empty());
+    }
+
+    #[test]
+    fn test_burst_loss_recovered() {
+        let msg = test_msg(100);
+        let datagrams = encode(1, 0, &msg, 16, 4).unwrap();
+
+        // Consecutive fragments are in different groups, so a burst as long as the number of
+        // groups can be rebuilt
+        let mut rx = Reassembler::default();
+        let recvd = deliver(&mut rx, without(&datagrams, &[3, 4]));
+        assert_eq!(recvd, vec![msg]);
+        assert_eq!(rx.stats().msgs_recovered, 1);
+    }
+
+    #[test]
+    fn test_interleaved_messages() {
+        let msgs = [test_msg(50), test_msg(70)];
+        let first = encode(1, 0, &msgs[0], 16, 4).unwrap();
+        let second = encode(1, 1, &msgs[1], 16, 4).unwrap();
+
+        // Alternate between the messages, with the second one's last datagrams arriving first
+        let mut datagrams = vec![];
+        for i in (0..second.len()).rev() {
+            datagrams.push(&second[i]);
+            if let Some(d) = first.get(i) {
+                datagrams.push(d);
+            }
+        }
+
+        let mut rx = Reassembler::default();
+        let recvd = deliver(&mut rx, datagrams);
+        assert_eq!(recvd.len(), 2);
+        assert!(recvd.contains(&msgs[0]) && recvd.contains(&msgs[1]));
+        assert_eq!(rx.stats().msgs_lost, 0);
+    }
+
+    #[test]
+    fn test_duplicate_datagrams() {
+        let msg = test_msg(100);
+        let datagrams = encode(1, 0, &msg, 16, 4).unwrap();
+
+        // Every datagram twice, and all of them again after delivery
+        let mut rx = Reassembler::default();
+        let doubled = datagrams.iter().flat_map(|d| [d, d]);
+        assert_eq!(deliver(&mut rx, doubled), vec![msg]);
+        assert!(deliver(&mut rx, &datagrams).is_empty());
+
+        let stats = rx.stats();
+        assert_eq!(stats.msgs_recvd, 1);
+        assert_eq!(stats.msgs_lost, 0);
+        assert_eq!(stats.datagrams_recvd, 3 * datagrams.len() as u64);
+    }
+
+    #[test]
+    fn test_corrupt_datagrams_ignored() {
+        let msg = test_msg(100);
+        let datagrams = encode(1, 0, &msg, 16, 4).unwrap();
+        let mut rx = Reassembler::default();
+
+        // Too short for a header, and a header which disagrees with the rest of the message
+        assert!(rx.push(&[0u8; HEADER_LEN - 1]).is_none());
+        let mut bad = datagrams[0].clone();
+        bad[16..20].copy_from_slice(&99u32.to_be_bytes());
+        assert!(rx.push(&datagrams[1]).is_none());
+        assert!(rx.push(&bad).is_none());
+
+        assert_eq!(deliver(&mut rx, &datagrams), vec![msg]);
+    }
+}
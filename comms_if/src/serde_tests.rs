@@ -0,0 +1,364 @@
+//! # Serialisation round-trip tests
+//!
+//! Ground tools parse the JSON produced by the rover, so any change to how a type serialises
+//! breaks them. These tests check that each TC, TM and equipment type comes back unchanged from a
+//! serialise then deserialise, including at boundary values.
+//!
+//! Most of these types don't implement `PartialEq`, so the value is compared by serialising it
+//! again and checking the JSON is identical.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{collections::HashMap, fmt::Debug, path::PathBuf};
+
+use chrono::{TimeZone, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    eqpt::{
+        cam::{CamFrame, CamId, CamRequest, CamResponse, CameraControl, FrameRequest, ImageFormat},
+        mech::{
+            ActId, ArmFault, MechDems, MechDemsFlags, MechDemsPacket, MechDemsResponse,
+            MechSensData, MechSensPacket,
+        },
+    },
+    net::chunk::Chunk,
+    tc::{
+        arm_ctrl::ArmCmd,
+        auto::{AutoCmd, AutoMnvrCmd},
+        calibrate::{CalibrateCmd, SteerCalCmd, TurnCalCmd},
+        cam::CamCmd,
+        drawbar::DrawbarCmd,
+        eqpt::{Eqpt, EqptCmd},
+        loco_ctrl::MnvrCmd,
+        mast_ctrl::MastCmd,
+        path::{PathChunk, PathCmd},
+        Tc, TcPacket, TcRejectReason, TcResponse,
+    },
+    tm::{
+        img::ImageChunk,
+        latency::TcStamps,
+        query::{TmQuery, TmQueryResponse},
+    },
+    units::{Curvature, Meters, MetersPerSec, RadPerSec, Radians},
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Awkward floating point values, all of which JSON can represent.
+const BOUNDARY_F64: [f64; 7] = [0.0, -0.0, 0.1, -1.5e-300, f64::MIN_POSITIVE, f64::MAX, f64::MIN];
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Assert that the value serialises to the same JSON after a round trip, and return the JSON.
+fn assert_round_trip<T: Serialize + DeserializeOwned + Debug>(value: &T) -> Value {
+    let json = serde_json::to_string(value).unwrap();
+    let parsed: T = serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("Could not parse {} back: {}", json, e));
+
+    let before = serde_json::to_value(value).unwrap();
+    let after = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(before, after, "{:?} changed in the round trip", value);
+
+    before
+}
+
+/// Demands for every actuator, with the given value.
+fn all_act_dems(value: f64) -> MechDems {
+    let all = ActId::drv_ids()
+        .iter()
+        .chain(ActId::str_ids())
+        .chain(ActId::arm_ids())
+        .chain(ActId::mast_ids());
+
+    MechDems {
+        pos_rad: all.clone().map(|&id| (id, value)).collect(),
+        speed_rads: all.map(|&id| (id, -value)).collect(),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[test]
+fn test_tc_round_trip() {
+    for &v in BOUNDARY_F64.iter() {
+        let tcs = vec![
+            Tc::MakeSafe,
+            Tc::MakeUnsafe,
+            Tc::SelfTest,
+            Tc::LocoCtrlMnvr(MnvrCmd::Ackerman {
+                speed_ms: MetersPerSec(v),
+                curv_m: Curvature(-v),
+                crab_rad: Radians(v),
+            }),
+            Tc::LocoCtrlMnvr(MnvrCmd::PointTurn { rate_rads: RadPerSec(v) }),
+            Tc::LocoCtrlMnvr(MnvrCmd::SkidSteer {
+                speed_ms: MetersPerSec(v),
+                curv_m: Curvature(v),
+            }),
+            Tc::LocoCtrlMnvr(MnvrCmd::Stop),
+            Tc::ArmCmd(ArmCmd::BasicRotation { dems: all_act_dems(v) }),
+            Tc::ArmCmd(ArmCmd::InverseKinematics {
+                base_pos_rad: v,
+                horizontal_distance_m: v,
+                vertical_distance_m: -v,
+                wrist_pos_rad: v,
+                grabber_pos_rad: v,
+            }),
+            Tc::ArmCmd(ArmCmd::Stop),
+            Tc::Autonomy(AutoCmd::Manouvre(AutoMnvrCmd::Ackerman {
+                speed_ms: MetersPerSec(v),
+                curv_m: Curvature(v),
+                crab_rad: Radians(v),
+                dist_m: Meters(v),
+            })),
+            Tc::Autonomy(AutoCmd::Manouvre(AutoMnvrCmd::PointTurn {
+                rate_rads: RadPerSec(v),
+                dist_rad: Radians(v),
+            })),
+            Tc::Autonomy(AutoCmd::Goto { x_m_lm: v, y_m_lm: -v }),
+            Tc::MastCmd(MastCmd::Point { az_rad: v, el_rad: v }),
+            Tc::Drawbar(DrawbarCmd::Start { speed_ms: v, duration_s: v }),
+            Tc::Calibrate(CalibrateCmd::Steer(SteerCalCmd::Jog {
+                axis: ActId::StrRR,
+                delta_rad: v,
+            })),
+            Tc::Calibrate(CalibrateCmd::Turn(TurnCalCmd::Start {
+                speed_ms: MetersPerSec(v),
+                max_curv_m: Curvature(v),
+                num_curvs: u32::MAX,
+                arc_duration_s: v,
+            })),
+        ];
+
+        for tc in tcs.iter() {
+            assert_round_trip(tc);
+        }
+    }
+
+    let tcs = vec![
+        Tc::Autonomy(AutoCmd::Follow { path: PathBuf::from(""), corridor_m: None }),
+        Tc::Autonomy(AutoCmd::Follow {
+            path: PathBuf::from("paths/ünïcödé path.json"),
+            corridor_m: Some(0.0),
+        }),
+        Tc::MastCmd(MastCmd::Stow),
+        Tc::MastCmd(MastCmd::Stop),
+        Tc::Drawbar(DrawbarCmd::Abort),
+        Tc::Cam(CamCmd::Control(CameraControl {
+            camera: CamId::RightNav,
+            auto_exposure: Some(false),
+            exposure_us: Some(u32::MAX),
+            gain: Some(0),
+            auto_white_balance: None,
+            white_balance_k: None,
+        })),
+        Tc::Path(PathCmd::Chunk(PathChunk {
+            name: String::from("\"quoted\"\n"),
+            chunk: Chunk {
+                session_id: u64::MAX,
+                transfer_id: 0,
+                kind: String::new(),
+                seq: 0,
+                num_chunks: u32::MAX,
+                payload_len: u64::MAX,
+                payload_crc: 0,
+                crc: u32::MAX,
+                b64_data: String::new(),
+            },
+        })),
+        Tc::Path(PathCmd::Check { path: PathBuf::from("a/b.csv") }),
+        Tc::Calibrate(CalibrateCmd::Steer(SteerCalCmd::Capture)),
+        Tc::Calibrate(CalibrateCmd::Steer(SteerCalCmd::Abort)),
+        Tc::Calibrate(CalibrateCmd::Turn(TurnCalCmd::Abort)),
+        Tc::Eqpt(EqptCmd::Reconnect { eqpt: Eqpt::Mech }),
+        Tc::Eqpt(EqptCmd::Reconnect { eqpt: Eqpt::Cam }),
+    ];
+
+    for tc in tcs.iter() {
+        assert_round_trip(tc);
+    }
+}
+
+#[test]
+fn test_tc_format() {
+    // Ground tools and scripts build these by hand, so the format itself mustn't change
+    assert_eq!(assert_round_trip(&Tc::MakeSafe), json!("MakeSafe"));
+    assert_eq!(
+        assert_round_trip(&Tc::LocoCtrlMnvr(MnvrCmd::PointTurn { rate_rads: RadPerSec(0.5) })),
+        json!({"LocoCtrlMnvr": {"PointTurn": {"rate_rads": 0.5}}})
+    );
+    assert_eq!(
+        assert_round_trip(&TcResponse::CannotExecute(TcRejectReason::ModeNotAllowed)),
+        json!({"CannotExecute": "ModeNotAllowed"})
+    );
+}
+
+#[test]
+fn test_tc_packet_round_trip() {
+    let stamp = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+
+    let packets = [
+        TcPacket { vehicle_id: None, tc: Tc::MakeSafe, sent: None },
+        TcPacket { vehicle_id: Some(String::new()), tc: Tc::MakeUnsafe, sent: Some(stamp) },
+        TcPacket {
+            vehicle_id: Some(String::from("phobos-1")),
+            tc: Tc::LocoCtrlMnvr(MnvrCmd::Stop),
+            sent: Some(Utc.timestamp_opt(0, 0).unwrap()),
+        },
+    ];
+
+    for packet in packets.iter() {
+        let json = assert_round_trip(packet);
+
+        // The rover parses packets with its own parser, which must agree with serde
+        let parsed = TcPacket::from_json(&json.to_string()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+    }
+
+    for response in [
+        TcResponse::Ok,
+        TcResponse::Invalid,
+        TcResponse::CannotExecute(TcRejectReason::ModeNotAllowed),
+        TcResponse::WrongVehicle,
+        TcResponse::Queued,
+    ] {
+        assert_round_trip(&response);
+    }
+}
+
+#[test]
+fn test_mech_round_trip() {
+    assert_round_trip(&MechDems::default());
+
+    for &v in BOUNDARY_F64.iter() {
+        assert_round_trip(&MechDemsPacket {
+            session_id: u64::MAX,
+            seq: u64::MAX,
+            dems: all_act_dems(v),
+            flags: MechDemsFlags {
+                autonomy_active: true,
+                capture_steer_zero: false,
+                estop: true,
+            },
+        });
+
+        let dems = all_act_dems(v);
+        assert_round_trip(&MechSensPacket {
+            last_dems_session_id: Some(0),
+            last_dems_seq: Some(0),
+            dems_response: Some(MechDemsResponse::EqptInvalid),
+            sens: MechSensData {
+                pos_rad: dems.pos_rad,
+                speed_rads: dems.speed_rads,
+                current_a: HashMap::new(),
+                dems_age_s: Some(v),
+                dems_expired: true,
+            },
+            arm_fault: Some(ArmFault {
+                act_id: ActId::ArmGrabber,
+                current_a: v,
+                limit_a: v,
+                pos_rad: -v,
+            }),
+        });
+    }
+
+    assert_round_trip(&MechSensPacket::default());
+    assert_round_trip(&MechDemsPacket::default());
+}
+
+#[test]
+fn test_mech_packet_defaults() {
+    // Packets from before the optional fields were added must still parse
+    let packet: MechDemsPacket = serde_json::from_value(json!({
+        "seq": 3,
+        "dems": {"pos_rad": {}, "speed_rads": {"DrvFL": 1.0}}
+    }))
+    .unwrap();
+    assert_eq!(packet.session_id, 0);
+    assert!(!packet.flags.estop);
+
+    let packet: MechSensPacket = serde_json::from_value(json!({
+        "last_dems_seq": null,
+        "dems_response": "DemsOk",
+        "sens": {"pos_rad": {}, "speed_rads": {}}
+    }))
+    .unwrap();
+    assert!(packet.last_dems_session_id.is_none());
+    assert!(packet.arm_fault.is_none());
+    assert!(packet.sens.dems_age_s.is_none());
+}
+
+#[test]
+fn test_cam_round_trip() {
+    // Timestamps are sent in milliseconds
+    let stamp = Utc.timestamp_millis_opt(1_700_000_000_123).unwrap();
+
+    assert_round_trip(&CamRequest::FrameRequest(FrameRequest {
+        cameras: vec![CamId::LeftNav, CamId::RightNav],
+        format: ImageFormat::Jpeg(u8::MAX),
+    }));
+    assert_round_trip(&CamRequest::FrameRequest(FrameRequest {
+        cameras: vec![],
+        format: ImageFormat::Png,
+    }));
+
+    let mut frames = HashMap::new();
+    frames.insert(CamId::LeftNav, CamFrame {
+        timestamp: stamp,
+        format: ImageFormat::Jpeg(0),
+        b64_data: String::from("AAEC"),
+    });
+    assert_round_trip(&CamResponse::Frames(frames));
+    assert_round_trip(&CamResponse::Frames(HashMap::new()));
+    assert_round_trip(&CamResponse::CameraControlRejected);
+}
+
+#[test]
+fn test_tm_round_trip() {
+    let stamp = Utc.timestamp_opt(1_700_000_000, 999_999_999).unwrap();
+
+    assert_round_trip(&TcStamps { sent: None, recvd: stamp, executed: None, actuated: None });
+    let stamps = TcStamps {
+        sent: Some(Utc.timestamp_opt(0, 0).unwrap()),
+        recvd: stamp,
+        executed: Some(stamp),
+        actuated: Some(stamp),
+    };
+    assert_round_trip(&stamps);
+
+    // Stamps keep their full precision
+    let parsed: TcStamps =
+        serde_json::from_str(&serde_json::to_string(&stamps).unwrap()).unwrap();
+    assert_eq!(parsed, stamps);
+
+    assert_round_trip(&ImageChunk {
+        session_id: u64::MAX,
+        image_id: u64::MAX,
+        cam_id: CamId::LeftNav,
+        timestamp: Utc.timestamp_millis_opt(1_700_000_000_123).unwrap(),
+        format: ImageFormat::Png,
+        chunk_index: u32::MAX - 1,
+        num_chunks: u32::MAX,
+        b64_data: String::new(),
+    });
+
+    assert_round_trip(&TmQuery { channel: String::new() });
+    assert_round_trip(&TmQueryResponse::Value {
+        sim_time_s: f64::MAX,
+        value: json!({"a": [1, -2.5, null, "b"], "c": u64::MAX}),
+    });
+    assert_round_trip(&TmQueryResponse::UnknownChannel(String::from("loco.nope")));
+    assert_round_trip(&TmQueryResponse::NoData);
+    assert_round_trip(&TmQueryResponse::Invalid);
+}
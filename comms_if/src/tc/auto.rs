@@ -8,6 +8,8 @@ use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
 use structopt::StructOpt;
 
+use crate::units::{Curvature, Meters, MetersPerSec, RadPerSec, Radians};
+
 // ------------------------------------------------------------------------------------------------
 // ENUMS
 // ------------------------------------------------------------------------------------------------
@@ -19,11 +21,19 @@ pub enum AutoCmd {
     #[structopt(name = "mnvr")]
     Manouvre(AutoMnvrCmd),
 
-    /// Follow the path stored in the path file at the given system path.
+    /// Follow the path stored in the given path file.
     #[structopt(name = "follow")]
     Follow {
-        /// The path to the path file.
-        path: PathBuf
+        /// The name of the path file in the rover's paths directory, see `path chunk`.
+        path: PathBuf,
+
+        /// Width of the keep-in corridor centred on the path, in meters.
+        ///
+        /// If the rover ever gets further than half this width from the path the traverse is
+        /// aborted. If not given there is no corridor.
+        #[structopt(long)]
+        #[serde(default)]
+        corridor_m: Option<f64>,
     },
 
     /// Autonomously navigate to the given coordinates in the LocalMap frame.
@@ -50,26 +60,26 @@ pub enum AutoMnvrCmd {
         /// The speed of the manouvre in meters/second.
         ///
         /// Positive speeds are "forwards", negative speeds are "backwards"
-        speed_ms: f64,
+        speed_ms: MetersPerSec,
 
         /// The curvature of the manouvre in 1/meters.
         ///
         /// Follows the right hand rule about the rover's Z+ (upwards) axis, so that positive
         /// curvature is a turn to the left, and negative curvature a turn to the right.
-        curv_m: f64,
+        curv_m: Curvature,
 
         /// The crab angle of the manouvre in radians.
         ///
         /// Follows the right hand grip rule about the rover's Z+ (upwards) axis, so that positive
         /// crab angles will move to the left, and negative crab angle to the right.
-        crab_rad: f64,
+        crab_rad: Radians,
 
         /// The total distance to traverse in this manouvre.
         ///
         /// This is the complete distance the rover should traverse, along the arc of the manouvre.
         /// For a non-straight Ackerman this is equivalent to the length of the sector traced by the
         /// manouvre.
-        dist_m: f64,
+        dist_m: Meters,
     },
 
     /// A turn-on-the-spot manouvre about the centre of the rover's wheelbase.
@@ -80,9 +90,91 @@ pub enum AutoMnvrCmd {
         /// Follows the right hand rule about the rover's Z+ (upwrads) axis, so that a positive turn
         /// rate will rotate the rover to the left, and a negative turn rate will rotate the rover
         /// to the right.
-        rate_rads: f64,
+        rate_rads: RadPerSec,
 
         /// The absolute angular distance to traverse in this manouvre.
-        dist_rad: f64
+        dist_rad: Radians
     },
-}
\ No newline at end of file
+}
+
+/// Reasons an autonomy command is invalid.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum AutoCmdError {
+    #[error("{0} must be a finite number, got {1}")]
+    NotFinite(&'static str, f64),
+
+    #[error("{0} must be positive, got {1}")]
+    NotPositive(&'static str, f64),
+
+    #[error("{0} must not be zero")]
+    Zero(&'static str),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl AutoCmd {
+    /// Check that the command's values make sense, before it is passed to autonomy.
+    ///
+    /// Only the values themselves are checked, for example a path file given to `follow` is not
+    /// loaded.
+    pub fn validate(&self) -> Result<(), AutoCmdError> {
+        match *self {
+            AutoCmd::Manouvre(ref m) => m.validate(),
+            AutoCmd::Follow { corridor_m, .. } => match corridor_m {
+                Some(w) => positive("corridor_m", w),
+                None => Ok(()),
+            },
+            AutoCmd::Goto { x_m_lm, y_m_lm } => {
+                finite("x_m_lm", x_m_lm)?;
+                finite("y_m_lm", y_m_lm)
+            }
+        }
+    }
+}
+
+impl AutoMnvrCmd {
+    /// Check that the manouvre's values make sense.
+    pub fn validate(&self) -> Result<(), AutoCmdError> {
+        match *self {
+            AutoMnvrCmd::Ackerman { speed_ms, curv_m, crab_rad, dist_m } => {
+                non_zero("speed_ms", speed_ms.0)?;
+                finite("curv_m", curv_m.0)?;
+                finite("crab_rad", crab_rad.0)?;
+                positive("dist_m", dist_m.0)
+            }
+            AutoMnvrCmd::PointTurn { rate_rads, dist_rad } => {
+                non_zero("rate_rads", rate_rads.0)?;
+                positive("dist_rad", dist_rad.0)
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+fn finite(name: &'static str, value: f64) -> Result<(), AutoCmdError> {
+    match value.is_finite() {
+        true => Ok(()),
+        false => Err(AutoCmdError::NotFinite(name, value)),
+    }
+}
+
+fn positive(name: &'static str, value: f64) -> Result<(), AutoCmdError> {
+    finite(name, value)?;
+    match value > 0.0 {
+        true => Ok(()),
+        false => Err(AutoCmdError::NotPositive(name, value)),
+    }
+}
+
+fn non_zero(name: &'static str, value: f64) -> Result<(), AutoCmdError> {
+    finite(name, value)?;
+    match value != 0.0 {
+        true => Ok(()),
+        false => Err(AutoCmdError::Zero(name)),
+    }
+}
@@ -0,0 +1,83 @@
+//! # Calibration telecommands
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::{
+    eqpt::mech::ActId,
+    units::{Curvature, MetersPerSec},
+};
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A calibration command.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+pub enum CalibrateCmd {
+    /// Calibrate the steer axis offsets.
+    #[structopt(name = "steer")]
+    Steer(SteerCalCmd),
+
+    /// Calibrate the Ackerman steering geometry against the measured turning circle.
+    #[structopt(name = "turn")]
+    Turn(TurnCalCmd),
+}
+
+/// A step of the steer calibration.
+///
+/// Each steer axis is jogged until its wheel is mechanically straight ahead, then the positions of
+/// all axes are captured as their zeros. The drive axes are held still throughout.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+pub enum SteerCalCmd {
+    /// Move one steer axis by the given angle, starting calibration if it isn't already running.
+    #[structopt(name = "jog")]
+    Jog {
+        /// The steer axis to move, e.g. `StrFL`.
+        axis: ActId,
+
+        /// The angle to move the axis by in radians.
+        delta_rad: f64,
+    },
+
+    /// Capture the current positions of all steer axes as their zeros, ending calibration.
+    #[structopt(name = "capture")]
+    Capture,
+
+    /// End calibration without changing the offsets.
+    #[structopt(name = "abort")]
+    Abort,
+}
+
+/// A command for the turning circle calibration.
+///
+/// The rover drives a series of constant curvature arcs, alternating left and right, and the
+/// curvature of each arc measured by localisation is compared to the demand. Corrections to the
+/// wheelbase and track used by LocoCtrl are fitted from the results and saved to the session.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+pub enum TurnCalCmd {
+    /// Start the calibration.
+    #[structopt(name = "start")]
+    Start {
+        /// The speed to drive each arc at in meters/second.
+        speed_ms: MetersPerSec,
+
+        /// The largest curvature to drive in 1/meters, the others are spread evenly below it.
+        max_curv_m: Curvature,
+
+        /// The number of curvatures to drive, each of which is driven to the left and right.
+        num_curvs: u32,
+
+        /// The duration of each arc in seconds, not including the time allowed for the steering
+        /// to settle.
+        arc_duration_s: f64,
+    },
+
+    /// Stop the calibration without fitting the results.
+    #[structopt(name = "abort")]
+    Abort,
+}
@@ -0,0 +1,23 @@
+//! # Camera telecommands
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use crate::eqpt::cam::CameraControl;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A command for the cameras.
+#[derive(Debug, Clone, Serialize, Deserialize, StructOpt)]
+pub enum CamCmd {
+    /// Change the exposure, gain or white balance of a camera.
+    ///
+    /// For example `cam control left_nav --auto-exposure false --exposure-us 5000`.
+    #[structopt(name = "control")]
+    Control(CameraControl),
+}
@@ -0,0 +1,32 @@
+//! # Drawbar test telecommands
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A command for the drawbar (soil interaction) test mode.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+pub enum DrawbarCmd {
+    /// Start a constant speed run, driving straight ahead while archiving the run.
+    #[structopt(name = "start")]
+    Start {
+        /// The demanded speed of the run in meters/second.
+        ///
+        /// Positive speeds are "forwards", negative speeds are "backwards"
+        speed_ms: f64,
+
+        /// The duration of the run in seconds, after which the rover will stop.
+        duration_s: f64,
+    },
+
+    /// Stop the current run.
+    #[structopt(name = "abort")]
+    Abort,
+}
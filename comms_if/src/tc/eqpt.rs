@@ -0,0 +1,54 @@
+//! # Equipment telecommands
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A command for the links to the equipment servers.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+pub enum EqptCmd {
+    /// Close and reopen the link to an equipment server, for example after the server has been
+    /// restarted. The rest of the rover's state is kept.
+    ///
+    /// For example `eqpt reconnect mech`.
+    #[structopt(name = "reconnect")]
+    Reconnect {
+        /// The equipment to reconnect, either mech or cam
+        eqpt: Eqpt,
+    },
+}
+
+/// Equipment servers the rover has a link to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Eqpt {
+    /// The mechanisms server
+    Mech,
+
+    /// The camera server
+    Cam,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl FromStr for Eqpt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mech" => Ok(Eqpt::Mech),
+            "cam" => Ok(Eqpt::Cam),
+            _ => Err(format!("Unknown equipment \"{}\", expected mech or cam", s)),
+        }
+    }
+}
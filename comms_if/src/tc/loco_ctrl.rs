@@ -7,12 +7,14 @@
 use serde::{Serialize, Deserialize};
 use structopt::StructOpt;
 
+use crate::units::{Curvature, MetersPerSec, RadPerSec, Radians};
+
 // ------------------------------------------------------------------------------------------------
 // ENUMS
 // ------------------------------------------------------------------------------------------------
 
 /// A manouvre that can be completed by locomotion control.
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, StructOpt)]
 pub enum MnvrCmd {
     /// A generic ackerman command.
     ///
@@ -24,19 +26,19 @@ pub enum MnvrCmd {
         /// The speed of the manouvre in meters/second.
         ///
         /// Positive speeds are "forwards", negative speeds are "backwards"
-        speed_ms: f64,
+        speed_ms: MetersPerSec,
 
         /// The curvature of the manouvre in 1/meters.
         ///
         /// Follows the right hand rule about the rover's Z+ (upwards) axis, so that positive
         /// curvature is a turn to the left, and negative curvature a turn to the right.
-        curv_m: f64,
+        curv_m: Curvature,
 
         /// The crab angle of the manouvre in radians.
         ///
         /// Follows the right hand grip rule about the rover's Z+ (upwards) axis, so that positive
         /// crab angles will move to the left, and negative crab angle to the right.
-        crab_rad: f64
+        crab_rad: Radians
     },
 
     /// A turn-on-the-spot manouvre about the centre of the rover's wheelbase.
@@ -47,7 +49,7 @@ pub enum MnvrCmd {
         /// Follows the right hand rule about the rover's Z+ (upwrads) axis, so that a positive turn
         /// rate will rotate the rover to the left, and a negative turn rate will rotate the rover
         /// to the right.
-        rate_rads: f64
+        rate_rads: RadPerSec
     },
 
     /// A tank-like steering manouvre in which all wheels point forwards and the rover is steered
@@ -57,13 +59,13 @@ pub enum MnvrCmd {
         /// The speed of the manouvre in meters/second.
         ///
         /// Positive speeds are "forwards", negative speeds are "backwards"
-        speed_ms: f64,
+        speed_ms: MetersPerSec,
 
         /// The curvature of the manouvre in 1/meters.
         ///
         /// Follows the right hand rule about the rover's Z+ (upwards) axis, so that positive
         /// curvature is a turn to the left, and negative curvature a turn to the right.
-        curv_m: f64,
+        curv_m: Curvature,
     },
 
     /// Stop the rover, maintaining the current steer axis angles but setting all drive axes to zero
@@ -0,0 +1,40 @@
+//! # Mast control telecommands
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A command for the pan/tilt mast.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, StructOpt)]
+pub enum MastCmd {
+    /// Point the mast at the given azimuth and elevation.
+    #[structopt(name = "point")]
+    Point {
+        /// Azimuth (pan) angle in radians.
+        ///
+        /// Follows the right hand rule about the rover's Z+ (upwards) axis, so that positive
+        /// azimuths look to the left. Zero looks straight ahead.
+        az_rad: f64,
+
+        /// Elevation (tilt) angle in radians.
+        ///
+        /// Positive elevations look up, negative elevations look down towards the ground. Zero
+        /// is level with the rover body.
+        el_rad: f64,
+    },
+
+    /// Return the mast to its stowed position.
+    #[structopt(name = "stow")]
+    Stow,
+
+    /// Stop the mast, holding its current position.
+    #[structopt(name = "stop")]
+    Stop,
+}
@@ -8,17 +8,46 @@
 
 pub mod arm_ctrl;
 pub mod auto;
+pub mod calibrate;
+pub mod cam;
+pub mod drawbar;
+pub mod eqpt;
 pub mod loco_ctrl;
+pub mod mast_ctrl;
+pub mod path;
+pub mod script;
 
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
+use chrono::{DateTime, Utc};
 use log::info;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use structopt::{clap::AppSettings, StructOpt};
 
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A telecommand addressed to a particular vehicle.
+///
+/// This is what is sent over the network by the ground, allowing more than one rover to be run on
+/// the same network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcPacket {
+    /// The ID of the vehicle this TC is for, or `None` if any vehicle may execute it.
+    pub vehicle_id: Option<String>,
+
+    /// The telecommand itself
+    pub tc: Tc,
+
+    /// Time the TC was sent by the ground, used to measure the command latency
+    #[serde(default)]
+    pub sent: Option<DateTime<Utc>>,
+}
+
 // ------------------------------------------------------------------------------------------------
 // ENUMS
 // ------------------------------------------------------------------------------------------------
@@ -56,6 +85,35 @@ pub enum Tc {
     /// Perform a autonomous command.
     #[structopt(name = "auto")]
     Autonomy(auto::AutoCmd),
+
+    /// Send a pointing command to mast control.
+    #[structopt(name = "mast")]
+    MastCmd(mast_ctrl::MastCmd),
+
+    /// Control the drawbar (soil interaction) test mode.
+    #[structopt(name = "drawbar")]
+    Drawbar(drawbar::DrawbarCmd),
+
+    /// Send a command to the cameras.
+    #[structopt(name = "cam")]
+    Cam(cam::CamCmd),
+
+    /// Uplink or check a path file.
+    #[structopt(name = "path")]
+    Path(path::PathCmd),
+
+    /// Calibrate the rover's mechanisms.
+    #[structopt(name = "calibrate")]
+    Calibrate(calibrate::CalibrateCmd),
+
+    /// Manage the links to the equipment servers.
+    #[structopt(name = "eqpt")]
+    Eqpt(eqpt::EqptCmd),
+
+    /// Run the staged self test of the rover's equipment. The rover must not be in safe mode, as
+    /// the steer axes are moved.
+    #[structopt(name = "selftest")]
+    SelfTest,
 }
 
 /// Response to an issued telecommand
@@ -67,9 +125,22 @@ pub enum TcResponse {
     /// The TC message was invalid and could not be parsed
     Invalid,
 
-    /// The TC cannot be executed because the rover is:
-    /// 1. in safe mode
-    CannotExecute,
+    /// The TC cannot be executed, for the given reason
+    CannotExecute(TcRejectReason),
+
+    /// The TC was addressed to a different vehicle and was not executed
+    WrongVehicle,
+
+    /// The TC was queued by a store-and-forward relay, and will be delivered to the rover when it
+    /// is next in contact. The rover's response is reported by the relay.
+    Queued,
+}
+
+/// Reason a TC could not be executed
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum TcRejectReason {
+    /// The rover's current mode (e.g. safe mode) doesn't allow the TC
+    ModeNotAllowed,
 }
 
 /// Errors that can occur during parsing
@@ -82,11 +153,28 @@ pub enum TcParseError {
     RawTcError(String),
 }
 
+/// Errors found when validating a parsed TC
+#[derive(Debug, thiserror::Error)]
+pub enum TcValidateError {
+    #[error("Invalid autonomy command: {0}")]
+    Auto(auto::AutoCmdError),
+}
+
 // ------------------------------------------------------------------------------------------------
 // IMPLS
 // ------------------------------------------------------------------------------------------------
 
 impl Tc {
+    /// Check that the TC's values make sense, beyond what is checked while parsing it.
+    ///
+    /// The rover rejects TCs which fail this check. Currently only autonomy commands are checked.
+    pub fn validate(&self) -> Result<(), TcValidateError> {
+        match self {
+            Tc::Autonomy(a) => a.validate().map_err(TcValidateError::Auto),
+            _ => Ok(()),
+        }
+    }
+
     /// Parse a TC from a given json string
     pub fn from_json(json_str: &str) -> Result<Self, TcParseError> {
         // Parse the JSON string to a value
@@ -124,3 +212,53 @@ impl Tc {
         serde_json::from_str(json_str).map_err(|e| TcParseError::JsonError(e.to_string()))
     }
 }
+
+impl TcPacket {
+    /// Parse a TC packet from a given json string.
+    ///
+    /// For compatibility with older ground tools and scripts a bare TC (in any format accepted by
+    /// [`Tc::from_json`]) is also accepted, and is treated as not being addressed to any particular
+    /// vehicle.
+    pub fn from_json(json_str: &str) -> Result<Self, TcParseError> {
+        // Parse the JSON string to a value
+        let json_value: Value = match serde_json::from_str(json_str) {
+            Ok(v) => v,
+            Err(e) => return Err(TcParseError::JsonError(e.to_string())),
+        };
+
+        // If the value is an object containing a "tc" key it's a packet, otherwise it's a bare TC
+        match json_value.as_object() {
+            Some(json_obj) if json_obj.contains_key("tc") => {
+                let vehicle_id = match json_obj.get("vehicle_id") {
+                    Some(Value::String(s)) => Some(s.clone()),
+                    Some(Value::Null) | None => None,
+                    Some(v) => return Err(TcParseError::JsonError(format!(
+                        "Expected vehicle_id to be a string, found {}", v
+                    )))
+                };
+
+                // Parse the inner TC using the normal TC parser so that raw TCs are also supported
+                let tc = Tc::from_json(&json_obj["tc"].to_string())?;
+
+                // A missing or invalid stamp only affects the latency measurement, so is ignored
+                let sent = json_obj.get("sent")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok());
+
+                Ok(Self { vehicle_id, tc, sent })
+            },
+            _ => Ok(Self {
+                vehicle_id: None,
+                tc: Tc::from_json(json_str)?,
+                sent: None
+            })
+        }
+    }
+
+    /// Returns `true` if this packet should be executed by the vehicle with the given ID.
+    pub fn is_for(&self, vehicle_id: &str) -> bool {
+        match self.vehicle_id {
+            Some(ref id) => id == vehicle_id,
+            None => true
+        }
+    }
+}
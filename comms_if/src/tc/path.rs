@@ -0,0 +1,145 @@
+//! # Path telecommands
+//!
+//! Path files are too large to fit in a single TC, so they are uplinked as a number of chunks, each
+//! carrying part of the file as a [`Chunk`] of a chunked transfer. The rover stores the file once
+//! every chunk has arrived and the file has been checked, after which it can be followed with
+//! `auto follow <name>`.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use crate::net::chunk::{Chunk, ChunkSender};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Kind of the chunked transfers which uplink path files.
+pub const PATH_CHUNK_KIND: &str = "path";
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// One chunk of a path file being uplinked.
+#[derive(Debug, Clone, Serialize, Deserialize, StructOpt)]
+pub struct PathChunk {
+    /// The name the file will be stored as, including the `json` or `csv` extension.
+    pub name: String,
+
+    /// This chunk of the file's transfer
+    #[structopt(flatten)]
+    pub chunk: Chunk,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// A command to manage the path files stored on the rover.
+#[derive(Debug, Clone, Serialize, Deserialize, StructOpt)]
+pub enum PathCmd {
+    /// Uplink one chunk of a path file.
+    #[structopt(name = "chunk")]
+    Chunk(PathChunk),
+
+    /// Check that a stored path file is valid, without following it.
+    #[structopt(name = "check")]
+    Check {
+        /// The name of the path file.
+        path: PathBuf,
+    },
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl PathChunk {
+    /// Split the contents of a path file into chunks for uplink, as one transfer of the sender.
+    pub fn split_file(sender: &mut ChunkSender, name: &str, data: &[u8]) -> Vec<Self> {
+        sender
+            .split(PATH_CHUNK_KIND, data)
+            .into_iter()
+            .map(|chunk| Self {
+                name: name.to_string(),
+                chunk,
+            })
+            .collect()
+    }
+
+    /// Format the chunk as the text of a `path chunk` TC.
+    pub fn to_tc_string(&self) -> String {
+        let c = &self.chunk;
+        format!(
+            "path chunk {} {} {} {} {} {} {} {} {} {}",
+            self.name,
+            c.session_id,
+            c.transfer_id,
+            c.kind,
+            c.seq,
+            c.num_chunks,
+            c.payload_len,
+            c.payload_crc,
+            c.crc,
+            c.b64_data
+        )
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{net::chunk::ChunkReassembler, tc::Tc};
+
+    /// Parse the text of a TC the way the ground tool does.
+    fn parse(line: &str) -> Tc {
+        Tc::from_iter_safe(line.split(' ')).unwrap()
+    }
+
+    #[test]
+    fn test_tc_string_round_trip() {
+        let mut sender = ChunkSender::new(16, 0);
+        let mut reassembler = ChunkReassembler::new(1, 100);
+
+        let data = b"x,y\n0.0,0.0\n1.0,0.0\n1.0,1.0\n";
+        let chunks = PathChunk::split_file(&mut sender, "square.csv", data);
+        assert_eq!(chunks.len(), 2);
+
+        let mut transfer = None;
+        for chunk in chunks {
+            match parse(&chunk.to_tc_string()) {
+                Tc::Path(PathCmd::Chunk(c)) => {
+                    assert_eq!(c.name, "square.csv");
+                    assert_eq!(c.chunk.kind, PATH_CHUNK_KIND);
+                    transfer = reassembler.push(c.chunk).unwrap();
+                }
+                tc => panic!("Parsed the wrong TC: {:?}", tc),
+            }
+        }
+
+        assert_eq!(transfer.unwrap().payload, data);
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let mut sender = ChunkSender::new(16, 0);
+
+        let chunks = PathChunk::split_file(&mut sender, "empty.csv", &[]);
+        assert_eq!(chunks.len(), 1);
+
+        match parse(&chunks[0].to_tc_string()) {
+            Tc::Path(PathCmd::Chunk(c)) => assert!(c.chunk.b64_data.is_empty()),
+            tc => panic!("Parsed the wrong TC: {:?}", tc),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! # Telecommand Scripts
+//!
+//! Parses Phobos Rover Scripts (`.prs` files), which list TCs to be executed at given times. Each
+//! command is written as `<time in seconds>: <JSON TC>;`, and anything outside of a command, such
+//! as `#` comments, is ignored.
+//!
+//! This is shared by the rover's script interpreter and by ground tools, so scripts can be checked
+//! before they're run.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use regex::RegexBuilder;
+
+use super::{Tc, TcParseError};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A TC which is scripted to be executed at a specific time.
+#[derive(Debug, Clone)]
+pub struct ScriptedTc {
+    /// Time since the start of the script that the TC is executed at
+    pub exec_time_s: f64,
+
+    pub tc: Tc,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Errors which can occur while parsing a script.
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptParseError {
+    #[error("The script is empty (or is so bad it can't be read)")]
+    ScriptEmpty,
+
+    #[error(
+        "Script contains an invalid timestamp: {0}. \
+        Should be a float (like 1.0)")]
+    InvalidTimestamp(String),
+
+    #[error("Script contains an invalid TC at {0} s: {1}")]
+    InvalidTc(f64, TcParseError),
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Parse the TCs in a script, in the order they appear.
+pub fn parse(script: &str) -> Result<Vec<ScriptedTc>, ScriptParseError> {
+    // Go through the script executing __the magic regex__.
+    let re = RegexBuilder::new(r"^\s*(\d+(\.\d+)?)\s*:\s*([^;]*);")
+        .multi_line(true)
+        .build()
+        .unwrap();
+
+    let mut tcs = Vec::new();
+
+    for cap in re.captures_iter(script) {
+        // Parse the exec time
+        let exec_time_s: f64 = cap
+            .get(1)
+            .unwrap()
+            .as_str()
+            .parse()
+            .map_err(|e| ScriptParseError::InvalidTimestamp(format!("{}", e)))?;
+
+        // Parse the TC from the payload. The scripts contain JSON only.
+        let tc = Tc::from_json(cap.get(3).unwrap().as_str())
+            .map_err(|e| ScriptParseError::InvalidTc(exec_time_s, e))?;
+
+        tcs.push(ScriptedTc { exec_time_s, tc });
+    }
+
+    if tcs.is_empty() {
+        return Err(ScriptParseError::ScriptEmpty);
+    }
+
+    Ok(tcs)
+}
@@ -0,0 +1,513 @@
+//! # Image Downlink
+//!
+//! Images are too large to send inside the main telemetry packet, so they are sent over a
+//! dedicated telemetry channel instead. Before sending an image is recompressed (and if needed
+//! downscaled) to fit within a byte budget, then split into chunks small enough not to hold up the
+//! link. The ground reassembles the chunks using an [`ImageReassembler`].
+//!
+//! Image IDs count from zero each time the rover executable starts, so each chunk also carries the
+//! session ID of the sender. Chunks sent before a restart are never mixed into a new image.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use chrono::{serde::ts_milliseconds, DateTime, Utc};
+use image::{imageops::FilterType, GenericImageView, ImageResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Instant};
+
+use crate::eqpt::cam::{CamFrame, CamId, CamImage, ImageFormat};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// JPEG qualities tried, in order, when fitting an image to the budget.
+const JPEG_QUALITY_STEPS: [u8; 5] = [85, 70, 55, 40, 25];
+
+/// Smallest width an image will be downscaled to when fitting it to the budget.
+const MIN_DOWNSCALE_WIDTH: u32 = 160;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Limits on the size of a downlinked image.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DownlinkBudget {
+    /// Maximum size of the encoded image in bytes.
+    pub max_bytes: usize,
+
+    /// Maximum size of the data in each chunk in bytes (before base64 encoding).
+    pub chunk_bytes: usize,
+}
+
+/// An image encoded for downlink.
+pub struct EncodedImage {
+    /// Format of the encoded image
+    pub format: ImageFormat,
+
+    /// Width of the encoded image in pixels, which may be smaller than the original.
+    pub width: u32,
+
+    /// Height of the encoded image in pixels, which may be smaller than the original.
+    pub height: u32,
+
+    /// The encoded data
+    pub data: Vec<u8>,
+}
+
+/// A single chunk of a downlinked image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageChunk {
+    /// ID of the sender's session, picked when the rover executable starts.
+    pub session_id: u64,
+
+    /// ID of the image this chunk belongs to, unique within the sender's session.
+    pub image_id: u64,
+
+    /// Camera which acquired the image
+    pub cam_id: CamId,
+
+    /// UTC timestamp at which the image was acquired
+    #[serde(with = "ts_milliseconds")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Format of the complete image
+    pub format: ImageFormat,
+
+    /// Index of this chunk in the image
+    pub chunk_index: u32,
+
+    /// Total number of chunks in the image
+    pub num_chunks: u32,
+
+    /// This chunk's part of the encoded image, in base64.
+    pub b64_data: String,
+}
+
+/// Reassembles images from their chunks on the ground.
+///
+/// Chunks can arrive in any order. If chunks are lost the incomplete image is eventually discarded
+/// once more than `max_pending` newer images are also incomplete. Chunks come from the link so
+/// aren't trusted, images claiming more than `max_chunks` chunks are rejected before any space is
+/// allocated for them.
+pub struct ImageReassembler {
+    /// Images which are still missing chunks, keyed by session and image ID
+    pending: BTreeMap<(u64, u64), PendingImage>,
+
+    /// Maximum number of incomplete images to keep
+    max_pending: usize,
+
+    /// Maximum number of chunks in an image
+    max_chunks: u32,
+
+    /// Number of images discarded because they were never completed
+    num_dropped: u64,
+}
+
+/// An image which is being reassembled.
+struct PendingImage {
+    cam_id: CamId,
+    timestamp: DateTime<Utc>,
+    format: ImageFormat,
+    chunks: Vec<Option<Vec<u8>>>,
+
+    /// Time the last chunk of this image was recieved
+    last_recv: Instant,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReassemblyError {
+    #[error("Chunk {0} of image {1} could not be decoded from base64: {2}")]
+    DecodeError(u32, u64, base64::DecodeError),
+
+    #[error("Chunk {0} of image {1} is outside the expected {2} chunks")]
+    InvalidChunkIndex(u32, u64, u32),
+
+    #[error("Image {0} has {1} chunks, more than the maximum of {2}")]
+    TooManyChunks(u64, u32, u32),
+
+    #[error("Chunk of image {0} says the image has {1} chunks, but earlier chunks said {2}")]
+    ChunkCountMismatch(u64, u32, u32),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl EncodedImage {
+    /// Encode an image so that it fits within the given budget.
+    ///
+    /// The image is first encoded as a JPEG at decreasing qualities, and if it still doesn't fit
+    /// it is halved in size and the qualities tried again. If the image cannot be made to fit
+    /// the smallest encoding is returned, so the caller should check the length of the data.
+    pub fn fit_to_budget(image: &CamImage, budget: &DownlinkBudget) -> ImageResult<Self> {
+        let mut scaled = image.image.clone();
+        let mut smallest: Option<Self> = None;
+
+        loop {
+            for &quality in JPEG_QUALITY_STEPS.iter() {
+                let mut data = Vec::new();
+                scaled.write_to(&mut data, image::ImageOutputFormat::Jpeg(quality))?;
+
+                let encoded = Self {
+                    format: ImageFormat::Jpeg(quality),
+                    width: scaled.width(),
+                    height: scaled.height(),
+                    data,
+                };
+
+                if encoded.data.len() <= budget.max_bytes {
+                    return Ok(encoded);
+                }
+
+                smallest = match smallest {
+                    Some(s) if s.data.len() <= encoded.data.len() => Some(s),
+                    _ => Some(encoded),
+                };
+            }
+
+            // Halve the size and try again, unless we're already too small
+            if scaled.width() / 2 < MIN_DOWNSCALE_WIDTH {
+                break;
+            }
+            scaled = scaled.resize(
+                scaled.width() / 2,
+                scaled.height() / 2,
+                FilterType::Triangle,
+            );
+        }
+
+        // The quality steps are never empty so there will always be an image here
+        Ok(smallest.expect("No image was encoded"))
+    }
+
+    /// Split the encoded image into chunks for downlink.
+    pub fn into_chunks(
+        self,
+        session_id: u64,
+        image_id: u64,
+        cam_id: CamId,
+        timestamp: DateTime<Utc>,
+        chunk_bytes: usize,
+    ) -> Vec<ImageChunk> {
+        // Zero sized chunks would never finish
+        let chunk_bytes = chunk_bytes.max(1);
+
+        let num_chunks = self.data.len().div_ceil(chunk_bytes).max(1) as u32;
+
+        // An empty image still needs one (empty) chunk so the ground sees it
+        if self.data.is_empty() {
+            return vec![ImageChunk {
+                session_id,
+                image_id,
+                cam_id,
+                timestamp,
+                format: self.format,
+                chunk_index: 0,
+                num_chunks,
+                b64_data: String::new(),
+            }];
+        }
+
+        self.data
+            .chunks(chunk_bytes)
+            .enumerate()
+            .map(|(i, d)| ImageChunk {
+                session_id,
+                image_id,
+                cam_id,
+                timestamp,
+                format: self.format,
+                chunk_index: i as u32,
+                num_chunks,
+                b64_data: base64::encode(d),
+            })
+            .collect()
+    }
+}
+
+impl ImageReassembler {
+    /// Create a new reassembler which keeps at most `max_pending` incomplete images, each of at
+    /// most `max_chunks` chunks.
+    pub fn new(max_pending: usize, max_chunks: u32) -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            max_pending: max_pending.max(1),
+            max_chunks: max_chunks.max(1),
+            num_dropped: 0,
+        }
+    }
+
+    /// Number of images which were discarded before all their chunks arrived.
+    pub fn num_dropped(&self) -> u64 {
+        self.num_dropped
+    }
+
+    /// Add a chunk to the reassembler.
+    ///
+    /// If this chunk completes an image the camera ID and the complete frame are returned.
+    pub fn push(&mut self, chunk: ImageChunk) -> Result<Option<(CamId, CamFrame)>, ReassemblyError> {
+        if chunk.chunk_index >= chunk.num_chunks {
+            return Err(ReassemblyError::InvalidChunkIndex(
+                chunk.chunk_index,
+                chunk.image_id,
+                chunk.num_chunks,
+            ));
+        }
+
+        if chunk.num_chunks > self.max_chunks {
+            return Err(ReassemblyError::TooManyChunks(
+                chunk.image_id,
+                chunk.num_chunks,
+                self.max_chunks,
+            ));
+        }
+
+        let key = (chunk.session_id, chunk.image_id);
+
+        // Ignore chunks which disagree with the first one about the size of the image
+        if let Some(pending) = self.pending.get(&key) {
+            if pending.chunks.len() != chunk.num_chunks as usize {
+                return Err(ReassemblyError::ChunkCountMismatch(
+                    chunk.image_id,
+                    chunk.num_chunks,
+                    pending.chunks.len() as u32,
+                ));
+            }
+        }
+
+        let data = base64::decode(&chunk.b64_data)
+            .map_err(|e| ReassemblyError::DecodeError(chunk.chunk_index, chunk.image_id, e))?;
+
+        let pending = self
+            .pending
+            .entry(key)
+            .or_insert_with(|| PendingImage {
+                cam_id: chunk.cam_id,
+                timestamp: chunk.timestamp,
+                format: chunk.format,
+                chunks: vec![None; chunk.num_chunks as usize],
+                last_recv: Instant::now(),
+            });
+
+        pending.chunks[chunk.chunk_index as usize] = Some(data);
+        pending.last_recv = Instant::now();
+
+        // If complete remove and build the frame
+        if pending.chunks.iter().all(|c| c.is_some()) {
+            let pending = self
+                .pending
+                .remove(&key)
+                .expect("Pending image missing");
+
+            let data: Vec<u8> = pending.chunks.into_iter().flatten().flatten().collect();
+
+            return Ok(Some((
+                pending.cam_id,
+                CamFrame {
+                    timestamp: pending.timestamp,
+                    format: pending.format,
+                    b64_data: base64::encode(data),
+                },
+            )));
+        }
+
+        // Discard the least recently recieved incomplete images if there are too many. Session IDs
+        // aren't ordered, so the keys can't be used to find the oldest.
+        while self.pending.len() > self.max_pending {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, p)| p.last_recv)
+                .map(|(&k, _)| k)
+                .expect("Pending images empty");
+            self.pending.remove(&oldest);
+            self.num_dropped += 1;
+        }
+
+        Ok(None)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    /// A noisy image, which doesn't compress well.
+    fn noisy_image(width: u32, height: u32) -> CamImage {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729) ^ (x * y)) as u8;
+            image::Rgb([v, v.wrapping_mul(3), v.wrapping_add(y as u8)])
+        });
+
+        CamImage {
+            timestamp: Utc::now(),
+            image: DynamicImage::ImageRgb8(image),
+        }
+    }
+
+    fn budget(max_bytes: usize) -> DownlinkBudget {
+        DownlinkBudget {
+            max_bytes,
+            chunk_bytes: 1000,
+        }
+    }
+
+    /// Chunks of an encoded image with the given data, in order.
+    fn chunks(session_id: u64, image_id: u64, data: Vec<u8>, chunk_bytes: usize) -> Vec<ImageChunk> {
+        EncodedImage {
+            format: ImageFormat::Jpeg(85),
+            width: 1,
+            height: 1,
+            data,
+        }
+        .into_chunks(session_id, image_id, CamId::LeftNav, Utc::now(), chunk_bytes)
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let image = noisy_image(640, 480);
+
+        // A generous budget gets the best quality at full size
+        let encoded = EncodedImage::fit_to_budget(&image, &budget(usize::MAX)).unwrap();
+        assert!(matches!(encoded.format, ImageFormat::Jpeg(q) if q == JPEG_QUALITY_STEPS[0]));
+        assert_eq!((encoded.width, encoded.height), (640, 480));
+        let full_len = encoded.data.len();
+
+        // A tighter one lowers the quality or size to fit
+        let encoded = EncodedImage::fit_to_budget(&image, &budget(full_len / 4)).unwrap();
+        assert!(encoded.data.len() <= full_len / 4);
+        assert!(image::load_from_memory(&encoded.data).is_ok());
+
+        // One which can't be met returns the smallest encoding, no smaller than the minimum width
+        let encoded = EncodedImage::fit_to_budget(&image, &budget(1)).unwrap();
+        assert!(encoded.data.len() > 1);
+        assert!(encoded.width >= MIN_DOWNSCALE_WIDTH);
+        assert!(encoded.width < 640);
+        assert!(matches!(encoded.format, ImageFormat::Jpeg(q) if q == JPEG_QUALITY_STEPS[4]));
+    }
+
+    #[test]
+    fn test_into_chunks() {
+        let data: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+
+        let chunks = chunks(7, 3, data.clone(), 1000);
+        assert_eq!(chunks.len(), 3);
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.session_id, 7);
+            assert_eq!(chunk.image_id, 3);
+            assert_eq!(chunk.chunk_index, i as u32);
+            assert_eq!(chunk.num_chunks, 3);
+        }
+
+        let joined: Vec<u8> = chunks
+            .iter()
+            .flat_map(|c| base64::decode(&c.b64_data).unwrap())
+            .collect();
+        assert_eq!(joined, data);
+
+        // An empty image is still one chunk, and a zero chunk size doesn't loop forever
+        assert_eq!(self::chunks(7, 4, Vec::new(), 1000).len(), 1);
+        assert_eq!(self::chunks(7, 5, vec![0; 3], 0).len(), 3);
+    }
+
+    #[test]
+    fn test_push_out_of_order() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i * 3) as u8).collect();
+        let mut reassembler = ImageReassembler::new(2, 10);
+
+        let mut frames = chunks(1, 0, data.clone(), 1000)
+            .into_iter()
+            .rev()
+            .filter_map(|c| reassembler.push(c).unwrap());
+
+        let (cam_id, frame) = frames.next().expect("Image not complete");
+        assert!(frames.next().is_none());
+        assert_eq!(cam_id, CamId::LeftNav);
+        assert!(matches!(frame.format, ImageFormat::Jpeg(85)));
+        assert_eq!(base64::decode(&frame.b64_data).unwrap(), data);
+    }
+
+    #[test]
+    fn test_push_rejects_invalid_chunks() {
+        let mut reassembler = ImageReassembler::new(2, 2);
+
+        // Claiming more chunks than allowed
+        let mut three = chunks(1, 0, vec![0; 2500], 1000);
+        assert!(matches!(
+            reassembler.push(three.remove(0)),
+            Err(ReassemblyError::TooManyChunks(0, 3, 2))
+        ));
+
+        // Index outside the image
+        let mut chunk = chunks(1, 1, vec![0; 1500], 1000).remove(0);
+        chunk.chunk_index = 2;
+        assert!(matches!(
+            reassembler.push(chunk),
+            Err(ReassemblyError::InvalidChunkIndex(2, 1, 2))
+        ));
+
+        // Disagreeing with earlier chunks about the number of chunks
+        let mut two = chunks(1, 2, vec![0; 1500], 1000);
+        reassembler.push(two.remove(0)).unwrap();
+        let mut chunk = two.remove(0);
+        chunk.num_chunks = 1;
+        chunk.chunk_index = 0;
+        assert!(matches!(
+            reassembler.push(chunk),
+            Err(ReassemblyError::ChunkCountMismatch(2, 1, 2))
+        ));
+
+        // Bad base64
+        let mut chunk = chunks(1, 3, vec![0; 10], 1000).remove(0);
+        chunk.b64_data = String::from("not base64!");
+        assert!(matches!(
+            reassembler.push(chunk),
+            Err(ReassemblyError::DecodeError(0, 3, _))
+        ));
+    }
+
+    #[test]
+    fn test_push_drops_oldest_incomplete() {
+        let mut reassembler = ImageReassembler::new(1, 10);
+
+        let first = chunks(1, 0, vec![1; 1500], 1000);
+        let second = chunks(1, 1, vec![2; 1500], 1000);
+
+        assert!(reassembler.push(first[0].clone()).unwrap().is_none());
+        assert!(reassembler.push(second[0].clone()).unwrap().is_none());
+        assert_eq!(reassembler.num_dropped(), 1);
+
+        // The first image was dropped, so its last chunk starts it again
+        assert!(reassembler.push(first[1].clone()).unwrap().is_none());
+        assert!(reassembler.push(second[1].clone()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_push_restarted_sender() {
+        let mut reassembler = ImageReassembler::new(2, 10);
+
+        // Both sessions count image IDs from zero
+        let before = chunks(1, 0, vec![1; 1500], 1000);
+        let after = chunks(2, 0, vec![2; 1500], 1000);
+
+        assert!(reassembler.push(before[0].clone()).unwrap().is_none());
+        assert!(reassembler.push(after[0].clone()).unwrap().is_none());
+
+        let (_, frame) = reassembler.push(after[1].clone()).unwrap().unwrap();
+        assert_eq!(base64::decode(&frame.b64_data).unwrap(), vec![2; 1500]);
+    }
+}
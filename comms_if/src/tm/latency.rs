@@ -0,0 +1,74 @@
+//! # Latency Timestamps
+//!
+//! TCs are stamped as they pass through each stage between the ground console and the mechanisms,
+//! and the stamps of the last TC are included in the telemetry so that the ground can measure the
+//! command to actuation latency.
+//!
+//! Stamps are taken on both the ground and the rover, so the clocks of the two should be
+//! synchronised (for example with NTP) for the measured latencies to be meaningful.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::TmMeta;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Times at which a TC passed through each stage of the command chain.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TmMeta)]
+pub struct TcStamps {
+    /// Time the TC was sent by the ground console, if the console stamped it
+    pub sent: Option<DateTime<Utc>>,
+
+    /// Time the TC was recieved by the rover
+    pub recvd: DateTime<Utc>,
+
+    /// Time the TC was executed, if it was accepted
+    pub executed: Option<DateTime<Utc>>,
+
+    /// Time the first demands after the TC was executed were sent to the mechanisms
+    pub actuated: Option<DateTime<Utc>>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl TcStamps {
+    /// Stamps for a TC recieved now.
+    pub fn recvd(sent: Option<DateTime<Utc>>) -> Self {
+        Self {
+            sent,
+            recvd: Utc::now(),
+            executed: None,
+            actuated: None,
+        }
+    }
+
+    /// Time from the TC being sent by the console to the demands being sent to the mechanisms,
+    /// in milliseconds.
+    pub fn command_to_actuation_ms(&self) -> Option<f64> {
+        match (self.sent, self.actuated) {
+            (Some(s), Some(a)) => Some(ms_between(s, a)),
+            _ => None,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Time from `start` to `end` in milliseconds, negative if `end` is before `start`.
+pub fn ms_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+    (end - start)
+        .num_microseconds()
+        .map(|us| us as f64 / 1000.0)
+        .unwrap_or(f64::NAN)
+}
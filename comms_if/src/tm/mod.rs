@@ -0,0 +1,62 @@
+//! # Telemetry module
+//!
+//! This module provides telemetry definitions which are shared between the rover and the ground.
+
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+/// Image downlink over the dedicated image telemetry channel
+pub mod img;
+
+/// Timestamps for measuring the end-to-end latency
+pub mod latency;
+
+/// Queries for the latest value of a telemetry channel
+pub mod query;
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+pub use tm_derive::TmMeta;
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// Engineering metadata of a telemetry struct's fields, usually implemented with
+/// `#[derive(TmMeta)]`.
+pub trait TmMeta {
+    /// Get the metadata of each field, in declaration order.
+    fn tm_fields() -> Vec<TmFieldMeta>;
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Metadata of a single telemetry field.
+#[derive(Debug, Clone)]
+pub struct TmFieldMeta {
+    pub name: &'static str,
+
+    /// Engineering unit of the field, if it has one
+    pub unit: Option<&'static str>,
+
+    pub desc: &'static str,
+
+    /// Metadata of the fields of the field's type, if it has been given
+    pub fields: Vec<TmFieldMeta>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+/// Optional fields are described by the fields of their value.
+impl<T: TmMeta> TmMeta for Option<T> {
+    fn tm_fields() -> Vec<TmFieldMeta> {
+        T::tm_fields()
+    }
+}
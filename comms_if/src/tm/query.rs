@@ -0,0 +1,67 @@
+//! # Telemetry Queries
+//!
+//! Rather than subscribing to the TM stream, a client can ask the rover for the latest value of a
+//! single channel over a REQ/REP query endpoint. This suits scripts and consoles which only need a
+//! snapshot, such as checking the rover is out of safe mode before sending a TC.
+//!
+//! A channel is a field of the TM packet, such as `safe` or `mode`, and fields of nested structs
+//! are named with dots, such as `last_tc_stamps.executed`. An empty channel name gets the whole
+//! packet.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A request for the latest value of a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TmQuery {
+    /// Name of the channel, see the module documentation
+    pub channel: String,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Response to a [`TmQuery`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TmQueryResponse {
+    /// The latest value of the channel
+    Value {
+        /// Time since the session started of the packet the value was taken from
+        sim_time_s: f64,
+
+        value: Value,
+    },
+
+    /// There is no channel with the requested name
+    UnknownChannel(String),
+
+    /// No telemetry has been produced yet
+    NoData,
+
+    /// The query could not be parsed
+    Invalid,
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Find the value of a channel in a TM packet, which has been converted into a JSON value.
+pub fn lookup<'a>(packet: &'a Value, channel: &str) -> Option<&'a Value> {
+    if channel.is_empty() {
+        return Some(packet);
+    }
+
+    channel
+        .split('.')
+        .try_fold(packet, |value, field| value.get(field))
+}
@@ -0,0 +1,313 @@
+//! # Units
+//!
+//! Newtypes for physical quantities, so that a value in one unit can't be passed where another is
+//! expected, for example a curvature in 1/meters where a radius in meters is expected. Each type
+//! wraps an `f64` in the unit given by its name, and converting between units is explicit, such as
+//! [`Curvature::radius`].
+//!
+//! The types serialise as plain numbers and parse from plain numbers on the command line, so
+//! existing TCs, parameter files and telemetry are unchanged. Values are unwrapped with `.0` at the
+//! point they enter the maths.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    num::ParseFloatError,
+    ops::{Add, Div, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+// ------------------------------------------------------------------------------------------------
+// MACROS
+// ------------------------------------------------------------------------------------------------
+
+/// Define a unit newtype with the operations which keep its unit.
+macro_rules! unit {
+    ($(#[$meta:meta])* $name:ident, $unit:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub f64);
+
+        impl $name {
+            /// Symbol of the unit, as used in the TM dictionary.
+            pub const UNIT: &'static str = $unit;
+
+            pub fn abs(self) -> Self {
+                Self(self.0.abs())
+            }
+
+            pub fn signum(self) -> f64 {
+                self.0.signum()
+            }
+
+            pub fn is_finite(self) -> bool {
+                self.0.is_finite()
+            }
+
+            /// Limit the value to between `min` and `max`.
+            ///
+            /// As with [`f64::clamp`] a NaN value stays NaN rather than being limited, so that an
+            /// invalid value can't silently become one of the limits. Panics if `min > max` or
+            /// either limit is NaN.
+            pub fn clamp(self, min: Self, max: Self) -> Self {
+                Self(self.0.clamp(min.0, max.0))
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self(self.0 + rhs.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self(self.0 - rhs.0)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self(-self.0)
+            }
+        }
+
+        impl Mul<f64> for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: f64) -> Self {
+                Self(self.0 * rhs)
+            }
+        }
+
+        impl Div<f64> for $name {
+            type Output = Self;
+
+            fn div(self, rhs: f64) -> Self {
+                Self(self.0 / rhs)
+            }
+        }
+
+        /// The ratio of two values of the same unit has no unit.
+        impl Div for $name {
+            type Output = f64;
+
+            fn div(self, rhs: Self) -> f64 {
+                self.0 / rhs.0
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseFloatError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map(Self)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{} {}", self.0, $unit)
+            }
+        }
+    };
+}
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+unit!(
+    /// A length in meters.
+    Meters,
+    "m"
+);
+
+unit!(
+    /// A speed in meters/second.
+    MetersPerSec,
+    "m/s"
+);
+
+unit!(
+    /// An angle in radians.
+    Radians,
+    "rad"
+);
+
+unit!(
+    /// An angular rate in radians/second.
+    RadPerSec,
+    "rad/s"
+);
+
+unit!(
+    /// The curvature of a turn in 1/meters, the inverse of the turn's radius.
+    ///
+    /// Positive curvatures turn to the left, following the right hand rule about the rover's Z+
+    /// axis. A straight line has zero curvature, which is why curvature rather than radius is
+    /// used in commands.
+    Curvature,
+    "1/m"
+);
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Curvature {
+    /// Get the radius of the turn, or `None` for a straight line.
+    ///
+    /// The radius has the same sign as the curvature.
+    pub fn radius(self) -> Option<Meters> {
+        match self.0 == 0.0 {
+            true => None,
+            false => Some(Meters(1.0 / self.0)),
+        }
+    }
+}
+
+impl Meters {
+    /// Get the curvature of a turn with this radius.
+    pub fn curvature(self) -> Curvature {
+        Curvature(1.0 / self.0)
+    }
+}
+
+impl Radians {
+    pub fn from_degrees(deg: f64) -> Self {
+        Self(deg.to_radians())
+    }
+
+    pub fn to_degrees(self) -> f64 {
+        self.0.to_degrees()
+    }
+
+    pub fn sin(self) -> f64 {
+        self.0.sin()
+    }
+
+    pub fn cos(self) -> f64 {
+        self.0.cos()
+    }
+}
+
+/// The rate a wheel of the given radius turns at when rolling at this speed.
+impl Div<Meters> for MetersPerSec {
+    type Output = RadPerSec;
+
+    fn div(self, radius: Meters) -> RadPerSec {
+        RadPerSec(self.0 / radius.0)
+    }
+}
+
+/// The speed of a point at the given radius from the centre of rotation.
+impl Mul<Meters> for RadPerSec {
+    type Output = MetersPerSec;
+
+    fn mul(self, radius: Meters) -> MetersPerSec {
+        MetersPerSec(self.0 * radius.0)
+    }
+}
+
+/// The yaw rate of a rover driving a turn of this curvature at the given speed.
+impl Mul<Curvature> for MetersPerSec {
+    type Output = RadPerSec;
+
+    fn mul(self, curv: Curvature) -> RadPerSec {
+        RadPerSec(self.0 * curv.0)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp() {
+        let (min, max) = (MetersPerSec(-0.2), MetersPerSec(0.2));
+
+        assert_eq!(MetersPerSec(0.1).clamp(min, max), MetersPerSec(0.1));
+        assert_eq!(MetersPerSec(1.0).clamp(min, max), max);
+        assert_eq!(MetersPerSec(-1.0).clamp(min, max), min);
+        assert_eq!(MetersPerSec(f64::INFINITY).clamp(min, max), max);
+        assert_eq!(MetersPerSec(f64::NEG_INFINITY).clamp(min, max), min);
+    }
+
+    #[test]
+    fn test_clamp_nan() {
+        // NaN must not become one of the limits
+        assert!(Curvature(f64::NAN).clamp(Curvature(-1.0), Curvature(1.0)).0.is_nan());
+        assert!(!Curvature(f64::NAN).is_finite());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_clamp_inverted_limits() {
+        Radians(0.0).clamp(Radians(1.0), Radians(-1.0));
+    }
+
+    #[test]
+    fn test_abs_signum() {
+        assert_eq!(RadPerSec(-2.0).abs(), RadPerSec(2.0));
+        assert_eq!(RadPerSec(-2.0).signum(), -1.0);
+        assert_eq!(RadPerSec(2.0).signum(), 1.0);
+    }
+
+    #[test]
+    fn test_ops() {
+        assert_eq!(Meters(1.0) + Meters(2.0), Meters(3.0));
+        assert_eq!(Meters(1.0) - Meters(2.0), Meters(-1.0));
+        assert_eq!(-Meters(1.0), Meters(-1.0));
+        assert_eq!(Meters(1.5) * 2.0, Meters(3.0));
+        assert_eq!(Meters(3.0) / 2.0, Meters(1.5));
+        assert_eq!(Meters(3.0) / Meters(2.0), 1.5);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(Curvature(0.0).radius(), None);
+        assert_eq!(Curvature(-0.5).radius(), Some(Meters(-2.0)));
+        assert_eq!(Meters(4.0).curvature(), Curvature(0.25));
+
+        assert_eq!(MetersPerSec(0.1) / Meters(0.05), RadPerSec(2.0));
+        assert_eq!(RadPerSec(2.0) * Meters(0.05), MetersPerSec(0.1));
+        assert_eq!(MetersPerSec(0.5) * Curvature(2.0), RadPerSec(1.0));
+
+        assert!((Radians::from_degrees(90.0).0 - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert!((Radians(std::f64::consts::PI).to_degrees() - 180.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!("0.25".parse::<Curvature>().unwrap(), Curvature(0.25));
+        assert!("fast".parse::<MetersPerSec>().is_err());
+
+        // NaN parses, so must be caught by whatever uses the value
+        assert!("NaN".parse::<MetersPerSec>().unwrap().0.is_nan());
+
+        assert_eq!(MetersPerSec(0.5).to_string(), "0.5 m/s");
+        assert_eq!(Curvature::UNIT, "1/m");
+    }
+
+    #[test]
+    fn test_serde_transparent() {
+        assert_eq!(serde_json::to_string(&Radians(1.5)).unwrap(), "1.5");
+        assert_eq!(serde_json::from_str::<Radians>("1.5").unwrap(), Radians(1.5));
+    }
+}
@@ -0,0 +1,371 @@
+//! # Link Simulator
+//!
+//! A proxy which sits between the ground tools and the rover and degrades the link between them,
+//! so that operations procedures can be rehearsed under realistic link constraints.
+//!
+//! Telecommands flow over the uplink, and TC responses, telemetry and images over the downlink.
+//! Each direction has its own latency, jitter, loss and bandwidth. The bandwidth of a direction is
+//! shared by everything sent over it, so a large image will delay the telemetry behind it.
+//!
+//! The ground tools and the rover are pointed at the simulator instead of each other:
+//!
+//! ```text
+//! command_line_rover (tcp://*:5020) <- link_sim -> (tcp://*:6020) <- rov_exec tc_endpoint
+//! tm_gateway -> (tcp://*:6030, 6031) link_sim -> (tcp://localhost:5030, 5031) rov_exec
+//! ```
+//!
+//! Only the zmq transport is supported.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use color_eyre::{eyre::WrapErr, Result};
+use comms_if::net::{zmq, MonitoredSocket, SocketOptions};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Command line options for the simulator.
+#[derive(StructOpt)]
+#[structopt(name = "link_sim", about = "Degrade the link between the ground tools and the rover")]
+struct Opts {
+    /// Endpoint of the ground's TcServer (command_line_rover --endpoint).
+    #[structopt(long, default_value = "tcp://localhost:5020")]
+    ground_tc_endpoint: String,
+
+    /// Endpoint for the rover's TcClient to connect to, set as tc_endpoint in the rover's net.toml.
+    #[structopt(long, default_value = "tcp://*:6020")]
+    rover_tc_endpoint: String,
+
+    /// Endpoint of the rover's TmServer.
+    #[structopt(long, default_value = "tcp://localhost:5030")]
+    rover_tm_endpoint: String,
+
+    /// Endpoint for tm_gateway to subscribe to telemetry on (tm_gateway --tm-endpoint).
+    #[structopt(long, default_value = "tcp://*:6030")]
+    ground_tm_endpoint: String,
+
+    /// Endpoint of the rover's image telemetry channel.
+    #[structopt(long, default_value = "tcp://localhost:5031")]
+    rover_img_endpoint: String,
+
+    /// Endpoint for tm_gateway to subscribe to images on (tm_gateway --img-endpoint).
+    #[structopt(long, default_value = "tcp://*:6031")]
+    ground_img_endpoint: String,
+
+    /// One way latency of the uplink, in milliseconds.
+    #[structopt(long, default_value = "0")]
+    uplink_latency_ms: f64,
+
+    /// Maximum random variation of the uplink latency, in milliseconds.
+    #[structopt(long, default_value = "0")]
+    uplink_jitter_ms: f64,
+
+    /// Probability of an uplink message being lost, between 0 and 1.
+    #[structopt(long, default_value = "0")]
+    uplink_loss: f64,
+
+    /// Bandwidth of the uplink in bits/second, 0 for unlimited.
+    #[structopt(long, default_value = "0")]
+    uplink_bandwidth_bps: f64,
+
+    /// One way latency of the downlink, in milliseconds.
+    #[structopt(long, default_value = "0")]
+    downlink_latency_ms: f64,
+
+    /// Maximum random variation of the downlink latency, in milliseconds.
+    #[structopt(long, default_value = "0")]
+    downlink_jitter_ms: f64,
+
+    /// Probability of a downlink message being lost, between 0 and 1.
+    #[structopt(long, default_value = "0")]
+    downlink_loss: f64,
+
+    /// Bandwidth of the downlink in bits/second, 0 for unlimited.
+    #[structopt(long, default_value = "0")]
+    downlink_bandwidth_bps: f64,
+
+    /// Seed for the random jitter and loss, so that a run can be repeated. If not given the seed
+    /// is taken from the clock.
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Interval between printing the link statistics, in seconds.
+    #[structopt(long, default_value = "10")]
+    stats_interval_s: u64,
+}
+
+/// The model of one direction of the link.
+struct Link {
+    name: &'static str,
+
+    latency: Duration,
+
+    jitter_s: f64,
+
+    loss: f64,
+
+    /// Bandwidth in bits/second, 0 for unlimited
+    bandwidth_bps: f64,
+
+    /// Time at which the link finishes sending the last queued message
+    free_at: Instant,
+
+    /// Time at which the last queued message is delivered, so that messages stay in order
+    last_delivery: Instant,
+
+    stats: LinkStats,
+}
+
+/// Statistics for one direction of the link.
+#[derive(Default)]
+struct LinkStats {
+    num_sent: u64,
+    num_lost: u64,
+    num_bytes: u64,
+}
+
+/// A message waiting to be delivered.
+struct Delayed {
+    deliver_at: Instant,
+
+    /// Index of the socket to send the message on
+    dest: usize,
+
+    frames: Vec<Vec<u8>>,
+}
+
+/// A small xorshift random number generator, so that runs can be repeated from a seed.
+struct Rng(u64);
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Longest time to wait for a message before checking the delivery queue.
+const MAX_POLL_MS: i64 = 100;
+
+// Indexes of the sockets
+const GROUND_TC: usize = 0;
+const ROVER_TC: usize = 1;
+const ROVER_TM: usize = 2;
+const GROUND_TM: usize = 3;
+const ROVER_IMG: usize = 4;
+const GROUND_IMG: usize = 5;
+
+// ------------------------------------------------------------------------------------------------
+// MAIN
+// ------------------------------------------------------------------------------------------------
+
+fn main() -> Result<()> {
+    let opts = Opts::from_args();
+
+    let ctx = zmq::Context::new();
+
+    let socket = |socket_type, bind, endpoint: &str| {
+        MonitoredSocket::new(
+            &ctx,
+            socket_type,
+            SocketOptions {
+                bind,
+                block_on_first_connect: false,
+                linger: 0,
+                ..Default::default()
+            },
+            endpoint,
+        )
+        .wrap_err_with(|| format!("Could not open {}", endpoint))
+    };
+
+    // TCs use DEALER sockets rather than REP/REQ so that messages can be lost without breaking
+    // the strict request/reply ordering. The REQ and REP envelopes are passed through untouched.
+    let sockets = [
+        socket(zmq::DEALER, false, &opts.ground_tc_endpoint)?,
+        socket(zmq::DEALER, true, &opts.rover_tc_endpoint)?,
+        socket(zmq::SUB, false, &opts.rover_tm_endpoint)?,
+        socket(zmq::PUB, true, &opts.ground_tm_endpoint)?,
+        socket(zmq::SUB, false, &opts.rover_img_endpoint)?,
+        socket(zmq::PUB, true, &opts.ground_img_endpoint)?,
+    ];
+
+    let mut uplink = Link::new(
+        "Uplink",
+        opts.uplink_latency_ms,
+        opts.uplink_jitter_ms,
+        opts.uplink_loss,
+        opts.uplink_bandwidth_bps,
+    );
+    let mut downlink = Link::new(
+        "Downlink",
+        opts.downlink_latency_ms,
+        opts.downlink_jitter_ms,
+        opts.downlink_loss,
+        opts.downlink_bandwidth_bps,
+    );
+
+    let seed = opts.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_nanos() as u64)
+            .unwrap_or(1)
+    });
+    let mut rng = Rng::new(seed);
+
+    println!("Link simulator started, seed {}", seed);
+
+    let mut queue: VecDeque<Delayed> = VecDeque::new();
+    let mut last_stats = Instant::now();
+
+    loop {
+        // Wait for a message, but not beyond the next delivery
+        let timeout_ms = match queue.front() {
+            Some(d) => d
+                .deliver_at
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                .min(MAX_POLL_MS as u128) as i64,
+            None => MAX_POLL_MS,
+        };
+
+        let mut items = [
+            sockets[GROUND_TC].as_poll_item(zmq::POLLIN),
+            sockets[ROVER_TC].as_poll_item(zmq::POLLIN),
+            sockets[ROVER_TM].as_poll_item(zmq::POLLIN),
+            sockets[ROVER_IMG].as_poll_item(zmq::POLLIN),
+        ];
+        zmq::poll(&mut items, timeout_ms).wrap_err("Could not poll the sockets")?;
+
+        // Source socket, destination socket and direction of each flow
+        let flows = [
+            (items[0].is_readable(), GROUND_TC, ROVER_TC, true),
+            (items[1].is_readable(), ROVER_TC, GROUND_TC, false),
+            (items[2].is_readable(), ROVER_TM, GROUND_TM, false),
+            (items[3].is_readable(), ROVER_IMG, GROUND_IMG, false),
+        ];
+
+        for &(readable, src, dest, is_uplink) in flows.iter() {
+            if !readable {
+                continue;
+            }
+
+            // Take everything that's waiting so a busy flow can't starve the others
+            while let Ok(frames) = sockets[src].recv_multipart(zmq::DONTWAIT) {
+                let link = match is_uplink {
+                    true => &mut uplink,
+                    false => &mut downlink,
+                };
+
+                if let Some(deliver_at) = link.transmit(&frames, &mut rng) {
+                    let d = Delayed {
+                        deliver_at,
+                        dest,
+                        frames,
+                    };
+
+                    // Both links are in one queue, so keep it in delivery order
+                    let pos = queue
+                        .iter()
+                        .position(|q| q.deliver_at > d.deliver_at)
+                        .unwrap_or(queue.len());
+                    queue.insert(pos, d);
+                }
+            }
+        }
+
+        // Deliver everything that's due
+        let now = Instant::now();
+        while queue.front().is_some_and(|d| d.deliver_at <= now) {
+            let d = queue.pop_front().unwrap();
+            if let Err(e) = sockets[d.dest].send_multipart(d.frames, zmq::DONTWAIT) {
+                println!("Could not deliver a message: {}", e);
+            }
+        }
+
+        if opts.stats_interval_s > 0
+            && last_stats.elapsed() >= Duration::from_secs(opts.stats_interval_s)
+        {
+            last_stats = Instant::now();
+            uplink.print_stats();
+            downlink.print_stats();
+            println!("{} messages in flight", queue.len());
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Link {
+    fn new(name: &'static str, latency_ms: f64, jitter_ms: f64, loss: f64, bandwidth_bps: f64) -> Self {
+        let now = Instant::now();
+
+        Self {
+            name,
+            latency: Duration::from_secs_f64(latency_ms.max(0.0) / 1000.0),
+            jitter_s: jitter_ms.max(0.0) / 1000.0,
+            loss: loss.clamp(0.0, 1.0),
+            bandwidth_bps: bandwidth_bps.max(0.0),
+            free_at: now,
+            last_delivery: now,
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// Send a message over the link, returning the time it arrives or `None` if it was lost.
+    fn transmit(&mut self, frames: &[Vec<u8>], rng: &mut Rng) -> Option<Instant> {
+        let now = Instant::now();
+        let num_bytes: usize = frames.iter().map(|f| f.len()).sum();
+
+        // Lost messages still use up the link
+        let start = self.free_at.max(now);
+        self.free_at = match self.bandwidth_bps > 0.0 {
+            true => start + Duration::from_secs_f64(num_bytes as f64 * 8.0 / self.bandwidth_bps),
+            false => start,
+        };
+
+        if rng.next_f64() < self.loss {
+            self.stats.num_lost += 1;
+            return None;
+        }
+
+        self.stats.num_sent += 1;
+        self.stats.num_bytes += num_bytes as u64;
+
+        let jitter = Duration::from_secs_f64(rng.next_f64() * self.jitter_s);
+        let deliver_at = (self.free_at + self.latency + jitter).max(self.last_delivery);
+        self.last_delivery = deliver_at;
+
+        Some(deliver_at)
+    }
+
+    fn print_stats(&self) {
+        println!(
+            "{}: {} messages sent ({} bytes), {} lost",
+            self.name, self.stats.num_sent, self.stats.num_bytes, self.stats.num_lost
+        );
+    }
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift
+        Self(seed.max(1))
+    }
+
+    /// Get a random number in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
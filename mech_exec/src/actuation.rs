@@ -0,0 +1,148 @@
+//! # Actuation Module
+//!
+//! Demands arrive from the rover executable at its cycle rate (10 Hz) and can occasionally be
+//! late or missed. The actuators are instead driven from a faster local loop, which interpolates
+//! from the previously actuated values to each new demand, holds the demand while it is valid, and
+//! ramps drive speeds down to zero once it expires.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::eqpt::mech::{ActId, MechDems};
+use std::{collections::HashMap, time::Instant};
+
+use crate::params::MechExecParams;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Produces the demands to actuate on each tick of the actuation loop.
+pub struct DemandInterpolator {
+    /// Time taken to move from the previously actuated values to a new demand
+    interp_time_s: f64,
+
+    /// Time after being received that a demand is valid for
+    validity_s: f64,
+
+    /// Time taken to ramp speeds down to zero once a demand has expired
+    expiry_ramp_time_s: f64,
+
+    /// The values being actuated when the latest demand arrived
+    start: MechDems,
+
+    /// The latest demand and the time it arrived
+    target: Option<(MechDems, Instant)>,
+
+    /// The values actuated on the last update
+    current: MechDems,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl DemandInterpolator {
+    /// Create a new interpolator using the actuation parameters.
+    pub fn new(params: &MechExecParams) -> Self {
+        Self {
+            // Interpolating for longer than the validity would mean never reaching the demand
+            interp_time_s: params.dems_interp_time_s.min(params.dems_validity_s),
+            validity_s: params.dems_validity_s,
+            expiry_ramp_time_s: params.dems_expiry_ramp_time_s,
+            start: MechDems::default(),
+            target: None,
+            current: MechDems::default(),
+        }
+    }
+
+    /// Set a newly received demand.
+    pub fn set_demands(&mut self, dems: MechDems, now: Instant) {
+        self.start = self.current.clone();
+        self.target = Some((dems, now));
+    }
+
+    /// Immediately stop all actuators.
+    ///
+    /// Drops the current demand and zeros all speeds, holding the current positions.
+    pub fn stop(&mut self) {
+        self.target = None;
+        for s in self.current.speed_rads.values_mut() {
+            *s = 0.0;
+        }
+    }
+
+    /// Returns true if there is no demand, or the latest demand has expired.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        match self.target {
+            Some((_, t)) => (now - t).as_secs_f64() > self.validity_s,
+            None => true,
+        }
+    }
+
+    /// Time since the latest demand was received, or `None` if there is no demand.
+    pub fn demand_age_s(&self, now: Instant) -> Option<f64> {
+        self.target.as_ref().map(|(_, t)| (now - *t).as_secs_f64())
+    }
+
+    /// Get the demands to actuate at the given time.
+    pub fn update(&mut self, now: Instant) -> &MechDems {
+        let (target, target_time) = match self.target {
+            Some((ref d, t)) => (d, t),
+            None => return &self.current,
+        };
+
+        let age_s = (now - target_time).as_secs_f64();
+
+        self.current = if age_s <= self.validity_s {
+            // Move towards the demand, then hold it
+            let frac = match self.interp_time_s > 0.0 {
+                true => (age_s / self.interp_time_s).min(1.0),
+                false => 1.0,
+            };
+
+            MechDems {
+                pos_rad: lerp_map(&self.start.pos_rad, &target.pos_rad, frac),
+                speed_rads: lerp_map(&self.start.speed_rads, &target.speed_rads, frac),
+            }
+        } else {
+            // Expired, so hold positions and ramp speeds down to zero
+            let frac = match self.expiry_ramp_time_s > 0.0 {
+                true => ((age_s - self.validity_s) / self.expiry_ramp_time_s).min(1.0),
+                false => 1.0,
+            };
+
+            MechDems {
+                pos_rad: target.pos_rad.clone(),
+                speed_rads: target
+                    .speed_rads
+                    .iter()
+                    .map(|(&id, &s)| (id, s * (1.0 - frac)))
+                    .collect(),
+            }
+        };
+
+        &self.current
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Linearly interpolate each actuator in `end` from its value in `start`.
+///
+/// Actuators which aren't in `start` go straight to their value in `end`.
+fn lerp_map(
+    start: &HashMap<ActId, f64>,
+    end: &HashMap<ActId, f64>,
+    frac: f64,
+) -> HashMap<ActId, f64> {
+    end.iter()
+        .map(|(&id, &e)| match start.get(&id) {
+            Some(&s) => (id, s + (e - s) * frac),
+            None => (id, e),
+        })
+        .collect()
+}
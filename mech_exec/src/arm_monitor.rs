@@ -0,0 +1,314 @@
+//! # Arm Torque Monitor
+//!
+//! The arm servos will push against an obstacle with their full torque until they burn out. This
+//! module monitors the current drawn by each arm joint, and if a joint stays over its limit for
+//! longer than the stall time it raises an [`ArmFault`] and holds the whole arm where it is. The
+//! fault clears when a new demand moves the stalled joint back the way it came.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::eqpt::mech::{ActId, ArmFault, MechDems, MechSensData};
+use std::{collections::HashMap, time::Instant};
+
+use crate::params::MechExecParams;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Monitors the arm joint currents for stalls.
+pub struct ArmTorqueMonitor {
+    /// Current limit of each joint
+    limits_a: HashMap<ActId, f64>,
+
+    /// Time a joint must be over its limit for before it is considered stalled
+    stall_time_s: f64,
+
+    /// Time at which each joint which is over its limit went over it
+    over_limit_since: HashMap<ActId, Instant>,
+
+    /// The active stall, if any
+    stall: Option<Stall>,
+}
+
+/// An active stall.
+struct Stall {
+    /// The fault reported to the client
+    fault: ArmFault,
+
+    /// Positions the arm joints are held at
+    hold_pos_rad: HashMap<ActId, f64>,
+
+    /// Sign of the direction the stalled joint was moving in when it stalled, 0 if it isn't known
+    direction: f64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl ArmTorqueMonitor {
+    /// Create a new monitor using the arm current limit parameters.
+    pub fn new(params: &MechExecParams) -> Self {
+        Self {
+            limits_a: ActId::arm_ids()
+                .iter()
+                .copied()
+                .zip(params.arm_current_limit_a.iter().copied())
+                .collect(),
+            stall_time_s: params.arm_stall_time_s,
+            over_limit_since: HashMap::new(),
+            stall: None,
+        }
+    }
+
+    /// Get the active fault, if any.
+    pub fn fault(&self) -> Option<ArmFault> {
+        self.stall.as_ref().map(|s| s.fault)
+    }
+
+    /// Check the measured currents against the limits.
+    ///
+    /// `actuated` is the demand being actuated, which gives the direction of any stall. Returns
+    /// the fault if one was raised on this update.
+    pub fn update(
+        &mut self,
+        sens: &MechSensData,
+        actuated: &MechDems,
+        now: Instant,
+    ) -> Option<ArmFault> {
+        // Nothing more to check while already holding the arm
+        if self.stall.is_some() {
+            return None;
+        }
+
+        let mut stalled = None;
+
+        for (&act_id, &limit_a) in self.limits_a.iter() {
+            match sens.current_a.get(&act_id) {
+                Some(&current_a) if current_a.abs() > limit_a => {
+                    let since = *self.over_limit_since.entry(act_id).or_insert(now);
+
+                    if (now - since).as_secs_f64() >= self.stall_time_s {
+                        stalled = Some((act_id, current_a, limit_a));
+                    }
+                }
+                _ => {
+                    self.over_limit_since.remove(&act_id);
+                }
+            }
+        }
+
+        let (act_id, current_a, limit_a) = stalled?;
+        self.over_limit_since.clear();
+
+        // Hold every joint at its measured position, falling back to the demand if there's no
+        // measurement
+        let hold_pos_rad: HashMap<ActId, f64> = ActId::arm_ids()
+            .iter()
+            .filter_map(|id| {
+                sens.pos_rad
+                    .get(id)
+                    .or_else(|| actuated.pos_rad.get(id))
+                    .map(|&p| (*id, p))
+            })
+            .collect();
+
+        let pos_rad = hold_pos_rad.get(&act_id).copied().unwrap_or_default();
+        // Unknown if there's no demand, or if it's already been reached
+        let direction = match actuated.pos_rad.get(&act_id) {
+            Some(&d) if d > pos_rad => 1.0,
+            Some(&d) if d < pos_rad => -1.0,
+            _ => 0.0,
+        };
+
+        let fault = ArmFault {
+            act_id,
+            current_a,
+            limit_a,
+            pos_rad,
+        };
+
+        self.stall = Some(Stall {
+            fault,
+            hold_pos_rad,
+            direction,
+        });
+
+        Some(fault)
+    }
+
+    /// Check a newly received demand, clearing the fault if it backs the stalled joint off.
+    ///
+    /// Returns true if the fault was cleared.
+    pub fn check_demands(&mut self, dems: &MechDems) -> bool {
+        let backing_off = match self.stall {
+            Some(ref s) => match dems.pos_rad.get(&s.fault.act_id) {
+                // If the stall direction isn't known any new demand clears it
+                Some(&d) => s.direction == 0.0 || (d - s.fault.pos_rad) * s.direction < 0.0,
+                None => false,
+            },
+            None => return false,
+        };
+
+        if backing_off {
+            self.stall = None;
+        }
+
+        backing_off
+    }
+
+    /// Replace the arm positions in the demands with the held positions if there is a fault.
+    pub fn apply(&self, dems: &mut MechDems) {
+        if let Some(ref s) = self.stall {
+            for (&id, &pos_rad) in s.hold_pos_rad.iter() {
+                dems.pos_rad.insert(id, pos_rad);
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn monitor() -> ArmTorqueMonitor {
+        ArmTorqueMonitor::new(&MechExecParams {
+            arm_current_limit_a: vec![1.0; 5],
+            arm_stall_time_s: 0.5,
+            ..Default::default()
+        })
+    }
+
+    fn sens(current_a: &[(ActId, f64)], pos_rad: &[(ActId, f64)]) -> MechSensData {
+        MechSensData {
+            current_a: current_a.iter().copied().collect(),
+            pos_rad: pos_rad.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn dems(pos_rad: &[(ActId, f64)]) -> MechDems {
+        MechDems {
+            pos_rad: pos_rad.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn after(t0: Instant, secs: f64) -> Instant {
+        t0 + Duration::from_secs_f64(secs)
+    }
+
+    /// Stall the elbow, measured at 0.5 rad, while it is demanded to `dem_rad`.
+    fn stall_elbow(monitor: &mut ArmTorqueMonitor, dem_rad: Option<f64>) -> ArmFault {
+        let t0 = Instant::now();
+        let sens = sens(&[(ActId::ArmElbow, 2.0)], &[(ActId::ArmElbow, 0.5)]);
+        let dem: Vec<_> = dem_rad.iter().map(|&d| (ActId::ArmElbow, d)).collect();
+        let actuated = dems(&dem);
+
+        assert!(monitor.update(&sens, &actuated, t0).is_none());
+        monitor.update(&sens, &actuated, after(t0, 0.5)).unwrap()
+    }
+
+    #[test]
+    fn test_stall_timing() {
+        let mut monitor = monitor();
+        let t0 = Instant::now();
+        let over = sens(&[(ActId::ArmElbow, -2.0)], &[(ActId::ArmElbow, 0.5)]);
+        let under = sens(&[(ActId::ArmElbow, 0.5)], &[(ActId::ArmElbow, 0.5)]);
+        let actuated = dems(&[(ActId::ArmElbow, 1.0)]);
+
+        // Dropping under the limit restarts the stall time
+        assert!(monitor.update(&over, &actuated, t0).is_none());
+        assert!(monitor.update(&over, &actuated, after(t0, 0.4)).is_none());
+        assert!(monitor.update(&under, &actuated, after(t0, 0.45)).is_none());
+        assert!(monitor.update(&over, &actuated, after(t0, 0.5)).is_none());
+        assert!(monitor.update(&over, &actuated, after(t0, 0.9)).is_none());
+        assert!(monitor.fault().is_none());
+
+        let fault = monitor.update(&over, &actuated, after(t0, 1.0)).unwrap();
+        assert_eq!(
+            fault,
+            ArmFault {
+                act_id: ActId::ArmElbow,
+                current_a: -2.0,
+                limit_a: 1.0,
+                pos_rad: 0.5,
+            }
+        );
+        assert_eq!(monitor.fault(), Some(fault));
+
+        // Only raised once
+        assert!(monitor.update(&over, &actuated, after(t0, 2.0)).is_none());
+        assert_eq!(monitor.fault(), Some(fault));
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut monitor = monitor();
+        let t0 = Instant::now();
+        let sens = sens(
+            &[(ActId::ArmElbow, 2.0)],
+            &[(ActId::ArmBase, 0.1), (ActId::ArmElbow, 0.5)],
+        );
+        let actuated = dems(&[(ActId::ArmShoulder, 0.2), (ActId::ArmElbow, 1.0)]);
+
+        // Demands pass through while there's no fault
+        let mut new_dems = dems(&[(ActId::ArmBase, 1.0), (ActId::StrFL, 0.3)]);
+        monitor.apply(&mut new_dems);
+        assert_eq!(new_dems.pos_rad[&ActId::ArmBase], 1.0);
+
+        monitor.update(&sens, &actuated, t0);
+        monitor.update(&sens, &actuated, after(t0, 0.5)).unwrap();
+
+        // Joints are held where they were measured, or where they were demanded if they weren't
+        // measured. Other actuators are untouched.
+        monitor.apply(&mut new_dems);
+        assert_eq!(new_dems.pos_rad[&ActId::ArmBase], 0.1);
+        assert_eq!(new_dems.pos_rad[&ActId::ArmShoulder], 0.2);
+        assert_eq!(new_dems.pos_rad[&ActId::ArmElbow], 0.5);
+        assert_eq!(new_dems.pos_rad[&ActId::StrFL], 0.3);
+    }
+
+    #[test]
+    fn test_clear_on_back_off() {
+        let mut monitor = monitor();
+        stall_elbow(&mut monitor, Some(1.0));
+
+        // Further the same way, or not moving the elbow, doesn't clear
+        assert!(!monitor.check_demands(&dems(&[(ActId::ArmElbow, 0.8)])));
+        assert!(!monitor.check_demands(&dems(&[(ActId::ArmElbow, 0.5)])));
+        assert!(!monitor.check_demands(&dems(&[(ActId::ArmBase, 0.0)])));
+        assert!(monitor.fault().is_some());
+
+        assert!(monitor.check_demands(&dems(&[(ActId::ArmElbow, 0.4)])));
+        assert!(monitor.fault().is_none());
+
+        let mut new_dems = dems(&[(ActId::ArmElbow, 0.4)]);
+        monitor.apply(&mut new_dems);
+        assert_eq!(new_dems.pos_rad[&ActId::ArmElbow], 0.4);
+
+        // Nothing to clear
+        assert!(!monitor.check_demands(&dems(&[(ActId::ArmElbow, 0.0)])));
+    }
+
+    #[test]
+    fn test_clear_unknown_direction() {
+        // Demanded to where it already is, or not demanded at all, so any demand clears it
+        for dem_rad in [Some(0.5), None] {
+            let mut monitor = monitor();
+            stall_elbow(&mut monitor, dem_rad);
+
+            assert!(!monitor.check_demands(&dems(&[(ActId::ArmBase, 0.0)])));
+            assert!(monitor.check_demands(&dems(&[(ActId::ArmElbow, 0.8)])));
+            assert!(monitor.fault().is_none());
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! # Dynamixel Arm
+//!
+//! Drives the upgraded arm's Dynamixel smart servos through a [`ServoCtrl`], used when
+//! `arm_servos.driver` is `dynamixel`. Each cycle the arm joints' position demands are written to
+//! the servos and their measured positions are read back into the sensor data.
+//!
+//! If the bus fails the error is logged and the bus is left alone for `RETRY_PERIOD`, so that
+//! a disconnected arm doesn't stall the actuation loop with timeouts.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::eqpt::mech::{ActId, MechDems, MechSensData};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    fs::File,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    params::ArmServoParams,
+    servo_ctrl::{dynamixel::Dynamixel, serial, ServoConfig, ServoCtrl, ServoError},
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Time to wait after a bus error before trying the bus again.
+const RETRY_PERIOD: Duration = Duration::from_secs(1);
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// The arm's Dynamixel servos.
+pub struct DynamixelArm {
+    ctrl: ServoCtrl<Dynamixel<File>, ActId>,
+
+    /// Time of the last bus error, while the bus is being left alone
+    failed_at: Option<Instant>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum DynamixelArmError {
+    #[error("Could not open the Dynamixel bus: {0}")]
+    OpenError(std::io::Error),
+
+    #[error("Expected {0} Dynamixel IDs, one per arm joint, but {1} were given")]
+    WrongNumIds(usize, usize),
+
+    #[error("Could not enable the torque of servo {0}: {1}")]
+    TorqueEnableError(u8, ServoError),
+
+    #[error("Invalid servo configuration: {0}")]
+    ConfigError(ServoError),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl DynamixelArm {
+    /// Open the bus and enable the torque of every arm servo.
+    pub fn new(params: &ArmServoParams) -> Result<Self, DynamixelArmError> {
+        let num_joints = ActId::arm_ids().len();
+        if params.dynamixel_ids.len() != num_joints {
+            return Err(DynamixelArmError::WrongNumIds(
+                num_joints,
+                params.dynamixel_ids.len(),
+            ));
+        }
+
+        let bus = serial::open(
+            &params.dynamixel_port,
+            params.dynamixel_baud,
+            Duration::from_secs_f64(params.dynamixel_timeout_s),
+        )
+        .map_err(DynamixelArmError::OpenError)?;
+
+        let mut driver = Dynamixel::new(bus);
+
+        // One turn of the servo is centred on the joint's zero
+        let mut config = HashMap::new();
+        for (&act_id, &id) in ActId::arm_ids().iter().zip(params.dynamixel_ids.iter()) {
+            driver
+                .set_torque_enabled(id, true)
+                .map_err(|e| DynamixelArmError::TorqueEnableError(id, e))?;
+
+            config.insert(
+                act_id,
+                ServoConfig::Positional {
+                    channel: (0, id),
+                    min_angle_rad: -PI,
+                    max_angle_rad: PI,
+                },
+            );
+        }
+
+        let ctrl = ServoCtrl::new(vec![driver], config).map_err(DynamixelArmError::ConfigError)?;
+
+        info!(
+            "Dynamixel arm servos {:?} enabled on {}",
+            params.dynamixel_ids, params.dynamixel_port
+        );
+
+        Ok(Self {
+            ctrl,
+            failed_at: None,
+        })
+    }
+
+    /// Read the measured position of each arm joint into the sensor data.
+    pub fn read_sens(&mut self, sens: &mut MechSensData, now: Instant) {
+        if !self.bus_available(now) {
+            return;
+        }
+
+        for act_id in ActId::arm_ids() {
+            match self.ctrl.get_position(act_id) {
+                Ok(pos_rad) => {
+                    sens.pos_rad.insert(*act_id, pos_rad);
+                }
+                Err(e) => return self.fail(*act_id, e, now),
+            }
+        }
+    }
+
+    /// Write the position demand of each demanded arm joint to its servo.
+    pub fn actuate(&mut self, dems: &MechDems, now: Instant) {
+        if !self.bus_available(now) {
+            return;
+        }
+
+        for act_id in ActId::arm_ids() {
+            if let Some(&pos_rad) = dems.pos_rad.get(act_id) {
+                if let Err(e) = self.ctrl.set_position(act_id, pos_rad) {
+                    return self.fail(*act_id, e, now);
+                }
+            }
+        }
+    }
+
+    /// Whether the bus may be used, which is false for `RETRY_PERIOD` after an error.
+    fn bus_available(&mut self, now: Instant) -> bool {
+        match self.failed_at {
+            Some(t) if now - t < RETRY_PERIOD => false,
+            Some(_) => {
+                self.failed_at = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Log a bus error and leave the bus alone until the retry period has passed.
+    fn fail(&mut self, act_id: ActId, error: ServoError, now: Instant) {
+        warn!(
+            "Dynamixel servo for {:?} failed, retrying in {} s: {}",
+            act_id,
+            RETRY_PERIOD.as_secs_f64(),
+            error
+        );
+        self.failed_at = Some(now);
+    }
+}
@@ -0,0 +1,108 @@
+//! # GPIO Module
+//!
+//! Handles the rover Pi's GPIO pins: the hardware E-stop input and the status LEDs. Only available
+//! with the `io` feature, which requires a Raspberry Pi target.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use rppal::gpio::{Gpio, InputPin, Level, OutputPin};
+
+use crate::params::IoParams;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// The rover Pi's GPIO pins.
+pub struct Io {
+    /// The E-stop input
+    estop_pin: InputPin,
+
+    /// The level of the E-stop input when the E-stop is pressed
+    estop_engaged_level: Level,
+
+    /// LED lit while in safe mode
+    safe_led: OutputPin,
+
+    /// LED lit while an autonomous traverse is active
+    autonomy_led: OutputPin,
+
+    /// LED lit while there is a fault
+    fault_led: OutputPin,
+}
+
+/// The states shown on the status LEDs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LedStatus {
+    pub safe: bool,
+    pub autonomy_active: bool,
+    pub fault: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Errors which can occur in the [`Io`]
+#[derive(thiserror::Error, Debug)]
+pub enum IoError {
+    #[error("Could not access the GPIO pins: {0}")]
+    GpioError(rppal::gpio::Error),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Io {
+    /// Claim the GPIO pins given in the parameters.
+    ///
+    /// All LEDs start off.
+    pub fn new(params: &IoParams) -> Result<Self, IoError> {
+        let gpio = Gpio::new().map_err(IoError::GpioError)?;
+
+        // Pull the input towards the engaged level, so a disconnected switch engages the E-stop
+        let estop_pin = gpio.get(params.estop_pin).map_err(IoError::GpioError)?;
+        let (estop_pin, estop_engaged_level) = match params.estop_active_low {
+            true => (estop_pin.into_input_pulldown(), Level::Low),
+            false => (estop_pin.into_input_pullup(), Level::High),
+        };
+
+        let output = |pin| -> Result<OutputPin, IoError> {
+            Ok(gpio.get(pin).map_err(IoError::GpioError)?.into_output_low())
+        };
+
+        Ok(Self {
+            estop_pin,
+            estop_engaged_level,
+            safe_led: output(params.safe_led_pin)?,
+            autonomy_led: output(params.autonomy_led_pin)?,
+            fault_led: output(params.fault_led_pin)?,
+        })
+    }
+
+    /// Returns true if the E-stop is pressed.
+    pub fn is_estop_engaged(&self) -> bool {
+        self.estop_pin.read() == self.estop_engaged_level
+    }
+
+    /// Show the given status on the LEDs.
+    pub fn set_leds(&mut self, status: LedStatus) {
+        set_led(&mut self.safe_led, status.safe);
+        set_led(&mut self.autonomy_led, status.autonomy_active);
+        set_led(&mut self.fault_led, status.fault);
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+fn set_led(pin: &mut OutputPin, on: bool) {
+    match on {
+        true => pin.set_high(),
+        false => pin.set_low(),
+    }
+}
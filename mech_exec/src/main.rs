@@ -18,18 +18,45 @@ mod mech_server;
 /// Parameters for the mechanisms executable.
 mod params;
 
+/// Interpolation and hold of demands for the actuation loop.
+mod actuation;
+
+/// Arm joint current monitoring.
+mod arm_monitor;
+
+/// Simulated actuators for running without hardware.
+mod sim_actuators;
+
+/// Steer axis offsets and inversions.
+mod steer_cal;
+
+/// Arm driven by Dynamixel smart servos.
+mod dynamixel_arm;
+
+/// GPIO E-stop input and status LEDs.
+#[cfg(feature = "io")]
+mod io;
+
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
 // External
-use comms_if::eqpt::mech::MechDemsResponse;
-use log::{info, warn, trace};
+use comms_if::eqpt::mech::{ActId, MechDemsResponse, MechSensData, MechSensPacket};
+use log::{debug, info, warn, trace};
 use color_eyre::{Result, eyre::WrapErr};
+use std::{env, fs, thread, time::{Duration, Instant}};
 
 // Internal
+use actuation::DemandInterpolator;
+use arm_monitor::ArmTorqueMonitor;
+use dynamixel_arm::DynamixelArm;
 use mech_server::MechServer;
+use params::ArmServoDriver;
+use sim_actuators::SimActuators;
+use steer_cal::SteerCal;
 use util::{
+    build_info::BuildInfo,
     host,
     logger::{logger_init, LevelFilter},
     session::Session,
@@ -61,14 +88,54 @@ fn main() -> Result<()> {
     );
     info!("Session directory: {:?}\n", session.session_root);
 
+    // Record the build so the session's data can be matched to the software which produced it
+    let features: &[&str] = match cfg!(feature = "io") {
+        true => &["io"],
+        false => &[],
+    };
+    BuildInfo::new("mech_exec", features)
+        .save(&session)
+        .wrap_err("Failed to save the build information")?;
+
     info!("Initialising...");
 
     // ---- LOAD PARAMETERS ----
 
-    let params = util::params::load("mech_exec.toml")?;
+    let params: params::MechExecParams = util::params::load("mech_exec.toml")?;
 
     info!("Parameters loaded");
 
+    // ---- ACTUATOR INITIALISATION ----
+
+    // Collect all arguments
+    let args: Vec<String> = env::args().collect();
+
+    debug!("CLI arguments: {:?}", args);
+
+    let mut sim_actuators = match args.iter().skip(1).any(|a| a == "--sim-actuators") {
+        true => {
+            info!("Using simulated actuators");
+            Some(SimActuators::new(&params))
+        }
+        false => None,
+    };
+
+    // The Dynamixel arm is driven directly, the PCA9685 channels are driven with the other
+    // actuators
+    let mut dynamixel_arm = match (&sim_actuators, params.arm_servos.driver) {
+        (None, ArmServoDriver::Dynamixel) => Some(
+            DynamixelArm::new(&params.arm_servos)
+                .wrap_err("Failed to initialise the Dynamixel arm servos")?
+        ),
+        _ => None,
+    };
+
+    #[cfg(feature = "io")]
+    let mut io = io::Io::new(&params.io).wrap_err("Failed to initialise the GPIO pins")?;
+
+    #[cfg(feature = "io")]
+    info!("GPIO initialised");
+
     // ---- SERVER INITIALISATION ----
 
     let mut server: MechServer = MechServer::new(&params)
@@ -80,44 +147,181 @@ fn main() -> Result<()> {
 
     info!("Initialisation complete, entering main loop in safe mode");
 
+    let mut interpolator = DemandInterpolator::new(&params);
+    let mut arm_monitor = ArmTorqueMonitor::new(&params);
+    let mut steer_cal = SteerCal::new(&params);
+    let period = Duration::from_secs_f64(1.0 / params.actuation_frequency_hz);
+
+    let sens_period = Duration::from_secs_f64(params.sens_publish_period_s);
+    let mut last_sens_publish: Option<Instant> = None;
+
+    let mut last_dems_session_id: Option<u64> = None;
+    let mut last_dems_seq: Option<u64> = None;
+    let mut dems_response: Option<MechDemsResponse> = None;
+
     let mut safe_mode = true;
+    let mut estop_engaged = false;
+    let mut autonomy_active = false;
 
     loop {
-        // Get demands from client
-        let dems = match server.get_demands() {
-            Some(d) => {
-                if safe_mode {
-                    info!("Recieved valid demand, exiting safe mode");
-                    safe_mode = false;
+        let cycle_start = Instant::now();
+
+        // Check the E-stop first so that no demand is actuated while it's pressed
+        #[cfg(feature = "io")]
+        let estop_now = io.is_estop_engaged();
+        #[cfg(not(feature = "io"))]
+        let estop_now = false;
+
+        match (estop_engaged, estop_now) {
+            (false, true) => warn!("E-stop engaged, stopping all actuators"),
+            (true, false) => info!("E-stop released"),
+            _ => (),
+        }
+        estop_engaged = estop_now;
+
+        if estop_engaged {
+            interpolator.stop();
+        }
+
+        // Get demands from the client, if any have arrived
+        if let Some(packet) = server.get_demands() {
+            trace!("Recieved demands {}, validating...", packet.seq);
+
+            last_dems_session_id = Some(packet.session_id);
+            last_dems_seq = Some(packet.seq);
+
+            // TODO: Validate demands
+
+            if estop_engaged {
+                dems_response = Some(MechDemsResponse::EqptInvalid);
+            } else if packet.flags.estop {
+                // The client has crashed, so stop until it sends demands again
+                warn!("Software E-stop recieved from the client, stopping all actuators");
+                interpolator.stop();
+                autonomy_active = false;
+                dems_response = Some(MechDemsResponse::DemsOk);
+            } else {
+                if arm_monitor.check_demands(&packet.dems) {
+                    info!("Arm demanded away from the stall, resuming arm motion");
                 }
-                d
-            },
-            None => {
-                if !safe_mode {
-                    warn!("Entering safe mode");
-                    safe_mode = true;
+
+                let mut dems = packet.dems;
+
+                // Take the current steer positions as the zeros, which keeps the axes where they
+                // are once the demands are replaced with zeros
+                if packet.flags.capture_steer_zero {
+                    steer_cal.capture_zero(&dems);
+                    for act_id in ActId::str_ids() {
+                        if let Some(pos_rad) = dems.pos_rad.get_mut(act_id) {
+                            *pos_rad = 0.0;
+                        }
+                    }
+
+                    let offsets = steer_cal.offsets();
+                    info!("Captured steer offsets: {:?}", offsets);
+
+                    // Save the offsets so they can be copied into the parameters
+                    let cal_path = session.session_root.join("steer_cal.toml");
+                    match fs::write(&cal_path, format!("str_offset_rad = {:?}\n", offsets)) {
+                        Ok(()) => info!("Steer offsets saved to {:?}", cal_path),
+                        Err(e) => warn!("Couldn't save the steer offsets: {}", e),
+                    }
+                }
+
+                steer_cal.dems_to_actuator(&mut dems);
+                interpolator.set_demands(dems, Instant::now());
+
+                if packet.flags.autonomy_active != autonomy_active {
+                    info!("Autonomy active: {}", packet.flags.autonomy_active);
+                    autonomy_active = packet.flags.autonomy_active;
                 }
-                continue
+
+                dems_response = Some(MechDemsResponse::DemsOk);
+            }
+        }
+
+        let now = Instant::now();
+
+        // Safe mode is entered when the demands expire, and left when a new demand arrives
+        match (safe_mode, interpolator.is_expired(now)) {
+            (true, false) => {
+                info!("Recieved valid demand, exiting safe mode");
+                safe_mode = false;
+            }
+            (false, true) => {
+                warn!(
+                    "Demands expired, entering safe mode ({} demands dropped so far)",
+                    server.num_dropped()
+                );
+                safe_mode = true;
+                autonomy_active = false;
             }
+            _ => (),
+        }
+
+        #[cfg(feature = "io")]
+        io.set_leds(io::LedStatus {
+            safe: safe_mode,
+            autonomy_active,
+            fault: estop_engaged || arm_monitor.fault().is_some(),
+        });
+
+        let mut sens = match sim_actuators {
+            Some(ref sim) => sim.sens_data(),
+            // TODO: Read sensors
+            None => MechSensData::default(),
         };
+        if let Some(ref mut arm) = dynamixel_arm {
+            arm.read_sens(&mut sens, now);
+        }
+        steer_cal.sens_from_actuator(&mut sens);
 
-        trace!("Recieved demands, validating...");
-        
-        // TODO: Validate demands
+        // Report the dead-man timer, so the client can see if its demands are arriving
+        sens.dems_age_s = interpolator.demand_age_s(now);
+        sens.dems_expired = safe_mode;
 
-        trace!("Validated, sending response...");
+        // Actuate every cycle, including after expiry so that the speeds are ramped down and then
+        // held at zero
+        let mut dems = interpolator.update(now).clone();
 
-        // Send response to client
-        match server.send_dems_response(&MechDemsResponse::DemsOk) {
-            Ok(_) => (),
-            Err(_) => {
-                warn!("Couldn't send response to client, entering safe mode");
-                safe_mode = true;
-                continue
+        // Stop the arm if any joint has stalled
+        if let Some(fault) = arm_monitor.update(&sens, &dems, now) {
+            warn!(
+                "{:?} stalled drawing {:.2} A (limit {:.2} A), holding the arm",
+                fault.act_id, fault.current_a, fault.limit_a
+            );
+        }
+        arm_monitor.apply(&mut dems);
+
+        match sim_actuators {
+            Some(ref mut sim) => sim.actuate(&dems, now),
+            // TODO: Actuate demands
+            None => trace!("Actuating {:#?}", dems),
+        }
+        if let Some(ref mut arm) = dynamixel_arm {
+            arm.actuate(&dems, now);
+        }
+
+        // Publish sensor data, which is sent even without demands so the client knows we're alive
+        if last_sens_publish.is_none_or(|t| now - t >= sens_period) {
+            let packet = MechSensPacket {
+                last_dems_session_id,
+                last_dems_seq,
+                dems_response,
+                sens,
+                arm_fault: arm_monitor.fault(),
+            };
+
+            if let Err(e) = server.send_sens_data(&packet) {
+                warn!("Couldn't publish sensor data: {}", e);
             }
+
+            last_sens_publish = Some(now);
         }
 
-        // TODO: Actuate demands
-        info!("Actuating {:#?}", dems);
+        // Wait for the next cycle
+        if let Some(d) = period.checked_sub(cycle_start.elapsed()) {
+            thread::sleep(d);
+        }
     }
 }
@@ -3,6 +3,10 @@
 //! This module abstracts over the networking side of the mechanisms executable. The server accepts
 //! connections from the client in the rover executable, allowing demands to be recieved from the
 //! client and sensor data to be sent to the client.
+//!
+//! Demands arrive on a PULL socket tagged with sequence numbers, so there is no reply for the
+//! client to wait on. Sensor data, including the sequence number of the latest accepted demands,
+//! is published separately on a PUB socket.
 
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
@@ -10,9 +14,9 @@
 
 use comms_if::{
     net::{zmq, MonitoredSocket, SocketOptions, MonitoredSocketError}, 
-    eqpt::mech::{MechDems, MechDemsResponse}
+    eqpt::mech::{MechDemsPacket, MechSensPacket}
 };
-use log::warn;
+use log::{debug, info, warn};
 
 use crate::params::MechExecParams;
 
@@ -24,15 +28,22 @@ use crate::params::MechExecParams;
 ///
 /// The server accepts connections from the client in the rover executable, allowing demands to be 
 /// recieved from the client and sensor data to be sent to the client.
-///
-/// TODO: Sensor data chain
 pub struct MechServer {
 
-    /// REP socket which accepts demands from the client
+    /// PULL socket which accepts demands from the client
     dems_socket: MonitoredSocket,
 
     /// PUB socket which sends sensor data to the client
-    _sens_socket: MonitoredSocket,
+    sens_socket: MonitoredSocket,
+
+    /// Session ID of the latest accepted demands
+    last_session_id: Option<u64>,
+
+    /// Sequence number of the latest accepted demands
+    last_seq: Option<u64>,
+
+    /// Number of demands which were lost or arrived out of order
+    num_dropped: u64,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -41,6 +52,7 @@ pub struct MechServer {
 
 /// Errors which can occur in the [`MechServer`]
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum MechServerError {
     #[error("Socket error: {0}")]
     SocketError(MonitoredSocketError),
@@ -50,7 +62,10 @@ pub enum MechServerError {
     // NotConnected,
 
     #[error("Could not send data to the client: {0}")]
-    SendError(zmq::Error)
+    SendError(zmq::Error),
+
+    #[error("Could not serialize the data: {0}")]
+    SerializationError(serde_json::Error),
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -71,8 +86,6 @@ impl MechServer {
         let dems_socket_options = SocketOptions {
             bind: true,
             block_on_first_connect: false,
-            recv_timeout: 200,
-            send_timeout: 10,
             ..Default::default()
         };
         let sens_socket_options =  SocketOptions {
@@ -84,7 +97,7 @@ impl MechServer {
         // Create the sockets
         let dems_socket = MonitoredSocket::new(
             &ctx, 
-            zmq::REP,
+            zmq::PULL,
             dems_socket_options, 
             &params.demands_endpoint
         )?;
@@ -98,59 +111,99 @@ impl MechServer {
         // Create self
         Ok(Self {
             dems_socket,
-            _sens_socket: sens_socket
+            sens_socket,
+            last_session_id: None,
+            last_seq: None,
+            num_dropped: 0
         })
     }
 
-    /// Retrieve a set of demands from the client.
+    /// Retrieve the latest demands from the client, if any have arrived.
     ///
-    /// The user MUST call [`send_dems_response`] at the earliest opportunity in order to notify the
-    /// client.
+    /// This function does not block. All waiting demands are read, and only the newest is 
+    /// returned. Demands older than the last ones returned are discarded, unless they come from a
+    /// new client session, in which case the sequence numbers start again.
     ///
-    /// `None` is returned if no valid demand is recieved. In this case the exec must stop the 
-    /// mechanisms.
-    pub fn get_demands(&mut self) -> Option<MechDems> {
-
-        // Read from the socket
-        let msg = self.dems_socket.recv_msg(0);
-
-        match msg {
-            Ok(m) => {
-                match serde_json::from_str(m.as_str().unwrap_or("")) {
-                    Ok(d) => Some(d),
-                    Err(e) => {
-                        warn!("Could not deserialize demands: {}", e);
-                        None
-                    }
+    /// `None` is returned if no new valid demands have been recieved.
+    pub fn get_demands(&mut self) -> Option<MechDemsPacket> {
+
+        let mut latest: Option<MechDemsPacket> = None;
+
+        // Read until there are no more messages waiting
+        loop {
+            let msg = match self.dems_socket.recv_msg(zmq::DONTWAIT) {
+                Ok(m) => m,
+                Err(zmq::Error::EAGAIN) => break,
+                Err(e) => {
+                    warn!("Could not read from demands socket: {}", e);
+                    break
+                }
+            };
+
+            let packet: MechDemsPacket = match serde_json::from_slice(&msg) {
+                Ok(p) => p,
+                Err(e) => {
+                    warn!("Could not deserialize demands: {}", e);
+                    continue
                 }
-            },
-            Err(_e) => {
-                // warn!("Could not read from demands socket: {}", e);
-                None
+            };
+
+            // A new session means the client restarted and its sequence numbers with it
+            let session_id = latest.as_ref().map(|p| p.session_id).or(self.last_session_id);
+            if session_id != Some(packet.session_id) {
+                if session_id.is_some() {
+                    info!(
+                        "New client session {:x}, restarting demand sequence numbers",
+                        packet.session_id
+                    );
+                }
+                self.last_session_id = Some(packet.session_id);
+                self.last_seq = None;
+                latest = Some(packet);
+                continue
             }
+
+            // Discard anything older than what's already been accepted
+            let newest_seq = latest.as_ref().map(|p| p.seq).or(self.last_seq);
+            match newest_seq {
+                Some(s) if packet.seq <= s => {
+                    debug!("Discarding out of order demands {} (latest {})", packet.seq, s);
+                    self.num_dropped += 1;
+                    continue
+                },
+                Some(s) if packet.seq > s + 1 => {
+                    debug!("Lost demands {} to {}", s + 1, packet.seq - 1);
+                    self.num_dropped += packet.seq - s - 1;
+                },
+                _ => ()
+            }
+
+            latest = Some(packet);
         }
+
+        if let Some(ref p) = latest {
+            self.last_session_id = Some(p.session_id);
+            self.last_seq = Some(p.seq);
+        }
+
+        latest
     }
 
-    /// Send a response to the client based on the recieved demands.
-    pub fn send_dems_response(
+    /// Number of demands which have been lost or discarded for arriving out of order.
+    pub fn num_dropped(&self) -> u64 {
+        self.num_dropped
+    }
+
+    /// Publish sensor data to the client.
+    pub fn send_sens_data(
         &mut self, 
-        response: &MechDemsResponse
+        packet: &MechSensPacket
     ) -> Result<(), MechServerError> {
-        // TODO: This seems to hang the server, find out why
-        // // If not connected return an error
-        // if !self.dems_socket.connected() {
-        //     return Err(MechServerError::NotConnected)
-        // }
-
-        // Serialize response
-        let resp_str = serde_json::to_string(response)
-            .expect("Response serialization failed. This should not happen");
-        
-        // Send response
-        match self.dems_socket.send(&resp_str, 0) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(MechServerError::SendError(e))
-        }
+        let packet_str = serde_json::to_string(packet)
+            .map_err(MechServerError::SerializationError)?;
+
+        self.sens_socket.send(&packet_str, 0)
+            .map_err(MechServerError::SendError)
     }
 }
 
@@ -18,4 +18,101 @@ pub struct MechExecParams {
 
     /// Endpoint for the sensor data socket
     pub sensor_data_endpoint: String,
-}
\ No newline at end of file
+
+    /// Frequency of the actuation loop
+    pub actuation_frequency_hz: f64,
+
+    /// Time taken to move from the previously actuated values to a newly received demand
+    pub dems_interp_time_s: f64,
+
+    /// Time after being received that a demand is valid for
+    pub dems_validity_s: f64,
+
+    /// Time taken to ramp drive speeds down to zero once a demand has expired
+    pub dems_expiry_ramp_time_s: f64,
+
+    /// Period at which sensor data is published
+    pub sens_publish_period_s: f64,
+
+    /// Time constant of the simulated actuators' position response, used with `--sim-actuators`
+    pub sim_pos_time_const_s: f64,
+
+    /// Time constant of the simulated actuators' speed response, used with `--sim-actuators`
+    pub sim_speed_time_const_s: f64,
+
+    /// Actuator angle at which each steer axis is straight ahead, in `ActId::str_ids` order
+    pub str_offset_rad: Vec<f64>,
+
+    /// If true the steer axis turns the opposite way to its demand, in `ActId::str_ids` order
+    pub str_invert: Vec<bool>,
+
+    /// Current limit of each arm joint, in `ActId::arm_ids` order
+    pub arm_current_limit_a: Vec<f64>,
+
+    /// Time an arm joint must be over its current limit for before it is considered stalled
+    pub arm_stall_time_s: f64,
+
+    /// Arm servo driver configuration
+    pub arm_servos: ArmServoParams,
+
+    /// GPIO configuration
+    #[cfg(feature = "io")]
+    pub io: IoParams,
+}
+
+/// Arm servo driver configuration.
+#[derive(Deserialize, Default)]
+pub struct ArmServoParams {
+
+    /// Driver the arm servos are connected to
+    pub driver: ArmServoDriver,
+
+    /// Serial port of the Dynamixel bus
+    pub dynamixel_port: String,
+
+    /// Baud rate of the Dynamixel bus, which must match the servos
+    pub dynamixel_baud: u32,
+
+    /// Time to wait for a Dynamixel servo to reply before it is considered lost
+    pub dynamixel_timeout_s: f64,
+
+    /// Dynamixel ID of each arm joint, in `ActId::arm_ids` order
+    pub dynamixel_ids: Vec<u8>,
+}
+
+/// GPIO pin configuration. Pin numbers are BCM GPIO numbers, not physical header pins.
+#[cfg(feature = "io")]
+#[derive(Deserialize, Default)]
+pub struct IoParams {
+
+    /// Pin the E-stop switch is connected to
+    pub estop_pin: u8,
+
+    /// If true the E-stop is engaged when its pin is low
+    pub estop_active_low: bool,
+
+    /// Pin of the LED lit in safe mode
+    pub safe_led_pin: u8,
+
+    /// Pin of the LED lit while an autonomous traverse is active
+    pub autonomy_led_pin: u8,
+
+    /// Pin of the LED lit while there is a fault
+    pub fault_led_pin: u8,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Drivers the arm servos can be connected to.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ArmServoDriver {
+    /// PWM channels of the PCA9685 boards, mapped by `arm_idx_map`
+    #[default]
+    Pca9685,
+
+    /// Dynamixel smart servos on a serial bus
+    Dynamixel,
+}
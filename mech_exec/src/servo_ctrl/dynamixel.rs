@@ -0,0 +1,333 @@
+//! [`ServoDriver`] implementation for a chain of Dynamixel smart servos on a serial bus
+//!
+//! Dynamixel servos are daisy chained on a half duplex UART bus, and addressed by their ID. This
+//! driver uses protocol 2.0 and the X-series control table. The bus can be anything which
+//! implements [`Read`] and [`Write`], usually the serial port of the USB adaptor (e.g. U2D2),
+//! which must already be configured for the servos' baud rate.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::io::{Read, Write};
+
+use super::{PositionFeedback, ServoDriver, ServoError};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Maximum raw position value, at one full turn.
+const MAX_POSITION: u32 = 4095;
+
+/// Packet header, followed by the reserved byte.
+const HEADER: [u8; 4] = [0xFF, 0xFF, 0xFD, 0x00];
+
+/// Instruction to read from the control table.
+const INST_READ: u8 = 0x02;
+
+/// Instruction to write to the control table.
+const INST_WRITE: u8 = 0x03;
+
+/// Instruction byte of status packets returned by the servos.
+const INST_STATUS: u8 = 0x55;
+
+/// Control table address of the torque enable flag (1 byte).
+const ADDR_TORQUE_ENABLE: u16 = 64;
+
+/// Control table address of the goal position (4 bytes).
+const ADDR_GOAL_POSITION: u16 = 116;
+
+/// Control table address of the present position (4 bytes).
+const ADDR_PRESENT_POSITION: u16 = 132;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A chain of Dynamixel servos on one bus.
+pub struct Dynamixel<B: Read + Write> {
+    bus: B,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl<B: Read + Write> Dynamixel<B> {
+    /// Create a new driver on the given bus.
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    /// Enable or disable the torque of a servo.
+    ///
+    /// Servos power up with torque disabled and won't move to a goal position until it's enabled.
+    pub fn set_torque_enabled(&mut self, id: u8, enabled: bool) -> Result<(), ServoError> {
+        self.write(id, ADDR_TORQUE_ENABLE, &[enabled as u8])
+    }
+
+    /// Write data into a servo's control table, and wait for its status.
+    fn write(&mut self, id: u8, address: u16, data: &[u8]) -> Result<(), ServoError> {
+        let mut params = address.to_le_bytes().to_vec();
+        params.extend_from_slice(data);
+
+        self.send(id, INST_WRITE, &params)?;
+        self.recv_status(id)?;
+
+        Ok(())
+    }
+
+    /// Read `len` bytes from a servo's control table.
+    fn read(&mut self, id: u8, address: u16, len: u16) -> Result<Vec<u8>, ServoError> {
+        let mut params = address.to_le_bytes().to_vec();
+        params.extend_from_slice(&len.to_le_bytes());
+
+        self.send(id, INST_READ, &params)?;
+        let data = self.recv_status(id)?;
+
+        match data.len() == len as usize {
+            true => Ok(data),
+            false => Err(ServoError::InvalidStatus),
+        }
+    }
+
+    /// Send an instruction packet.
+    fn send(&mut self, id: u8, instruction: u8, params: &[u8]) -> Result<(), ServoError> {
+        let mut body = vec![instruction];
+        body.extend_from_slice(params);
+        let body = stuff(&body);
+
+        // Length covers the instruction, parameters and CRC
+        let mut packet = HEADER.to_vec();
+        packet.push(id);
+        packet.extend_from_slice(&(body.len() as u16 + 2).to_le_bytes());
+        packet.extend_from_slice(&body);
+        packet.extend_from_slice(&crc(&packet).to_le_bytes());
+
+        self.bus.write_all(&packet).map_err(|_| ServoError::Serial)?;
+        self.bus.flush().map_err(|_| ServoError::Serial)
+    }
+
+    /// Receive a status packet from the given servo, returning its parameters.
+    fn recv_status(&mut self, id: u8) -> Result<Vec<u8>, ServoError> {
+        // Header, ID and length
+        let mut packet = vec![0u8; 7];
+        self.bus.read_exact(&mut packet).map_err(|_| ServoError::Serial)?;
+
+        if packet[..4] != HEADER || packet[4] != id {
+            return Err(ServoError::InvalidStatus);
+        }
+
+        // Instruction, error, parameters and CRC
+        let len = u16::from_le_bytes([packet[5], packet[6]]) as usize;
+        if len < 4 {
+            return Err(ServoError::InvalidStatus);
+        }
+        packet.resize(7 + len, 0);
+        self.bus.read_exact(&mut packet[7..]).map_err(|_| ServoError::Serial)?;
+
+        let crc_start = packet.len() - 2;
+        if crc(&packet[..crc_start]).to_le_bytes() != packet[crc_start..] {
+            return Err(ServoError::InvalidStatus);
+        }
+
+        if packet[7] != INST_STATUS {
+            return Err(ServoError::InvalidStatus);
+        }
+
+        // The top bit is the hardware alert flag, which is reported separately to the error
+        match packet[8] & 0x7F {
+            0 => Ok(unstuff(&packet[9..crc_start])),
+            e => Err(ServoError::ServoReported(e)),
+        }
+    }
+}
+
+impl<B: Read + Write> ServoDriver for Dynamixel<B> {
+    /// The ID of the servo on the bus
+    type Channel = u8;
+
+    /// Set the goal position of a servo, with the duty cycle spanning one full turn.
+    fn set_duty_cycle(&mut self, channel: Self::Channel, duty_cycle: f64) -> Result<(), ServoError> {
+        if !(0.0..=1.0).contains(&duty_cycle) {
+            return Err(ServoError::InvalidDutyCycle);
+        }
+
+        let position = (duty_cycle * MAX_POSITION as f64).round() as u32;
+
+        self.write(channel, ADDR_GOAL_POSITION, &position.to_le_bytes())
+    }
+}
+
+impl<B: Read + Write> PositionFeedback for Dynamixel<B> {
+    fn get_position(&mut self, channel: Self::Channel) -> Result<f64, ServoError> {
+        let data = self.read(channel, ADDR_PRESENT_POSITION, 4)?;
+        let position = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+
+        // Servos in extended position mode can report outside of one turn
+        Ok(position as f64 / MAX_POSITION as f64)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Calculate the CRC-16 of a packet, as defined by the Dynamixel protocol.
+fn crc(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = match crc & 0x8000 != 0 {
+                true => (crc << 1) ^ 0x8005,
+                false => crc << 1,
+            };
+        }
+    }
+
+    crc
+}
+
+/// Add byte stuffing to the body of a packet, so that it never contains the header.
+///
+/// An extra `0xFD` is inserted after each `0xFF 0xFF 0xFD`.
+fn stuff(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+
+    for &b in data {
+        out.push(b);
+        if out.ends_with(&HEADER[..3]) {
+            out.push(0xFD);
+        }
+    }
+
+    out
+}
+
+/// Remove the byte stuffing from the body of a packet.
+fn unstuff(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        out.push(data[i]);
+        if out.ends_with(&HEADER[..3]) && data.get(i + 1) == Some(&0xFD) {
+            i += 1;
+        }
+        i += 1;
+    }
+
+    out
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+
+    /// Bus which records what is written and replies with canned data.
+    struct MockBus {
+        written: Vec<u8>,
+        replies: Cursor<Vec<u8>>,
+    }
+
+    impl Read for MockBus {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for MockBus {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Build a status packet from the given servo.
+    fn status(id: u8, error: u8, params: &[u8]) -> Vec<u8> {
+        let mut packet = HEADER.to_vec();
+        packet.push(id);
+        packet.extend_from_slice(&(params.len() as u16 + 4).to_le_bytes());
+        packet.push(INST_STATUS);
+        packet.push(error);
+        packet.extend_from_slice(params);
+        packet.extend_from_slice(&crc(&packet).to_le_bytes());
+        packet
+    }
+
+    fn driver(replies: Vec<u8>) -> Dynamixel<MockBus> {
+        Dynamixel::new(MockBus {
+            written: Vec::new(),
+            replies: Cursor::new(replies),
+        })
+    }
+
+    #[test]
+    fn test_crc() {
+        // Ping of servo 1, from the protocol 2.0 documentation
+        let packet = [0xFF, 0xFF, 0xFD, 0x00, 0x01, 0x03, 0x00, 0x01];
+        assert_eq!(crc(&packet), 0x4E19);
+    }
+
+    #[test]
+    fn test_stuffing() {
+        let data = [0x01, 0xFF, 0xFF, 0xFD, 0x02];
+        let stuffed = stuff(&data);
+        assert_eq!(stuffed, [0x01, 0xFF, 0xFF, 0xFD, 0xFD, 0x02]);
+        assert_eq!(unstuff(&stuffed), data);
+    }
+
+    #[test]
+    fn test_set_duty_cycle() {
+        let mut d = driver(status(3, 0, &[]));
+        d.set_duty_cycle(3, 0.5).unwrap();
+
+        // Write of 2048 to the goal position
+        let mut expected = vec![0xFF, 0xFF, 0xFD, 0x00, 3, 9, 0, INST_WRITE, 116, 0];
+        expected.extend_from_slice(&2048u32.to_le_bytes());
+        expected.extend_from_slice(&crc(&expected).to_le_bytes());
+        assert_eq!(d.bus.written, expected);
+
+        assert!(matches!(d.set_duty_cycle(3, 1.5), Err(ServoError::InvalidDutyCycle)));
+    }
+
+    #[test]
+    fn test_get_position() {
+        let mut d = driver(status(3, 0, &1024i32.to_le_bytes()));
+        let pos = d.get_position(3).unwrap();
+        assert!((pos - 1024.0 / MAX_POSITION as f64).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bad_status() {
+        // Reported error
+        let mut d = driver(status(3, 0x02, &[]));
+        assert!(matches!(d.set_torque_enabled(3, true), Err(ServoError::ServoReported(0x02))));
+
+        // Reply from the wrong servo
+        let mut d = driver(status(4, 0, &[]));
+        assert!(matches!(d.set_torque_enabled(3, true), Err(ServoError::InvalidStatus)));
+
+        // Corrupted CRC
+        let mut reply = status(3, 0, &[]);
+        *reply.last_mut().unwrap() ^= 0xFF;
+        let mut d = driver(reply);
+        assert!(matches!(d.set_torque_enabled(3, true), Err(ServoError::InvalidStatus)));
+
+        // No reply, as when the bus times out
+        let mut d = driver(Vec::new());
+        assert!(matches!(d.set_torque_enabled(3, true), Err(ServoError::Serial)));
+    }
+}
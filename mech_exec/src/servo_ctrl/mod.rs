@@ -2,8 +2,6 @@
 //!
 //! This module provides a unified servo control interface which can abstract over different types
 //! of servo driver boards.
-//!
-//! TODO: This module is still in progress and shouldn't be used right now
 
 // ------------------------------------------------------------------------------------------------
 // MODULES
@@ -12,6 +10,12 @@
 /// [`ServoDriver`] implementation for the Adafruit PCA9685 16 channel servo driver board.
 pub mod pca9685;
 
+/// [`ServoDriver`] implementation for Dynamixel smart servos on a serial bus.
+pub mod dynamixel;
+
+/// Opening serial ports for bus servos.
+pub mod serial;
+
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
@@ -43,10 +47,21 @@ pub trait ServoDriver {
 
 }
 
+/// Trait for [`ServoDriver`]s which can read back the position of their servos.
+pub trait PositionFeedback: ServoDriver {
+
+    /// Get the measured position of a channel.
+    ///
+    /// The position is on the same scale as the duty cycle given to `set_duty_cycle`, so that a
+    /// servo which has reached its demand reports the demanded duty cycle.
+    fn get_position(&mut self, channel: Self::Channel) -> Result<f64, ServoError>;
+}
+
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
 // ------------------------------------------------------------------------------------------------
 
+/// Controls a set of servos, identified by `S`, spread across one or more drivers.
 pub struct ServoCtrl<D, S>
 where
     D: ServoDriver,
@@ -56,6 +71,7 @@ where
 
     servo_config_map: HashMap<S, ServoConfig<D::Channel>>,
 }
+
 #[derive(Serialize, Deserialize)]
 pub struct ControllerConfig<S, C>
 where
@@ -78,10 +94,31 @@ pub enum ServoError {
     I2c,
 
     #[error("Duty cycle must be between 0.0 and 1.0")]
-    InvalidDutyCycle
+    InvalidDutyCycle,
+
+    #[error("A serial bus error occured")]
+    Serial,
+
+    #[error("Recieved an invalid status packet from the servo")]
+    InvalidStatus,
+
+    #[error("The servo reported error {0:#04x}")]
+    ServoReported(u8),
+
+    #[error("Servo is mapped to driver {0} but there are only {1} drivers")]
+    InvalidDriverIndex(usize, usize),
+
+    #[error("Servo range must have a minimum below its maximum")]
+    InvalidRange,
+
+    #[error("No servo with this ID is configured")]
+    UnknownServo,
+
+    #[error("Only positional servos can be commanded with a position")]
+    WrongServoType,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServoConfig<C> {
     Positional {
         channel: (usize, C),
@@ -99,29 +136,177 @@ pub enum ServoConfig<C> {
 // IMPLS
 // ------------------------------------------------------------------------------------------------
 
-impl<D, S> ServoCtrl<D, S> 
-where 
+impl<D, S> ServoCtrl<D, S>
+where
     D: ServoDriver,
+    D::Channel: Copy,
     S: Eq + Hash
 {
     /// Create a new servo controller.
     ///
     /// ## Arguments
     /// - `drivers` - A vector of initialised [`ServoDriver`] boards
-    /// - `config` - A configuration for the servos managed by this controller
+    /// - `servo_config` - The configuration of each servo, whose channels index into `drivers`
     pub fn new(
-        config: ControllerConfig<S, D::Channel>
+        drivers: Vec<D>,
+        servo_config: HashMap<S, ServoConfig<D::Channel>>
     ) -> Result<Self, ServoError> {
 
-        todo!("ServoCtrl not currently implemented");
+        for config in servo_config.values() {
+            let (driver, min, max) = match *config {
+                ServoConfig::Positional { channel, min_angle_rad, max_angle_rad } =>
+                    (channel.0, min_angle_rad, max_angle_rad),
+                ServoConfig::Continuous { channel, min_speed_rads, max_speed_rads } =>
+                    (channel.0, min_speed_rads, max_speed_rads),
+            };
 
-        // Create drivers
+            if driver >= drivers.len() {
+                return Err(ServoError::InvalidDriverIndex(driver, drivers.len()))
+            }
 
-        // TODO: Check the config is valid
+            if min.is_nan() || max.is_nan() || min >= max {
+                return Err(ServoError::InvalidRange)
+            }
+        }
+
+        Ok(Self {
+            drivers,
+            servo_config_map: servo_config
+        })
+    }
+
+    /// Move a positional servo to the given angle, which is clamped to the servo's range.
+    pub fn set_position(&mut self, servo: &S, angle_rad: f64) -> Result<(), ServoError> {
+        match self.servo_config_map.get(servo) {
+            Some(&ServoConfig::Positional { channel, min_angle_rad, max_angle_rad }) => {
+                let duty_cycle = to_duty_cycle(angle_rad, min_angle_rad, max_angle_rad);
+                self.drivers[channel.0].set_duty_cycle(channel.1, duty_cycle)
+            },
+            Some(_) => Err(ServoError::WrongServoType),
+            None => Err(ServoError::UnknownServo)
+        }
+    }
+}
 
-        // Ok(Self {
-        //     drivers,
-        //     servo_config_map: config.servo_config
-        // })
+impl<D, S> ServoCtrl<D, S>
+where
+    D: PositionFeedback,
+    D::Channel: Copy,
+    S: Eq + Hash
+{
+    /// Get the measured angle of a positional servo.
+    pub fn get_position(&mut self, servo: &S) -> Result<f64, ServoError> {
+        match self.servo_config_map.get(servo) {
+            Some(&ServoConfig::Positional { channel, min_angle_rad, max_angle_rad }) => {
+                let duty_cycle = self.drivers[channel.0].get_position(channel.1)?;
+                Ok(min_angle_rad + duty_cycle * (max_angle_rad - min_angle_rad))
+            },
+            Some(_) => Err(ServoError::WrongServoType),
+            None => Err(ServoError::UnknownServo)
+        }
     }
-}
\ No newline at end of file
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Map a value in the range `min` to `max` onto a duty cycle, clamping it to the range.
+fn to_duty_cycle(value: f64, min: f64, max: f64) -> f64 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Driver which records the duty cycles set, and reports them back as the positions.
+    #[derive(Default)]
+    struct MockDriver {
+        duty_cycles: HashMap<u8, f64>,
+    }
+
+    impl ServoDriver for MockDriver {
+        type Channel = u8;
+
+        fn set_duty_cycle(&mut self, channel: u8, duty_cycle: f64) -> Result<(), ServoError> {
+            self.duty_cycles.insert(channel, duty_cycle);
+            Ok(())
+        }
+    }
+
+    impl PositionFeedback for MockDriver {
+        fn get_position(&mut self, channel: u8) -> Result<f64, ServoError> {
+            self.duty_cycles.get(&channel).copied().ok_or(ServoError::InvalidStatus)
+        }
+    }
+
+    fn ctrl() -> ServoCtrl<MockDriver, &'static str> {
+        let mut config = HashMap::new();
+        config.insert("elbow", ServoConfig::Positional {
+            channel: (1, 3),
+            min_angle_rad: -1.0,
+            max_angle_rad: 3.0,
+        });
+        config.insert("wheel", ServoConfig::Continuous {
+            channel: (0, 0),
+            min_speed_rads: -2.0,
+            max_speed_rads: 2.0,
+        });
+
+        ServoCtrl::new(vec![MockDriver::default(), MockDriver::default()], config).unwrap()
+    }
+
+    #[test]
+    fn test_position() {
+        let mut ctrl = ctrl();
+
+        ctrl.set_position(&"elbow", 0.0).unwrap();
+        assert_eq!(ctrl.drivers[1].duty_cycles[&3], 0.25);
+        assert_eq!(ctrl.get_position(&"elbow").unwrap(), 0.0);
+
+        // Clamped to the range
+        ctrl.set_position(&"elbow", 10.0).unwrap();
+        assert_eq!(ctrl.get_position(&"elbow").unwrap(), 3.0);
+        ctrl.set_position(&"elbow", -10.0).unwrap();
+        assert_eq!(ctrl.get_position(&"elbow").unwrap(), -1.0);
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let mut ctrl = ctrl();
+
+        assert!(matches!(ctrl.set_position(&"wheel", 0.0), Err(ServoError::WrongServoType)));
+        assert!(matches!(ctrl.get_position(&"wheel"), Err(ServoError::WrongServoType)));
+        assert!(matches!(ctrl.set_position(&"wrist", 0.0), Err(ServoError::UnknownServo)));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let mut config = HashMap::new();
+        config.insert("elbow", ServoConfig::Positional {
+            channel: (1, 0),
+            min_angle_rad: 0.0,
+            max_angle_rad: 1.0,
+        });
+        assert!(matches!(
+            ServoCtrl::new(vec![MockDriver::default()], config),
+            Err(ServoError::InvalidDriverIndex(1, 1))
+        ));
+
+        let mut config = HashMap::new();
+        config.insert("elbow", ServoConfig::Positional {
+            channel: (0, 0),
+            min_angle_rad: 1.0,
+            max_angle_rad: 1.0,
+        });
+        assert!(matches!(
+            ServoCtrl::new(vec![MockDriver::default()], config),
+            Err(ServoError::InvalidRange)
+        ));
+    }
+}
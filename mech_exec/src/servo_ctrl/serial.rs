@@ -0,0 +1,83 @@
+//! Opening serial ports for bus servos
+//!
+//! The port is put into raw mode at the given baud rate, with a read timeout so that a servo which
+//! doesn't reply can't hang the actuation loop.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{fs::File, io, time::Duration};
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Open the serial port at `path`, such as `/dev/ttyUSB0`.
+///
+/// Reads which wait longer than `timeout`, rounded up to a tenth of a second, return no data.
+#[cfg(target_os = "linux")]
+pub fn open(path: &str, baud: u32, timeout: Duration) -> io::Result<File> {
+    use std::{
+        fs::OpenOptions,
+        os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    };
+
+    let speed = match baud {
+        9600 => libc::B9600,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        1_000_000 => libc::B1000000,
+        2_000_000 => libc::B2000000,
+        3_000_000 => libc::B3000000,
+        4_000_000 => libc::B4000000,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported baud rate {}", baud),
+            ))
+        }
+    };
+
+    // VTIME is in tenths of a second, and zero would mean block forever
+    let timeout_ds = (timeout.as_millis() as f64 / 100.0).ceil().clamp(1.0, 255.0) as libc::cc_t;
+
+    let port = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+
+    // Safety: the termios struct is initialised by tcgetattr before it is read or modified, and
+    // the file descriptor stays open for the duration of these calls.
+    unsafe {
+        let fd = port.as_raw_fd();
+
+        let mut tty: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut tty) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        libc::cfmakeraw(&mut tty);
+        tty.c_cflag |= libc::CLOCAL | libc::CREAD;
+        tty.c_cc[libc::VMIN] = 0;
+        tty.c_cc[libc::VTIME] = timeout_ds;
+
+        if libc::cfsetspeed(&mut tty, speed) != 0
+            || libc::tcsetattr(fd, libc::TCSANOW, &tty) != 0
+            || libc::tcflush(fd, libc::TCIOFLUSH) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(port)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open(_path: &str, _baud: u32, _timeout: Duration) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Serial ports are only supported on Linux",
+    ))
+}
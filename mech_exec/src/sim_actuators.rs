@@ -0,0 +1,96 @@
+//! # Simulated Actuators
+//!
+//! Stands in for the servo hardware when mech_exec is run with `--sim-actuators`, so that the full
+//! rov_exec to mech_exec link can be run on a desktop. Each actuator is modelled as a first order
+//! lag towards its demand, and the modelled positions and speeds are published as the sensor data.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::eqpt::mech::{MechDems, MechSensData};
+use std::{collections::HashMap, hash::Hash, time::Instant};
+
+use crate::params::MechExecParams;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Simulated set of actuators.
+pub struct SimActuators {
+    /// Time constant of the position response
+    pos_time_const_s: f64,
+
+    /// Time constant of the speed response
+    speed_time_const_s: f64,
+
+    /// Time of the last update
+    last_update: Option<Instant>,
+
+    /// Current state of the actuators
+    state: MechSensData,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl SimActuators {
+    /// Create a new set of simulated actuators, all at rest at zero.
+    pub fn new(params: &MechExecParams) -> Self {
+        Self {
+            pos_time_const_s: params.sim_pos_time_const_s,
+            speed_time_const_s: params.sim_speed_time_const_s,
+            last_update: None,
+            state: MechSensData::default(),
+        }
+    }
+
+    /// Move the actuators towards the given demands over the time since the last update.
+    pub fn actuate(&mut self, dems: &MechDems, now: Instant) {
+        let dt_s = match self.last_update {
+            Some(t) => (now - t).as_secs_f64(),
+            None => 0.0,
+        };
+        self.last_update = Some(now);
+
+        step_lag(&mut self.state.pos_rad, &dems.pos_rad, dt_s, self.pos_time_const_s);
+        step_lag(
+            &mut self.state.speed_rads,
+            &dems.speed_rads,
+            dt_s,
+            self.speed_time_const_s,
+        );
+    }
+
+    /// Get the current state of the actuators as sensor data.
+    pub fn sens_data(&self) -> MechSensData {
+        self.state.clone()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Step each value in `state` towards its demand in `dems` with a first order lag.
+///
+/// Actuators which have never been demanded aren't added to the state, so they aren't reported.
+fn step_lag<K: Copy + Eq + Hash>(
+    state: &mut HashMap<K, f64>,
+    dems: &HashMap<K, f64>,
+    dt_s: f64,
+    time_const_s: f64,
+) {
+    // Exact discretisation of the lag, with a zero time constant meaning an ideal actuator
+    let frac = match time_const_s > 0.0 {
+        true => 1.0 - (-dt_s / time_const_s).exp(),
+        false => 1.0,
+    };
+
+    for (&id, &dem) in dems.iter() {
+        let value = state.entry(id).or_insert(0.0);
+        *value += (dem - *value) * frac;
+    }
+}
@@ -0,0 +1,104 @@
+//! # Steer Calibration
+//!
+//! Assembly tolerances mean that each steer servo's zero isn't quite straight ahead, and servos
+//! mounted the other way up turn the wrong way. Each steer axis therefore has an offset and an
+//! optional inversion, which are applied to incoming demands to get the actuator angle, and
+//! removed from outgoing sensor data.
+//!
+//! New offsets are captured with the `calibrate steer` TC, which jogs each axis until its wheel is
+//! mechanically straight and then sets the offsets so that the current positions are the zeros.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use comms_if::eqpt::mech::{ActId, MechDems, MechSensData};
+use std::collections::HashMap;
+
+use crate::params::MechExecParams;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Offsets and inversions of each steer axis.
+pub struct SteerCal {
+    /// Actuator angle at which each axis is straight ahead
+    offset_rad: HashMap<ActId, f64>,
+
+    /// -1 for inverted axes, +1 otherwise
+    sign: HashMap<ActId, f64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl SteerCal {
+    /// Create the calibration from the parameters.
+    ///
+    /// Axes missing from the parameters have no offset and aren't inverted.
+    pub fn new(params: &MechExecParams) -> Self {
+        let ids = ActId::str_ids();
+
+        Self {
+            offset_rad: ids
+                .iter()
+                .copied()
+                .zip(params.str_offset_rad.iter().copied())
+                .collect(),
+            sign: ids
+                .iter()
+                .copied()
+                .zip(params.str_invert.iter().map(|&i| if i { -1.0 } else { 1.0 }))
+                .collect(),
+        }
+    }
+
+    /// Convert demands from rover angles to actuator angles.
+    pub fn dems_to_actuator(&self, dems: &mut MechDems) {
+        for (id, pos_rad) in dems.pos_rad.iter_mut() {
+            *pos_rad = self.sign(id) * *pos_rad + self.offset(id);
+        }
+        for (id, speed_rads) in dems.speed_rads.iter_mut() {
+            *speed_rads *= self.sign(id);
+        }
+    }
+
+    /// Convert sensor data from actuator angles to rover angles.
+    pub fn sens_from_actuator(&self, sens: &mut MechSensData) {
+        for (id, pos_rad) in sens.pos_rad.iter_mut() {
+            *pos_rad = self.sign(id) * (*pos_rad - self.offset(id));
+        }
+        for (id, speed_rads) in sens.speed_rads.iter_mut() {
+            *speed_rads *= self.sign(id);
+        }
+    }
+
+    /// Take the steer positions in the given demands, which are in rover angles, as the zero of
+    /// each axis.
+    ///
+    /// After this the same actuator angles correspond to zero, so the demands should be replaced
+    /// by zeros to keep the axes where they are.
+    pub fn capture_zero(&mut self, dems: &MechDems) {
+        for id in ActId::str_ids() {
+            if let Some(&pos_rad) = dems.pos_rad.get(id) {
+                let actuator_rad = self.sign(id) * pos_rad + self.offset(id);
+                self.offset_rad.insert(*id, actuator_rad);
+            }
+        }
+    }
+
+    /// Get the offsets in `ActId::str_ids` order, as used in the parameters.
+    pub fn offsets(&self) -> Vec<f64> {
+        ActId::str_ids().iter().map(|id| self.offset(id)).collect()
+    }
+
+    fn offset(&self, id: &ActId) -> f64 {
+        self.offset_rad.get(id).copied().unwrap_or(0.0)
+    }
+
+    fn sign(&self, id: &ActId) -> f64 {
+        self.sign.get(id).copied().unwrap_or(1.0)
+    }
+}
@@ -0,0 +1,340 @@
+//! Compares two rov_exec sessions.
+//!
+//! Reads the cycle archive and log of each session and prints a report of key metrics side by
+//! side: cycle timing, the traverse, WheelRateCtrl errors and safe mode events. Useful for
+//! checking the effect of a parameter or algorithm change between two test drives:
+//!
+//! ```shell
+//! cargo run --bin analyze -- sessions/rov_exec_20211020_101500 sessions/rov_exec_20211020_113000
+//! ```
+//!
+//! Sessions can be given as paths, or as names within `$SUSF_PHOBOS_SW_ROOT/sessions`.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Report,
+};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::Path,
+};
+
+use rov_lib::{
+    cycle_arch::{self, CycleRecord},
+    CYCLE_PERIOD_S,
+};
+use util::session::find_session_dir;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Log message written when the rover enters safe mode, followed by the cause.
+const SAFE_MODE_LOG_MSG: &str = "Make safe requested, cause: ";
+
+/// Ground speed above which the rover is counted as moving.
+const MOVING_SPEED_MS: f64 = 0.01;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Metrics of a single session.
+struct SessionMetrics {
+    sections: Vec<(&'static str, Vec<Metric>)>,
+}
+
+/// A single metric, `None` if it couldn't be calculated for the session.
+struct Metric {
+    name: String,
+    unit: &'static str,
+    value: Option<f64>,
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+fn main() -> Result<(), Report> {
+    color_eyre::install()?;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 2 {
+        return Err(eyre!("Usage: analyze <session_a> <session_b>"));
+    }
+
+    let dir_a = find_session_dir(&args[0])
+        .ok_or_else(|| eyre!("Cannot find the session {:?}", args[0]))?;
+    let dir_b = find_session_dir(&args[1])
+        .ok_or_else(|| eyre!("Cannot find the session {:?}", args[1]))?;
+
+    let a = analyze(&dir_a).wrap_err_with(|| format!("Could not analyze {:?}", dir_a))?;
+    let b = analyze(&dir_b).wrap_err_with(|| format!("Could not analyze {:?}", dir_b))?;
+
+    println!("A: {}", dir_a.display());
+    println!("B: {}", dir_b.display());
+
+    print_report(&a, &b);
+
+    Ok(())
+}
+
+/// Calculate the metrics of the session in the given directory.
+fn analyze(dir: &Path) -> Result<SessionMetrics, Report> {
+    let records = cycle_arch::read(dir)
+        .wrap_err("Could not read the cycle archive, was the session recorded by rov_exec?")?;
+
+    if records.is_empty() {
+        return Err(eyre!("The session's cycle archive is empty"));
+    }
+
+    let log = fs::read_to_string(dir.join("rov_exec.log"))
+        .wrap_err("Could not read the session's log")?;
+
+    Ok(SessionMetrics {
+        sections: vec![
+            ("Cycle timing", cycle_timing(&records)),
+            ("Traverse", traverse(&records)),
+            ("WheelRateCtrl", rate_ctrl(&records)),
+            ("Safe mode", safe_mode(&records, &log)),
+        ],
+    })
+}
+
+fn cycle_timing(records: &[CycleRecord]) -> Vec<Metric> {
+    let mut durs_s: Vec<f64> = records.iter().map(|r| r.cycle_dur_s).collect();
+    durs_s.sort_by(|a, b| a.total_cmp(b));
+
+    let num_overruns = records.iter().filter(|r| r.overrun).count();
+
+    vec![
+        Metric::new("cycles", "", Some(records.len() as f64)),
+        Metric::new("mean duration", "ms", mean(&durs_s).map(|d| d * 1e3)),
+        Metric::new("median duration", "ms", percentile(&durs_s, 50.0).map(|d| d * 1e3)),
+        Metric::new("95th percentile duration", "ms", percentile(&durs_s, 95.0).map(|d| d * 1e3)),
+        Metric::new("99th percentile duration", "ms", percentile(&durs_s, 99.0).map(|d| d * 1e3)),
+        Metric::new("max duration", "ms", durs_s.last().map(|d| d * 1e3)),
+        Metric::new("period", "ms", Some(CYCLE_PERIOD_S * 1e3)),
+        Metric::new("overruns", "", Some(num_overruns as f64)),
+        Metric::new("overrun rate", "%", Some(100.0 * num_overruns as f64 / records.len() as f64)),
+    ]
+}
+
+fn traverse(records: &[CycleRecord]) -> Vec<Metric> {
+    let positions: Vec<(f64, f64, f64)> = records
+        .iter()
+        .filter_map(|r| match (r.pos_x_m_lm, r.pos_y_m_lm) {
+            (Some(x), Some(y)) => Some((r.time_s, x, y)),
+            _ => None,
+        })
+        .collect();
+
+    let duration_s = records[records.len() - 1].time_s - records[0].time_s;
+
+    let mut distance_m = 0.0;
+    let mut moving_time_s = 0.0;
+    for w in positions.windows(2) {
+        let (t0, x0, y0) = w[0];
+        let (t1, x1, y1) = w[1];
+        let step_m = (x1 - x0).hypot(y1 - y0);
+        let dt_s = t1 - t0;
+
+        distance_m += step_m;
+        if dt_s > 0.0 && step_m / dt_s > MOVING_SPEED_MS {
+            moving_time_s += dt_s;
+        }
+    }
+
+    let displacement_m = match (positions.first(), positions.last()) {
+        (Some(&(_, x0, y0)), Some(&(_, x1, y1))) if positions.len() > 1 => {
+            Some((x1 - x0).hypot(y1 - y0))
+        }
+        _ => None,
+    };
+
+    // Without localisation nothing about the traverse is known
+    let has_pose = positions.len() > 1;
+
+    vec![
+        Metric::new("session duration", "s", Some(duration_s)),
+        Metric::new(
+            "localised cycles",
+            "%",
+            Some(100.0 * positions.len() as f64 / records.len() as f64),
+        ),
+        Metric::new("distance travelled", "m", has_pose.then_some(distance_m)),
+        Metric::new("net displacement", "m", displacement_m),
+        Metric::new("time moving", "s", has_pose.then_some(moving_time_s)),
+        Metric::new(
+            "mean speed while moving",
+            "m/s",
+            (moving_time_s > 0.0).then(|| distance_m / moving_time_s),
+        ),
+    ]
+}
+
+fn rate_ctrl(records: &[CycleRecord]) -> Vec<Metric> {
+    // Rate errors are only meaningful when the controller was using measured rates
+    let errors: Vec<f64> = records
+        .iter()
+        .filter(|r| r.closed_loop)
+        .map(|r| r.max_rate_error_rads)
+        .collect();
+
+    let rms = match errors.is_empty() {
+        true => None,
+        false => Some((errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt()),
+    };
+
+    vec![
+        Metric::new(
+            "closed loop cycles",
+            "%",
+            Some(100.0 * errors.len() as f64 / records.len() as f64),
+        ),
+        Metric::new("RMS max rate error", "rad/s", rms),
+        Metric::new("peak rate error", "rad/s", errors.iter().cloned().reduce(f64::max)),
+    ]
+}
+
+fn safe_mode(records: &[CycleRecord], log: &str) -> Vec<Metric> {
+    // Time in safe mode, counting each cycle as lasting until the next one starts
+    let time_safe_s: f64 = records
+        .windows(2)
+        .filter(|w| w[0].safe)
+        .map(|w| w[1].time_s - w[0].time_s)
+        .sum();
+
+    // Entries by cause, from the log since the archive doesn't record the cause
+    let mut causes: BTreeMap<String, usize> = BTreeMap::new();
+    for line in log.lines() {
+        if let Some(idx) = line.find(SAFE_MODE_LOG_MSG) {
+            let cause = line[idx + SAFE_MODE_LOG_MSG.len()..].trim();
+            *causes.entry(cause.to_string()).or_default() += 1;
+        }
+    }
+
+    let mut metrics = vec![
+        Metric::new("entries", "", Some(causes.values().sum::<usize>() as f64)),
+        Metric::new("time safe", "s", Some(time_safe_s)),
+    ];
+
+    for (cause, num) in causes {
+        metrics.push(Metric::new(&format!("entries caused by {}", cause), "", Some(num as f64)));
+    }
+
+    metrics
+}
+
+/// Print the metrics of both sessions side by side with their difference.
+///
+/// Metrics which appear in only one session, such as a safe mode cause which only happened in
+/// one, are shown as zero in the other.
+fn print_report(a: &SessionMetrics, b: &SessionMetrics) {
+    println!(
+        "\n{:<36} {:>12} {:>12} {:>12} {:>9}",
+        "Metric", "A", "B", "B - A", "Change"
+    );
+
+    for (section, metrics_a) in a.sections.iter() {
+        let metrics_b = b
+            .sections
+            .iter()
+            .find(|(s, _)| s == section)
+            .map(|(_, m)| m.as_slice())
+            .unwrap_or(&[]);
+
+        println!("\n{}", section);
+
+        // Keep the order of A, then add anything only B has
+        let mut names: Vec<&str> = metrics_a.iter().map(|m| m.name.as_str()).collect();
+        for m in metrics_b {
+            if !names.contains(&m.name.as_str()) {
+                names.push(&m.name);
+            }
+        }
+
+        for name in names {
+            let find =
+                |metrics: &[Metric]| metrics.iter().find(|m| m.name == name).map(|m| m.value);
+            let (va, vb) = match (find(metrics_a), find(metrics_b)) {
+                (Some(va), Some(vb)) => (va, vb),
+                (Some(va), None) => (va, Some(0.0)),
+                (None, Some(vb)) => (Some(0.0), vb),
+                (None, None) => continue,
+            };
+
+            let unit = metrics_a
+                .iter()
+                .chain(metrics_b)
+                .find(|m| m.name == name)
+                .map(|m| m.unit)
+                .unwrap_or("");
+            let label = match unit.is_empty() {
+                true => name.to_string(),
+                false => format!("{} [{}]", name, unit),
+            };
+
+            let diff = match (va, vb) {
+                (Some(va), Some(vb)) => Some(vb - va),
+                _ => None,
+            };
+            let change = match (va, diff) {
+                (Some(va), Some(d)) if va != 0.0 => format!("{:+.1}%", 100.0 * d / va.abs()),
+                _ => String::from("-"),
+            };
+
+            println!(
+                "  {:<34} {:>12} {:>12} {:>12} {:>9}",
+                label,
+                fmt_value(va),
+                fmt_value(vb),
+                diff.map(|d| format!("{:+.3}", d)).unwrap_or_else(|| String::from("-")),
+                change
+            );
+        }
+    }
+}
+
+fn fmt_value(value: Option<f64>) -> String {
+    match value {
+        Some(v) => format!("{:.3}", v),
+        None => String::from("-"),
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    match values.is_empty() {
+        true => None,
+        false => Some(values.iter().sum::<f64>() / values.len() as f64),
+    }
+}
+
+/// Get a percentile of sorted values, using the nearest rank.
+fn percentile(sorted: &[f64], pct: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl Metric {
+    fn new(name: &str, unit: &'static str, value: Option<f64>) -> Self {
+        Self {
+            name: String::from(name),
+            unit,
+            value,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Exports the TC and TM dictionary.
+//!
+//! Writes `dictionary.json`, `tc_dictionary.csv` and `tm_dictionary.csv` into the directory given
+//! as the only argument, or the current directory if there isn't one:
+//!
+//! ```shell
+//! cargo run --bin export_dict -- dict/
+//! ```
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use color_eyre::{eyre::WrapErr, Report};
+use std::{env, path::PathBuf};
+
+use rov_lib::{data_store::DataStore, tm_server::TmPacket};
+use util::dict::Dictionary;
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+fn main() -> Result<(), Report> {
+    let out_dir: PathBuf = env::args().nth(1).unwrap_or_else(|| ".".into()).into();
+    std::fs::create_dir_all(&out_dir).wrap_err("Could not create the output directory")?;
+
+    // A packet from a fresh data store is enough to get every field
+    let sample = TmPacket::from_datastore(&DataStore::default(), "");
+    let dict = Dictionary::new(&sample).wrap_err("Could not generate the dictionary")?;
+
+    dict.write_json(out_dir.join("dictionary.json"))
+        .wrap_err("Could not write the JSON dictionary")?;
+    dict.write_csv(&out_dir)
+        .wrap_err("Could not write the CSV dictionary")?;
+
+    println!(
+        "Exported {} TCs and {} TM fields to {:?}",
+        dict.tc.len(),
+        dict.tm.len(),
+        out_dir
+    );
+
+    Ok(())
+}
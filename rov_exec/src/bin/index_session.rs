@@ -0,0 +1,60 @@
+//! Indexes finished rov_exec sessions.
+//!
+//! Writes `index.json` into each session given, aligning the session's archives, logs and images
+//! by cycle, see [`rov_lib::session_index`]:
+//!
+//! ```shell
+//! cargo run --bin index_session -- rov_exec_20211020_101500
+//! ```
+//!
+//! Sessions can be given as paths, or as names within `$SUSF_PHOBOS_SW_ROOT/sessions`.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Report,
+};
+use std::env;
+
+use rov_lib::session_index::SessionIndex;
+use util::session::find_session_dir;
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+fn main() -> Result<(), Report> {
+    color_eyre::install()?;
+
+    let sessions: Vec<String> = env::args().skip(1).collect();
+    if sessions.is_empty() {
+        return Err(eyre!("Usage: index_session <session>..."));
+    }
+
+    for session in sessions.iter() {
+        let dir = find_session_dir(session)
+            .ok_or_else(|| eyre!("Cannot find the session {:?}", session))?;
+
+        let index = SessionIndex::build(&dir)
+            .wrap_err_with(|| format!("Could not index {:?}", dir))?;
+        let path = index
+            .save(&dir)
+            .wrap_err_with(|| format!("Could not save the index of {:?}", dir))?;
+
+        println!(
+            "Indexed {} cycles, {} archives ({} unaligned), {} logs and {} images into {:?}",
+            index.cycles.len(),
+            index.archives.len(),
+            index.unaligned_archives.len(),
+            index.logs.len(),
+            index.cycles.iter().map(|c| c.entry.images.len()).sum::<usize>()
+                + index.startup.images.len(),
+            path
+        );
+    }
+
+    Ok(())
+}
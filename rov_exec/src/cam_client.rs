@@ -8,6 +8,8 @@
 
 use std::collections::HashMap;
 
+use log::info;
+
 use comms_if::{
     eqpt::cam::*, 
     net::{MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, zmq}
@@ -65,7 +67,10 @@ pub enum CamClientError {
     NonUtf8Response,
 
     #[error("Expected a set of frames from the camera server but got a different response instead")]
-    ExpectedFrames
+    ExpectedFrames,
+
+    #[error("The camera server rejected the camera control settings")]
+    CameraControlRejected
 
 }
 
@@ -106,6 +111,19 @@ impl CamClient {
         })
     }
 
+    /// Close and reopen the socket to the server.
+    ///
+    /// Any request still awaiting a response is abandoned.
+    pub fn reconnect(
+        &mut self,
+        ctx: &zmq::Context,
+        params: &NetParams,
+    ) -> Result<(), CamClientError> {
+        *self = Self::new(ctx, params)?;
+
+        Ok(())
+    }
+
     /// Send request for images.
     ///
     /// Sending a request while still waiting on the response to a previous request will result in
@@ -146,12 +164,48 @@ impl CamClient {
         Ok(())
     }
 
+    /// Send a request to change a camera's imaging settings.
+    ///
+    /// The server's response is recieved by [`CamClient::recieve_frames`] in the same way as a
+    /// response to a frame request. Sending a request while still waiting on the response to a
+    /// previous request will result in an error.
+    pub fn request_camera_control(
+        &mut self,
+        control: CameraControl
+    ) -> Result<(), CamClientError> {
+        // If not connected return an error
+        if !self.socket.connected() {
+            return Err(CamClientError::NotConnected)
+        }
+
+        // If still waiting return an error
+        if self.awaiting_response {
+            return Err(CamClientError::WaitingForResponse)
+        }
+
+        // Serialize the request
+        let request_str = serde_json::to_string(&CamRequest::CameraControl(control))
+            .map_err(CamClientError::SerializationError)?;
+
+        // Send the request
+        self.socket.send(&request_str, 0)
+            .map_err(CamClientError::SendError)?;
+
+        // Set the awaiting response flag
+        self.awaiting_response = true;
+
+        Ok(())
+    }
+
     /// Receive the frames in response to a request.
     ///
     /// Returns a hashmap of `CamId`s to `CamFrames`s, or `None` if no response was recieved within
     /// the client's `recv_timeout`.
     ///
     /// Receiving images while not awaiting a response to a request will result in an error.
+    ///
+    /// If the response is to a camera control request `None` is returned once the server has
+    /// accepted the settings.
     pub fn recieve_frames(&mut self) -> Result<Option<HashMap<CamId, CamFrame>>, CamClientError> {
         // If not connected return an error
         // TODO: Reset the await flag?
@@ -186,6 +240,11 @@ impl CamClient {
         // Check that the response is a `Frames` object
         match response {
             CamResponse::Frames(m) => Ok(Some(m)),
+            CamResponse::CameraControlAccepted => {
+                info!("Camera control settings accepted");
+                Ok(None)
+            },
+            CamResponse::CameraControlRejected => Err(CamClientError::CameraControlRejected),
             _ => Err(CamClientError::ExpectedFrames)
         }
     }
@@ -0,0 +1,96 @@
+//! # Crash Handler
+//!
+//! If the rover executable panics the mechanisms server would keep actuating the last demands
+//! until they expire. The crash handler installs a panic hook which instead stops the mechanisms
+//! straight away, using a [`MechStopper`], and flushes the session log before the process dies.
+//!
+//! The same stop is sent by the [`StopGuard`] when `main` returns, so that an error exit also
+//! leaves the rover stopped, and when the process is interrupted by SIGINT or SIGTERM, for example
+//! by Ctrl-C or by the service manager.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use log::{error, info, warn};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::{panic, process, sync::Arc, thread};
+
+use crate::mech_client::MechStopper;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Stops the mechanisms when dropped.
+pub struct StopGuard {
+    stopper: Arc<MechStopper>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl Drop for StopGuard {
+    fn drop(&mut self) {
+        match self.stopper.stop() {
+            Ok(()) => info!("Stop sent to the MechServer"),
+            Err(e) => warn!("Could not send the stop to the MechServer: {}", e),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Install the panic hook and the signal handler, returning a guard which also stops the
+/// mechanisms when dropped.
+///
+/// The hook runs before the default one, which still prints the panic message.
+pub fn install(stopper: MechStopper) -> std::io::Result<StopGuard> {
+    let stopper = Arc::new(stopper);
+    let hook_stopper = stopper.clone();
+    let signal_stopper = stopper.clone();
+
+    // Signals are handled in their own thread rather than in the signal handler itself, since
+    // sending the stop and logging aren't async-signal-safe
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            warn!("Rover executable interrupted by signal {}", signal);
+
+            // The main loop keeps running until the exit below, but the client refuses to send
+            // demands once the stop has been sent, so it can't undo the stop
+            match signal_stopper.stop() {
+                Ok(()) => warn!("Stop sent to the MechServer"),
+                Err(e) => error!("Could not send the stop to the MechServer: {}", e),
+            }
+
+            log::logger().flush();
+
+            // Exit with the conventional status for a process killed by the signal
+            process::exit(128 + signal);
+        }
+    });
+
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        error!("Rover executable panicked: {}", info);
+
+        match hook_stopper.stop() {
+            Ok(()) => error!("Stop sent to the MechServer"),
+            Err(e) => error!("Could not send the stop to the MechServer: {}", e),
+        }
+
+        log::logger().flush();
+
+        default_hook(info);
+    }));
+
+    Ok(StopGuard { stopper })
+}
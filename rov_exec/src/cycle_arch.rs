@@ -0,0 +1,145 @@
+//! # Cycle Archive
+//!
+//! Archives a summary of every cycle to `arch/cycle.csv` in the session directory: how long the
+//! cycle took, whether the rover was safe, where it was and how well the wheel rates were tracked.
+//! The `analyze` tool reads this archive back to compare sessions.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Internal
+use crate::{data_store::DataStore, CYCLE_PERIOD_S};
+use comms_if::tm::TmMeta;
+use util::{
+    archive::{ArchiveReadError, ArchiveReader, Archiver},
+    session::Session,
+};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Path of the cycle archive relative to the session's archive root.
+pub const CYCLE_ARCH_PATH: &str = "cycle.csv";
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Writes a [`CycleRecord`] for each cycle.
+pub struct CycleArchive {
+    archiver: Archiver,
+}
+
+/// A row of the cycle archive.
+#[derive(Debug, Clone, Serialize, Deserialize, TmMeta)]
+pub struct CycleRecord {
+    /// Number of the cycle since the start of the session
+    pub cycle: u64,
+
+    /// Time since the start of the session at the start of the cycle
+    #[tm(unit = "s")]
+    pub time_s: f64,
+
+    /// Time taken to process the cycle, not including the sleep until the next one
+    #[tm(unit = "s")]
+    pub cycle_dur_s: f64,
+
+    /// True if the cycle took longer than the cycle period
+    pub overrun: bool,
+
+    /// True if the rover was in safe mode at the end of the cycle
+    pub safe: bool,
+
+    /// Position of the rover in the LM frame, if localisation was available
+    #[tm(unit = "m")]
+    pub pos_x_m_lm: Option<f64>,
+
+    /// Position of the rover in the LM frame, if localisation was available
+    #[tm(unit = "m")]
+    pub pos_y_m_lm: Option<f64>,
+
+    /// True if WheelRateCtrl trimmed the drive demands using measured rates
+    pub closed_loop: bool,
+
+    /// Largest absolute drive rate error from WheelRateCtrl
+    #[tm(unit = "rad/s")]
+    pub max_rate_error_rads: f64,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum CycleArchError {
+    #[error("Could not create the cycle archive: {0}")]
+    InitError(String),
+
+    #[error("Could not write to the cycle archive: {0}")]
+    WriteError(String),
+
+    #[error("Could not read the cycle archive: {0}")]
+    ReadError(ArchiveReadError),
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl CycleArchive {
+    /// Create the cycle archive in the given session.
+    pub fn new(session: &Session) -> Result<Self, CycleArchError> {
+        let archiver = Archiver::from_path_with_meta::<CycleRecord, _>(session, CYCLE_ARCH_PATH)
+            .map_err(|e| CycleArchError::InitError(e.to_string()))?;
+
+        Ok(Self { archiver })
+    }
+
+    /// Archive the cycle which has just been processed, which took `cycle_dur_s`.
+    pub fn write(&mut self, ds: &DataStore, cycle_dur_s: f64) -> Result<(), CycleArchError> {
+        self.archiver
+            .serialise(CycleRecord::from_datastore(ds, cycle_dur_s))
+            .map_err(|e| CycleArchError::WriteError(e.to_string()))
+    }
+}
+
+impl CycleRecord {
+    /// Build the record of the current cycle from the datastore.
+    pub fn from_datastore(ds: &DataStore, cycle_dur_s: f64) -> Self {
+        let rate_rpt = &ds.loco.wheel_rate_ctrl_status_rpt;
+
+        Self {
+            cycle: ds.hk.num_cycles as u64,
+            time_s: ds.hk.sim_time_s,
+            cycle_dur_s,
+            overrun: cycle_dur_s > CYCLE_PERIOD_S,
+            safe: ds.safety.is_safe(),
+            pos_x_m_lm: ds.auto.rov_pose_lm.as_ref().map(|p| p.position_m_lm[0]),
+            pos_y_m_lm: ds.auto.rov_pose_lm.as_ref().map(|p| p.position_m_lm[1]),
+            closed_loop: rate_rpt.closed_loop,
+            max_rate_error_rads: rate_rpt
+                .rate_error_rads
+                .iter()
+                .fold(0.0, |max: f64, e| max.max(e.abs())),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Read every record from the cycle archive of the session in `session_dir`.
+pub fn read<P: AsRef<Path>>(session_dir: P) -> Result<Vec<CycleRecord>, CycleArchError> {
+    ArchiveReader::open_in_session(session_dir, CYCLE_ARCH_PATH)
+        .map_err(CycleArchError::ReadError)?
+        .records()
+        .collect::<Result<Vec<CycleRecord>, _>>()
+        .map_err(CycleArchError::ReadError)
+}
@@ -1,10 +1,19 @@
 //! # Data Store
+//!
+//! The data store is split into sub-stores by area (housekeeping, safety, locomotion, mechanisms,
+//! autonomy and checkout), so that one area can be borrowed mutably while another is read, and so
+//! that each area can clear its own per-cycle data.
 
-use comms_if::eqpt::{cam::CamImage, mech::MechDems};
+use chrono::{DateTime, Utc};
+use comms_if::{eqpt::{cam::{CamImage, CameraControl}, mech::{ActId, ArmFault, MechDems, MechSensData}}, tc::eqpt::Eqpt, tm::latency::TcStamps};
 use log::{info, warn};
+use std::collections::HashMap;
 use util::session::Session;
 
-use crate::{arm_ctrl, loc::Pose, loco_ctrl};
+use crate::{
+    arm_ctrl, drawbar_test, loc::Pose, loco_ctrl, mast_ctrl, mode_mgr::ModeManager,
+    path_store::PathStore, self_test, turn_cal, wheel_rate_ctrl,
+};
 
 // ---------------------------------------------------------------------------
 // ENUMS
@@ -25,7 +34,22 @@ pub enum SafeModeCause {
 /// Global data store for the executable.
 #[derive(Default)]
 pub struct DataStore {
-    // Cycle management
+    pub hk: Housekeeping,
+
+    pub safety: Safety,
+
+    pub loco: Locomotion,
+
+    pub mech: Mechanisms,
+
+    pub auto: Autonomy,
+
+    pub checkout: Checkout,
+}
+
+/// Cycle management and monitoring.
+#[derive(Default)]
+pub struct Housekeeping {
     /// Number of cycles already executed
     pub num_cycles: u128,
 
@@ -35,21 +59,57 @@ pub struct DataStore {
     /// Simulation elapsed time
     pub sim_time_s: f64,
 
-    // Safe mode variables
+    /// UTC time at which the cycle started, before the cycle's sensor data was read
+    pub cycle_start_utc: DateTime<Utc>,
+
+    /// Latency stamps of the last TC recieved from the ground
+    pub last_tc_stamps: Option<TcStamps>,
+
+    /// Number of consecutive cycle overruns
+    pub num_consec_cycle_overruns: u64,
+
+    /// Number of cycles in which the per-cycle TC budget was used up with more TCs still waiting
+    pub num_tc_budget_overflows: u64,
+
+    /// Number of TC messages rejected for being too large
+    pub num_tc_oversized: u64,
+
+    /// Equipment to reconnect this cycle, requested by TC
+    pub eqpt_reconnect: Vec<Eqpt>,
+
+    /// Random ID of this session, see `util::session::get_session_id`
+    pub session_id: String,
+
+    /// ID of this build, see `util::build_info::build_id`
+    pub build_id: String,
+}
+
+/// Safe mode and the operational mode.
+///
+/// Safe mode can only be changed through `DataStore::make_safe` and `DataStore::make_unsafe`,
+/// which also make the control modules safe.
+#[derive(Default)]
+pub struct Safety {
     /// Determines if the rover is in safe mode.
-    pub safe: bool,
+    safe: Tracked<bool>,
 
     /// Gives the reason for the rover being in safe mode.
-    pub safe_cause: Option<SafeModeCause>,
-    pub safe_cause_string: String,
+    cause: Option<SafeModeCause>,
+    cause_string: String,
 
-    // Camera images
-    pub left_cam_image: Option<CamImage>,
-    pub right_cam_image: Option<CamImage>,
+    /// Operational mode of the rover
+    pub mode_mgr: ModeManager,
 
-    // Localisation
-    pub rov_pose_lm: Option<Pose>,
+    /// Number of consecutive cycles without sensor data from the mechanisms server
+    pub num_consec_mech_recv_errors: u64,
+
+    /// Number of consecutive errors recieving images from the camera server
+    pub num_consec_cam_errors: u64,
+}
 
+/// Locomotion control, from manouvre commands to wheel demands.
+#[derive(Default)]
+pub struct Locomotion {
     // LocoCtrl
     pub loco_ctrl: loco_ctrl::LocoCtrl,
     pub loco_ctrl_input: loco_ctrl::InputData,
@@ -57,6 +117,32 @@ pub struct DataStore {
     pub loco_ctrl_status_rpt: loco_ctrl::StatusReport,
     pub loco_params: loco_ctrl::Params,
 
+    // WheelRateCtrl
+    pub wheel_rate_ctrl: wheel_rate_ctrl::WheelRateCtrl,
+    pub wheel_rate_ctrl_input: wheel_rate_ctrl::InputData,
+    pub wheel_rate_ctrl_output: MechDems,
+    pub wheel_rate_ctrl_status_rpt: wheel_rate_ctrl::StatusReport,
+
+    // Steer calibration
+    /// Steer positions demanded during steer calibration, or `None` if not calibrating
+    pub steer_cal_pos_rad: Option<HashMap<ActId, f64>>,
+
+    /// If true the steer zeros are captured on this cycle, ending calibration
+    pub steer_cal_capture: bool,
+}
+
+/// The mechanisms server link, and the arm and mast.
+#[derive(Default)]
+pub struct Mechanisms {
+    /// Sensor data received from the mechanisms server on this cycle
+    pub sens_data: Option<MechSensData>,
+
+    /// The arm fault last reported by the mechanisms server, if any
+    pub arm_fault: Tracked<Option<ArmFault>>,
+
+    /// True if the mechanisms server's acknowledgement of the demands is stale
+    pub dems_ack_stale: Tracked<bool>,
+
     // ArmCtrl
     pub arm_ctrl: arm_ctrl::ArmCtrl,
     pub arm_ctrl_input: arm_ctrl::InputData,
@@ -64,12 +150,55 @@ pub struct DataStore {
     pub arm_ctrl_status_rpt: arm_ctrl::StatusReport,
     pub arm_params: arm_ctrl::Params,
 
-    // Monitoring Counters
-    /// Number of consecutive cycle overruns
-    pub num_consec_cycle_overruns: u64,
+    // MastCtrl
+    pub mast_ctrl: mast_ctrl::MastCtrl,
+    pub mast_ctrl_input: mast_ctrl::InputData,
+    pub mast_ctrl_output: MechDems,
+    pub mast_ctrl_status_rpt: mast_ctrl::StatusReport,
+}
 
-    /// Number of consecutive mechanisms client recieve errors
-    pub num_consec_mech_recv_errors: u64,
+/// Perception and navigation data.
+#[derive(Default)]
+pub struct Autonomy {
+    // Camera images
+    pub left_cam_image: Option<CamImage>,
+    pub right_cam_image: Option<CamImage>,
+
+    /// Camera control settings waiting to be sent to the camera server
+    pub cam_control: Option<CameraControl>,
+
+    // Localisation
+    pub rov_pose_lm: Option<Pose>,
+
+    // Path files
+    pub path_store: PathStore,
+}
+
+/// Test modes and checkouts.
+#[derive(Default)]
+pub struct Checkout {
+    // Drawbar test mode
+    pub drawbar_test: drawbar_test::DrawbarTest,
+    pub drawbar_input: drawbar_test::InputData,
+    pub drawbar_status_rpt: drawbar_test::StatusReport,
+
+    // Self test
+    pub self_test: self_test::SelfTest,
+    pub self_test_input: self_test::InputData,
+    pub self_test_output: self_test::OutputData,
+    pub self_test_status_rpt: self_test::StatusReport,
+
+    // Turning circle calibration
+    pub turn_cal: turn_cal::TurnCal,
+    pub turn_cal_input: turn_cal::InputData,
+    pub turn_cal_status_rpt: turn_cal::StatusReport,
+}
+
+/// A value which records whether it was changed on the current cycle.
+#[derive(Default)]
+pub struct Tracked<T> {
+    value: T,
+    changed: bool,
 }
 
 // ---------------------------------------------------------------------------
@@ -79,22 +208,22 @@ pub struct DataStore {
 impl DataStore {
     /// Puts the rover into safe mode with the given cause.
     pub fn make_safe(&mut self, cause: SafeModeCause) {
-        if !self.safe {
-            warn!("Make safe requested, cause: {:?}", cause);
-            self.safe = true;
-            self.safe_cause = Some(cause);
-
-            if cause == SafeModeCause::MakeSafeTc {
-                self.safe_cause_string = String::from("Safe telecommand");
-            } else if cause == SafeModeCause::TcClientNotConnected {
-                self.safe_cause_string = String::from("TC client not connected");
-            } else if cause == SafeModeCause::MechClientNotConnected {
-                self.safe_cause_string = String::from("Mech client not connected");
-            }
+        if !self.safety.make_safe(cause) {
+            return;
+        }
+
+        // Make loco_ctrl safe
+        self.loco.loco_ctrl.make_safe();
+        self.loco.wheel_rate_ctrl.make_safe();
 
-            // Make loco_ctrl safe
-            self.loco_ctrl.make_safe();
+        // Hold the mast where it is
+        self.mech.mast_ctrl.make_safe();
+
+        // Abandon any steer calibration
+        if self.loco.steer_cal_pos_rad.take().is_some() {
+            warn!("Steer calibration aborted");
         }
+        self.loco.steer_cal_capture = false;
     }
 
     /// Attempts to disable the safe mode by clearing the given cause.
@@ -105,25 +234,92 @@ impl DataStore {
     ///
     /// If safe mode was not enabled `Ok(())` is returned
     pub fn make_unsafe(&mut self, cause: SafeModeCause) -> Result<(), ()> {
-        if !self.safe {
+        self.safety.make_unsafe(cause)
+    }
+
+    /// Perform actions required at the start of a cycle.
+    ///
+    /// Clears those items that need clearing at the start of a cycle, and sets the 1Hz cycle flag.
+    pub fn cycle_start(&mut self, cycle_frequency_hz: f64) {
+        self.hk.cycle_start(cycle_frequency_hz);
+        self.safety.cycle_start();
+        self.loco.cycle_start();
+        self.mech.cycle_start();
+        self.checkout.cycle_start();
+    }
+}
+
+impl Housekeeping {
+    fn cycle_start(&mut self, cycle_frequency_hz: f64) {
+        if self.num_cycles % (cycle_frequency_hz as u128) == 0 {
+            self.is_1_hz_cycle = true;
+        } else {
+            self.is_1_hz_cycle = false;
+        }
+
+        self.sim_time_s = util::session::get_elapsed_seconds();
+        self.cycle_start_utc = Utc::now();
+    }
+}
+
+impl Safety {
+    /// True if the rover is in safe mode.
+    pub fn is_safe(&self) -> bool {
+        *self.safe.get()
+    }
+
+    /// True if safe mode was entered or left on this cycle.
+    pub fn safe_changed(&self) -> bool {
+        self.safe.changed()
+    }
+
+    /// The reason the rover is in safe mode, if it is.
+    pub fn cause(&self) -> Option<SafeModeCause> {
+        self.cause
+    }
+
+    /// Description of the reason the rover is in safe mode, which is empty if it isn't.
+    pub fn cause_string(&self) -> &str {
+        &self.cause_string
+    }
+
+    /// Enter safe mode with the given cause, returning true if the rover wasn't already safe.
+    fn make_safe(&mut self, cause: SafeModeCause) -> bool {
+        if self.is_safe() {
+            return false;
+        }
+
+        warn!("Make safe requested, cause: {:?}", cause);
+        self.safe.set(true);
+        self.cause = Some(cause);
+
+        self.cause_string = String::from(match cause {
+            SafeModeCause::MakeSafeTc => "Safe telecommand",
+            SafeModeCause::TcClientNotConnected => "TC client not connected",
+            SafeModeCause::MechClientNotConnected => "Mech client not connected",
+        });
+
+        self.mode_mgr.make_safe();
+
+        true
+    }
+
+    fn make_unsafe(&mut self, cause: SafeModeCause) -> Result<(), ()> {
+        if !self.is_safe() {
             return Ok(());
         }
 
-        match self.safe_cause {
+        match self.cause {
             Some(root_cause) => {
                 if cause == root_cause {
-                    self.safe = false;
-                    self.safe_cause = None;
-                    self.safe_cause_string = String::from("");
+                    self.safe.set(false);
+                    self.cause = None;
+                    self.cause_string = String::from("");
                     info!("Make unsafe requested, root cause match, safe mode disabled");
+
+                    self.mode_mgr.clear_safe(cause == SafeModeCause::MakeSafeTc);
                     Ok(())
                 } else {
-                    // info!(
-                    //     "Make unsafe requested, root cause ({:?}) differs from response ({:?}), \
-                    //     rejected",
-                    //     root_cause,
-                    //     cause
-                    // );
                     Err(())
                 }
             }
@@ -131,23 +327,70 @@ impl DataStore {
         }
     }
 
-    /// Perform actions required at the start of a cycle.
-    ///
-    /// Clears those items that need clearing at the start of a cycle, and sets the 1Hz cycle flag.
-    pub fn cycle_start(&mut self, cycle_frequency_hz: f64) {
-        if self.num_cycles % (cycle_frequency_hz as u128) == 0 {
-            self.is_1_hz_cycle = true;
-        } else {
-            self.is_1_hz_cycle = false;
-        }
+    fn cycle_start(&mut self) {
+        self.safe.clear_changed();
+    }
+}
 
+impl Locomotion {
+    fn cycle_start(&mut self) {
         self.loco_ctrl_input = loco_ctrl::InputData::default();
         self.loco_ctrl_output = MechDems::empty_loco();
         self.loco_ctrl_status_rpt = loco_ctrl::StatusReport::default();
 
+        self.wheel_rate_ctrl_input = wheel_rate_ctrl::InputData::default();
+        self.wheel_rate_ctrl_output = MechDems::empty_loco();
+        self.wheel_rate_ctrl_status_rpt = wheel_rate_ctrl::StatusReport::default();
+    }
+}
+
+impl Mechanisms {
+    fn cycle_start(&mut self) {
+        self.sens_data = None;
+        self.arm_fault.clear_changed();
+        self.dems_ack_stale.clear_changed();
+
         self.arm_ctrl_input = arm_ctrl::InputData::default();
         self.arm_ctrl_status_rpt = arm_ctrl::StatusReport::default();
 
-        self.sim_time_s = util::session::get_elapsed_seconds();
+        self.mast_ctrl_input = mast_ctrl::InputData::default();
+    }
+}
+
+impl Checkout {
+    fn cycle_start(&mut self) {
+        self.drawbar_input = drawbar_test::InputData::default();
+
+        self.self_test_input = self_test::InputData::default();
+
+        self.turn_cal_input = turn_cal::InputData::default();
+    }
+}
+
+impl<T: PartialEq> Tracked<T> {
+    /// Get the value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Set the value, returning true if it's different to the current value.
+    pub fn set(&mut self, value: T) -> bool {
+        if value == self.value {
+            return false;
+        }
+
+        self.value = value;
+        self.changed = true;
+
+        true
+    }
+
+    /// True if the value was changed on this cycle.
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    fn clear_changed(&mut self) {
+        self.changed = false;
     }
 }
@@ -0,0 +1,274 @@
+//! # Drawbar Test Mode
+//!
+//! Executes constant speed straight line runs for traction characterisation, archiving the
+//! demanded and measured motion of the rover to `arch/drawbar/runs.csv` in the session directory.
+//! Each row is tagged with a run number so several runs can be made in one session.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+// Internal
+use crate::loc::Pose;
+use comms_if::{
+    eqpt::mech::{ActId, MechDems},
+    tc::{drawbar::DrawbarCmd, loco_ctrl::MnvrCmd},
+    tm::TmMeta,
+    units::{Curvature, MetersPerSec, Radians},
+};
+use util::{archive::Archiver, module::State, session::Session};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Drawbar test mode state
+#[derive(Default)]
+pub struct DrawbarTest {
+    /// The run currently in progress, if any
+    run: Option<Run>,
+
+    /// Number of runs started in this session
+    num_runs: u32,
+
+    /// Time and pose from the previous cycle, used to estimate ground speed
+    last_pose: Option<(f64, Pose)>,
+
+    archiver: Archiver,
+}
+
+/// Input data to the drawbar test mode.
+#[derive(Default)]
+pub struct InputData {
+    /// The command to execute, or `None` if there is no new command on this cycle.
+    pub cmd: Option<DrawbarCmd>,
+
+    /// True if the rover is in safe mode, which aborts any run.
+    pub safe: bool,
+
+    /// Current time in seconds
+    pub time_s: f64,
+
+    /// Current pose of the rover, if known
+    pub pose: Option<Pose>,
+}
+
+/// Status report for the drawbar test mode.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, TmMeta)]
+pub struct StatusReport {
+    /// True while a run is in progress
+    pub running: bool,
+
+    /// Number of the current (or last) run
+    pub run: u32,
+}
+
+/// A single constant speed run.
+struct Run {
+    speed_ms: f64,
+    end_time_s: f64,
+}
+
+/// A row of the runs archive.
+#[derive(Serialize, TmMeta)]
+struct Record {
+    run: u32,
+    #[tm(unit = "s")]
+    time_s: f64,
+    #[tm(unit = "m/s")]
+    dem_speed_ms: f64,
+    #[tm(unit = "rad/s")]
+    dem_drv_fl_rads: Option<f64>,
+    #[tm(unit = "rad/s")]
+    dem_drv_ml_rads: Option<f64>,
+    #[tm(unit = "rad/s")]
+    dem_drv_rl_rads: Option<f64>,
+    #[tm(unit = "rad/s")]
+    dem_drv_fr_rads: Option<f64>,
+    #[tm(unit = "rad/s")]
+    dem_drv_mr_rads: Option<f64>,
+    #[tm(unit = "rad/s")]
+    dem_drv_rr_rads: Option<f64>,
+    #[tm(unit = "m")]
+    pos_x_m_lm: Option<f64>,
+    #[tm(unit = "m")]
+    pos_y_m_lm: Option<f64>,
+    #[tm(unit = "m")]
+    pos_z_m_lm: Option<f64>,
+    #[tm(unit = "m/s")]
+    ground_speed_ms: Option<f64>,
+    #[tm(desc = "Slip ratio, 1 - ground speed / demanded speed")]
+    slip: Option<f64>,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Possible errors that can occur in the drawbar test mode.
+#[derive(Debug, thiserror::Error)]
+pub enum DrawbarTestError {
+    #[error("Could not create the drawbar archive: {0}")]
+    ArchiveInitError(String),
+
+    #[error("Invalid drawbar run: {0}")]
+    InvalidRun(String),
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl State for DrawbarTest {
+    type InitData = ();
+    type InitError = DrawbarTestError;
+
+    type InputData = InputData;
+    /// A manouvre for LocoCtrl, if one needs to be sent this cycle
+    type OutputData = Option<MnvrCmd>;
+    type StatusReport = StatusReport;
+    type ProcError = DrawbarTestError;
+
+    /// Initialise the drawbar test mode, creating the archive.
+    fn init(&mut self, _init_data: (), session: &Session) -> Result<(), Self::InitError> {
+        let mut arch_path = session.arch_root.clone();
+        arch_path.push("drawbar");
+        std::fs::create_dir_all(arch_path)
+            .map_err(|e| DrawbarTestError::ArchiveInitError(e.to_string()))?;
+
+        self.archiver = Archiver::from_path_with_meta::<Record, _>(session, "drawbar/runs.csv")
+            .map_err(|e| DrawbarTestError::ArchiveInitError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Start, stop or continue a run.
+    fn proc(
+        &mut self,
+        input_data: &Self::InputData,
+    ) -> Result<(Self::OutputData, Self::StatusReport), Self::ProcError> {
+        let mut output = None;
+
+        // Safe mode always ends the run, LocoCtrl will already have stopped
+        if input_data.safe && self.run.is_some() {
+            warn!("Drawbar run {} aborted by safe mode", self.num_runs);
+            self.run = None;
+        }
+
+        match input_data.cmd {
+            Some(DrawbarCmd::Start {
+                speed_ms,
+                duration_s,
+            }) => {
+                if input_data.safe {
+                    return Err(DrawbarTestError::InvalidRun(
+                        "cannot start a run in safe mode".into(),
+                    ));
+                }
+                if !speed_ms.is_finite() || !duration_s.is_finite() || duration_s <= 0.0 {
+                    return Err(DrawbarTestError::InvalidRun(format!(
+                        "speed {} m/s for {} s",
+                        speed_ms, duration_s
+                    )));
+                }
+
+                self.num_runs += 1;
+                info!(
+                    "Starting drawbar run {} at {} m/s for {} s",
+                    self.num_runs, speed_ms, duration_s
+                );
+
+                self.run = Some(Run {
+                    speed_ms,
+                    end_time_s: input_data.time_s + duration_s,
+                });
+
+                output = Some(MnvrCmd::Ackerman {
+                    speed_ms: MetersPerSec(speed_ms),
+                    curv_m: Curvature(0.0),
+                    crab_rad: Radians(0.0),
+                });
+            }
+            Some(DrawbarCmd::Abort) if self.run.is_some() => {
+                info!("Drawbar run {} aborted", self.num_runs);
+                self.run = None;
+                output = Some(MnvrCmd::Stop);
+            }
+            Some(DrawbarCmd::Abort) | None => (),
+        }
+
+        // End the run once it's complete
+        if let Some(ref run) = self.run {
+            if input_data.time_s >= run.end_time_s {
+                info!("Drawbar run {} complete", self.num_runs);
+                self.run = None;
+                output = Some(MnvrCmd::Stop);
+            }
+        }
+
+        Ok((
+            output,
+            StatusReport {
+                running: self.run.is_some(),
+                run: self.num_runs,
+            },
+        ))
+    }
+}
+
+impl DrawbarTest {
+    /// Write a row to the archive if a run is in progress.
+    ///
+    /// Must be called after LocoCtrl processing so that `loco_output` contains the wheel demands
+    /// for this cycle.
+    pub fn write(&mut self, input_data: &InputData, loco_output: &MechDems) {
+        // Estimate ground speed from the change in pose since the last cycle
+        let ground_speed_ms = match (&self.last_pose, &input_data.pose) {
+            (Some((last_time_s, last)), Some(pose)) if input_data.time_s > *last_time_s => {
+                let dx = pose.position_m_lm[0] - last.position_m_lm[0];
+                let dy = pose.position_m_lm[1] - last.position_m_lm[1];
+                Some(dx.hypot(dy) / (input_data.time_s - last_time_s))
+            }
+            _ => None,
+        };
+        self.last_pose = input_data.pose.map(|p| (input_data.time_s, p));
+
+        let run = match self.run {
+            Some(ref r) => r,
+            None => return,
+        };
+
+        // Slip is only meaningful when the wheels are actually being driven
+        let slip = match ground_speed_ms {
+            Some(v) if run.speed_ms.abs() > f64::EPSILON => Some(1.0 - v / run.speed_ms.abs()),
+            _ => None,
+        };
+
+        let drv = |id| loco_output.speed_rads.get(&id).copied();
+
+        let record = Record {
+            run: self.num_runs,
+            time_s: input_data.time_s,
+            dem_speed_ms: run.speed_ms,
+            dem_drv_fl_rads: drv(ActId::DrvFL),
+            dem_drv_ml_rads: drv(ActId::DrvML),
+            dem_drv_rl_rads: drv(ActId::DrvRL),
+            dem_drv_fr_rads: drv(ActId::DrvFR),
+            dem_drv_mr_rads: drv(ActId::DrvMR),
+            dem_drv_rr_rads: drv(ActId::DrvRR),
+            pos_x_m_lm: input_data.pose.map(|p| p.position_m_lm[0]),
+            pos_y_m_lm: input_data.pose.map(|p| p.position_m_lm[1]),
+            pos_z_m_lm: input_data.pose.map(|p| p.position_m_lm[2]),
+            ground_speed_ms,
+            slip,
+        };
+
+        if let Err(e) = self.archiver.serialise(record) {
+            warn!("Could not write drawbar archive: {}", e);
+        }
+    }
+}
@@ -22,23 +22,58 @@ pub mod loco_ctrl;
 // Arm control module - converts high level arm commands into individual joint commands
 pub mod arm_ctrl;
 
+/// Wheel rate control module - trims drive demands using measured wheel rates
+pub mod wheel_rate_ctrl;
+
+/// Mast control module - points the pan/tilt mast
+pub mod mast_ctrl;
+
+/// Drawbar test mode - constant speed runs for traction characterisation
+pub mod drawbar_test;
+
+/// Self test - staged checkout of the rover's equipment
+pub mod self_test;
+
+/// Turning circle calibration - validates the Ackerman geometry against measured turns
+pub mod turn_cal;
+
 /// Trajectory control module - keeps the rover on the given path
 pub mod traj_ctrl;
 
+/// Mode manager - tracks the rover's operational mode and gates TCs by mode
+pub mod mode_mgr;
+
+/// Path store - holds path files uplinked from the ground
+pub mod path_store;
+
 /// Telecommand client - recieves telecommands from the tc server
 pub mod tc_client;
 
 /// Telemetry server - publishes telemetry
 pub mod tm_server;
 
+/// Cycle archive - records a summary of each cycle for comparing sessions
+pub mod cycle_arch;
+
+/// Session index - aligns a finished session's archives, logs and images by cycle
+pub mod session_index;
+
 /// Mechanisms client - sends actuator demands to the mechanisms server
 #[cfg(feature = "mech")]
 pub mod mech_client;
 
+/// Crash handler - stops the mechanisms if the rover executable panics
+#[cfg(feature = "mech")]
+pub mod crash_handler;
+
 /// Simulation client - provides data directly from the simulation (webots)
 #[cfg(feature = "sim")]
 pub mod sim_client;
 
+/// Test utilities - allocation counting and parameters for the unit tests
+#[cfg(test)]
+mod test_utils;
+
 // ---------------------------------------------------------------------------
 // CONSTANTS
 // ---------------------------------------------------------------------------
@@ -49,6 +84,10 @@ pub const CYCLE_PERIOD_S: f64 = 0.10;
 /// Number of cycles per second
 pub const CYCLE_FREQUENCY_HZ: f64 = 1.0 / CYCLE_PERIOD_S;
 
-/// Limit of the number of times recieve errors from the mech server can be created consecutively
-/// before safe mode will be engaged.
+/// Limit on the number of consecutive cycles without sensor data from the mech server before safe
+/// mode will be engaged.
 pub const MAX_MECH_RECV_ERROR_LIMIT: u64 = 5;
+
+/// Limit on the number of consecutive camera server errors before the link is considered to have
+/// failed.
+pub const MAX_CAM_ERROR_LIMIT: u64 = 5;
@@ -2,6 +2,10 @@
 //!
 //! This module provides localisation for the rover in the form of visual 
 //! odometry. This module is currently a stub.
+//!
+//! Anything which can tell the rover where it is implements `PoseSource`, so
+//! the exec can be given whichever source is available (the simulation, or a
+//! scripted sequence of poses for testing) without knowing which it is.
 
 // ---------------------------------------------------------------------------
 // MODULES
@@ -11,6 +15,8 @@
 // IMPORTS
 // ---------------------------------------------------------------------------
 
+use std::collections::VecDeque;
+
 // ---------------------------------------------------------------------------
 // DATA STRUCTURES
 // ---------------------------------------------------------------------------
@@ -30,6 +36,26 @@ pub struct Pose {
     pub attitude_q_lm: [f64; 4]
 }
 
+/// A pose source which returns a predefined sequence of poses, one per call,
+/// then keeps returning the last one.
+///
+/// Useful for exercising modules which depend on pose without a simulation.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedPoseSource {
+    poses: VecDeque<Pose>,
+    last: Option<Pose>
+}
+
+// ---------------------------------------------------------------------------
+// TRAITS
+// ---------------------------------------------------------------------------
+
+/// A source of rover pose estimates.
+pub trait PoseSource {
+    /// Get the latest pose estimate, or `None` if no estimate is available.
+    fn get_pose(&mut self) -> Option<Pose>;
+}
+
 // ---------------------------------------------------------------------------
 // IMPLEMENTATIONS
 // ---------------------------------------------------------------------------
@@ -41,4 +67,24 @@ impl Pose {
     pub fn get_heading(&self) -> f64 {
         2f64 * self.attitude_q_lm[3].acos()
     }
-}
\ No newline at end of file
+}
+
+impl ScriptedPoseSource {
+    /// Create a new source which will return the given poses in order.
+    pub fn new<I: IntoIterator<Item = Pose>>(poses: I) -> Self {
+        Self {
+            poses: poses.into_iter().collect(),
+            last: None
+        }
+    }
+}
+
+impl PoseSource for ScriptedPoseSource {
+    fn get_pose(&mut self) -> Option<Pose> {
+        if let Some(p) = self.poses.pop_front() {
+            self.last = Some(p);
+        }
+
+        self.last
+    }
+}
@@ -6,7 +6,7 @@
 
 // Internal imports
 use super::*;
-use util::maths::clamp;
+use comms_if::units::{Curvature, MetersPerSec, Radians};
 
 // ---------------------------------------------------------------------------
 // IMPLEMENTATIONS
@@ -31,19 +31,19 @@ impl LocoCtrl {
     /// TODO: Add crab
     pub(crate) fn calc_ackerman(
         &mut self, 
-        speed_ms: f64,
-        curv_m: f64, 
-        crab_rad: f64
+        speed: MetersPerSec,
+        curv: Curvature, 
+        crab: Radians
     ) -> Result<(), super::LocoCtrlError> {
 
         // If the demanded curvature is close to zero set the target to point
         // straight ahead.
-        if curv_m.abs() < self.params.ackerman_min_curvature_m {
-            self.calc_ackerman_straight(speed_ms, crab_rad)?;
+        if curv.abs() < self.params.ackerman_min_curvature_m {
+            self.calc_ackerman_straight(speed, crab)?;
         }
         // Otherwise perform the generic ackerman calculation
         else {
-            self.calc_ackerman_generic(speed_ms, curv_m, crab_rad)?;
+            self.calc_ackerman_generic(speed, curv, crab)?;
         }
 
         Ok(())
@@ -52,23 +52,23 @@ impl LocoCtrl {
     /// Calculate the ackerman outputs for a straight drive
     fn calc_ackerman_straight(
         &mut self,
-        speed_ms: f64,
-        crab_rad: f64
+        speed: MetersPerSec,
+        crab: Radians
     ) -> Result<(), super::LocoCtrlError> {
         // Convert the desired speed into normalised speed
         let mut str_axes = [AxisData::default(); NUM_STR_AXES];
         let mut drv_axes = [AxisData::default(); NUM_DRV_AXES];
 
-        // Calculate the required wheel speed in radians/second
-        let wheel_rate_rads = speed_ms/self.params.wheel_radius_m;
+        // Calculate the required wheel speed
+        let wheel_rate = speed / self.params.wheel_radius_m;
 
         for i in 0..NUM_DRV_AXES {
-            drv_axes[i].rate_rads = wheel_rate_rads;
+            drv_axes[i].rate_rads = wheel_rate.0;
         }
 
         // Set the wheel angles equal to the crab angle
         for i in 0..NUM_STR_AXES {
-            str_axes[i].abs_pos_rad = crab_rad
+            str_axes[i].abs_pos_rad = crab.0
         }
 
         // Build the new target
@@ -82,59 +82,38 @@ impl LocoCtrl {
 
     /// Calculate generic ackerman outputs.
     ///
-    /// The calling function must ensure that the curvature demand is not less
-    /// than `loco_ctrl::Params::ackerman_min_curvature_m`. Failure to do so
-    /// may result in a division by zero.
+    /// The calling function should ensure that the curvature demand is not
+    /// less than `loco_ctrl::Params::ackerman_min_curvature_m`, a zero
+    /// curvature is driven as a straight line.
     fn calc_ackerman_generic(
         &mut self,
-        speed_ms: f64,
-        curv_m: f64,
-        crab_rad: f64
+        speed: MetersPerSec,
+        curv: Curvature,
+        crab: Radians
     ) -> Result<(), super::LocoCtrlError> {
 
         // Axis arrays
         let mut str_axes = [AxisData::default(); NUM_STR_AXES];
         let mut drv_axes = [AxisData::default(); NUM_DRV_AXES];
 
-        // Compute the radius of curvature in meters.
-        //
-        // Clamp the curvature to the maximum curv value.
-        //
-        //  Note: No check is required for a division by zero as this check
-        //  is performed by the calling function.
-        let curv_radius_m =
-            1.0 
-            / 
-            clamp(
-                &curv_m,
-                &(-self.params.ackerman_max_curvature_m),
-                &self.params.ackerman_max_curvature_m);
-
-        // Calculate the maximum crab angle in radians.
-        //
-        // Clamp the crab angle to the maximum crab value.
-        let crab_limit_margin_rad = (self.params.str_axis_pos_m_rb[0][1] / curv_radius_m).acos() * 0.99;
-
-        let limited_crab_rad =
-            clamp(
-                &crab_rad,
-                &-crab_limit_margin_rad,
-                &crab_limit_margin_rad);
-
-        // Calculate the maximum speed in meters per second.
-        //
-        // Clamp the speed to the maximum value.
-        let speed_limit_ms = (self.params.drv_max_abs_rate_rads[0] * self.params.wheel_radius_m * curv_radius_m).abs()
-            / (
-                ((curv_radius_m * limited_crab_rad.cos()).abs() + self.params.str_axis_pos_m_rb[0][1]).powi(2)
-                + ((curv_radius_m * limited_crab_rad.sin()).abs() + self.params.str_axis_pos_m_rb[0][0]).powi(2)
-            ).sqrt() * 0.99;
-
-        let limited_speed_ms =
-            clamp(
-                &speed_ms,
-                &-speed_limit_ms,
-                &speed_limit_ms);
+        // Compute the radius of curvature, clamping the curvature to the
+        // maximum curvature value in case the envelope check was bypassed.
+        let curv_radius_m = match curv
+            .clamp(
+                -self.params.ackerman_max_curvature_m,
+                self.params.ackerman_max_curvature_m)
+            .radius()
+        {
+            Some(r) => r.0,
+            None => return self.calc_ackerman_straight(speed, crab)
+        };
+
+        let wheel_radius_m = self.params.wheel_radius_m.0;
+
+        // The command has already been limited to the kinematic envelope, so
+        // the crab angle and speed can be used directly.
+        let crab_rad = crab.0;
+        let speed_ms = speed.0;
 
         // Steer axis angles
         //
@@ -149,9 +128,9 @@ impl LocoCtrl {
 
             str_axes[i].abs_pos_rad = 
             (
-                (self.params.str_axis_pos_m_rb[i][0] + curv_radius_m * limited_crab_rad.sin())
+                (self.params.str_axis_pos_m_rb[i][0] + curv_radius_m * crab_rad.sin())
                 /
-                (curv_radius_m * limited_crab_rad.cos() - self.params.str_axis_pos_m_rb[i][1])
+                (curv_radius_m * crab_rad.cos() - self.params.str_axis_pos_m_rb[i][1])
             ).atan();
         }
 
@@ -166,15 +145,15 @@ impl LocoCtrl {
 
             // Calculate the desired speed at this wheel's radius from the
             // centre of rotation
-            let wheel_speed_ms = (limited_speed_ms / curv_radius_m.abs())
+            let wheel_speed_ms = (speed_ms / curv_radius_m.abs())
                 * (
-                    (curv_radius_m * limited_crab_rad.cos() - self.params.str_axis_pos_m_rb[i][1]).powi(2)
-                    + (self.params.str_axis_pos_m_rb[i][0] + curv_radius_m * limited_crab_rad.sin()).powi(2)
+                    (curv_radius_m * crab_rad.cos() - self.params.str_axis_pos_m_rb[i][1]).powi(2)
+                    + (self.params.str_axis_pos_m_rb[i][0] + curv_radius_m * crab_rad.sin()).powi(2)
                 ).sqrt();
 
             // Calculate the wheel rate by converting the speed into rads/s
             drv_axes[i].rate_rads = 
-                wheel_speed_ms / self.params.wheel_radius_m;
+                wheel_speed_ms / wheel_radius_m;
         }
 
         // Build the target configuration
@@ -6,6 +6,7 @@
 
 // Internal imports
 use super::*;
+use comms_if::units::RadPerSec;
 
 // ---------------------------------------------------------------------------
 // IMPLEMENTATIONS
@@ -14,7 +15,7 @@ use super::*;
 impl LocoCtrl {
 
     /// Perform the point turn command calculations
-    pub(crate) fn calc_point_turn(&mut self, rate_rads: f64) -> Result<(), super::LocoCtrlError> {
+    pub(crate) fn calc_point_turn(&mut self, rate: RadPerSec) -> Result<(), super::LocoCtrlError> {
 
         // Axis arrays
         let mut str_axes = [AxisData::default(); NUM_STR_AXES];
@@ -33,12 +34,12 @@ impl LocoCtrl {
         // Calculate drive axis rates
         for i in 0..NUM_DRV_AXES {
             drv_axes[i].rate_rads = 
-                rate_rads
+                rate.0
                 * (
                     self.params.str_axis_pos_m_rb[i][0].powi(2)
                     + self.params.str_axis_pos_m_rb[i][1].powi(2)
                 ).sqrt()
-                / self.params.wheel_radius_m;
+                / self.params.wheel_radius_m.0;
             
             // If on the right side reverse direction
             if i < 3 {
@@ -6,6 +6,7 @@
 
 // Internal imports
 use super::LocoCtrl;
+use comms_if::units::{Curvature, MetersPerSec};
 
 // ---------------------------------------------------------------------------
 // IMPLEMENTATIONS
@@ -16,8 +17,8 @@ impl LocoCtrl {
     /// Perform the skid steer command calculations
     pub(crate) fn calc_skid_steer(
         &mut self,
-        _speed_ms: f64,
-        _curv_m: f64
+        _speed: MetersPerSec,
+        _curv: Curvature
     ) -> Result<(), super::LocoCtrlError> {
         Err(super::LocoCtrlError::NotYetSupported(String::from(
             "Manouvre command 'Skid Steer' is not yet supported")))
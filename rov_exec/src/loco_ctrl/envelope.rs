@@ -0,0 +1,481 @@
+//! Kinematic envelope protection
+//!
+//! Manouvre commands are checked against what the rover can achieve, given
+//! its steer angle limits, drive rate limits and wheel geometry, before any
+//! axis demands are calculated. A command outside the envelope is either
+//! rejected or saturated to the edge of the envelope, depending on
+//! `Params::envelope_mode`. Saturating the command rather than the individual
+//! axes keeps all wheels consistent with a single centre of rotation.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Internal
+use super::{EnvelopeMode, LocoCtrl, LocoCtrlError, NUM_DRV_AXES, NUM_STR_AXES};
+use comms_if::{
+    tc::loco_ctrl::MnvrCmd,
+    units::{Curvature, MetersPerSec, RadPerSec, Radians},
+};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Fraction of the crab angle at which the centre of rotation would move
+/// inside the wheelbase that can be commanded.
+const CRAB_LIMIT_MARGIN: f64 = 0.99;
+
+/// Number of bisection steps used to find the largest curvature achievable
+/// within the steer angle limits.
+const CURV_BISECT_ITERS: usize = 32;
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// The achievable envelope around a manouvre command.
+///
+/// Limits are absolute values in the direction of the command, for example
+/// `max_abs_curv_m` of a right turn is the tightest right turn possible.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Envelope {
+    Ackerman {
+        /// Largest curvature achievable at the commanded crab angle.
+        max_abs_curv_m: Curvature,
+
+        /// Largest crab angle achievable at the commanded curvature.
+        max_abs_crab_rad: Radians,
+
+        /// Largest speed achievable at the achievable curvature and crab.
+        max_abs_speed_ms: MetersPerSec,
+    },
+
+    PointTurn {
+        /// Largest turn rate achievable, zero if the steer axes can't reach
+        /// the point turn position.
+        max_abs_rate_rads: RadPerSec,
+    },
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl fmt::Display for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Envelope::Ackerman {
+                max_abs_curv_m,
+                max_abs_crab_rad,
+                max_abs_speed_ms,
+            } => write!(
+                f,
+                "|curvature| <= {}, |crab| <= {}, |speed| <= {}",
+                max_abs_curv_m, max_abs_crab_rad, max_abs_speed_ms
+            ),
+            Envelope::PointTurn { max_abs_rate_rads } => {
+                write!(f, "|rate| <= {}", max_abs_rate_rads)
+            }
+        }
+    }
+}
+
+impl LocoCtrl {
+    /// Check a manouvre command against the rover's kinematic envelope.
+    ///
+    /// Returns the command to execute, which is either the given command if
+    /// it's achievable or the command saturated to the envelope. In reject
+    /// mode an unachievable command returns `LocoCtrlError::OutsideEnvelope`.
+    ///
+    /// Commands containing a non-finite value are always rejected with
+    /// `LocoCtrlError::InvalidMnvrCmd`, since saturating a NaN has no
+    /// meaningful result.
+    pub(crate) fn protect_envelope(&mut self, cmd: MnvrCmd) -> Result<MnvrCmd, LocoCtrlError> {
+        let finite = match cmd {
+            MnvrCmd::Ackerman {
+                speed_ms,
+                curv_m,
+                crab_rad,
+            } => speed_ms.is_finite() && curv_m.is_finite() && crab_rad.is_finite(),
+            MnvrCmd::PointTurn { rate_rads } => rate_rads.is_finite(),
+            MnvrCmd::SkidSteer { speed_ms, curv_m } => speed_ms.is_finite() && curv_m.is_finite(),
+            MnvrCmd::Stop => true,
+        };
+        if !finite {
+            return Err(LocoCtrlError::InvalidMnvrCmd);
+        }
+
+        let (saturated, envelope) = match cmd {
+            MnvrCmd::Ackerman {
+                speed_ms,
+                curv_m,
+                crab_rad,
+            } => self.saturate_ackerman(speed_ms, curv_m, crab_rad),
+            MnvrCmd::PointTurn { rate_rads } => self.saturate_point_turn(rate_rads),
+            // Stop is always achievable and skid steer isn't supported yet
+            MnvrCmd::Stop | MnvrCmd::SkidSteer { .. } => return Ok(cmd),
+        };
+
+        if saturated == cmd {
+            return Ok(cmd);
+        }
+
+        match self.params.envelope_mode {
+            EnvelopeMode::Reject => Err(LocoCtrlError::OutsideEnvelope { cmd, envelope }),
+            EnvelopeMode::Saturate => {
+                warn!(
+                    "Manouvre command outside the kinematic envelope ({}), saturated to {:?}",
+                    envelope, saturated
+                );
+                self.report.envelope_saturated = true;
+                Ok(saturated)
+            }
+        }
+    }
+
+    /// Saturate an Ackerman command to the envelope, returning the saturated
+    /// command and the envelope around it.
+    ///
+    /// The limits depend on each other, so they're found in order: the
+    /// curvature is limited to the Ackerman maximum, the crab angle is limited
+    /// so the centre of rotation stays outside the wheelbase at that
+    /// curvature, the curvature is limited so the steer axes are within their
+    /// limits at that crab angle, and finally the speed is limited so the
+    /// fastest wheel is within its drive rate limit.
+    fn saturate_ackerman(
+        &self,
+        speed: MetersPerSec,
+        curv: Curvature,
+        crab: Radians,
+    ) -> (MnvrCmd, Envelope) {
+        let max_curv = self.params.ackerman_max_curvature_m;
+        let curv_lim = curv.clamp(-max_curv, max_curv);
+
+        // Crab limit from the steer axes when driving straight, and from
+        // keeping the centre of rotation outside the wheelbase when turning.
+        let mut max_abs_crab = Radians(self.str_abs_pos_limit_rad());
+        if curv_lim.abs() >= self.params.ackerman_min_curvature_m {
+            let max_abs_y_m = self
+                .params
+                .str_axis_pos_m_rb
+                .iter()
+                .fold(0.0, |max: f64, p| max.max(p[1].abs()));
+            let cor_limit_rad = (max_abs_y_m * curv_lim.abs().0).min(1.0).acos();
+
+            max_abs_crab = Radians(max_abs_crab.0.min(cor_limit_rad * CRAB_LIMIT_MARGIN));
+        }
+        let crab_lim = crab.clamp(-max_abs_crab, max_abs_crab);
+
+        // Largest curvature the steer axes can reach at this crab angle
+        let max_abs_curv = self.max_steerable_curv(curv_lim, crab_lim);
+        let curv_lim = curv_lim.clamp(-max_abs_curv, max_abs_curv);
+
+        // Speed limit from the fastest wheel
+        let max_abs_speed = self.max_ackerman_speed(curv_lim, crab_lim);
+        let speed_lim = speed.clamp(-max_abs_speed, max_abs_speed);
+
+        (
+            MnvrCmd::Ackerman {
+                speed_ms: speed_lim,
+                curv_m: curv_lim,
+                crab_rad: crab_lim,
+            },
+            Envelope::Ackerman {
+                max_abs_curv_m: max_abs_curv,
+                max_abs_crab_rad: max_abs_crab,
+                max_abs_speed_ms: max_abs_speed,
+            },
+        )
+    }
+
+    /// Saturate a point turn command to the envelope, returning the saturated
+    /// command and the envelope around it.
+    fn saturate_point_turn(&self, rate: RadPerSec) -> (MnvrCmd, Envelope) {
+        // The steer axes point tangent to the circle through all wheels, if
+        // they can't get there no point turn is possible.
+        let reachable = (0..NUM_STR_AXES).all(|i| {
+            let pos = &self.params.str_axis_pos_m_rb[i];
+            self.is_str_pos_in_limits(i, -(pos[0] / pos[1]).atan())
+        });
+
+        let max_abs_rate = match reachable {
+            true => RadPerSec(
+                (0..NUM_DRV_AXES)
+                    .map(|i| {
+                        let pos = &self.params.str_axis_pos_m_rb[i];
+                        self.drv_abs_rate_limit_rads(i) * self.params.wheel_radius_m.0
+                            / pos[0].hypot(pos[1])
+                    })
+                    .fold(f64::INFINITY, f64::min),
+            ),
+            false => RadPerSec(0.0),
+        };
+
+        (
+            MnvrCmd::PointTurn {
+                rate_rads: rate.clamp(-max_abs_rate, max_abs_rate),
+            },
+            Envelope::PointTurn {
+                max_abs_rate_rads: max_abs_rate,
+            },
+        )
+    }
+
+    /// Find the largest curvature, in the direction of and no larger than
+    /// `curv`, at which all steer axes are within their limits.
+    ///
+    /// Steer angles change continuously with curvature and equal the crab
+    /// angle at zero curvature, so bisection between zero and `curv` finds the
+    /// limit as long as the crab angle itself is achievable.
+    fn max_steerable_curv(&self, curv: Curvature, crab: Radians) -> Curvature {
+        if self.are_ackerman_str_pos_in_limits(curv, crab) {
+            return curv.abs();
+        }
+
+        let (mut lo, mut hi) = (0.0, curv.abs().0);
+        for _ in 0..CURV_BISECT_ITERS {
+            let mid = 0.5 * (lo + hi);
+            match self.are_ackerman_str_pos_in_limits(Curvature(mid * curv.signum()), crab) {
+                true => lo = mid,
+                false => hi = mid,
+            }
+        }
+
+        Curvature(lo)
+    }
+
+    /// Check whether all steer axes are within their limits for an Ackerman
+    /// manouvre with the given curvature and crab angle.
+    ///
+    /// This is the same geometry as `calc_ackerman`, with the turn radius
+    /// replaced by the curvature so that straight driving is included.
+    fn are_ackerman_str_pos_in_limits(&self, curv: Curvature, crab: Radians) -> bool {
+        (0..NUM_STR_AXES).all(|i| {
+            let pos = &self.params.str_axis_pos_m_rb[i];
+            let pos_rad = ((pos[0] * curv.0 + crab.sin()) / (crab.cos() - pos[1] * curv.0)).atan();
+
+            self.is_str_pos_in_limits(i, pos_rad)
+        })
+    }
+
+    /// Largest speed of an Ackerman manouvre with the given curvature and crab
+    /// angle at which all drive axes are within their rate limits.
+    fn max_ackerman_speed(&self, curv: Curvature, crab: Radians) -> MetersPerSec {
+        // The ratio between a wheel's speed and the rover's speed is the ratio
+        // of their distances from the centre of rotation.
+        let max_abs_speed_ms = (0..NUM_DRV_AXES)
+            .map(|i| {
+                let pos = &self.params.str_axis_pos_m_rb[i];
+                let ratio = (crab.cos() - pos[1] * curv.0).hypot(pos[0] * curv.0 + crab.sin());
+
+                self.drv_abs_rate_limit_rads(i) * self.params.wheel_radius_m.0 / ratio
+            })
+            .fold(f64::INFINITY, f64::min);
+
+        MetersPerSec(max_abs_speed_ms)
+    }
+
+    fn is_str_pos_in_limits(&self, axis: usize, pos_rad: f64) -> bool {
+        pos_rad <= self.params.str_max_abs_pos_rad[axis]
+            && pos_rad >= self.params.str_min_abs_pos_rad[axis]
+    }
+
+    /// Largest steer angle reachable by every steer axis in either direction.
+    fn str_abs_pos_limit_rad(&self) -> f64 {
+        (0..NUM_STR_AXES)
+            .map(|i| self.params.str_max_abs_pos_rad[i].min(-self.params.str_min_abs_pos_rad[i]))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Largest drive rate of an axis in either direction.
+    fn drv_abs_rate_limit_rads(&self, axis: usize) -> f64 {
+        self.params.drv_max_abs_rate_rads[axis].min(-self.params.drv_min_abs_rate_rads[axis])
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loco_ctrl::Params;
+    use comms_if::units::Meters;
+
+    const WHEEL_RADIUS_M: f64 = 0.048;
+    const MAX_RATE_RADS: f64 = 3.6458;
+    const MAX_STR_RAD: f64 = 1.5865;
+    const MAX_CURV_M: f64 = 5.98;
+
+    /// Geometry and limits from `params/loco_ctrl.toml`.
+    fn loco_ctrl(mode: EnvelopeMode) -> LocoCtrl {
+        let pos = [
+            [0.15, 0.152, 0.0],
+            [0.0, 0.152, 0.0],
+            [-0.15, 0.152, 0.0],
+            [0.15, -0.152, 0.0],
+            [0.0, -0.152, 0.0],
+            [-0.15, -0.152, 0.0],
+        ];
+
+        let mut lc = LocoCtrl::default();
+        lc.params = Params {
+            wheel_radius_m: Meters(WHEEL_RADIUS_M),
+            str_axis_pos_m_rb: pos,
+            drv_axis_pos_m_rb: pos,
+            str_max_abs_pos_rad: [MAX_STR_RAD; NUM_STR_AXES],
+            str_min_abs_pos_rad: [-MAX_STR_RAD; NUM_STR_AXES],
+            drv_max_abs_rate_rads: [MAX_RATE_RADS; NUM_DRV_AXES],
+            drv_min_abs_rate_rads: [-MAX_RATE_RADS; NUM_DRV_AXES],
+            ackerman_min_curvature_m: Curvature(0.01),
+            ackerman_max_curvature_m: Curvature(MAX_CURV_M),
+            envelope_mode: mode,
+        };
+        lc
+    }
+
+    fn ackerman(speed_ms: f64, curv_m: f64, crab_rad: f64) -> MnvrCmd {
+        MnvrCmd::Ackerman {
+            speed_ms: MetersPerSec(speed_ms),
+            curv_m: Curvature(curv_m),
+            crab_rad: Radians(crab_rad),
+        }
+    }
+
+    /// Get the speed, curvature and crab of an Ackerman command.
+    fn unpack(cmd: MnvrCmd) -> (f64, f64, f64) {
+        match cmd {
+            MnvrCmd::Ackerman {
+                speed_ms,
+                curv_m,
+                crab_rad,
+            } => (speed_ms.0, curv_m.0, crab_rad.0),
+            c => panic!("Expected an Ackerman command, got {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_straight() {
+        let mut lc = loco_ctrl(EnvelopeMode::Reject);
+
+        let cmd = ackerman(0.1, 0.0, 0.0);
+        assert_eq!(lc.protect_envelope(cmd).unwrap(), cmd);
+        assert!(!lc.report.envelope_saturated);
+
+        assert_eq!(lc.protect_envelope(MnvrCmd::Stop).unwrap(), MnvrCmd::Stop);
+    }
+
+    #[test]
+    fn test_tightest_turn() {
+        let mut lc = loco_ctrl(EnvelopeMode::Saturate);
+
+        let (_, curv, _) = unpack(lc.protect_envelope(ackerman(0.01, 100.0, 0.0)).unwrap());
+        assert!((curv - MAX_CURV_M).abs() < 1e-9);
+        assert!(lc.report.envelope_saturated);
+
+        let (_, curv, _) = unpack(lc.protect_envelope(ackerman(0.01, -100.0, 0.0)).unwrap());
+        assert!((curv + MAX_CURV_M).abs() < 1e-9);
+
+        let mut lc = loco_ctrl(EnvelopeMode::Reject);
+        assert!(matches!(
+            lc.protect_envelope(ackerman(0.01, 100.0, 0.0)),
+            Err(LocoCtrlError::OutsideEnvelope { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crab_limit() {
+        let mut lc = loco_ctrl(EnvelopeMode::Saturate);
+
+        // Straight, limited by the steer axes
+        let (_, _, crab) = unpack(lc.protect_envelope(ackerman(0.01, 0.0, 2.0)).unwrap());
+        assert!((crab - MAX_STR_RAD).abs() < 1e-9);
+
+        // Turning, limited by keeping the centre of rotation outside the wheelbase
+        let (_, curv, crab) = unpack(lc.protect_envelope(ackerman(0.01, 5.0, -1.5)).unwrap());
+        let cor_limit = (0.152 * curv.abs()).acos() * CRAB_LIMIT_MARGIN;
+        assert!(crab < 0.0);
+        assert!(crab.abs() <= cor_limit + 1e-9);
+
+        // Every steer axis must be within its limits at the saturated command
+        assert!(lc.are_ackerman_str_pos_in_limits(Curvature(curv), Radians(crab)));
+    }
+
+    #[test]
+    fn test_point_turn_reachability() {
+        let mut lc = loco_ctrl(EnvelopeMode::Saturate);
+
+        // The corner wheels are furthest from the centre so limit the rate
+        let max_rate = MAX_RATE_RADS * WHEEL_RADIUS_M / 0.15f64.hypot(0.152);
+        let cmd = MnvrCmd::PointTurn {
+            rate_rads: RadPerSec(0.5 * max_rate),
+        };
+        assert_eq!(lc.protect_envelope(cmd).unwrap(), cmd);
+
+        match lc.protect_envelope(MnvrCmd::PointTurn { rate_rads: RadPerSec(10.0) }) {
+            Ok(MnvrCmd::PointTurn { rate_rads }) => assert!((rate_rads.0 - max_rate).abs() < 1e-9),
+            r => panic!("Unexpected result {:?}", r),
+        }
+
+        // Steer axes which can't reach the point turn angle, about 45 deg, allow no point turn
+        lc.params.str_max_abs_pos_rad = [0.5; NUM_STR_AXES];
+        lc.params.str_min_abs_pos_rad = [-0.5; NUM_STR_AXES];
+        match lc.protect_envelope(cmd) {
+            Ok(MnvrCmd::PointTurn { rate_rads }) => assert_eq!(rate_rads.0, 0.0),
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_speed_limit() {
+        let mut lc = loco_ctrl(EnvelopeMode::Saturate);
+        let max_speed = MAX_RATE_RADS * WHEEL_RADIUS_M;
+
+        // Straight, every wheel moves at the rover's speed
+        let (speed, _, _) = unpack(lc.protect_envelope(ackerman(1.0, 0.0, 0.0)).unwrap());
+        assert!((speed - max_speed).abs() < 1e-9);
+
+        let (speed, _, _) = unpack(lc.protect_envelope(ackerman(-1.0, 0.0, 0.0)).unwrap());
+        assert!((speed + max_speed).abs() < 1e-9);
+
+        // Turning left, the outside front and rear right wheels are fastest
+        let curv = 2.0;
+        let ratio = (1.0f64 + 0.152 * curv).hypot(0.15 * curv);
+        let (speed, _, _) = unpack(lc.protect_envelope(ackerman(1.0, curv, 0.0)).unwrap());
+        assert!((speed - max_speed / ratio).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_non_finite() {
+        for mode in [EnvelopeMode::Saturate, EnvelopeMode::Reject] {
+            let mut lc = loco_ctrl(mode);
+
+            for cmd in [
+                ackerman(f64::NAN, 0.0, 0.0),
+                ackerman(0.1, f64::NAN, 0.0),
+                ackerman(0.1, 0.0, f64::NAN),
+                ackerman(f64::INFINITY, 0.0, 0.0),
+                MnvrCmd::PointTurn {
+                    rate_rads: RadPerSec(f64::NAN),
+                },
+                MnvrCmd::SkidSteer {
+                    speed_ms: MetersPerSec(f64::NAN),
+                    curv_m: Curvature(0.0),
+                },
+            ] {
+                assert!(
+                    matches!(lc.protect_envelope(cmd), Err(LocoCtrlError::InvalidMnvrCmd)),
+                    "{:?} was not rejected",
+                    cmd
+                );
+            }
+        }
+    }
+}
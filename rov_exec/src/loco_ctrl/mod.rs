@@ -10,12 +10,15 @@ mod state;
 mod calc_ackerman;
 mod calc_point_turn;
 mod calc_skid_steer;
+mod envelope;
 
 // ---------------------------------------------------------------------------
 // IMPORTS
 // ---------------------------------------------------------------------------
 
 // Internal
+use comms_if::tc::loco_ctrl::MnvrCmd;
+pub use envelope::*;
 pub use loco_config::*;
 pub use params::*;
 pub use state::*;
@@ -45,4 +48,7 @@ pub enum LocoCtrlError {
 
     #[error("Recieved an invalid manouvre command")]
     InvalidMnvrCmd,
+
+    #[error("Manouvre command {cmd:?} is outside the kinematic envelope: {envelope}")]
+    OutsideEnvelope { cmd: MnvrCmd, envelope: Envelope },
 }
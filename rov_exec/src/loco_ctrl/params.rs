@@ -6,6 +6,7 @@
 
 use serde::{Serialize, Deserialize};
 use super::{NUM_STR_AXES, NUM_DRV_AXES};
+use comms_if::units::{Curvature, Meters};
 
 // ---------------------------------------------------------------------------
 // DATA STRUCTURES
@@ -20,7 +21,7 @@ pub struct Params {
     /// The radius of the rover's wheels.
     ///
     /// Units: meters.
-    pub wheel_radius_m: f64,
+    pub wheel_radius_m: Meters,
 
     /// The position of the steer axes in the rover body frame.
     ///
@@ -59,11 +60,34 @@ pub struct Params {
     /// Minimum curvature possible under an ackerman command.
     ///
     /// Units: 1/meters
-    pub ackerman_min_curvature_m: f64,
+    pub ackerman_min_curvature_m: Curvature,
 
     /// Maximum curvature possible under an ackerman command.
     ///
     /// Units: 1/meters
-    pub ackerman_max_curvature_m: f64
+    pub ackerman_max_curvature_m: Curvature,
 
+    // ---- ENVELOPE PROTECTION ----
+
+    /// What to do with a manouvre command which is outside the rover's
+    /// kinematic envelope.
+    #[serde(default)]
+    pub envelope_mode: EnvelopeMode
+
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Handling of manouvre commands outside the rover's kinematic envelope.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvelopeMode {
+    /// Reject the command with an error, keeping the previous command.
+    Reject,
+
+    /// Bring the command back to the edge of the envelope and execute it,
+    /// raising `StatusReport::envelope_saturated`.
+    #[default]
+    Saturate,
 }
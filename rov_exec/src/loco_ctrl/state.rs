@@ -13,6 +13,7 @@ use super::{AxisData, LocoConfig, Params, NUM_DRV_AXES, NUM_STR_AXES};
 use comms_if::{
     eqpt::mech::{ActId, MechDems},
     tc::loco_ctrl::MnvrCmd,
+    tm::TmMeta,
 };
 use std::collections::HashMap;
 use util::{
@@ -53,10 +54,14 @@ pub struct InputData {
 }
 
 /// Status report for LocoCtrl processing.
-#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, TmMeta)]
 pub struct StatusReport {
+    #[tm(desc = "True if the demand of a steer axis was limited by its position limits")]
     pub str_abs_pos_limited: [bool; NUM_STR_AXES],
+    #[tm(desc = "True if the demand of a drive axis was limited by its rate limits")]
     pub drv_rate_limited: [bool; NUM_STR_AXES],
+    #[tm(desc = "True if the manouvre command was saturated to the kinematic envelope")]
+    pub envelope_saturated: bool,
 }
 
 // ---------------------------------------------------------------------------
@@ -115,12 +120,16 @@ impl State for LocoCtrl {
 
         // Check to see if there's a new command
         if let Some(cmd) = input_data.cmd {
-            // Update the interal copy of the command
-            self.current_cmd = Some(cmd);
-
             // Ouptut the command in debug mode
             debug!("New LocoCtrl MnvrCmd::{:#?}", cmd);
 
+            // Check the command is achievable, saturating it if required. A
+            // rejected command leaves the previous command in place.
+            let cmd = self.protect_envelope(cmd)?;
+
+            // Update the interal copy of the command
+            self.current_cmd = Some(cmd);
+
             // Calculate the target configuration based on this new command.
             self.calc_target_config()?;
         }
@@ -244,7 +253,9 @@ impl LocoCtrl {
     /// Enforce the limits in the Rover's hardware capabilities.
     ///
     /// This function shall modify the current target configuration to ensure
-    /// that no capability of the rover is exceeded.
+    /// that no capability of the rover is exceeded. Commands are already
+    /// limited to the kinematic envelope by `protect_envelope`, so this is a
+    /// last line of protection for the individual axes.
     ///
     /// If a limit is reached the corresponding flag in the status report will
     /// be raised.
@@ -32,17 +32,17 @@ use cam_client::{CamClient, CamClientError};
 use comms_if::{
     eqpt::{
         cam::{CamId, ImageFormat},
-        mech::{MechDems, MechDemsResponse},
+        mech::{ActId, MechDems, MechDemsFlags, MechDemsResponse},
     },
-    net::NetParams,
-    tc::Tc,
-    tc::TcResponse,
+    net::{NetParams, TrafficCapture},
+    tc::{eqpt::Eqpt, TcResponse},
 };
 #[cfg(feature = "mech")]
 use mech_client::{MechClient, MechClientError};
 use rov_lib::{
+    cycle_arch::CycleArchive,
     data_store::{DataStore, SafeModeCause},
-    loc::Pose,
+    loc::PoseSource,
     tc_client::{TcClient, TcClientError},
     *,
 };
@@ -56,18 +56,22 @@ mod tc_processor;
 // ---------------------------------------------------------------------------
 
 // External
+use chrono::Utc;
 use color_eyre::{
     eyre::{eyre, WrapErr},
     Report,
 };
 use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 use std::thread;
 use std::time::{Duration, Instant};
-use tm_server::TmServer;
+use tm_server::{TmParams, TmServer};
 
 // Internal
 use util::{
+    build_info::BuildInfo,
     host,
     logger::{logger_init, LevelFilter},
     module::State,
@@ -75,6 +79,7 @@ use util::{
     script_interpreter::{PendingTcs, ScriptInterpreter},
     //archive::Archived
     session::Session,
+    startup::{Retry, Startup, StartupParams},
 };
 
 // ---------------------------------------------------------------------------
@@ -99,10 +104,39 @@ fn main() -> Result<(), Report> {
     );
     info!("Session directory: {:?}\n", session.session_root);
 
+    // Record the build so the session's data can be matched to the software which produced it
+    let build_info = BuildInfo::new("rov_exec", &enabled_features());
+    info!("Build: {:?}\n", build_info);
+    build_info
+        .save(&session)
+        .wrap_err("Failed to save the build information")?;
+
+    // ---- START-UP SEQUENCE ----
+
+    // Each stage declares the stages it needs. Stages which connect to other processes are retried
+    // for a while, so that rov_exec can be started before the equipment servers are up.
+    let mut startup = Startup::new();
+
     // ---- LOAD PARAMETERS ----
 
-    let net_params: NetParams =
-        util::params::load("net.toml").wrap_err("Could not load net params")?;
+    let net_params: NetParams = startup
+        .stage("net params", &[], Retry::Never, || util::params::load("net.toml"))
+        .wrap_err("Could not load net params")?;
+
+    let exec_params: ExecParams = startup
+        .stage("exec params", &[], Retry::Never, || util::params::load("rov_exec.toml"))
+        .wrap_err("Could not load exec params")?;
+
+    let tm_params: TmParams = startup
+        .stage("tm params", &[], Retry::Never, || util::params::load("tm_server.toml"))
+        .wrap_err("Could not load TM server params")?;
+
+    #[cfg(feature = "cam")]
+    let cam_calib_params: comms_if::eqpt::cam::CamCalibParams = startup
+        .stage("cam params", &[], Retry::Never, || util::params::load("cam.toml"))
+        .wrap_err("Could not load camera calibration params")?;
+
+    startup.set_params(exec_params.startup.clone());
 
     info!("Exec parameters loaded");
 
@@ -151,18 +185,52 @@ fn main() -> Result<(), Report> {
     info!("Initialising modules...");
 
     let mut ds = DataStore::default();
+    ds.hk.session_id = session.session_id.clone();
+    ds.hk.build_id = util::build_info::build_id();
 
     // ---- INITIALISE MODULES ----
 
-    ds.loco_ctrl
-        .init("loco_ctrl.toml", &session)
+    startup
+        .stage("LocoCtrl", &[], Retry::Never, || ds.loco.loco_ctrl.init("loco_ctrl.toml", &session))
         .wrap_err("Failed to initialise LocoCtrl")?;
-    info!("LocoCtrl init complete");
 
-    ds.arm_ctrl
-        .init("arm_ctrl.toml", &session)
+    startup
+        .stage("WheelRateCtrl", &[], Retry::Never, || {
+            ds.loco.wheel_rate_ctrl.init("wheel_rate_ctrl.toml", &session)
+        })
+        .wrap_err("Failed to initialise WheelRateCtrl")?;
+
+    startup
+        .stage("ArmCtrl", &[], Retry::Never, || ds.mech.arm_ctrl.init("arm_ctrl.toml", &session))
         .wrap_err("Failed to initialise ArmCtrl")?;
-    info!("ArmCtrl init complete");
+
+    startup
+        .stage("MastCtrl", &[], Retry::Never, || ds.mech.mast_ctrl.init("mast_ctrl.toml", &session))
+        .wrap_err("Failed to initialise MastCtrl")?;
+
+    startup
+        .stage("DrawbarTest", &[], Retry::Never, || ds.checkout.drawbar_test.init((), &session))
+        .wrap_err("Failed to initialise the drawbar test mode")?;
+
+    startup
+        .stage("SelfTest", &[], Retry::Never, || {
+            ds.checkout.self_test.init("self_test.toml", &session)
+        })
+        .wrap_err("Failed to initialise the self test")?;
+
+    startup
+        .stage("TurnCal", &[], Retry::Never, || {
+            ds.checkout.turn_cal.init(("turn_cal.toml", "loco_ctrl.toml"), &session)
+        })
+        .wrap_err("Failed to initialise the turning circle calibration")?;
+
+    startup
+        .stage("PathStore", &[], Retry::Never, || ds.auto.path_store.init("path_store.toml"))
+        .wrap_err("Failed to initialise the PathStore")?;
+
+    let mut cycle_arch = startup
+        .stage("cycle archive", &[], Retry::Never, || CycleArchive::new(&session))
+        .wrap_err("Failed to initialise the cycle archive")?;
 
     info!("Module initialisation complete\n");
 
@@ -172,45 +240,107 @@ fn main() -> Result<(), Report> {
 
     let zmq_ctx = comms_if::net::zmq::Context::new();
 
+    // Record the raw ground link traffic if requested
+    let traffic_capture = match net_params.traffic_capture.enabled {
+        true => {
+            let dir = session
+                .module_dir("traffic")
+                .wrap_err("Failed to create the traffic capture directory")?;
+            let c = startup
+                .stage("TrafficCapture", &["net params"], Retry::Never, || {
+                    TrafficCapture::new(&dir, &net_params.traffic_capture)
+                })
+                .wrap_err("Failed to initialise the traffic capture")?;
+            info!("Capturing ground link traffic into {:?}", dir);
+            Some(c)
+        }
+        false => None,
+    };
+
     if use_tc_client {
         tc_source = TcSource::Remote(
-            TcClient::new(&zmq_ctx, &net_params).wrap_err("Failed to initialise the TcClient")?,
+            startup
+                .stage("TcClient", &["net params"], Retry::Transient, || {
+                    TcClient::new(&zmq_ctx, &net_params, traffic_capture.as_ref())
+                })
+                .wrap_err("Failed to initialise the TcClient")?,
         );
-        info!("TcClient initialised");
     }
 
     #[cfg(feature = "mech")]
-    let mut mech_client = {
-        let c =
-            MechClient::new(&zmq_ctx, &net_params).wrap_err("Failed to initialise MechClient")?;
-        info!("MechClient initialised");
-        c
-    };
+    let mut mech_client = startup
+        .stage("MechClient", &["net params"], Retry::Transient, || {
+            MechClient::new(&zmq_ctx, &net_params)
+        })
+        .wrap_err("Failed to initialise MechClient")?;
+
+    // Stop the mechanisms if this executable panics, exits or is interrupted, the guard must
+    // live until the end of main
+    #[cfg(feature = "mech")]
+    let _stop_guard = crash_handler::install(
+        startup
+            .stage("MechStopper", &["MechClient"], Retry::Transient, || {
+                mech_client.stopper(&zmq_ctx)
+            })
+            .wrap_err("Failed to create the MechStopper")?,
+    )
+    .wrap_err("Failed to install the crash handler")?;
+    #[cfg(feature = "mech")]
+    info!("Crash handler installed");
 
     #[cfg(feature = "cam")]
-    let mut cam_client = {
-        let c = CamClient::new(&zmq_ctx, &net_params).wrap_err("Failed to initialise CamClient")?;
-        info!("CamClient initialised");
-        c
-    };
+    let mut cam_client = startup
+        .stage("CamClient", &["net params"], Retry::Transient, || {
+            CamClient::new(&zmq_ctx, &net_params)
+        })
+        .wrap_err("Failed to initialise CamClient")?;
 
     #[cfg(feature = "sim")]
-    let sim_client = {
-        let c = SimClient::new(&zmq_ctx, &net_params).wrap_err("Failed to initialise SimClient")?;
-        info!("SimClient initialised");
-        c
-    };
+    let sim_client = startup
+        .stage("SimClient", &["net params"], Retry::Transient, || {
+            SimClient::new(&zmq_ctx, &net_params)
+        })
+        .wrap_err("Failed to initialise SimClient")?;
 
-    let mut tm_server = {
-        let s = TmServer::new(&zmq_ctx, &net_params).wrap_err("Failed to initialise TmServer")?;
-        info!("TmServer initialised");
-        s
-    };
+    // Select the pose source, at the moment only the simulation can provide one
+    #[cfg(feature = "sim")]
+    let mut pose_source: Option<Box<dyn PoseSource>> = Some(Box::new(sim_client));
+    #[cfg(not(feature = "sim"))]
+    let mut pose_source: Option<Box<dyn PoseSource>> = None;
+
+    // Retried as the TM ports may still be held by a previous run which has just exited
+    let mut tm_server = startup
+        .stage("TmServer", &["net params", "tm params"], Retry::Transient, || {
+            TmServer::new(&zmq_ctx, &net_params, &tm_params, traffic_capture.as_ref())
+        })
+        .wrap_err("Failed to initialise TmServer")?;
 
     info!("Network initialisation complete");
 
+    startup.finish();
+
+    // ---- REAL-TIME SCHEDULING ----
+
+    // Done last so that the network threads started above aren't also pinned to the control core.
+    // Failure isn't fatal, the rover just runs with more jitter.
+    if let Some(core) = exec_params.control_loop_core {
+        match host::pin_to_core(core) {
+            Ok(()) => info!("Control loop pinned to core {}", core),
+            Err(e) => warn!("Could not pin the control loop to core {}: {}", core, e),
+        }
+    }
+    if let Some(priority) = exec_params.control_loop_fifo_priority {
+        match host::set_fifo_priority(priority) {
+            Ok(()) => info!("Control loop running with SCHED_FIFO priority {}", priority),
+            Err(e) => warn!("Could not set SCHED_FIFO priority {}: {}", priority, e),
+        }
+    }
+
     // ---- MAIN LOOP ----
 
+    // Time each equipment link started failing, for the automatic reconnection
+    let mut eqpt_failing_since_s: HashMap<Eqpt, f64> = HashMap::new();
+
     info!("Begining main loop\n");
 
     loop {
@@ -222,10 +352,63 @@ fn main() -> Result<(), Report> {
 
         // ---- DATA INPUT ----
 
-        // Debug: Get pose from simulation
-        #[cfg(feature = "sim")]
-        {
-            ds.rov_pose_lm = sim_client.rov_pose_lm();
+        // Get the latest pose
+        ds.auto.rov_pose_lm = pose_source.as_mut().and_then(|s| s.get_pose());
+
+        // Get the latest sensor data from the mechanisms server. The server publishes at a fixed
+        // rate, so if nothing arrives for too long it has stopped.
+        #[cfg(feature = "mech")]
+        match mech_client.get_sensor_data() {
+            Ok(Some(packet)) => {
+                ds.make_unsafe(SafeModeCause::MechClientNotConnected).ok();
+                ds.safety.num_consec_mech_recv_errors = 0;
+
+                match packet.dems_response {
+                    Some(MechDemsResponse::DemsOk) | None => (),
+                    Some(r) => warn!(
+                        "MechServer responded {:?} to demands {:?}",
+                        r, packet.last_dems_seq
+                    ),
+                }
+
+                if ds.mech.dems_ack_stale.set(mech_client.is_ack_stale()) {
+                    match ds.mech.dems_ack_stale.get() {
+                        true => warn!(
+                            "MechServer has not acknowledged recent demands, last acknowledged \
+                            {:?}",
+                            packet.last_dems_seq
+                        ),
+                        false => info!("MechServer acknowledging demands again"),
+                    }
+                }
+
+                if ds.mech.arm_fault.set(packet.arm_fault) {
+                    match packet.arm_fault {
+                        Some(f) => warn!(
+                            "Arm stopped, {:?} stalled drawing {:.2} A at {:.3} rad",
+                            f.act_id, f.current_a, f.pos_rad
+                        ),
+                        None => info!("Arm fault cleared"),
+                    }
+                }
+
+                ds.mech.sens_data = Some(packet.sens);
+            }
+            Ok(None) => {
+                ds.safety.num_consec_mech_recv_errors += 1;
+
+                // If over the limit print error and enter safe mode
+                if ds.safety.num_consec_mech_recv_errors > MAX_MECH_RECV_ERROR_LIMIT {
+                    if !ds.safety.is_safe() {
+                        error!(
+                            "No sensor data from the MechServer for {} cycles",
+                            MAX_MECH_RECV_ERROR_LIMIT
+                        );
+                    }
+                    ds.make_safe(SafeModeCause::MechClientNotConnected);
+                }
+            }
+            Err(e) => warn!("Could not get sensor data from the MechServer: {}", e),
         }
 
         // ---- TELECOMMAND PROCESSING ----
@@ -240,36 +423,55 @@ fn main() -> Result<(), Report> {
                 // If the client is connected remove any safe mode, otherwise make safe
                 if client.is_connected() {
                     ds.make_unsafe(SafeModeCause::TcClientNotConnected).ok();
+                    ds.safety.mode_mgr.boot_complete();
                 } else {
                     ds.make_safe(SafeModeCause::TcClientNotConnected);
                 }
 
-                // Get commands until none remain
+                // Get commands until none remain or the cycle's budget is used up. Every message
+                // counts against the budget, including those which are rejected.
+                let mut num_tcs = 0;
                 loop {
-                    match client.recieve_tc() {
-                        Ok(Some(tc)) => {
-                            // Branch based on safe mode. If we are in safe mode we need to send the
-                            // cannot execute response and should not process the TC, unless it is
-                            // the make unsafe TC
-                            let response_result = match ds.safe {
-                                true => {
-                                    // Execute TC if make unsafe
-                                    match tc {
-                                        Tc::MakeUnsafe => {
-                                            tc_processor::exec(&mut ds, &tc);
-                                            client.send_response(TcResponse::Ok)
-                                        }
-                                        _ => client.send_response(TcResponse::CannotExecute),
-                                    }
-                                }
-                                false => {
-                                    // Process the TC
-                                    tc_processor::exec(&mut ds, &tc);
+                    if num_tcs >= net_params.tc_max_per_cycle {
+                        // Any remaining TCs stay queued until the next cycle, it's only an
+                        // overflow if there actually is one waiting
+                        match client.has_pending_tc() {
+                            Ok(true) => {
+                                warn!(
+                                    "TC budget of {} per cycle used up, remaining TCs are deferred",
+                                    net_params.tc_max_per_cycle
+                                );
+                                ds.hk.num_tc_budget_overflows += 1;
+                            }
+                            Ok(false) => (),
+                            Err(e) => warn!("Could not check for pending TCs: {}", e),
+                        }
+                        break;
+                    }
 
-                                    // Send response
-                                    client.send_response(TcResponse::Ok)
+                    let result = client.recieve_tc();
+                    if !matches!(result, Ok(None) | Err(TcClientError::NotConnected)) {
+                        num_tcs += 1;
+                    }
+
+                    match result {
+                        Ok(Some((tc, mut stamps))) => {
+                            // Only execute the TC if the current mode allows it, otherwise send
+                            // the cannot execute response with the reason
+                            let response = match ds.safety.mode_mgr.accept_tc(&tc) {
+                                Ok(()) => {
+                                    tc_processor::exec(&mut ds, &tc);
+                                    stamps.executed = Some(Utc::now());
+                                    TcResponse::Ok
+                                }
+                                Err(e) => {
+                                    warn!("Rejected TC: {}", e);
+                                    TcResponse::CannotExecute(e.reject_reason())
                                 }
                             };
+                            ds.hk.last_tc_stamps = Some(stamps);
+
+                            let response_result = client.send_response(response);
 
                             // Print warning if couldn't send the response
                             match response_result {
@@ -280,7 +482,7 @@ fn main() -> Result<(), Report> {
                         Ok(None) => break,
                         // If not connected go into safe mode
                         Err(TcClientError::NotConnected) => {
-                            if !ds.safe {
+                            if !ds.safety.is_safe() {
                                 error!("Connection to TcServer lost");
                             }
 
@@ -291,6 +493,15 @@ fn main() -> Result<(), Report> {
                             warn!("Could not parse recieved TC: {}", e);
                             break;
                         }
+                        Err(TcClientError::WrongVehicle(id)) => {
+                            warn!("Rejected TC addressed to vehicle {:?}", id);
+                            continue;
+                        }
+                        Err(e @ TcClientError::Oversized(..)) => {
+                            warn!("Rejected TC: {}", e);
+                            ds.hk.num_tc_oversized += 1;
+                            continue;
+                        }
                         Err(e) => {
                             return Err(e)
                                 .wrap_err("An error occured while receiving TCs from the server")
@@ -300,10 +511,15 @@ fn main() -> Result<(), Report> {
             }
 
             TcSource::Script(ref mut si) => match si.get_pending_tcs() {
-                PendingTcs::None => (),
+                PendingTcs::None => ds.safety.mode_mgr.boot_complete(),
                 PendingTcs::Some(tc_vec) => {
+                    ds.safety.mode_mgr.boot_complete();
+
                     for tc in tc_vec.iter() {
-                        tc_processor::exec(&mut ds, tc);
+                        match ds.safety.mode_mgr.accept_tc(tc) {
+                            Ok(()) => tc_processor::exec(&mut ds, tc),
+                            Err(e) => warn!("Rejected scripted TC: {}", e),
+                        }
                     }
                 }
                 // Exit if end of script reached
@@ -314,15 +530,91 @@ fn main() -> Result<(), Report> {
             },
         };
 
+        // ---- EQUIPMENT RECONNECTION ----
+
+        // Reconnect the equipment requested by TC, and any whose link has been failing for longer
+        // than the reconnect period
+        let mut reconnect = std::mem::take(&mut ds.hk.eqpt_reconnect);
+
+        if let Some(period_s) = exec_params.eqpt_reconnect_period_s {
+            let failing = [
+                (Eqpt::Mech, ds.safety.num_consec_mech_recv_errors > MAX_MECH_RECV_ERROR_LIMIT),
+                (Eqpt::Cam, ds.safety.num_consec_cam_errors > MAX_CAM_ERROR_LIMIT),
+            ];
+
+            for (eqpt, is_failing) in failing {
+                match (is_failing, eqpt_failing_since_s.get(&eqpt)) {
+                    (false, _) => {
+                        eqpt_failing_since_s.remove(&eqpt);
+                    }
+                    (true, None) => {
+                        eqpt_failing_since_s.insert(eqpt, ds.hk.sim_time_s);
+                    }
+                    (true, Some(&t)) if ds.hk.sim_time_s - t >= period_s => {
+                        warn!(
+                            "{:?} link failing for {:.1} s, reconnecting",
+                            eqpt,
+                            ds.hk.sim_time_s - t
+                        );
+                        eqpt_failing_since_s.insert(eqpt, ds.hk.sim_time_s);
+                        reconnect.push(eqpt);
+                    }
+                    (true, Some(_)) => (),
+                }
+            }
+        }
+
+        for eqpt in [Eqpt::Mech, Eqpt::Cam] {
+            if !reconnect.contains(&eqpt) {
+                continue;
+            }
+
+            #[allow(unreachable_patterns)]
+            let result = match eqpt {
+                #[cfg(feature = "mech")]
+                Eqpt::Mech => mech_client
+                    .reconnect(&zmq_ctx, &net_params)
+                    .map_err(|e| e.to_string()),
+                #[cfg(feature = "cam")]
+                Eqpt::Cam => cam_client
+                    .reconnect(&zmq_ctx, &net_params)
+                    .map_err(|e| e.to_string()),
+                _ => Err(String::from("the rover was built without this equipment")),
+            };
+
+            match result {
+                Ok(()) => info!("Reconnected to the {:?} server", eqpt),
+                Err(e) => warn!("Could not reconnect to the {:?} server: {}", eqpt, e),
+            }
+        }
+
         // ---- AUTONOMY PROCESSING ----
 
-        // Make image request on the 1Hz if not in safe mode
+        // Send any pending camera control settings, these take priority over image requests so
+        // that the next images use the new settings
+        #[cfg(feature = "cam")]
+        if let Some(control) = ds.auto.cam_control.take() {
+            match cam_client.request_camera_control(control.clone()) {
+                Ok(()) => info!("Camera control request sent"),
+                Err(CamClientError::WaitingForResponse) => ds.auto.cam_control = Some(control),
+                Err(e) => warn!("Error processing camera control request: {}", e),
+            }
+        }
+
+        // Make image request on the 1Hz if not in safe mode, or if the self test asked for one on
+        // the last cycle
         #[cfg(feature = "cam")]
-        if ds.num_cycles % 5 == 0 && !ds.safe {
+        if (ds.hk.num_cycles % 5 == 0 && !ds.safety.is_safe())
+            || ds.checkout.self_test_output.request_frames
+        {
             match cam_client.request_frames(vec![CamId::LeftNav, CamId::RightNav], ImageFormat::Png)
             {
                 Ok(()) => info!("Camera request sent"),
-                Err(e) => warn!("Error processing camera request: {}", e),
+                Err(CamClientError::WaitingForResponse) => (),
+                Err(e) => {
+                    ds.safety.num_consec_cam_errors += 1;
+                    warn!("Error processing camera request: {}", e)
+                }
             }
         }
 
@@ -331,10 +623,17 @@ fn main() -> Result<(), Report> {
         match cam_client.recieve_images() {
             Ok(Some(images)) => {
                 info!("Got images from CamServer");
+                ds.safety.num_consec_cam_errors = 0;
 
                 let now = chrono::Utc::now();
 
                 for (cam_id, cam_image) in images {
+                    // Remove lens distortion before anything else uses the image
+                    let cam_image = match cam_calib_params.undistort {
+                        true => cam_image.undistort(cam_calib_params.get(cam_id)),
+                        false => cam_image,
+                    };
+
                     // Get the time difference between the image and now
                     let time_diff_ms = now
                         .signed_duration_since(cam_image.timestamp)
@@ -346,10 +645,17 @@ fn main() -> Result<(), Report> {
                         (time_diff_ms as f64) * 0.001
                     );
 
+                    // Downlink the image
+                    if let Err(e) = tm_server.send_image(cam_id, &cam_image) {
+                        warn!("Could not downlink {:?} image: {}", cam_id, e);
+                    }
+
+                    ds.checkout.self_test_input.images.push(cam_id);
+
                     // Set images in datastore
                     match cam_id {
-                        CamId::LeftNav => ds.left_cam_image = Some(cam_image),
-                        CamId::RightNav => ds.right_cam_image = Some(cam_image),
+                        CamId::LeftNav => ds.auto.left_cam_image = Some(cam_image),
+                        CamId::RightNav => ds.auto.right_cam_image = Some(cam_image),
                     };
 
                     // TODO: image saving should go in a separate thread
@@ -372,16 +678,62 @@ fn main() -> Result<(), Report> {
             }
             Ok(None) => (),
             Err(CamClientError::NoRequestMade) => (),
-            Err(e) => warn!("Could not get image response: {}", e),
+            Err(e) => {
+                ds.safety.num_consec_cam_errors += 1;
+                warn!("Could not get image response: {}", e)
+            }
         }
 
         // ---- CONTROL ALGORITHM PROCESSING ----
 
+        // Drawbar test processing, which may command LocoCtrl so must happen first
+        ds.checkout.drawbar_input.safe = ds.safety.is_safe();
+        ds.checkout.drawbar_input.time_s = ds.hk.sim_time_s;
+        ds.checkout.drawbar_input.pose = ds.auto.rov_pose_lm;
+        match ds.checkout.drawbar_test.proc(&ds.checkout.drawbar_input) {
+            Ok((o, r)) => {
+                if let Some(mnvr) = o {
+                    ds.loco.loco_ctrl_input.cmd = Some(mnvr);
+                }
+                ds.checkout.drawbar_status_rpt = r;
+            }
+            Err(e) => warn!("Error during DrawbarTest processing: {}", e),
+        };
+
+        // Turning circle calibration processing, which may also command LocoCtrl
+        ds.checkout.turn_cal_input.safe = ds.safety.is_safe();
+        ds.checkout.turn_cal_input.time_s = ds.hk.sim_time_s;
+        ds.checkout.turn_cal_input.pose = ds.auto.rov_pose_lm;
+        match ds.checkout.turn_cal.proc(&ds.checkout.turn_cal_input) {
+            Ok((o, r)) => {
+                if let Some(mnvr) = o {
+                    ds.loco.loco_ctrl_input.cmd = Some(mnvr);
+                }
+                ds.checkout.turn_cal_status_rpt = r;
+            }
+            Err(e) => warn!("Error during TurnCal processing: {}", e),
+        };
+
+        // Self test processing
+        ds.checkout.self_test_input.safe = ds.safety.is_safe();
+        ds.checkout.self_test_input.time_s = ds.hk.sim_time_s;
+        ds.checkout.self_test_input.sens = ds.mech.sens_data.clone();
+        match ds.checkout.self_test.proc(&ds.checkout.self_test_input) {
+            Ok((o, r)) => {
+                ds.checkout.self_test_output = o;
+                ds.checkout.self_test_status_rpt = r;
+            }
+            Err(e) => {
+                ds.checkout.self_test_output = self_test::OutputData::default();
+                warn!("Error during SelfTest processing: {}", e)
+            }
+        };
+
         // LocoCtrl processing
-        match ds.loco_ctrl.proc(&ds.loco_ctrl_input) {
+        match ds.loco.loco_ctrl.proc(&ds.loco.loco_ctrl_input) {
             Ok((o, r)) => {
-                ds.loco_ctrl_output = o;
-                ds.loco_ctrl_status_rpt = r;
+                ds.loco.loco_ctrl_output = o;
+                ds.loco.loco_ctrl_status_rpt = r;
             }
             Err(e) => {
                 // LocoCtrl errors usually just mean you sent the wrong TC, so just issue the
@@ -390,11 +742,22 @@ fn main() -> Result<(), Report> {
             }
         };
 
+        // WheelRateCtrl processing, trimming the drive demands using any measured rates
+        ds.loco.wheel_rate_ctrl_input.dems = ds.loco.loco_ctrl_output.clone();
+        ds.loco.wheel_rate_ctrl_input.sens = ds.mech.sens_data.clone();
+        match ds.loco.wheel_rate_ctrl.proc(&ds.loco.wheel_rate_ctrl_input) {
+            Ok((o, r)) => {
+                ds.loco.wheel_rate_ctrl_output = o;
+                ds.loco.wheel_rate_ctrl_status_rpt = r;
+            }
+            Err(e) => warn!("Error during WheelRateCtrl processing: {}", e),
+        };
+
         // ArmCtrl processing
-        match ds.arm_ctrl.proc(&ds.arm_ctrl_input) {
+        match ds.mech.arm_ctrl.proc(&ds.mech.arm_ctrl_input) {
             Ok((o, r)) => {
-                ds.arm_ctrl_output = o;
-                ds.arm_ctrl_status_rpt = r;
+                ds.mech.arm_ctrl_output = o;
+                ds.mech.arm_ctrl_status_rpt = r;
             }
             Err(e) => {
                 // LocoCtrl errors usually just mean you sent the wrong TC, so just issue the
@@ -403,50 +766,88 @@ fn main() -> Result<(), Report> {
             }
         };
 
-        // Merge demands from loco and arm ctrls
-        let mut mech_dems = ds.loco_ctrl_output.clone();
-        mech_dems.merge(&ds.arm_ctrl_output);
+        // Archive the drawbar run with this cycle's wheel demands
+        ds.checkout.drawbar_test.write(&ds.checkout.drawbar_input, &ds.loco.loco_ctrl_output);
+
+        // MastCtrl processing
+        match ds.mech.mast_ctrl.proc(&ds.mech.mast_ctrl_input) {
+            Ok((o, r)) => {
+                ds.mech.mast_ctrl_output = o;
+                ds.mech.mast_ctrl_status_rpt = r;
+            }
+            Err(e) => warn!("Error during MastCtrl processing: {}", e),
+        };
+
+        // Merge demands from loco (after wheel rate control), arm and mast ctrls
+        let mut mech_dems = ds.loco.wheel_rate_ctrl_output.clone();
+        mech_dems.merge(&ds.mech.arm_ctrl_output);
+        mech_dems.merge(&ds.mech.mast_ctrl_output);
+
+        // The self test takes over the wheels while it's running
+        if let Some(ref dems) = ds.checkout.self_test_output.dems {
+            for (&act_id, &pos_rad) in dems.pos_rad.iter() {
+                mech_dems.pos_rad.insert(act_id, pos_rad);
+            }
+            for (&act_id, &speed_rads) in dems.speed_rads.iter() {
+                mech_dems.speed_rads.insert(act_id, speed_rads);
+            }
+        }
+
+        // There is no autonomy manager yet so autonomy is never reported as active
+        let mut mech_flags = MechDemsFlags::default();
+
+        // During steer calibration the steer axes are driven to the calibration positions, with
+        // the rover held still
+        if let Some(ref cal) = ds.loco.steer_cal_pos_rad {
+            for (&act_id, &pos_rad) in cal.iter() {
+                mech_dems.pos_rad.insert(act_id, pos_rad);
+            }
+            for &act_id in ActId::drv_ids() {
+                mech_dems.speed_rads.insert(act_id, 0.0);
+            }
+
+            if ds.loco.steer_cal_capture {
+                info!("Capturing steer zeros, steer calibration complete");
+                mech_flags.capture_steer_zero = true;
+                ds.loco.steer_cal_pos_rad = None;
+            }
+        }
+        ds.loco.steer_cal_capture = false;
 
         // Send demands to mechanisms
         #[cfg(feature = "mech")]
-        match mech_client.send_demands(&mech_dems) {
-            Ok(MechDemsResponse::DemsOk) => {
-                ds.make_unsafe(SafeModeCause::MechClientNotConnected).ok();
-
-                // Reset the recieve error counter
-                ds.num_consec_mech_recv_errors = 0;
+        match mech_client.send_demands(&mech_dems, mech_flags) {
+            Ok(()) => {
+                // Stamp the actuation of the last TC the first time demands follow it
+                if let Some(ref mut s) = ds.hk.last_tc_stamps {
+                    if s.executed.is_some() && s.actuated.is_none() {
+                        s.actuated = Some(Utc::now());
+                    }
+                }
             }
-            Ok(r) => warn!("Recieved non-nominal response from MechServer: {:?}", r),
             Err(MechClientError::NotConnected) => {
-                if !ds.safe {
+                if !ds.safety.is_safe() {
                     error!("Connection to the MechServer lost");
                 }
                 ds.make_safe(SafeModeCause::MechClientNotConnected);
             }
-            Err(MechClientError::RecvError(_)) => {
-                ds.num_consec_mech_recv_errors += 1;
-
-                // If over the limit print error and enter safe mode
-                if ds.num_consec_mech_recv_errors > MAX_MECH_RECV_ERROR_LIMIT {
-                    if !ds.safe {
-                        error!(
-                            "Maximum number of MechClient Recieve Errors ({}) has been exceeded",
-                            MAX_MECH_RECV_ERROR_LIMIT
-                        );
-                    }
-                    ds.make_safe(SafeModeCause::MechClientNotConnected);
-                }
-            }
             Err(e) => warn!("MechClient processing error: {}", e),
         }
 
         // ---- WRITE ARCHIVES ----
         // FIXME: Currently disabled as archiving isn't working quite right
-        // ds.loco_ctrl.write().unwrap();
+        // ds.loco.loco_ctrl.write().unwrap();
 
         // ---- TELEMETRY ----
 
-        match tm_server.send(&ds) {
+        // The rover is in contact with the ground while the TC link is up, telemetry generated
+        // outside of contact is backfilled once it returns
+        let ground_contact = match tc_source {
+            TcSource::Remote(ref client) => client.is_connected(),
+            _ => true,
+        };
+
+        match tm_server.send(&ds, ground_contact) {
             Ok(_) => (),
             Err(e) => warn!("TmServer error: {}", e),
         };
@@ -455,10 +856,14 @@ fn main() -> Result<(), Report> {
 
         let cycle_dur = Instant::now() - cycle_start_instant;
 
+        if let Err(e) = cycle_arch.write(&ds, cycle_dur.as_secs_f64()) {
+            warn!("{}", e);
+        }
+
         // Get sleep duration
         match Duration::from_secs_f64(CYCLE_PERIOD_S).checked_sub(cycle_dur) {
             Some(d) => {
-                ds.num_consec_cycle_overruns = 0;
+                ds.hk.num_consec_cycle_overruns = 0;
                 thread::sleep(d);
             }
             None => {
@@ -466,11 +871,11 @@ fn main() -> Result<(), Report> {
                     "Cycle overran by {:.06} s",
                     cycle_dur.as_secs_f64() - Duration::from_secs_f64(CYCLE_PERIOD_S).as_secs_f64()
                 );
-                ds.num_consec_cycle_overruns += 1;
+                ds.hk.num_consec_cycle_overruns += 1;
 
                 // If number of overruns greater than the limit exit
                 // TODO impl as param?
-                // if ds.num_consec_cycle_overruns > 500 {
+                // if ds.hk.num_consec_cycle_overruns > 500 {
                 //     raise_error!("More than 500 consecutive cycle overruns!");
                 // }
             }
@@ -478,7 +883,7 @@ fn main() -> Result<(), Report> {
 
         // Increment cycle counter
         // TODO: put this in a DataStore::cycle_end() function?
-        ds.num_cycles += 1;
+        ds.hk.num_cycles += 1;
     }
 
     // ---- SHUTDOWN ----
@@ -488,6 +893,51 @@ fn main() -> Result<(), Report> {
     Ok(())
 }
 
+/// Get the names of the cargo features this executable was built with.
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+
+    if cfg!(feature = "mech") {
+        features.push("mech");
+    }
+    if cfg!(feature = "cam") {
+        features.push("cam");
+    }
+    if cfg!(feature = "imu") {
+        features.push("imu");
+    }
+    if cfg!(feature = "sim") {
+        features.push("sim");
+    }
+    if cfg!(feature = "grpc") {
+        features.push("grpc");
+    }
+
+    features
+}
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Parameters for the executable itself, loaded from `rov_exec.toml`.
+#[derive(Deserialize)]
+struct ExecParams {
+    /// Retries of the start-up stages
+    #[serde(default)]
+    startup: StartupParams,
+
+    /// Time an equipment link must have been failing for before it is automatically reconnected,
+    /// or `None` to only reconnect when commanded with `eqpt reconnect`
+    eqpt_reconnect_period_s: Option<f64>,
+
+    /// `SCHED_FIFO` priority to run the control loop at, or `None` to use the default scheduler
+    control_loop_fifo_priority: Option<i32>,
+
+    /// Core to pin the control loop to, or `None` to let the OS choose
+    control_loop_core: Option<usize>,
+}
+
 // ---------------------------------------------------------------------------
 // ENUMERATIONS
 // ---------------------------------------------------------------------------
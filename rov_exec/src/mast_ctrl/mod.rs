@@ -0,0 +1,36 @@
+//! Mast control module
+//!
+//! Points the pan/tilt mast, moving each axis towards its target at a limited rate.
+
+// ---------------------------------------------------------------------------
+// MODULES
+// ---------------------------------------------------------------------------
+
+mod params;
+mod state;
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// Internal
+pub use params::*;
+pub use state::*;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// The number of axes on the mast (pan then tilt).
+pub const NUM_MAST_AXES: usize = 2;
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Possible errors that can occur during MastCtrl operation.
+#[derive(Debug, thiserror::Error)]
+pub enum MastCtrlError {
+    #[error("Recieved an invalid mast command: {0}")]
+    InvalidMastCmd(String),
+}
@@ -0,0 +1,45 @@
+//! Parameters structure for MastCtrl
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use super::NUM_MAST_AXES;
+use serde::{Deserialize, Serialize};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Parameters for Mast control.
+///
+/// All arrays are ordered [pan, tilt].
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Params {
+    // ---- CAPABILITIES ----
+    /// Maximum axis position (highest positive value)
+    ///
+    /// Units: radians
+    pub max_pos_rad: [f64; NUM_MAST_AXES],
+
+    /// Minimum axis position (lowest negative value)
+    ///
+    /// Units: radians
+    pub min_pos_rad: [f64; NUM_MAST_AXES],
+
+    /// Maximum absolute axis rate
+    ///
+    /// Units: radians/second
+    pub max_abs_rate_rads: [f64; NUM_MAST_AXES],
+
+    /// Stowed position of the mast, also used at startup.
+    ///
+    /// Units: radians
+    pub stow_pos_rad: [f64; NUM_MAST_AXES],
+
+    /// Difference between the current and target position below which the mast is considered to
+    /// have reached its target.
+    ///
+    /// Units: radians
+    pub at_target_tolerance_rad: f64,
+}
@@ -0,0 +1,175 @@
+//! Implementations for the MastCtrl state structure
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Internal
+use super::{MastCtrlError, Params, NUM_MAST_AXES};
+use comms_if::{
+    eqpt::mech::{ActId, MechDems},
+    tc::mast_ctrl::MastCmd,
+    tm::TmMeta,
+};
+use util::{module::State, params, session::Session};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Mast control module state
+#[derive(Default)]
+pub struct MastCtrl {
+    pub(crate) params: Params,
+
+    pub(crate) report: StatusReport,
+
+    /// Current demanded position of each axis, [pan, tilt]
+    pub(crate) current_pos_rad: [f64; NUM_MAST_AXES],
+
+    /// Position each axis is moving towards, [pan, tilt]
+    pub(crate) target_pos_rad: [f64; NUM_MAST_AXES],
+}
+
+/// Input data to Mast Control.
+#[derive(Default)]
+pub struct InputData {
+    /// The command to be executed, or `None` if there is no new command on
+    /// this cycle.
+    pub cmd: Option<MastCmd>,
+}
+
+/// Status report for MastCtrl processing.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, TmMeta)]
+pub struct StatusReport {
+    /// True if the target of an axis was limited by its position limits
+    pub pos_limited: [bool; NUM_MAST_AXES],
+
+    /// True once the mast has reached its target
+    pub at_target: bool,
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl State for MastCtrl {
+    type InitData = &'static str;
+    type InitError = params::LoadError;
+
+    type InputData = InputData;
+    type OutputData = MechDems;
+    type StatusReport = StatusReport;
+    type ProcError = MastCtrlError;
+
+    /// Initialise the MastCtrl module.
+    ///
+    /// Expected init data is the path to the parameter file
+    fn init(
+        &mut self,
+        init_data: Self::InitData,
+        _session: &Session,
+    ) -> Result<(), Self::InitError> {
+        // Load the parameters
+        self.params = params::load(init_data)?;
+
+        // Start stowed
+        self.current_pos_rad = self.params.stow_pos_rad;
+        self.target_pos_rad = self.params.stow_pos_rad;
+
+        Ok(())
+    }
+
+    /// Perform cyclic processing of Mast Control.
+    fn proc(
+        &mut self,
+        input_data: &Self::InputData,
+    ) -> Result<(Self::OutputData, Self::StatusReport), Self::ProcError> {
+        // Position limit flags are latched until the next command
+        self.report.at_target = false;
+
+        // Check to see if there's a new command
+        if let Some(cmd) = input_data.cmd {
+            debug!("New MastCtrl MastCmd::{:?}", cmd);
+
+            self.report.pos_limited = [false; NUM_MAST_AXES];
+
+            match cmd {
+                MastCmd::Point { az_rad, el_rad } => {
+                    if !az_rad.is_finite() || !el_rad.is_finite() {
+                        return Err(MastCtrlError::InvalidMastCmd(format!(
+                            "Pointing angles must be finite, got ({}, {})",
+                            az_rad, el_rad
+                        )));
+                    }
+                    self.target_pos_rad = [az_rad, el_rad];
+                }
+                MastCmd::Stow => self.target_pos_rad = self.params.stow_pos_rad,
+                MastCmd::Stop => self.target_pos_rad = self.current_pos_rad,
+            }
+
+            self.enforce_limits();
+        }
+
+        // Move each axis towards the target at no more than the max rate
+        let mut at_target = true;
+        for i in 0..NUM_MAST_AXES {
+            let max_step_rad = self.params.max_abs_rate_rads[i] / crate::CYCLE_FREQUENCY_HZ;
+
+            let error_rad = self.target_pos_rad[i] - self.current_pos_rad[i];
+            self.current_pos_rad[i] += error_rad.clamp(-max_step_rad, max_step_rad);
+
+            if (self.target_pos_rad[i] - self.current_pos_rad[i]).abs()
+                > self.params.at_target_tolerance_rad
+            {
+                at_target = false;
+            }
+        }
+        self.report.at_target = at_target;
+
+        Ok((self.output(), self.report))
+    }
+}
+
+impl MastCtrl {
+    /// Function called when entering safe mode.
+    ///
+    /// Holds the mast at its current position.
+    pub fn make_safe(&mut self) {
+        self.target_pos_rad = self.current_pos_rad;
+    }
+
+    /// Build the actuator demands from the current position.
+    fn output(&self) -> MechDems {
+        let mut pos_rad = HashMap::new();
+
+        for (i, &act_id) in ActId::mast_ids().iter().enumerate() {
+            pos_rad.insert(act_id, self.current_pos_rad[i]);
+        }
+
+        MechDems {
+            pos_rad,
+            speed_rads: HashMap::new(),
+        }
+    }
+
+    /// Limit the target to the mast's range of motion, raising the
+    /// corresponding flag in the status report.
+    fn enforce_limits(&mut self) {
+        for i in 0..NUM_MAST_AXES {
+            if self.target_pos_rad[i] > self.params.max_pos_rad[i] {
+                self.target_pos_rad[i] = self.params.max_pos_rad[i];
+                self.report.pos_limited[i] = true;
+            }
+            if self.target_pos_rad[i] < self.params.min_pos_rad[i] {
+                self.target_pos_rad[i] = self.params.min_pos_rad[i];
+                self.report.pos_limited[i] = true;
+            }
+        }
+    }
+}
@@ -1,24 +1,98 @@
 //! # Mechanisms Client
 //!
 //! This module provides networking abstractions to connect to the mechanisms server.
+//!
+//! Demands are pushed to the server with a sequence number and no reply is expected, so sending
+//! never waits on the server. The server publishes sensor data at a fixed rate, which the client
+//! reads without blocking and also uses to tell that the server is alive.
+//!
+//! Each client picks a new session ID when it's created, so that the server knows to restart the
+//! sequence numbers after the rover executable restarts. The sensor data echoes the session and
+//! sequence number of the latest demands the server accepted, and the client flags the
+//! acknowledgement as stale if it belongs to another session or stops advancing.
+//!
+//! A [`MechStopper`] can be taken from the client to stop the mechanisms from anywhere, including
+//! a panic hook, see `crash_handler`. Once a stop has been sent the client refuses to send any more
+//! demands, so the main loop can't restart the mechanisms while the process is exiting.
 
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
 use comms_if::{
-    eqpt::mech::{MechDems, MechSensData, MechDemsResponse}, 
-    net::{MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, zmq}
+    eqpt::mech::{ActId, MechDems, MechDemsFlags, MechSensPacket}, 
+    net::{new_session_id, MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, zmq}
 };
 
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Initial size of the demands serialization buffer, large enough for all actuators.
+const DEMS_BUFFER_INITIAL_CAPACITY: usize = 2 * 1024;
+
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
 // ------------------------------------------------------------------------------------------------
 
 pub struct MechClient {
+    /// PUSH socket which sends demands to the server
     dems_socket: MonitoredSocket,
 
-    _sens_socket: MonitoredSocket
+    /// SUB socket which recieves sensor data from the server
+    sens_socket: MonitoredSocket,
+
+    /// ID of this client's session, sent with all demands
+    session_id: u64,
+
+    /// Sequence number of the last demands sent, shared with any stoppers
+    seq: Arc<AtomicU64>,
+
+    /// Set by any stopper before it sends the stop, after which no demands are sent
+    stopped: Arc<AtomicBool>,
+
+    /// Sequence number of the last demands sent when the previous sensor data arrived
+    prev_sens_sent_seq: u64,
+
+    /// True if the server's acknowledgement of the demands is stale
+    ack_stale: bool,
+
+    /// Endpoint of the demands socket, used to connect stoppers
+    dems_endpoint: String,
+
+    /// Buffer demands are serialized into, reused each cycle to avoid allocating
+    dems_buffer: Vec<u8>,
+
+    /// Message sensor data is recieved into, reused each cycle to avoid allocating
+    sens_msg: zmq::Message
+}
+
+/// Sends a stop to the mechanisms server independently of the client.
+///
+/// The stopper has its own socket, so it can be used from any thread, but shares the client's
+/// sequence numbers so the server always treats the stop as the newest demands.
+pub struct MechStopper {
+    socket: Mutex<zmq::Socket>,
+
+    session_id: u64,
+
+    seq: Arc<AtomicU64>,
+
+    stopped: Arc<AtomicBool>,
+}
+
+/// Borrowing equivalent of `MechDemsPacket`, so the demands don't have to be cloned to send them.
+#[derive(Serialize)]
+struct DemsPacketRef<'a> {
+    session_id: u64,
+    seq: u64,
+    dems: &'a MechDems,
+    flags: MechDemsFlags
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -43,9 +117,15 @@ pub enum MechClientError {
     #[error("Could not serialize the data: {0}")]
     SerializationError(serde_json::Error),
 
-    #[error("Could not deserialize the response from the server: {0}")]
+    #[error("Could not deserialize the sensor data from the server: {0}")]
     DeserializeError(serde_json::Error),
 
+    #[error("Could not create the stopper's socket: {0}")]
+    StopperSocketError(zmq::Error),
+
+    #[error("The mechanisms have been stopped, no more demands may be sent")]
+    Stopped,
+
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -64,27 +144,26 @@ impl MechClient {
             heartbeat_ttl: 1000,
             heartbeat_timeout: 1000,
             linger: 1,
-            recv_timeout: 35,
             send_timeout: 10,
-            req_correlate: true,
-            req_relaxed: true,
             ..Default::default()
         };
+        // Only the latest sensor data is used, so older packets needn't be queued
         let sens_socket_options = SocketOptions {
             block_on_first_connect: false,
+            conflate: true,
             ..Default::default()
         };
 
         // Create the sockets
         let dems_socket = MonitoredSocket::new(
             ctx,
-            zmq::REQ,
+            zmq::PUSH,
             dems_socket_options,
             &params.mech_dems_endpoint
         ).map_err(|e| MechClientError::SocketError(e))?;
         let sens_socket = MonitoredSocket::new(
             ctx,
-            zmq::REQ,
+            zmq::SUB,
             sens_socket_options,
             &params.mech_sens_endpoint
         ).map_err(|e| MechClientError::SocketError(e))?;
@@ -92,50 +171,297 @@ impl MechClient {
         // Create self
         Ok(Self {
             dems_socket,
-            _sens_socket: sens_socket
+            sens_socket,
+            session_id: new_session_id(),
+            seq: Arc::new(AtomicU64::new(0)),
+            stopped: Arc::new(AtomicBool::new(false)),
+            prev_sens_sent_seq: 0,
+            ack_stale: false,
+            dems_endpoint: params.mech_dems_endpoint.clone(),
+            dems_buffer: Vec::with_capacity(DEMS_BUFFER_INITIAL_CAPACITY),
+            sens_msg: zmq::Message::new()
         })
     }
 
+    /// Close and reopen the sockets to the server.
+    ///
+    /// The session and demand sequence number carry on from the old sockets, so the server and
+    /// any `MechStopper` still agree on which demands are newest.
+    pub fn reconnect(
+        &mut self,
+        ctx: &zmq::Context,
+        params: &NetParams,
+    ) -> Result<(), MechClientError> {
+        let new = Self::new(ctx, params)?;
+
+        self.dems_socket = new.dems_socket;
+        self.sens_socket = new.sens_socket;
+        self.dems_endpoint = new.dems_endpoint;
+
+        Ok(())
+    }
+
     /// Send demands to the server.
     ///
-    /// Sends the given mechanisms demands to the server. If the server acknowledges the demands
-    /// within the configured timeout then `Ok()` is returned, otherwise an `Err()` is returned.
+    /// Sends the given mechanisms demands to the server without waiting for a reply. Whether the
+    /// server accepted them is reported in the sensor data, see `get_sensor_data`.
+    ///
+    /// `flags` are passed on to the server along with the demands.
+    ///
+    /// Once a `MechStopper` has sent a stop this returns `Stopped` without sending anything.
     pub fn send_demands(
         &mut self, 
-        demands: &MechDems
-    ) -> Result<MechDemsResponse, MechClientError> {
+        demands: &MechDems, 
+        flags: MechDemsFlags
+    ) -> Result<(), MechClientError> {
         // If not connected return now
         if !self.dems_socket.connected() {
             return Err(MechClientError::NotConnected)
         }
 
-        // Serialize the demands
-        let dems_str = serde_json::to_string(demands)
-            .map_err(|e| MechClientError::SerializationError(e))?;
+        // Take the sequence number before checking for a stop. If the stop flag is still clear the
+        // stopper hasn't taken its number yet, so the stop is newer than these demands and the
+        // server will still act on it.
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.stopped.load(Ordering::SeqCst) {
+            return Err(MechClientError::Stopped)
+        }
+
+        // Serialize the demands into the reused buffer
+        self.dems_buffer.clear();
+        serde_json::to_writer(&mut self.dems_buffer, &DemsPacketRef {
+            session_id: self.session_id,
+            seq,
+            dems: demands,
+            flags
+        }).map_err(MechClientError::SerializationError)?;
 
         // Send the demands to the server
-        self.dems_socket.send(&dems_str, 0)
-            .map_err(|e| MechClientError::SendError(e))?;
-        
-        // Recieve response back from the server
-        let msg = self.dems_socket.recv_msg(0);
-
-        match msg {
-            Ok(m) => {
-                serde_json::from_str(m.as_str().unwrap_or(""))
-                    .map_err(|e| MechClientError::DeserializeError(e))
-            },
-            Err(e) => {
-                Err(MechClientError::RecvError(e))
+        self.dems_socket.send(&self.dems_buffer[..], zmq::DONTWAIT)
+            .map_err(MechClientError::SendError)
+    }
+
+    /// Create a stopper which can stop the mechanisms without access to this client.
+    ///
+    /// The stopper connects its socket straight away, so that it's ready to send if the rover
+    /// executable crashes.
+    pub fn stopper(&self, ctx: &zmq::Context) -> Result<MechStopper, MechClientError> {
+        let socket = ctx.socket(zmq::PUSH).map_err(MechClientError::StopperSocketError)?;
+
+        // Give the stop a short time to leave before the process exits, without holding up the
+        // exit if the server has gone
+        socket.set_linger(100).map_err(MechClientError::StopperSocketError)?;
+        socket.set_sndtimeo(10).map_err(MechClientError::StopperSocketError)?;
+        socket.connect(&self.dems_endpoint).map_err(MechClientError::StopperSocketError)?;
+
+        Ok(MechStopper {
+            socket: Mutex::new(socket),
+            session_id: self.session_id,
+            seq: self.seq.clone(),
+            stopped: self.stopped.clone(),
+        })
+    }
+
+    /// Get the latest sensor data from the server.
+    ///
+    /// All waiting sensor data is read and only the newest returned. If no sensor data has 
+    /// arrived since the last call `None` is returned.
+    ///
+    /// The server's acknowledgement of the demands in the returned packet is checked, see
+    /// `is_ack_stale`.
+    pub fn get_sensor_data(&mut self) -> Result<Option<MechSensPacket>, MechClientError> {
+        if !self.recv_sensor_msg()? {
+            return Ok(None)
+        }
+
+        let packet: MechSensPacket = serde_json::from_slice(&self.sens_msg)
+            .map_err(MechClientError::DeserializeError)?;
+
+        self.check_ack(&packet);
+
+        Ok(Some(packet))
+    }
+
+    /// True if the server's acknowledgement of the demands in the last sensor data was stale.
+    ///
+    /// The acknowledgement is stale if demands sent before the previous sensor data arrived still
+    /// haven't been acknowledged in this session. This allows the server one sensor period to
+    /// accept the demands.
+    pub fn is_ack_stale(&self) -> bool {
+        self.ack_stale
+    }
+
+    /// Read all waiting sensor data into the reused message, returning true if any arrived.
+    fn recv_sensor_msg(&mut self) -> Result<bool, MechClientError> {
+        let mut received = false;
+
+        loop {
+            match self.sens_socket.recv(&mut self.sens_msg, zmq::DONTWAIT) {
+                Ok(()) => received = true,
+                Err(zmq::Error::EAGAIN) => break,
+                Err(e) => return Err(MechClientError::RecvError(e))
             }
         }
+
+        Ok(received)
     }
 
-    /// Get the latest sensor data message from the server.
+    fn check_ack(&mut self, packet: &MechSensPacket) {
+        let acked = match (packet.last_dems_session_id, packet.last_dems_seq) {
+            (Some(id), Some(seq)) if id == self.session_id => seq,
+            _ => 0,
+        };
+
+        self.ack_stale = acked < self.prev_sens_sent_seq;
+        self.prev_sens_sent_seq = self.seq.load(Ordering::SeqCst);
+    }
+}
+
+impl MechStopper {
+    /// Send zero speed demands with the E-stop flag set, which stops all actuators.
     ///
-    /// If no sensor data is available `None` is returned.
-    /// TODO: implement
-    pub fn get_sensor_data(&mut self) -> Option<MechSensData> {
-        todo!("Not yet implemented")
+    /// This is a best effort, errors are returned but the demands may still be lost if the server
+    /// isn't running. The client won't send demands after this has been called, even if the stop
+    /// failed.
+    pub fn stop(&self) -> Result<(), MechClientError> {
+        // Must be set before the sequence number is taken, see `MechClient::send_demands`
+        self.stopped.store(true, Ordering::SeqCst);
+
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let mut dems = MechDems::default();
+        for &act_id in ActId::drv_ids() {
+            dems.speed_rads.insert(act_id, 0.0);
+        }
+
+        let data = serde_json::to_vec(&DemsPacketRef {
+            session_id: self.session_id,
+            seq,
+            dems: &dems,
+            flags: MechDemsFlags {
+                estop: true,
+                ..Default::default()
+            }
+        }).map_err(MechClientError::SerializationError)?;
+
+        // A poisoned lock means a previous stop panicked part way through, the socket is still
+        // usable
+        let socket = match self.socket.lock() {
+            Ok(s) => s,
+            Err(e) => e.into_inner()
+        };
+
+        socket.send(data, 0).map_err(MechClientError::SendError)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{count_allocs, net_params};
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_steady_state_does_not_allocate() {
+        let ctx = zmq::Context::new();
+
+        // Stand in for the server
+        let dems_server = ctx.socket(zmq::PULL).unwrap();
+        dems_server.bind("tcp://127.0.0.1:*").unwrap();
+        let sens_server = ctx.socket(zmq::PUB).unwrap();
+        sens_server.bind("tcp://127.0.0.1:*").unwrap();
+
+        let params = net_params(
+            &dems_server.get_last_endpoint().unwrap().unwrap(),
+            &sens_server.get_last_endpoint().unwrap().unwrap(),
+            "inproc://tm",
+            "inproc://img_tm",
+        );
+        let mut client = MechClient::new(&ctx, &params).unwrap();
+
+        let dems = MechDems::empty_loco();
+        let sens = serde_json::to_vec(&MechSensPacket::default()).unwrap();
+
+        // Wait for the subscription to reach the server
+        let mut received = false;
+        for _ in 0..100 {
+            sens_server.send(&sens, 0).unwrap();
+            thread::sleep(Duration::from_millis(10));
+
+            if client.recv_sensor_msg().unwrap() {
+                received = true;
+                break;
+            }
+        }
+        assert!(received, "No sensor data recieved");
+
+        // First send grows the buffer
+        client.send_demands(&dems, MechDemsFlags::default()).unwrap();
+
+        for _ in 0..10 {
+            sens_server.send(&sens, 0).unwrap();
+            thread::sleep(Duration::from_millis(10));
+
+            let num_allocs = count_allocs(|| {
+                client.send_demands(&dems, MechDemsFlags::default()).unwrap();
+                assert!(client.recv_sensor_msg().unwrap());
+            });
+            assert_eq!(num_allocs, 0);
+        }
+
+        // All the demands arrived
+        dems_server.set_rcvtimeo(1000).unwrap();
+        for _ in 0..11 {
+            dems_server.recv_bytes(0).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_no_demands_after_stop() {
+        let ctx = zmq::Context::new();
+
+        let dems_server = ctx.socket(zmq::PULL).unwrap();
+        dems_server.bind("tcp://127.0.0.1:*").unwrap();
+        dems_server.set_rcvtimeo(1000).unwrap();
+
+        let params = net_params(
+            &dems_server.get_last_endpoint().unwrap().unwrap(),
+            "tcp://127.0.0.1:1",
+            "inproc://tm",
+            "inproc://img_tm",
+        );
+        let mut client = MechClient::new(&ctx, &params).unwrap();
+        let stopper = client.stopper(&ctx).unwrap();
+
+        // Wait for the demands socket to connect
+        for _ in 0..100 {
+            if client.dems_socket.connected() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let dems = MechDems::empty_loco();
+        client.send_demands(&dems, MechDemsFlags::default()).unwrap();
+        stopper.stop().unwrap();
+        assert!(matches!(
+            client.send_demands(&dems, MechDemsFlags::default()),
+            Err(MechClientError::Stopped)
+        ));
+
+        let packets: Vec<serde_json::Value> = (0..2)
+            .map(|_| serde_json::from_slice(&dems_server.recv_bytes(0).unwrap()).unwrap())
+            .collect();
+        assert_eq!(packets[0]["flags"]["estop"], false);
+        assert_eq!(packets[1]["flags"]["estop"], true);
+        assert!(packets[1]["seq"].as_u64() > packets[0]["seq"].as_u64());
+
+        // Nothing was sent after the stop
+        dems_server.set_rcvtimeo(100).unwrap();
+        assert!(dems_server.recv_bytes(0).is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,415 @@
+//! # Mode Manager
+//!
+//! Tracks the operational mode of the rover and decides which TCs may be executed in each mode.
+//!
+//! Modes and the transitions between them:
+//!
+//! - `Boot` - initialising, left once the TC source is ready.
+//! - `Standby` - ready, nothing is being commanded.
+//! - `Manual` - under direct control from the ground, entered from `Standby` by the first motion
+//!   TC.
+//! - `Autonomous` - an autonomous traverse is driving the rover. Motion TCs are rejected so that
+//!   they don't fight autonomy for the wheels.
+//! - `Safe` - all motion disabled, entered from any mode by `DataStore::make_safe`.
+//! - `Recovery` - the cause of safe mode has cleared by itself, for example a link has come back.
+//!   Data TCs and checkouts which don't move the rover, such as capturing the steer offsets, may
+//!   be sent, but no motion is allowed until the `unsafe` TC is sent to return to `Standby`.
+//!
+//! Checkouts which move the rover, such as `selftest`, are treated as motion TCs.
+//!
+//! If safe mode was caused by the `safe` TC, `unsafe` returns straight to `Standby`.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::info;
+use serde::{Deserialize, Serialize};
+
+// Internal
+use comms_if::tc::{
+    calibrate::{CalibrateCmd, SteerCalCmd, TurnCalCmd},
+    Tc, TcRejectReason,
+};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Rover mode manager
+#[derive(Default)]
+pub struct ModeManager {
+    mode: Mode,
+
+    /// The mode before safe mode was entered
+    pre_safe_mode: Mode,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Operational mode of the rover.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    #[default]
+    Boot,
+    Standby,
+    Manual,
+    Autonomous,
+    Safe,
+    Recovery,
+}
+
+/// Classes of TC, which are allowed or rejected together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TcClass {
+    /// Entering and leaving safe mode
+    Safing,
+
+    /// Managing data on the rover, such as path uplinks and camera settings, and the links to the
+    /// equipment
+    Data,
+
+    /// Direct motion commands
+    Manual,
+
+    /// Starting and controlling autonomy
+    Autonomy,
+
+    /// Steps of checkouts and calibrations which don't move the rover
+    Checkout,
+}
+
+/// Errors from the mode manager.
+#[derive(Debug, thiserror::Error)]
+pub enum ModeError {
+    #[error("Cannot change mode from {0:?} to {1:?}")]
+    TransitionNotAllowed(Mode, Mode),
+
+    #[error("TC not allowed in {0:?} mode")]
+    TcNotAllowed(Mode),
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl ModeManager {
+    /// Get the current mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Change to the given mode, if the transition is allowed.
+    pub fn request(&mut self, to: Mode) -> Result<(), ModeError> {
+        if to == self.mode {
+            return Ok(());
+        }
+
+        if !transition_allowed(self.mode, to) {
+            return Err(ModeError::TransitionNotAllowed(self.mode, to));
+        }
+
+        info!("Mode change: {:?} -> {:?}", self.mode, to);
+
+        if to == Mode::Safe {
+            self.pre_safe_mode = self.mode;
+        }
+        self.mode = to;
+
+        Ok(())
+    }
+
+    /// Move from boot to standby once the TC source is ready.
+    pub fn boot_complete(&mut self) {
+        if self.mode == Mode::Boot {
+            self.request(Mode::Standby).ok();
+        }
+    }
+
+    /// Enter safe mode, which is allowed from any mode.
+    pub fn make_safe(&mut self) {
+        self.request(Mode::Safe).ok();
+    }
+
+    /// Leave safe mode once its cause has cleared.
+    ///
+    /// `by_operator` should be true if the safe TC was cleared by the unsafe TC. Otherwise the
+    /// rover goes into recovery, unless it was still booting.
+    pub fn clear_safe(&mut self, by_operator: bool) {
+        if self.mode != Mode::Safe {
+            return;
+        }
+
+        let to = match by_operator || self.pre_safe_mode == Mode::Boot {
+            true => Mode::Standby,
+            false => Mode::Recovery,
+        };
+
+        self.request(to).ok();
+    }
+
+    /// Check whether a TC may be executed in the current mode.
+    ///
+    /// If it may, any mode change implied by the TC is made, for example the first motion TC in
+    /// standby moves to manual.
+    pub fn accept_tc(&mut self, tc: &Tc) -> Result<(), ModeError> {
+        let class = classify(tc);
+
+        let allowed = match (self.mode, class) {
+            (_, TcClass::Safing) => true,
+            (Mode::Safe, _) => class == TcClass::Data,
+            (Mode::Boot, _) => false,
+            (Mode::Recovery, c) => c == TcClass::Data || c == TcClass::Checkout,
+            (Mode::Autonomous, c) => c == TcClass::Data || c == TcClass::Autonomy,
+            (Mode::Standby, _) | (Mode::Manual, _) => true,
+        };
+
+        if !allowed {
+            return Err(ModeError::TcNotAllowed(self.mode));
+        }
+
+        match (self.mode, tc) {
+            (Mode::Standby, _) if class == TcClass::Manual => self.request(Mode::Manual),
+            (Mode::Recovery, Tc::MakeUnsafe) => self.request(Mode::Standby),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ModeError {
+    /// Get the reason sent to the ground when a TC is rejected because of this error.
+    ///
+    /// Every error is currently a mode which doesn't allow the TC.
+    pub fn reject_reason(&self) -> TcRejectReason {
+        TcRejectReason::ModeNotAllowed
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// The table of allowed mode transitions.
+fn transition_allowed(from: Mode, to: Mode) -> bool {
+    use Mode::*;
+
+    matches!(
+        (from, to),
+        // Safe mode can always be entered
+        (_, Safe)
+            | (Boot, Standby)
+            | (Standby, Manual)
+            | (Standby, Autonomous)
+            | (Manual, Standby)
+            | (Manual, Autonomous)
+            | (Autonomous, Standby)
+            | (Autonomous, Manual)
+            | (Safe, Standby)
+            | (Safe, Recovery)
+            | (Recovery, Standby)
+    )
+}
+
+/// Get the class of a TC.
+fn classify(tc: &Tc) -> TcClass {
+    match tc {
+        Tc::MakeSafe | Tc::MakeUnsafe => TcClass::Safing,
+        Tc::Path(_) | Tc::Cam(_) | Tc::Eqpt(_) => TcClass::Data,
+        Tc::LocoCtrlMnvr(_) | Tc::ArmCmd(_) | Tc::MastCmd(_) | Tc::Drawbar(_) => TcClass::Manual,
+        Tc::Autonomy(_) => TcClass::Autonomy,
+
+        // Checkouts which drive or steer the wheels are motion commands, only the capture and
+        // abort steps of calibrations are not
+        Tc::Calibrate(CalibrateCmd::Turn(TurnCalCmd::Start { .. }))
+        | Tc::Calibrate(CalibrateCmd::Steer(SteerCalCmd::Jog { .. }))
+        | Tc::SelfTest => TcClass::Manual,
+        Tc::Calibrate(_) => TcClass::Checkout,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comms_if::tc::{
+        eqpt::{Eqpt, EqptCmd},
+        loco_ctrl::MnvrCmd,
+    };
+
+    const MODES: [Mode; 6] = [
+        Mode::Boot,
+        Mode::Standby,
+        Mode::Manual,
+        Mode::Autonomous,
+        Mode::Safe,
+        Mode::Recovery,
+    ];
+
+    /// Get a mode manager in the given mode, however that mode is reached.
+    fn in_mode(mode: Mode) -> ModeManager {
+        ModeManager {
+            mode,
+            pre_safe_mode: Mode::Standby,
+        }
+    }
+
+    fn mnvr() -> Tc {
+        Tc::LocoCtrlMnvr(MnvrCmd::Stop)
+    }
+
+    fn data() -> Tc {
+        Tc::Eqpt(EqptCmd::Reconnect { eqpt: Eqpt::Mech })
+    }
+
+    fn steer_capture() -> Tc {
+        Tc::Calibrate(CalibrateCmd::Steer(SteerCalCmd::Capture))
+    }
+
+    fn steer_jog() -> Tc {
+        Tc::Calibrate(CalibrateCmd::Steer(SteerCalCmd::Jog {
+            axis: comms_if::eqpt::mech::ActId::StrFL,
+            delta_rad: 0.1,
+        }))
+    }
+
+    #[test]
+    fn test_transition_table() {
+        use Mode::*;
+
+        let allowed = [
+            (Boot, Standby),
+            (Standby, Manual),
+            (Standby, Autonomous),
+            (Manual, Standby),
+            (Manual, Autonomous),
+            (Autonomous, Standby),
+            (Autonomous, Manual),
+            (Safe, Standby),
+            (Safe, Recovery),
+            (Recovery, Standby),
+        ];
+
+        for &from in MODES.iter() {
+            for &to in MODES.iter() {
+                let expected = to == Safe || allowed.contains(&(from, to));
+                assert_eq!(
+                    transition_allowed(from, to),
+                    expected,
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+
+                let mut mm = in_mode(from);
+                let result = mm.request(to);
+                if from == to || expected {
+                    assert!(result.is_ok());
+                    assert_eq!(mm.mode(), to);
+                } else {
+                    assert!(matches!(result, Err(ModeError::TransitionNotAllowed(..))));
+                    assert_eq!(mm.mode(), from);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_safe_and_clear() {
+        let mut mm = ModeManager::default();
+        mm.boot_complete();
+        assert_eq!(mm.mode(), Mode::Standby);
+
+        // Safe mode which clears by itself goes to recovery
+        mm.make_safe();
+        assert_eq!(mm.mode(), Mode::Safe);
+        mm.clear_safe(false);
+        assert_eq!(mm.mode(), Mode::Recovery);
+
+        // And by the operator straight to standby
+        mm.make_safe();
+        mm.clear_safe(true);
+        assert_eq!(mm.mode(), Mode::Standby);
+
+        // Safe mode during boot returns to standby
+        let mut mm = ModeManager::default();
+        mm.make_safe();
+        mm.clear_safe(false);
+        assert_eq!(mm.mode(), Mode::Standby);
+    }
+
+    #[test]
+    fn test_safing_always_accepted() {
+        for &mode in MODES.iter() {
+            assert!(in_mode(mode).accept_tc(&Tc::MakeSafe).is_ok(), "{:?}", mode);
+            assert!(
+                in_mode(mode).accept_tc(&Tc::MakeUnsafe).is_ok(),
+                "{:?}",
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn test_motion_gating() {
+        for tc in [mnvr(), Tc::SelfTest, steer_jog()].iter() {
+            for &mode in [Mode::Boot, Mode::Safe, Mode::Recovery, Mode::Autonomous].iter() {
+                assert!(
+                    in_mode(mode).accept_tc(tc).is_err(),
+                    "{:?} accepted in {:?}",
+                    tc,
+                    mode
+                );
+            }
+
+            // The first motion TC in standby moves to manual
+            let mut mm = in_mode(Mode::Standby);
+            assert!(mm.accept_tc(tc).is_ok());
+            assert_eq!(mm.mode(), Mode::Manual);
+            assert!(mm.accept_tc(tc).is_ok());
+            assert_eq!(mm.mode(), Mode::Manual);
+        }
+    }
+
+    #[test]
+    fn test_recovery_gating() {
+        let mut mm = in_mode(Mode::Recovery);
+
+        // Data and checkouts which don't move the rover are allowed
+        assert!(mm.accept_tc(&data()).is_ok());
+        assert!(mm.accept_tc(&steer_capture()).is_ok());
+        assert_eq!(mm.mode(), Mode::Recovery);
+
+        // Motion isn't, including checkouts which move the rover
+        assert!(matches!(
+            mm.accept_tc(&Tc::SelfTest),
+            Err(ModeError::TcNotAllowed(Mode::Recovery))
+        ));
+
+        // Until unsafe returns to standby
+        assert!(mm.accept_tc(&Tc::MakeUnsafe).is_ok());
+        assert_eq!(mm.mode(), Mode::Standby);
+        assert!(mm.accept_tc(&Tc::SelfTest).is_ok());
+    }
+
+    #[test]
+    fn test_data_gating() {
+        for &mode in MODES.iter() {
+            let expected = mode != Mode::Boot;
+            assert_eq!(
+                in_mode(mode).accept_tc(&data()).is_ok(),
+                expected,
+                "{:?}",
+                mode
+            );
+        }
+
+        // Checkouts which don't move the rover aren't data, so are rejected in safe mode
+        assert!(in_mode(Mode::Safe).accept_tc(&steer_capture()).is_err());
+    }
+}
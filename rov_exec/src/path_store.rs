@@ -0,0 +1,388 @@
+//! # Path Store
+//!
+//! Holds the path files which can be followed by the rover. Files live in the paths directory
+//! (relative to the software root) and can either be placed there before execution or uplinked in
+//! chunks with the `path chunk` TC. Every file is checked against the limits in `path_store.toml`
+//! before being stored or followed.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::info;
+use serde::Deserialize;
+use std::{fs, path::PathBuf};
+
+// Internal
+use crate::traj_ctrl::{Path, PathError, PathLoadError, PathParams};
+use comms_if::{
+    net::chunk::{ChunkError, ChunkReassembler},
+    tc::path::PathChunk,
+};
+use util::{host, params};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Parameters for the path store.
+#[derive(Default, Deserialize)]
+pub struct Params {
+    /// Directory containing the path files, relative to the software root.
+    pub paths_dir: PathBuf,
+
+    /// Maximum size of an uplinked path file in bytes.
+    pub max_file_bytes: usize,
+
+    /// Maximum number of chunks in an uplinked path file.
+    pub max_chunks: u32,
+
+    /// Limits on the paths which can be stored.
+    pub path: PathParams,
+}
+
+/// The path store.
+#[derive(Default)]
+pub struct PathStore {
+    params: Params,
+
+    /// Absolute path to the paths directory
+    paths_dir: PathBuf,
+
+    /// The file currently being uplinked, if any
+    uplink: Option<Uplink>,
+}
+
+/// A path file which is being uplinked.
+struct Uplink {
+    name: String,
+    session_id: u64,
+    transfer_id: u64,
+    reassembler: ChunkReassembler,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Errors that can occur in the path store.
+#[derive(Debug, thiserror::Error)]
+pub enum PathStoreError {
+    #[error("Could not load parameters: {0}")]
+    ParamLoadError(params::LoadError),
+
+    #[error("The software root environment variable (SUSF_PHOBOS_SW_ROOT) is not set")]
+    SwRootNotSet,
+
+    #[error("Could not create the paths directory: {0}")]
+    CreateDirError(std::io::Error),
+
+    #[error("{0:?} is not a valid path file name, it must not contain a directory")]
+    InvalidName(String),
+
+    #[error("Chunk of {0:?} rejected: {1}")]
+    ChunkError(String, ChunkError),
+
+    #[error("Uplinked file {0:?} is larger than the limit of {1} bytes")]
+    FileTooLarge(String, usize),
+
+    #[error("Could not write the path file: {0}")]
+    WriteError(std::io::Error),
+
+    #[error("Could not load the path file: {0}")]
+    LoadError(PathLoadError),
+
+    #[error("Path file {0:?} is invalid: {1}")]
+    InvalidPath(PathBuf, PathError),
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl PathStore {
+    /// Initialise the store from the given parameter file, creating the paths directory if needed.
+    pub fn init(&mut self, params_file: &str) -> Result<(), PathStoreError> {
+        self.params = params::load(params_file).map_err(PathStoreError::ParamLoadError)?;
+
+        let mut paths_dir = host::get_phobos_sw_root().map_err(|_| PathStoreError::SwRootNotSet)?;
+        paths_dir.push(&self.params.paths_dir);
+
+        fs::create_dir_all(&paths_dir).map_err(PathStoreError::CreateDirError)?;
+
+        self.paths_dir = paths_dir;
+
+        Ok(())
+    }
+
+    /// Add an uplinked chunk to the store.
+    ///
+    /// Once the last chunk of a file arrives the file is checked and, if valid, written to the
+    /// paths directory, returning the loaded path. Chunks for a different file or transfer abandon
+    /// any incomplete uplink, as do invalid chunks.
+    pub fn push_chunk(&mut self, chunk: &PathChunk) -> Result<Option<Path>, PathStoreError> {
+        let result = self.push_chunk_inner(chunk);
+
+        if result.is_err() {
+            self.uplink = None;
+        }
+
+        result
+    }
+
+    /// Load and validate the path file with the given name from the paths directory.
+    ///
+    /// The returned path has been resampled to the preferred point separation.
+    pub fn load(&self, name: &std::path::Path) -> Result<Path, PathStoreError> {
+        // Only allow files directly inside the paths directory
+        if name.file_name() != Some(name.as_os_str()) {
+            return Err(PathStoreError::InvalidName(name.to_string_lossy().into()));
+        }
+
+        let path = Path::load(&self.paths_dir.join(name)).map_err(PathStoreError::LoadError)?;
+
+        self.validate(name, &path)
+    }
+
+    fn push_chunk_inner(&mut self, chunk: &PathChunk) -> Result<Option<Path>, PathStoreError> {
+        let name = std::path::Path::new(&chunk.name);
+        if name.file_name() != Some(name.as_os_str()) {
+            return Err(PathStoreError::InvalidName(chunk.name.clone()));
+        }
+
+        // The reassembler checks the length of the complete file against this
+        if chunk.chunk.payload_len > self.params.max_file_bytes as u64 {
+            return Err(PathStoreError::FileTooLarge(
+                chunk.name.clone(),
+                self.params.max_file_bytes,
+            ));
+        }
+
+        // Start a new uplink if this chunk isn't part of the current one
+        let restart = match self.uplink {
+            Some(ref u) => {
+                u.name != chunk.name
+                    || u.session_id != chunk.chunk.session_id
+                    || u.transfer_id != chunk.chunk.transfer_id
+            }
+            None => true,
+        };
+        if restart {
+            self.uplink = Some(Uplink {
+                name: chunk.name.clone(),
+                session_id: chunk.chunk.session_id,
+                transfer_id: chunk.chunk.transfer_id,
+                reassembler: ChunkReassembler::new(1, self.params.max_chunks),
+            });
+        }
+
+        // Unwrap is safe as the uplink was created above if needed
+        let transfer = match self
+            .uplink
+            .as_mut()
+            .unwrap()
+            .reassembler
+            .push(chunk.chunk.clone())
+            .map_err(|e| PathStoreError::ChunkError(chunk.name.clone(), e))?
+        {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        // Complete, so check the file before storing it. It's written to a temporary file first so
+        // that an invalid uplink never replaces a good file with the same name.
+        let uplink = self.uplink.take().unwrap();
+        let data = transfer.payload;
+
+        let file_path = self.paths_dir.join(&uplink.name);
+
+        // Keep the extension so the right format is used when loading
+        let tmp_path = self.paths_dir.join(format!(".part.{}", uplink.name));
+
+        fs::write(&tmp_path, data).map_err(PathStoreError::WriteError)?;
+
+        let path = match Path::load(&tmp_path)
+            .map_err(PathStoreError::LoadError)
+            .and_then(|p| self.validate(name, &p))
+        {
+            Ok(p) => p,
+            Err(e) => {
+                fs::remove_file(&tmp_path).ok();
+                return Err(e);
+            }
+        };
+
+        fs::rename(&tmp_path, &file_path).map_err(PathStoreError::WriteError)?;
+
+        info!(
+            "Path file {:?} uplinked, {} points, {:.2} m",
+            uplink.name,
+            path.get_num_points(),
+            path.get_length().unwrap_or(0.0)
+        );
+
+        Ok(Some(path))
+    }
+
+    /// Validate the path against the limits in the parameters.
+    fn validate(&self, name: &std::path::Path, path: &Path) -> Result<Path, PathStoreError> {
+        path.validate(&self.params.path)
+            .map_err(|e| PathStoreError::InvalidPath(name.to_path_buf(), e))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comms_if::{net::chunk::ChunkSender, units::Curvature};
+
+    const LINE_CSV: &str = "x,y\n0,0\n0.5,0\n1,0\n";
+
+    /// Create a store with its paths directory in a new temporary directory.
+    fn test_store(test_name: &str) -> PathStore {
+        let paths_dir =
+            std::env::temp_dir().join(format!("path_store_{}_{}", test_name, std::process::id()));
+        fs::remove_dir_all(&paths_dir).ok();
+        fs::create_dir_all(&paths_dir).unwrap();
+
+        PathStore {
+            params: Params {
+                paths_dir: paths_dir.clone(),
+                max_file_bytes: 1024,
+                max_chunks: 64,
+                path: PathParams {
+                    min_num_points: 2,
+                    max_point_separation_m: 1.0,
+                    max_length_m: 100.0,
+                    max_curvature_m: Curvature(2.0),
+                    preferred_separation_m: 0.05,
+                    max_heading_step_rad: 0.05,
+                },
+            },
+            paths_dir,
+            uplink: None,
+        }
+    }
+
+    fn split(name: &str, data: &str) -> Vec<PathChunk> {
+        PathChunk::split_file(&mut ChunkSender::new(8, 0), name, data.as_bytes())
+    }
+
+    #[test]
+    fn test_uplink_out_of_order() {
+        let mut store = test_store("out_of_order");
+        let chunks = split("line.csv", LINE_CSV);
+        assert!(chunks.len() > 2);
+
+        for c in chunks[1..].iter().rev() {
+            assert!(store.push_chunk(c).unwrap().is_none());
+        }
+        let path = store.push_chunk(&chunks[0]).unwrap().unwrap();
+        assert!((path.get_length().unwrap() - 1.0).abs() < 1e-9);
+
+        // Stored under its own name, with no temporary file left behind
+        let file_path = store.paths_dir.join("line.csv");
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), LINE_CSV);
+        assert!(!store.paths_dir.join(".part.line.csv").exists());
+        assert!(store.uplink.is_none());
+
+        let loaded = store.load(std::path::Path::new("line.csv")).unwrap();
+        assert_eq!(loaded.get_num_points(), path.get_num_points());
+
+        fs::remove_dir_all(&store.paths_dir).ok();
+    }
+
+    #[test]
+    fn test_invalid_uplink_not_stored() {
+        let mut store = test_store("invalid");
+        let file_path = store.paths_dir.join("line.csv");
+        fs::write(&file_path, LINE_CSV).unwrap();
+
+        // The points are too far apart, so the path is rejected once complete
+        let chunks = split("line.csv", "0,0\n5,0\n");
+        let (last, rest) = chunks.split_last().unwrap();
+        for c in rest {
+            assert!(store.push_chunk(c).unwrap().is_none());
+        }
+        assert!(matches!(
+            store.push_chunk(last),
+            Err(PathStoreError::InvalidPath(_, PathError::PointsTooFar(..)))
+        ));
+
+        // The existing file is untouched
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), LINE_CSV);
+        assert!(!store.paths_dir.join(".part.line.csv").exists());
+
+        fs::remove_dir_all(&store.paths_dir).ok();
+    }
+
+    #[test]
+    fn test_chunk_rejections() {
+        let mut store = test_store("rejections");
+
+        for name in ["../line.csv", "dir/line.csv", ""] {
+            assert!(matches!(
+                store.push_chunk(&split(name, LINE_CSV)[0]),
+                Err(PathStoreError::InvalidName(_))
+            ));
+        }
+
+        // A rejected chunk abandons the uplink it was part of
+        let chunks = split("line.csv", LINE_CSV);
+        store.push_chunk(&chunks[0]).unwrap();
+        let mut corrupt = chunks[1].clone();
+        corrupt.chunk.crc ^= 1;
+        assert!(matches!(
+            store.push_chunk(&corrupt),
+            Err(PathStoreError::ChunkError(
+                _,
+                ChunkError::CorruptChunk(1, _)
+            ))
+        ));
+        assert!(store.uplink.is_none());
+
+        store.params.max_chunks = 2;
+        assert!(matches!(
+            store.push_chunk(&chunks[0]),
+            Err(PathStoreError::ChunkError(_, ChunkError::TooManyChunks(..)))
+        ));
+
+        store.params.max_file_bytes = LINE_CSV.len() - 1;
+        assert!(matches!(
+            store.push_chunk(&chunks[0]),
+            Err(PathStoreError::FileTooLarge(_, _))
+        ));
+
+        assert!(!store.paths_dir.join("line.csv").exists());
+
+        fs::remove_dir_all(&store.paths_dir).ok();
+    }
+
+    #[test]
+    fn test_new_transfer_restarts_uplink() {
+        let mut store = test_store("restart");
+        let mut sender = ChunkSender::new(8, 0);
+        let first = PathChunk::split_file(&mut sender, "line.csv", LINE_CSV.as_bytes());
+        let second = PathChunk::split_file(&mut sender, "line.csv", LINE_CSV.as_bytes());
+
+        // The second transfer replaces the first part way through
+        store.push_chunk(&first[0]).unwrap();
+        let (last, rest) = second.split_last().unwrap();
+        for c in rest {
+            assert!(store.push_chunk(c).unwrap().is_none());
+        }
+        assert!(store.push_chunk(last).unwrap().is_some());
+
+        // So the first transfer's chunk was forgotten, and finishing it isn't enough
+        for c in &first[1..] {
+            assert!(store.push_chunk(c).unwrap().is_none());
+        }
+
+        fs::remove_dir_all(&store.paths_dir).ok();
+    }
+}
@@ -0,0 +1,466 @@
+//! # Self Test
+//!
+//! Runs a staged checkout of the rover before a drive, started with the `selftest` TC:
+//!
+//! 1. Equipment - sensor data must arrive from the mechanisms server.
+//! 2. Cameras - one frame is requested and must be received from each navigation camera.
+//! 3. Steer - each steer axis is swept to either side and back to zero, and the measured positions
+//!    must follow.
+//! 4. Drive - the drive axes are held at zero speed, and must all report that they're stopped.
+//!
+//! Each stage passes or fails independently, and the report is saved to the session's `self_test`
+//! directory as `self_test_<n>.json` once the test is complete. Safe mode aborts the test.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Internal
+use comms_if::{
+    eqpt::{
+        cam::CamId,
+        mech::{ActId, MechDems, MechSensData},
+    },
+    tm::TmMeta,
+};
+use util::{
+    format::{self, FormatHeader},
+    module::State,
+    params,
+    session::{Session, SessionError},
+};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Self test state
+#[derive(Default)]
+pub struct SelfTest {
+    params: Params,
+
+    /// The test in progress, if any
+    run: Option<Run>,
+
+    /// Number of tests started in this session
+    num_runs: u32,
+
+    /// Results of the current (or last) test
+    report: StatusReport,
+
+    /// Directory the reports are saved in
+    report_dir: PathBuf,
+}
+
+/// Parameters for the self test.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Params {
+    /// Time allowed for each stage (or each step of the steer sweep) to pass.
+    ///
+    /// Units: seconds
+    pub stage_timeout_s: f64,
+
+    /// Angle the steer axes are swept to on either side of zero.
+    ///
+    /// Units: radians
+    pub steer_sweep_rad: f64,
+
+    /// Maximum difference between the demanded and measured steer positions for a step of the
+    /// sweep to pass.
+    ///
+    /// Units: radians
+    pub steer_tolerance_rad: f64,
+
+    /// Time the drive axes are held at zero speed before their speeds are checked.
+    ///
+    /// Units: seconds
+    pub drive_pulse_s: f64,
+
+    /// Maximum measured drive speed for the drive stage to pass.
+    ///
+    /// Units: radians/second
+    pub drive_tolerance_rads: f64,
+}
+
+/// Input data to the self test.
+#[derive(Default)]
+pub struct InputData {
+    /// True if a self test should be started on this cycle.
+    pub start: bool,
+
+    /// True if the rover is in safe mode, which aborts the test.
+    pub safe: bool,
+
+    /// Current time in seconds
+    pub time_s: f64,
+
+    /// Sensor data received from the mechanisms server on this cycle, if any
+    pub sens: Option<MechSensData>,
+
+    /// Cameras which images were received from on this cycle
+    pub images: Vec<CamId>,
+}
+
+/// Output data from the self test.
+#[derive(Default)]
+pub struct OutputData {
+    /// Demands which replace those of the control modules, or `None` if the test isn't moving
+    /// anything.
+    pub dems: Option<MechDems>,
+
+    /// True if one frame from each camera should be requested on this cycle.
+    pub request_frames: bool,
+}
+
+/// Status report for the self test, which is also the saved report.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, TmMeta)]
+pub struct StatusReport {
+    /// True while a test is in progress
+    pub running: bool,
+
+    /// Number of the current (or last) test
+    pub run: u32,
+
+    /// Result of the equipment stage
+    pub equipment: CheckStatus,
+
+    /// Result of the camera stage
+    pub cameras: CheckStatus,
+
+    /// Result of the steer sweep stage
+    pub steer: CheckStatus,
+
+    /// Result of the drive stage
+    pub drive: CheckStatus,
+}
+
+/// A test in progress.
+struct Run {
+    stage: Stage,
+
+    /// Time the current stage (or step) started
+    stage_start_s: f64,
+
+    /// Index of the current step of the steer sweep
+    steer_step: usize,
+
+    /// True once frames have been requested in the camera stage
+    frames_requested: bool,
+
+    /// Cameras which an image has been received from in the camera stage
+    images: Vec<CamId>,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Result of checking one subsystem.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    #[default]
+    NotRun,
+    Running,
+    Pass,
+    Fail,
+}
+
+/// The stages of the test, in the order they're run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    Equipment,
+    Cameras,
+    Steer,
+    Drive,
+}
+
+/// Possible errors that can occur in the self test.
+#[derive(Debug, thiserror::Error)]
+pub enum SelfTestError {
+    #[error("Could not load the self test parameters: {0}")]
+    ParamsError(params::LoadError),
+
+    #[error("Cannot start a self test: {0}")]
+    CannotStart(&'static str),
+
+    #[error("Could not create the self test report directory: {0}")]
+    SessionError(SessionError),
+}
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Name of the format of the saved reports.
+pub const REPORT_FORMAT_NAME: &str = "self_test_report";
+
+/// Version of the saved report format, which shall be incremented when `StatusReport` changes.
+pub const REPORT_FORMAT_VERSION: u32 = 1;
+
+/// Cameras which must return an image for the camera stage to pass.
+const CAMS: [CamId; 2] = [CamId::LeftNav, CamId::RightNav];
+
+/// Steer sweep steps, as multiples of the sweep angle.
+const STEER_STEPS: [f64; 3] = [1.0, -1.0, 0.0];
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl State for SelfTest {
+    type InitData = &'static str;
+    type InitError = SelfTestError;
+
+    type InputData = InputData;
+    type OutputData = OutputData;
+    type StatusReport = StatusReport;
+    type ProcError = SelfTestError;
+
+    /// Initialise the self test.
+    ///
+    /// Expected init data is the path to the parameter file
+    fn init(&mut self, init_data: Self::InitData, session: &Session) -> Result<(), Self::InitError> {
+        self.params = params::load(init_data).map_err(SelfTestError::ParamsError)?;
+        self.report_dir = session
+            .module_dir("self_test")
+            .map_err(SelfTestError::SessionError)?;
+
+        Ok(())
+    }
+
+    /// Start or continue a test.
+    fn proc(
+        &mut self,
+        input_data: &Self::InputData,
+    ) -> Result<(Self::OutputData, Self::StatusReport), Self::ProcError> {
+        let mut output = OutputData::default();
+
+        // Safe mode always ends the test
+        if input_data.safe && self.run.is_some() {
+            warn!("Self test {} aborted by safe mode", self.num_runs);
+            self.run = None;
+            self.finish();
+        }
+
+        if input_data.start {
+            if input_data.safe {
+                return Err(SelfTestError::CannotStart("the rover is in safe mode"));
+            }
+            if self.run.is_some() {
+                return Err(SelfTestError::CannotStart("a self test is already running"));
+            }
+
+            self.num_runs += 1;
+            info!("Starting self test {}", self.num_runs);
+
+            self.report = StatusReport {
+                running: true,
+                run: self.num_runs,
+                equipment: CheckStatus::Running,
+                ..Default::default()
+            };
+            self.run = Some(Run {
+                stage: Stage::Equipment,
+                stage_start_s: input_data.time_s,
+                steer_step: 0,
+                frames_requested: false,
+                images: Vec::new(),
+            });
+        }
+
+        if self.run.is_some() {
+            self.proc_stage(input_data, &mut output);
+        }
+
+        Ok((output, self.report))
+    }
+}
+
+impl SelfTest {
+    /// Process the current stage of the test.
+    fn proc_stage(&mut self, input_data: &InputData, output: &mut OutputData) {
+        let params = &self.params;
+        let run = match self.run {
+            Some(ref mut r) => r,
+            None => return,
+        };
+
+        let elapsed_s = input_data.time_s - run.stage_start_s;
+        let timed_out = elapsed_s > params.stage_timeout_s;
+
+        // The result of the stage, if it's finished on this cycle
+        let result = match run.stage {
+            Stage::Equipment => match (input_data.sens.is_some(), timed_out) {
+                (true, _) => Some(CheckStatus::Pass),
+                (false, true) => {
+                    warn!("Self test: no sensor data from the mechanisms server");
+                    Some(CheckStatus::Fail)
+                }
+                (false, false) => None,
+            },
+            Stage::Cameras => {
+                // Request frames as the stage starts
+                if !run.frames_requested {
+                    output.request_frames = true;
+                    run.frames_requested = true;
+                }
+
+                for cam_id in input_data.images.iter() {
+                    if !run.images.contains(cam_id) {
+                        run.images.push(*cam_id);
+                    }
+                }
+
+                if CAMS.iter().all(|c| run.images.contains(c)) {
+                    Some(CheckStatus::Pass)
+                } else if timed_out {
+                    warn!("Self test: only got images from {:?}", run.images);
+                    Some(CheckStatus::Fail)
+                } else {
+                    None
+                }
+            }
+            Stage::Steer => {
+                let dem_rad = STEER_STEPS[run.steer_step] * params.steer_sweep_rad;
+                output.dems = Some(hold_dems(dem_rad));
+
+                let reached = input_data.sens.as_ref().is_some_and(|s| {
+                    ActId::str_ids().iter().all(|id| match s.pos_rad.get(id) {
+                        Some(p) => (p - dem_rad).abs() <= params.steer_tolerance_rad,
+                        None => false,
+                    })
+                });
+
+                if reached {
+                    run.steer_step += 1;
+                    run.stage_start_s = input_data.time_s;
+
+                    match run.steer_step == STEER_STEPS.len() {
+                        true => Some(CheckStatus::Pass),
+                        false => None,
+                    }
+                } else if timed_out {
+                    warn!("Self test: steer axes didn't reach {:.3} rad", dem_rad);
+                    Some(CheckStatus::Fail)
+                } else {
+                    None
+                }
+            }
+            Stage::Drive => {
+                output.dems = Some(hold_dems(0.0));
+
+                if elapsed_s < params.drive_pulse_s {
+                    None
+                } else {
+                    let stopped = input_data.sens.as_ref().map(|s| {
+                        ActId::drv_ids().iter().all(|id| match s.speed_rads.get(id) {
+                            Some(v) => v.abs() <= params.drive_tolerance_rads,
+                            None => false,
+                        })
+                    });
+
+                    match (stopped, timed_out) {
+                        (Some(true), _) => Some(CheckStatus::Pass),
+                        (Some(false), _) | (None, true) => {
+                            warn!("Self test: drive axes didn't all report zero speed");
+                            Some(CheckStatus::Fail)
+                        }
+                        (None, false) => None,
+                    }
+                }
+            }
+        };
+
+        let result = match result {
+            Some(r) => r,
+            None => return,
+        };
+
+        let stage = run.stage;
+        info!("Self test: {:?} {:?}", stage, result);
+
+        // Move on to the next stage
+        let next = match stage {
+            Stage::Equipment => {
+                self.report.equipment = result;
+                self.report.cameras = CheckStatus::Running;
+                Some(Stage::Cameras)
+            }
+            Stage::Cameras => {
+                self.report.cameras = result;
+                self.report.steer = CheckStatus::Running;
+                Some(Stage::Steer)
+            }
+            Stage::Steer => {
+                self.report.steer = result;
+                self.report.drive = CheckStatus::Running;
+                Some(Stage::Drive)
+            }
+            Stage::Drive => {
+                self.report.drive = result;
+                None
+            }
+        };
+
+        match next {
+            Some(s) => {
+                run.stage = s;
+                run.stage_start_s = input_data.time_s;
+            }
+            None => {
+                self.run = None;
+                self.finish();
+            }
+        }
+    }
+
+    /// End the test, saving the report.
+    fn finish(&mut self) {
+        self.report.running = false;
+
+        // Stages which were cut short have failed
+        for status in [
+            &mut self.report.equipment,
+            &mut self.report.cameras,
+            &mut self.report.steer,
+            &mut self.report.drive,
+        ] {
+            if *status == CheckStatus::Running {
+                *status = CheckStatus::Fail;
+            }
+        }
+
+        info!("Self test {} complete: {:?}", self.num_runs, self.report);
+
+        let path = self.report_dir.join(format!("self_test_{}.json", self.num_runs));
+        let result = format::save_json(
+            &path,
+            &FormatHeader::new(REPORT_FORMAT_NAME, REPORT_FORMAT_VERSION),
+            &self.report,
+        );
+
+        if let Err(e) = result {
+            warn!("Could not save the self test report: {}", e);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Demands holding all steer axes at the given angle with the drive axes stopped.
+fn hold_dems(str_rad: f64) -> MechDems {
+    let mut dems = MechDems::empty_loco();
+
+    for id in ActId::str_ids() {
+        dems.pos_rad.insert(*id, str_rad);
+    }
+
+    dems
+}
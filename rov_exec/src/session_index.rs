@@ -0,0 +1,321 @@
+//! # Session Index
+//!
+//! Builds a manifest of a finished session which aligns its data sources by cycle: for each cycle
+//! in the cycle archive (see [`crate::cycle_arch`]) it gives the time of the cycle, the rows of
+//! each archive and the lines of each log written during the cycle, and any images taken during
+//! it. The manifest is saved as `index.json` in the session root by the `index_session` tool.
+//!
+//! Everything is aligned by the time since the start of the session. Archives are aligned by
+//! their `time_s` column, archives without one are listed as unaligned. Log lines are aligned by
+//! the time at the start of each line, with lines that have no time (such as the rest of a
+//! multi-line message) belonging to the line before. Images are aligned by the timestamp at the
+//! end of their name, either the seconds since the start of the session (as used by
+//! `Session::save_with_timestamp`) or a UNIX timestamp in milliseconds.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Internal
+use crate::cycle_arch::{self, CycleArchError};
+use util::{
+    archive::{self, ArchiveReadError, TIME_COLUMN},
+    format::{self, FormatError, FormatHeader},
+};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Name of the session index format.
+pub const FORMAT_NAME: &str = "session_index";
+
+/// Version of the session index format.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Name of the index file in the session root.
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+/// Message logged at start-up which gives the session epoch, see `util::logger`.
+const EPOCH_LOG_MSG: &str = "Session epoch: ";
+
+/// UNIX timestamps in milliseconds are larger than this, and times since the start of the session
+/// in seconds are smaller.
+const MIN_UNIX_MS: f64 = 1e11;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Manifest aligning the data of a session by cycle.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionIndex {
+    /// Start of the session, if it was found in the log
+    pub epoch: Option<DateTime<Utc>>,
+
+    /// Archives which were aligned, relative to the session root
+    pub archives: Vec<String>,
+
+    /// Archives without a `time_s` column, which could not be aligned
+    pub unaligned_archives: Vec<String>,
+
+    /// Logs which were aligned, relative to the session root
+    pub logs: Vec<String>,
+
+    /// Data before the first cycle, during start-up
+    pub startup: IndexEntry,
+
+    /// Data of each cycle, in order
+    pub cycles: Vec<CycleEntry>,
+}
+
+/// Data of a single cycle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CycleEntry {
+    /// Number of the cycle
+    pub cycle: u64,
+
+    /// Time since the start of the session at the start of the cycle
+    pub time_s: f64,
+
+    /// Time at the start of the cycle, if the session epoch is known
+    pub utc: Option<DateTime<Utc>>,
+
+    #[serde(flatten)]
+    pub entry: IndexEntry,
+}
+
+/// The data written during one period of the session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Rows of each archive, keyed by the archive's path. Rows are numbered from 0, not counting
+    /// the header.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub archive_rows: BTreeMap<String, Span>,
+
+    /// Lines of each log, keyed by the log's path. Lines are numbered from 1.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub log_lines: BTreeMap<String, Span>,
+
+    /// Images, relative to the session root
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub images: Vec<String>,
+}
+
+/// A range of rows or lines, from `first` to `last` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub first: usize,
+    pub last: usize,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionIndexError {
+    #[error("Could not read the cycle archive: {0}")]
+    CycleArchError(CycleArchError),
+
+    #[error("Could not read the archive {0:?}: {1}")]
+    ArchiveError(String, ArchiveReadError),
+
+    #[error("Could not read {0:?}: {1}")]
+    ReadError(PathBuf, std::io::Error),
+
+    #[error("Could not save the index: {0}")]
+    SaveError(FormatError),
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl SessionIndex {
+    /// Build the index of the session in `session_dir`.
+    pub fn build<P: AsRef<Path>>(session_dir: P) -> Result<Self, SessionIndexError> {
+        let root = session_dir.as_ref();
+
+        let records = cycle_arch::read(root).map_err(SessionIndexError::CycleArchError)?;
+
+        let mut files = Vec::new();
+        list_files(root, root, &mut files)?;
+
+        let mut index = Self {
+            cycles: records
+                .iter()
+                .map(|r| CycleEntry {
+                    cycle: r.cycle,
+                    time_s: r.time_s,
+                    utc: None,
+                    entry: IndexEntry::default(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        // Logs first, since the epoch is needed to align the rest
+        for path in files.iter().filter(|p| has_extension(p, &["log"])) {
+            let log = fs::read_to_string(root.join(path))
+                .map_err(|e| SessionIndexError::ReadError(root.join(path), e))?;
+
+            if index.epoch.is_none() {
+                index.epoch = find_epoch(&log);
+            }
+
+            index.add_log(path, &log);
+            index.logs.push(path.clone());
+        }
+
+        for path in files.iter().filter(|p| has_extension(p, &["csv"])) {
+            let columns = archive::read_columns(root.join(path))
+                .map_err(|e| SessionIndexError::ArchiveError(path.clone(), e))?;
+
+            match columns.column(TIME_COLUMN) {
+                Some(times_s) => {
+                    for (row, &time_s) in times_s.iter().enumerate() {
+                        index.entry_at(time_s).add_archive_row(path, row);
+                    }
+                    index.archives.push(path.clone());
+                }
+                None => index.unaligned_archives.push(path.clone()),
+            }
+        }
+
+        for path in files.iter().filter(|p| has_extension(p, &["png", "jpg", "jpeg"])) {
+            if let Some(time_s) = image_time_s(path, index.epoch.as_ref()) {
+                index.entry_at(time_s).images.push(path.clone());
+            }
+        }
+
+        if let Some(epoch) = index.epoch {
+            for cycle in index.cycles.iter_mut() {
+                let elapsed = chrono::Duration::microseconds((cycle.time_s * 1e6) as i64);
+                cycle.utc = Some(epoch + elapsed);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Save the index as `index.json` in the session root, returning its path.
+    pub fn save<P: AsRef<Path>>(&self, session_dir: P) -> Result<PathBuf, SessionIndexError> {
+        let path = session_dir.as_ref().join(INDEX_FILE_NAME);
+
+        format::save_json(&path, &FormatHeader::new(FORMAT_NAME, FORMAT_VERSION), self)
+            .map_err(SessionIndexError::SaveError)?;
+
+        Ok(path)
+    }
+
+    /// Get the entry of the cycle running at the given time.
+    fn entry_at(&mut self, time_s: f64) -> &mut IndexEntry {
+        // Cycles are in time order, so find the last one which started at or before the time
+        match self.cycles.partition_point(|c| c.time_s <= time_s) {
+            0 => &mut self.startup,
+            i => &mut self.cycles[i - 1].entry,
+        }
+    }
+
+    fn add_log(&mut self, path: &str, log: &str) {
+        let mut time_s = f64::NEG_INFINITY;
+
+        for (i, line) in log.lines().enumerate() {
+            if let Some(t) = log_line_time_s(line) {
+                time_s = t;
+            }
+
+            let line_num = i + 1;
+            self.entry_at(time_s)
+                .log_lines
+                .entry(String::from(path))
+                .and_modify(|s| s.last = line_num)
+                .or_insert(Span {
+                    first: line_num,
+                    last: line_num,
+                });
+        }
+    }
+}
+
+impl IndexEntry {
+    fn add_archive_row(&mut self, path: &str, row: usize) {
+        self.archive_rows
+            .entry(String::from(path))
+            .and_modify(|s| s.last = row)
+            .or_insert(Span {
+                first: row,
+                last: row,
+            });
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// List every file below `dir`, relative to `root`, in a stable order.
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> Result<(), SessionIndexError> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| SessionIndexError::ReadError(dir.to_path_buf(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            list_files(root, &path, files)?;
+        } else if let Ok(rel) = path.strip_prefix(root) {
+            // The index doesn't index itself
+            if rel != Path::new(INDEX_FILE_NAME) {
+                files.push(rel.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    Path::new(path)
+        .extension()
+        .map(|e| extensions.contains(&e.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Find the session epoch in a log.
+fn find_epoch(log: &str) -> Option<DateTime<Utc>> {
+    let line = log.lines().find(|l| l.contains(EPOCH_LOG_MSG))?;
+    let epoch = line.split(EPOCH_LOG_MSG).nth(1)?.trim().trim_end_matches(" UTC");
+
+    NaiveDateTime::parse_from_str(epoch, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|e| Utc.from_utc_datetime(&e))
+}
+
+/// Get the time of a log line, which starts `[{time} {level}]`.
+fn log_line_time_s(line: &str) -> Option<f64> {
+    line.strip_prefix('[')?.split_whitespace().next()?.parse().ok()
+}
+
+/// Get the time since the start of the session of an image from the end of its name, for example
+/// `LeftNav_1634724900123.png`.
+fn image_time_s(path: &str, epoch: Option<&DateTime<Utc>>) -> Option<f64> {
+    let stem = Path::new(path).file_stem()?.to_string_lossy().into_owned();
+    let stamp: f64 = stem.rsplit('_').next()?.parse().ok()?;
+
+    match stamp > MIN_UNIX_MS {
+        true => epoch.map(|e| (stamp - e.timestamp_millis() as f64) * 1e-3),
+        false => Some(stamp),
+    }
+}
@@ -22,7 +22,7 @@ use std::{sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, thread::{self, Joi
 use log::{error, warn};
 use serde::Deserialize;
 
-use crate::loc::Pose;
+use crate::loc::{Pose, PoseSource};
 use comms_if::{
     eqpt::cam::{CamFrame, CamImage}, 
     net::{MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, zmq}
@@ -154,6 +154,12 @@ impl SimClient {
     }
 }
 
+impl PoseSource for SimClient {
+    fn get_pose(&mut self) -> Option<Pose> {
+        self.rov_pose_lm()
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // FUNCTIONS
 // ------------------------------------------------------------------------------------------------
@@ -4,7 +4,9 @@
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
 
-use comms_if::{net::{MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, zmq}, tc::{Tc, TcParseError, TcResponse}};
+use std::{cell::RefCell, convert::TryInto};
+
+use comms_if::{net::{capture, transport, NetParams, SocketOptions, TrafficCapture, Transport, TransportError, TransportExt, zmq}, tc::{Tc, TcPacket, TcParseError, TcResponse}, tm::latency::TcStamps};
 
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
@@ -12,7 +14,16 @@ use comms_if::{net::{MonitoredSocket, MonitoredSocketError, NetParams, SocketOpt
 
 /// Telecommand client
 pub struct TcClient {
-    socket: MonitoredSocket
+    socket: Box<dyn Transport>,
+
+    /// ID of this vehicle, TCs addressed to other vehicles are rejected
+    vehicle_id: String,
+
+    /// Maximum size of a TC message, larger messages are rejected
+    max_bytes: usize,
+
+    /// A message recieved by `has_pending_tc` which hasn't been handled yet
+    pending: RefCell<Option<Vec<u8>>>
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -21,26 +32,29 @@ pub struct TcClient {
 
 #[derive(Debug, thiserror::Error)]
 pub enum TcClientError {
-    #[error("Socket error: {0}")]
-    SocketError(MonitoredSocketError),
+    #[error("Transport error: {0}")]
+    TransportError(TransportError),
 
     #[error("The client is not connected to the server")]
     NotConnected,
 
-    #[error("Could not send demands to the server: {0}")]
-    SendError(zmq::Error),
+    #[error("Could not send the response to the server: {0}")]
+    SendError(TransportError),
 
     #[error("Could not recieve a message from the server: {0}")]
-    RecvError(zmq::Error),
-
-    #[error("Could not serialize the data: {0}")]
-    SerializationError(serde_json::Error),
+    RecvError(TransportError),
 
     #[error("Could not parse the recieved telecommand")]
     TcParseError(TcParseError),
 
     #[error("The server sent a message which was not valid UTF-8")]
-    NonUtf8Response
+    NonUtf8Response,
+
+    #[error("Recieved a TC addressed to vehicle {0}")]
+    WrongVehicle(String),
+
+    #[error("Recieved a {0} byte TC message, larger than the {1} byte maximum")]
+    Oversized(usize, usize)
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -51,8 +65,13 @@ impl TcClient {
 
     /// Create a new instance of the TC Client.
     ///
-    /// This function will not block until the server connects.
-    pub fn new(ctx: &zmq::Context, params: &NetParams) -> Result<Self, TcClientError> {
+    /// This function will not block until the server connects. If a traffic capture is given all
+    /// TCs and responses are recorded into it.
+    pub fn new(
+        ctx: &zmq::Context,
+        params: &NetParams,
+        capture: Option<&TrafficCapture>
+    ) -> Result<Self, TcClientError> {
         // Create the socket options
         // TODO: Move these into a parameter file
         let socket_options = SocketOptions {
@@ -66,20 +85,27 @@ impl TcClient {
             send_timeout: 10,
             req_correlate: true,
             req_relaxed: false,
+            // Stop zmq from buffering a huge message before the size check in `recieve_tc`
+            max_msg_size: params.tc_max_bytes.try_into().unwrap_or(-1),
             ..Default::default()
         };
 
         // Connect the socket
-        let socket = MonitoredSocket::new(
+        let socket = transport::open(
+            params.ground_transport,
             ctx, 
             zmq::REP, 
             socket_options, 
             &params.tc_endpoint
-        ).map_err(|e| TcClientError::SocketError(e))?;
+        ).map_err(TcClientError::TransportError)?;
+        let socket = capture::maybe_wrap(capture, socket, "tc", &params.tc_endpoint);
 
         // Create self
         Ok(Self {
-            socket
+            socket,
+            vehicle_id: params.vehicle_id.clone(),
+            max_bytes: params.tc_max_bytes,
+            pending: RefCell::new(None)
         })
     }
 
@@ -97,25 +123,47 @@ impl TcClient {
     /// After recieving a valid TC the client must send a response using `.send_response()` before
     /// attempting to recieve another TC. If an error occurs in receiving the TC the response will
     /// be sent automatically by this function.
-    pub fn recieve_tc(&self) -> Result<Option<Tc>, TcClientError> {
+    ///
+    /// TCs addressed to a different vehicle are answered with `TcResponse::WrongVehicle` and
+    /// returned as a `TcClientError::WrongVehicle` error, they must not be executed. Messages
+    /// larger than `tc_max_bytes` are answered with `TcResponse::Invalid` without being parsed.
+    /// Over zmq they never get this far, the socket disconnects the server instead of recieving
+    /// them.
+    ///
+    /// The TC is returned with its latency stamps, which the caller should complete as the TC is
+    /// executed.
+    pub fn recieve_tc(&self) -> Result<Option<(Tc, TcStamps)>, TcClientError> {
         // Check the server is connected
         if !self.socket.connected() {
             return Err(TcClientError::NotConnected)
         }
 
-        // Attempt to read a string from the socket
-        let tc_str = match self.socket.recv_string(0) {
-            // Valid message
-            Ok(Ok(s)) => s,
-            // Non UTF-8 message
-            Ok(Err(_)) => {
-                // Send invalid message response
+        // Attempt to read a string from the socket, starting with any message already recieved by
+        // `has_pending_tc`
+        let msg = match self.pending.borrow_mut().take() {
+            Some(msg) => Ok(Some(msg)),
+            None => self.socket.recv_bytes()
+        };
+        let tc_str = match msg {
+            // Oversized message, rejected before spending any time parsing it
+            Ok(Some(msg)) if msg.len() > self.max_bytes => {
                 self.send_response(TcResponse::Invalid)?;
 
-                return Err(TcClientError::NonUtf8Response)
+                return Err(TcClientError::Oversized(msg.len(), self.max_bytes))
+            },
+            // Valid message
+            Ok(Some(msg)) => match String::from_utf8(msg) {
+                Ok(s) => s,
+                // Non UTF-8 message
+                Err(_) => {
+                    // Send invalid message response
+                    self.send_response(TcResponse::Invalid)?;
+
+                    return Err(TcClientError::NonUtf8Response)
+                }
             },
             // No message in timeout
-            Err(zmq::Error::EAGAIN) => return Ok(None),
+            Ok(None) => return Ok(None),
             // Recieve error
             Err(e) => {
                 // No response is sent if we could not recieve
@@ -124,15 +172,40 @@ impl TcClient {
         };
 
         // Parse the TC
-        Tc::from_json(&tc_str)
+        let packet = TcPacket::from_json(&tc_str)
             .map_err(|e| {
                 // Send the invalid response
                 // TODO: add proper error handling here
                 self.send_response(TcResponse::Invalid).ok();
 
                 TcClientError::TcParseError(e)
-            })
-            .map(|t| Some(t))
+            })?;
+
+        // Reject the TC if it's meant for someone else
+        if !packet.is_for(&self.vehicle_id) {
+            self.send_response(TcResponse::WrongVehicle)?;
+
+            return Err(TcClientError::WrongVehicle(packet.vehicle_id.unwrap_or_default()))
+        }
+
+        Ok(Some((packet.tc, TcStamps::recvd(packet.sent))))
+    }
+
+    /// Check if another TC is waiting to be recieved, without handling it.
+    ///
+    /// A waiting message is held by the client and returned by the next call to `.recieve_tc()`,
+    /// which must be made before the server will send anything else.
+    pub fn has_pending_tc(&self) -> Result<bool, TcClientError> {
+        if !self.socket.connected() {
+            return Ok(false)
+        }
+
+        let mut pending = self.pending.borrow_mut();
+        if pending.is_none() {
+            *pending = self.socket.recv_bytes().map_err(TcClientError::RecvError)?;
+        }
+
+        Ok(pending.is_some())
     }
 
     /// Send the given response back to the server.
@@ -144,12 +217,8 @@ impl TcClient {
             return Err(TcClientError::NotConnected)
         }
 
-        // Serialise the response
-        let response_str = serde_json::to_string(&response)
-            .map_err(|e| TcClientError::SerializationError(e))?;
-
         // Send the response
-        self.socket.send(&response_str, 0)
-            .map_err(|e| TcClientError::SendError(e))
+        self.socket.send_json(&response)
+            .map_err(TcClientError::SendError)
     }
 }
\ No newline at end of file
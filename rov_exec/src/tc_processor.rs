@@ -7,11 +7,21 @@
 // ---------------------------------------------------------------------------
 
 // External
-use log::{debug, warn};
+use log::{debug, info, warn};
 
 // Internal
 use crate::data_store::{DataStore, SafeModeCause};
-use comms_if::tc::Tc;
+use comms_if::{
+    eqpt::mech::ActId,
+    tc::{
+        auto::AutoCmd,
+        calibrate::{CalibrateCmd, SteerCalCmd},
+        cam::CamCmd,
+        eqpt::EqptCmd,
+        path::PathCmd,
+        Tc,
+    },
+};
 
 // ---------------------------------------------------------------------------
 // PUBLIC FUNCTIONS
@@ -21,6 +31,11 @@ use comms_if::tc::Tc;
 ///
 /// Mutates the datastore to send commands to different modules.
 pub(crate) fn exec(ds: &mut DataStore, tc: &Tc) {
+    if let Err(e) = tc.validate() {
+        warn!("Rejected TC: {}", e);
+        return;
+    }
+
     // Handle different Tcs
     match tc {
         Tc::MakeSafe => {
@@ -31,10 +46,81 @@ pub(crate) fn exec(ds: &mut DataStore, tc: &Tc) {
             debug!("Recieved MakeUnsafe command");
             ds.make_unsafe(SafeModeCause::MakeSafeTc).ok();
         }
-        Tc::LocoCtrlMnvr(m) => ds.loco_ctrl_input.cmd = Some(*m),
-        Tc::ArmCmd(m) => ds.arm_ctrl_input.cmd = Some(m.clone()),
+        Tc::LocoCtrlMnvr(m) => ds.loco.loco_ctrl_input.cmd = Some(*m),
+        Tc::ArmCmd(m) => ds.mech.arm_ctrl_input.cmd = Some(m.clone()),
+        Tc::MastCmd(m) => ds.mech.mast_ctrl_input.cmd = Some(*m),
+        Tc::Drawbar(d) => ds.checkout.drawbar_input.cmd = Some(*d),
+        // Once following is supported the corridor is passed to
+        // `TrajCtrl::begin_path_sequence` along with the path
+        Tc::Autonomy(AutoCmd::Follow { path, corridor_m }) => match ds.auto.path_store.load(path) {
+            Ok(_) => warn!(
+                "Path {:?} is valid (corridor {}), but path following is not yet supported",
+                path,
+                corridor_m.map_or("none".to_string(), |w| format!("{:.2} m", w))
+            ),
+            Err(e) => warn!("Rejected path {:?}: {}", path, e),
+        },
         Tc::Autonomy(_) => {
             warn!("Autonomy command is not yet supported");
         }
+        Tc::Cam(CamCmd::Control(c)) => ds.auto.cam_control = Some(c.clone()),
+        Tc::Path(PathCmd::Chunk(c)) => {
+            if let Err(e) = ds.auto.path_store.push_chunk(c) {
+                warn!("Path uplink failed: {}", e);
+            }
+        }
+        Tc::Path(PathCmd::Check { path }) => match ds.auto.path_store.load(path) {
+            Ok(p) => info!(
+                "Path {:?} is valid, {} points, {:.2} m",
+                path,
+                p.get_num_points(),
+                p.get_length().unwrap_or(0.0)
+            ),
+            Err(e) => warn!("Path {:?} is invalid: {}", path, e),
+        },
+        Tc::Calibrate(CalibrateCmd::Steer(c)) => exec_steer_cal(ds, c),
+        Tc::Calibrate(CalibrateCmd::Turn(c)) => ds.checkout.turn_cal_input.cmd = Some(*c),
+        Tc::SelfTest => ds.checkout.self_test_input.start = true,
+        Tc::Eqpt(EqptCmd::Reconnect { eqpt }) => ds.hk.eqpt_reconnect.push(*eqpt),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Execute a step of the steer calibration.
+fn exec_steer_cal(ds: &mut DataStore, cmd: &SteerCalCmd) {
+    match cmd {
+        SteerCalCmd::Jog { axis, delta_rad } => {
+            if ds.safety.is_safe() {
+                warn!("Cannot calibrate the steer axes in safe mode");
+                return;
+            }
+
+            if !ActId::str_ids().contains(axis) {
+                warn!("{:?} is not a steer axis", axis);
+                return;
+            }
+
+            // Start from the current zeros
+            let cal = ds.loco.steer_cal_pos_rad.get_or_insert_with(|| {
+                info!("Starting steer calibration");
+                ActId::str_ids().iter().map(|&id| (id, 0.0)).collect()
+            });
+
+            let pos_rad = cal.entry(*axis).or_insert(0.0);
+            *pos_rad += delta_rad;
+            info!("Steer calibration: {:?} at {:.4} rad", axis, pos_rad);
+        }
+        SteerCalCmd::Capture => match ds.loco.steer_cal_pos_rad {
+            Some(_) => ds.loco.steer_cal_capture = true,
+            None => warn!("Steer calibration is not running, nothing to capture"),
+        },
+        SteerCalCmd::Abort => {
+            if ds.loco.steer_cal_pos_rad.take().is_some() {
+                info!("Steer calibration aborted");
+            }
+        }
     }
 }
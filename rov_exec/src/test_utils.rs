@@ -0,0 +1,99 @@
+//! # Test utilities
+//!
+//! Helpers shared by the unit tests. The global allocator of the test binary counts the
+//! allocations made by each thread, so that tests can check the cycle's hot paths don't allocate
+//! without being upset by other tests running in parallel.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use comms_if::net::NetParams;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Allocator which counts the allocations of each thread and passes them on to the system
+/// allocator.
+struct CountingAlloc;
+
+// ------------------------------------------------------------------------------------------------
+// STATICS
+// ------------------------------------------------------------------------------------------------
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+thread_local! {
+    /// Number of allocations and reallocations made by this thread
+    static NUM_ALLOCS: Cell<u64> = const { Cell::new(0) };
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_alloc();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_alloc();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_alloc();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+fn count_alloc() {
+    // The counter may already be gone while the thread is exiting
+    NUM_ALLOCS.try_with(|n| n.set(n.get() + 1)).ok();
+}
+
+/// Run the function, returning the number of allocations it made on this thread.
+pub fn count_allocs<F: FnOnce()>(f: F) -> u64 {
+    let before = NUM_ALLOCS.with(Cell::get);
+    f();
+    NUM_ALLOCS.with(Cell::get) - before
+}
+
+/// Network parameters with the given endpoints for the mechanisms and TM sockets.
+pub fn net_params(mech_dems: &str, mech_sens: &str, tm: &str, img_tm: &str) -> NetParams {
+    serde_json::from_value(serde_json::json!({
+        "vehicle_id": "test",
+        "tm_udp_frag_bytes": 1024,
+        "tm_udp_fec_group_size": 0,
+        "tm_backfill_max_packets": 10,
+        "mech_dems_endpoint": mech_dems,
+        "mech_sens_endpoint": mech_sens,
+        "cam_endpoint": "inproc://cam",
+        "tc_endpoint": "inproc://tc",
+        "tc_max_per_cycle": 10,
+        "tc_max_bytes": 65536,
+        "tm_endpoint": tm,
+        "img_tm_endpoint": img_tm,
+        "img_downlink_max_bytes": 60000,
+        "img_downlink_chunk_bytes": 8000,
+        "sim_endpoint": "inproc://sim",
+    }))
+    .unwrap()
+}
@@ -1,16 +1,66 @@
 //! # TM Server
+//!
+//! While the rover is out of contact with the ground one packet per second is kept, up to
+//! `tm_backfill_max_packets`, and the stored packets are sent marked as backfill once contact is
+//! regained so that the ground can fill in the gap.
+//!
+//! Which fields of the packet are sent can be tuned in `tm_server.toml`, see [`TmParams`], for
+//! example to leave out large parameter sets during a test which doesn't need them. Fields which
+//! identify the packet, such as the vehicle ID and time, are always sent.
+//!
+//! Images are recompressed and sent on their own channel by a worker thread, so that encoding them
+//! doesn't hold up the cycle. If the worker falls behind, new images are dropped rather than
+//! queued.
 
 // ------------------------------------------------------------------------------------------------
 // IMPORTS
 // ------------------------------------------------------------------------------------------------
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread,
+};
 
-use comms_if::{eqpt::{cam::{CamFrame, ImageFormat}, mech::MechDems}, net::{MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, zmq}, tc::{Tc, TcParseError, TcResponse}};
+use comms_if::{eqpt::{cam::{CamId, CamImage}, mech::{ArmFault, MechDems}}, net::{capture, new_session_id, transport, MonitoredSocket, MonitoredSocketError, NetParams, SocketOptions, TrafficCapture, Transport, TransportError, zmq}, tc::{Tc, TcParseError, TcResponse}, tm::{img::{DownlinkBudget, EncodedImage}, latency::TcStamps, query::{self, TmQuery, TmQueryResponse}, TmFieldMeta, TmMeta}};
+use log::{info, warn};
 
 use crate::data_store::DataStore;
 
 use crate::loco_ctrl;
 use crate::arm_ctrl;
+use crate::mast_ctrl;
+use crate::wheel_rate_ctrl;
+use crate::{drawbar_test, mode_mgr::Mode, self_test, turn_cal};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Initial size of the serialization buffer. A packet is a few kB, so this is large enough that the
+/// buffer never needs to grow in normal operation.
+const TM_BUFFER_INITIAL_CAPACITY: usize = 16 * 1024;
+
+/// Time contact must be held before backfilling starts, giving the ground time to resubscribe to
+/// the TM channel so the first backfilled packets aren't lost.
+const BACKFILL_HOLDOFF_S: f64 = 2.0;
+
+/// Maximum number of backfilled packets sent each cycle, so the backfill doesn't swamp the link.
+const BACKFILL_PACKETS_PER_CYCLE: usize = 5;
+
+/// Maximum number of TM queries answered each cycle.
+const QUERIES_PER_CYCLE: usize = 5;
+
+/// Number of images which may be waiting to be encoded before further images are dropped.
+const IMG_QUEUE_LEN: usize = 2;
+
+/// Fields which are always sent, whatever the filter, as the ground needs them to identify the
+/// packet.
+const TM_ALWAYS_SENT: [&str; 6] = [
+    "vehicle_id", "session_id", "build_id", "backfill", "sim_time_s", "sensed"
+];
 
 // ------------------------------------------------------------------------------------------------
 // STRUCTS
@@ -18,31 +68,169 @@ use crate::arm_ctrl;
 
 /// Telemetry server
 pub struct TmServer {
-    socket: MonitoredSocket
+    socket: Box<dyn Transport>,
+
+    /// Queue of images for the image sender's thread
+    img_tx: SyncSender<(CamId, CamImage)>,
+
+    /// ID of this vehicle, included in every packet
+    vehicle_id: String,
+
+    /// Buffer packets are serialized into, reused each cycle to avoid allocating
+    buffer: Vec<u8>,
+
+    /// Packet updated in place from the data store each cycle, to avoid allocating
+    packet: TmPacket,
+
+    /// Serialized packets stored while out of contact, oldest first
+    backfill: VecDeque<Vec<u8>>,
+
+    /// Maximum number of packets to store for backfill
+    backfill_max_packets: usize,
+
+    /// Number of packets dropped from the backfill because it was full
+    backfill_num_dropped: u64,
+
+    /// Time at which contact with the ground was regained, if in contact
+    contact_since_s: Option<f64>,
+
+    /// Socket TM queries are answered on, if enabled
+    query_socket: Option<MonitoredSocket>,
+
+    /// Time of the packet in `buffer`, or `None` if no packet has been sent yet
+    latest_sim_time_s: Option<f64>,
+
+    /// Filter applied to every packet, or `None` if all fields are sent
+    filter: Option<TmFilter>,
+}
+
+/// Encodes images and sends them on the dedicated image telemetry channel, in its own thread.
+struct ImageSender {
+    socket: Box<dyn Transport>,
+
+    /// Size limits for downlinked images
+    budget: DownlinkBudget,
+
+    /// ID of this sender's session, sent with every chunk
+    session_id: u64,
+
+    /// ID to give the next downlinked image
+    next_image_id: u64,
+
+    /// Buffer chunks are serialized into
+    buffer: Vec<u8>,
+}
+
+/// Parameters of the telemetry server.
+///
+/// The filter also applies to the packets stored for backfill and to TM queries.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct TmParams {
+    /// If not empty only these fields of the packet are sent, along with `TM_ALWAYS_SENT`
+    pub include: Vec<String>,
+
+    /// Fields of the packet which are never sent, applied after `include`
+    pub exclude: Vec<String>,
+}
+
+/// Removes fields from serialized packets.
+///
+/// Fields are named as in TM queries, with dots separating the fields of nested structs.
+struct TmFilter {
+    include: Vec<Vec<String>>,
+    exclude: Vec<Vec<String>>,
 }
 
 /// Telemetry packet that is output by the server.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, TmMeta)]
 pub struct TmPacket {
+    /// ID of the vehicle which sent this packet
+    pub vehicle_id: String,
+
+    /// Random ID of the session, which tells apart data from different runs of the rover
+    pub session_id: String,
+
+    /// ID of the build of the rover software, the git commit it was built from
+    pub build_id: String,
+
+    /// True if the packet was stored while the rover was out of contact and sent once contact was
+    /// regained, rather than sent live
+    #[serde(default)]
+    pub backfill: bool,
+
+    /// Time since the session started
+    #[tm(unit = "s")]
     pub sim_time_s: f64,
 
-    pub left_cam_frame: Option<CamFrame>,
+    /// UTC time at which the cycle's sensor data was read, used to measure the sensation to
+    /// display latency
+    pub sensed: DateTime<Utc>,
+
+    /// Latency stamps of the last TC recieved from the ground
+    #[tm(nested)]
+    pub last_tc_stamps: Option<TcStamps>,
 
-    pub right_cam_frame: Option<CamFrame>,
+    /// Number of cycles in which the per-cycle TC budget was used up, leaving TCs queued
+    #[serde(default)]
+    pub num_tc_budget_overflows: u64,
 
+    /// Number of TC messages rejected for being too large
+    #[serde(default)]
+    pub num_tc_oversized: u64,
+
+    /// True if the rover is in safe mode
     pub safe: bool,
 
+    /// The reasons the rover is in safe mode
     pub safe_cause: String,
 
+    /// Operational mode of the rover
+    pub mode: Mode,
+
+    /// Demands output by locomotion control
+    #[tm(nested)]
     pub loco_ctrl_output: MechDems,
 
+    #[tm(nested)]
     pub loco_ctrl_status_rpt: loco_ctrl::StatusReport,
 
+    /// Locomotion control parameters, including the current steer axis limits
     pub loco_params: loco_ctrl::Params,
 
+    /// Demands after trimming by wheel rate control
+    #[tm(nested)]
+    pub wheel_rate_ctrl_output: MechDems,
+
+    #[tm(nested)]
+    pub wheel_rate_ctrl_status_rpt: wheel_rate_ctrl::StatusReport,
+
+    /// Demands output by arm control
+    #[tm(nested)]
     pub arm_ctrl_output: MechDems,
 
+    /// Arm control parameters
     pub arm_params: arm_ctrl::Params,
+
+    /// Demands output by mast control
+    #[tm(nested)]
+    pub mast_ctrl_output: MechDems,
+
+    #[tm(nested)]
+    pub mast_ctrl_status_rpt: mast_ctrl::StatusReport,
+
+    #[tm(nested)]
+    pub drawbar_status_rpt: drawbar_test::StatusReport,
+
+    /// The active arm stall fault reported by the mechanisms server, if any
+    #[tm(nested)]
+    pub mech_arm_fault: Option<ArmFault>,
+
+    #[tm(nested)]
+    pub self_test_status_rpt: self_test::StatusReport,
+
+    #[tm(nested)]
+    pub turn_cal_status_rpt: turn_cal::StatusReport,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -51,14 +239,29 @@ pub struct TmPacket {
 
 #[derive(Debug, thiserror::Error)]
 pub enum TmServerError {
-    #[error("Socket error: {0}")]
-    SocketError(MonitoredSocketError),
+    #[error("Transport error: {0}")]
+    TransportError(TransportError),
 
     #[error("Could not send telemetry: {0}")]
-    SendError(zmq::Error),
+    SendError(TransportError),
 
     #[error("Could not serialize the telemetry: {0}")]
     SerializationError(serde_json::Error),
+
+    #[error("Could not encode the image for downlink: {0}")]
+    ImageEncodeError(image::ImageError),
+
+    #[error("The image downlink is still busy with earlier images, the image was dropped")]
+    ImageQueueFull,
+
+    #[error("The image downlink thread has stopped")]
+    ImageSenderStopped,
+
+    #[error("Could not open the TM query socket: {0}")]
+    QuerySocketError(MonitoredSocketError),
+
+    #[error("The TM filter names a field which isn't in the TM packet: {0:?}")]
+    UnknownTmField(String),
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -68,8 +271,26 @@ pub enum TmServerError {
 impl TmServer {
     /// Create a new instance of the TM Server.
     ///
-    /// This function will not block until the server connects.
-    pub fn new(ctx: &zmq::Context, params: &NetParams) -> Result<Self, TmServerError> {
+    /// This function will not block until the server connects. If a traffic capture is given all
+    /// TM packets, and images if `include_images` is set, are recorded into it.
+    ///
+    /// Every field named in `tm_params` must be in the TM packet, so that typos don't silently
+    /// change the telemetry.
+    pub fn new(
+        ctx: &zmq::Context,
+        params: &NetParams,
+        tm_params: &TmParams,
+        capture: Option<&TrafficCapture>
+    ) -> Result<Self, TmServerError> {
+        let filter = TmFilter::new(tm_params)?;
+        if let Some(ref f) = filter {
+            info!(
+                "TM filter: {} included fields, {} excluded fields",
+                f.include.len(),
+                f.exclude.len()
+            );
+        }
+
         // Create the socket options
         // TODO: Move these into a parameter file
         let socket_options = SocketOptions {
@@ -86,59 +307,564 @@ impl TmServer {
         };
 
         // Connect the socket
-        let socket = MonitoredSocket::new(
+        let socket = transport::open(
+            params.tm_transport.unwrap_or(params.ground_transport),
             ctx,
             zmq::PUB,
-            socket_options,
+            SocketOptions {
+                udp_frag_bytes: params.tm_udp_frag_bytes,
+                udp_fec_group_size: params.tm_udp_fec_group_size,
+                ..socket_options.clone()
+            },
             &params.tm_endpoint
-        ).map_err(|e| TmServerError::SocketError(e))?;
+        ).map_err(TmServerError::TransportError)?;
+        let socket = capture::maybe_wrap(capture, socket, "tm", &params.tm_endpoint);
+
+        // Images go on their own channel so they don't hold up the rest of the telemetry
+        let img_socket = transport::open(
+            params.ground_transport,
+            ctx,
+            zmq::PUB,
+            socket_options.clone(),
+            &params.img_tm_endpoint
+        ).map_err(TmServerError::TransportError)?;
+
+        // Queries are answered without blocking the cycle
+        let query_socket = match params.tm_query_endpoint {
+            Some(ref endpoint) => Some(MonitoredSocket::new(
+                ctx,
+                zmq::REP,
+                SocketOptions {
+                    recv_timeout: 0,
+                    ..socket_options.clone()
+                },
+                endpoint
+            ).map_err(TmServerError::QuerySocketError)?),
+            None => None,
+        };
+        let img_socket = match params.traffic_capture.include_images {
+            true => capture::maybe_wrap(capture, img_socket, "img_tm", &params.img_tm_endpoint),
+            false => img_socket,
+        };
+
+        let (img_tx, img_rx) = mpsc::sync_channel(IMG_QUEUE_LEN);
+        let img_sender = ImageSender {
+            socket: img_socket,
+            budget: DownlinkBudget {
+                max_bytes: params.img_downlink_max_bytes,
+                chunk_bytes: params.img_downlink_chunk_bytes,
+            },
+            session_id: new_session_id(),
+            next_image_id: 0,
+            buffer: Vec::new(),
+        };
+        thread::spawn(move || img_sender.run(img_rx));
 
         // Create self
         Ok(Self {
-            socket
+            socket,
+            img_tx,
+            vehicle_id: params.vehicle_id.clone(),
+            buffer: Vec::with_capacity(TM_BUFFER_INITIAL_CAPACITY),
+            packet: TmPacket::default(),
+            backfill: VecDeque::with_capacity(params.tm_backfill_max_packets),
+            backfill_max_packets: params.tm_backfill_max_packets,
+            backfill_num_dropped: 0,
+            contact_since_s: Some(0.0),
+            query_socket,
+            latest_sim_time_s: None,
+            filter,
         })
     }
 
-    pub fn send(&mut self, ds: &DataStore) -> Result<(), TmServerError> {
-        // Build packet
-        let packet = TmPacket::from_datastore(ds);
+    /// Send the telemetry packet for this cycle.
+    ///
+    /// `ground_contact` is whether the rover is currently in contact with the ground. While it
+    /// isn't packets are stored for backfill, and once it has been regained the stored packets are
+    /// sent a few at a time.
+    ///
+    /// Once the packet's maps and strings have grown to fit, sending in contact without a filter
+    /// doesn't allocate.
+    pub fn send(&mut self, ds: &DataStore, ground_contact: bool) -> Result<(), TmServerError> {
+        // Update the packet in place
+        self.packet.update_from_datastore(ds, &self.vehicle_id);
 
-        // Serialize packet
-        let packet_string = serde_json::to_string(&packet)
-            .map_err(|e| TmServerError::SerializationError(e))?;
+        // Serialize packet into the reused buffer
+        self.buffer.clear();
+        serialize(&self.packet, self.filter.as_ref(), &mut self.buffer)?;
 
         // Send the packet
-        self.socket.send(&format!("{}", packet_string), 0)
-            .map_err(|e| TmServerError::SendError(e))
+        publish(&*self.socket, &self.buffer)?;
+
+        // The buffer now holds the latest packet, which queries are answered from
+        self.latest_sim_time_s = Some(ds.hk.sim_time_s);
+        self.serve_queries();
+
+        match (ground_contact, self.contact_since_s) {
+            (true, None) => {
+                info!(
+                    "Ground contact regained, {} TM packets to backfill ({} dropped)",
+                    self.backfill.len(),
+                    self.backfill_num_dropped
+                );
+                self.contact_since_s = Some(ds.hk.sim_time_s);
+                self.backfill_num_dropped = 0;
+            }
+            (false, Some(_)) => {
+                warn!("Ground contact lost, storing TM for backfill");
+                self.contact_since_s = None;
+            }
+            _ => (),
+        }
+
+        match self.contact_since_s {
+            // Store one packet per second while out of contact
+            None => {
+                if ds.hk.is_1_hz_cycle && self.backfill_max_packets > 0 {
+                    self.packet.backfill = true;
+
+                    if self.backfill.len() >= self.backfill_max_packets {
+                        self.backfill.pop_front();
+                        self.backfill_num_dropped += 1;
+                    }
+
+                    let mut msg = Vec::new();
+                    serialize(&self.packet, self.filter.as_ref(), &mut msg)?;
+                    self.backfill.push_back(msg);
+                }
+            }
+            Some(t) if ds.hk.sim_time_s - t >= BACKFILL_HOLDOFF_S => {
+                for _ in 0..BACKFILL_PACKETS_PER_CYCLE {
+                    match self.backfill.pop_front() {
+                        Some(msg) => publish(&*self.socket, &msg)?,
+                        None => break,
+                    }
+                }
+            }
+            Some(_) => (),
+        }
+
+        Ok(())
+    }
+
+    /// Answer the pending TM queries from the latest packet.
+    ///
+    /// Failing to answer a query doesn't stop the telemetry, so errors are only logged.
+    fn serve_queries(&mut self) {
+        // Used as a plain zmq socket, as the Transport methods don't take flags
+        let socket: &zmq::Socket = match self.query_socket {
+            Some(ref s) => s,
+            None => return,
+        };
+
+        // Only parsed if there's a query to answer
+        let mut packet: Option<serde_json::Value> = None;
+
+        for _ in 0..QUERIES_PER_CYCLE {
+            let msg = match socket.recv_bytes(zmq::DONTWAIT) {
+                Ok(m) => m,
+                Err(zmq::Error::EAGAIN) => break,
+                Err(e) => {
+                    warn!("Could not recieve a TM query: {}", e);
+                    break;
+                }
+            };
+
+            let response = match (serde_json::from_slice::<TmQuery>(&msg), self.latest_sim_time_s) {
+                (Err(_), _) => TmQueryResponse::Invalid,
+                (Ok(_), None) => TmQueryResponse::NoData,
+                (Ok(q), Some(sim_time_s)) => {
+                    if packet.is_none() {
+                        packet = serde_json::from_slice(&self.buffer).ok();
+                    }
+
+                    match packet.as_ref().and_then(|p| query::lookup(p, &q.channel)) {
+                        Some(value) => TmQueryResponse::Value {
+                            sim_time_s,
+                            value: value.clone(),
+                        },
+                        None => TmQueryResponse::UnknownChannel(q.channel),
+                    }
+                }
+            };
+
+            // A REP socket must always reply before recieving the next query
+            let sent = serde_json::to_vec(&response)
+                .map_err(|e| e.to_string())
+                .and_then(|r| socket.send(r, 0).map_err(|e| e.to_string()));
+
+            if let Err(e) = sent {
+                warn!("Could not answer a TM query: {}", e);
+                break;
+            }
+        }
+    }
+
+    /// Queue an image to be sent over the image telemetry channel.
+    ///
+    /// The image is recompressed to fit within the downlink budget and sent in chunks by the image
+    /// sender's thread. If earlier images are still waiting the image is dropped and
+    /// `ImageQueueFull` returned.
+    pub fn send_image(&mut self, cam_id: CamId, image: &CamImage) -> Result<(), TmServerError> {
+        match self.img_tx.try_send((cam_id, image.clone())) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(TmServerError::ImageQueueFull),
+            Err(TrySendError::Disconnected(_)) => Err(TmServerError::ImageSenderStopped),
+        }
+    }
+}
+
+impl ImageSender {
+    /// Send queued images until the server is dropped.
+    fn run(mut self, rx: Receiver<(CamId, CamImage)>) {
+        while let Ok((cam_id, image)) = rx.recv() {
+            if let Err(e) = self.send(cam_id, &image) {
+                warn!("Could not downlink {:?} image: {}", cam_id, e);
+            }
+        }
+    }
+
+    /// Recompress the image to fit within the downlink budget and send it in chunks.
+    fn send(&mut self, cam_id: CamId, image: &CamImage) -> Result<(), TmServerError> {
+        let encoded = EncodedImage::fit_to_budget(image, &self.budget)
+            .map_err(TmServerError::ImageEncodeError)?;
+
+        if encoded.data.len() > self.budget.max_bytes {
+            warn!(
+                "{:?} image is {} bytes, over the {} byte downlink budget",
+                cam_id,
+                encoded.data.len(),
+                self.budget.max_bytes
+            );
+        }
+
+        let image_id = self.next_image_id;
+        self.next_image_id += 1;
+
+        let chunks = encoded.into_chunks(
+            self.session_id,
+            image_id,
+            cam_id,
+            image.timestamp,
+            self.budget.chunk_bytes
+        );
+
+        for chunk in chunks {
+            self.buffer.clear();
+            serde_json::to_writer(&mut self.buffer, &chunk)
+                .map_err(TmServerError::SerializationError)?;
+
+            publish(&*self.socket, &self.buffer)?;
+        }
+
+        Ok(())
     }
 }
 
 impl TmPacket {
-    pub fn from_datastore(ds: &DataStore) -> Self {
-        Self {
-            sim_time_s: ds.sim_time_s,
-            safe: ds.safe,
-            safe_cause: ds.safe_cause_string.clone(),
-            loco_ctrl_output: ds.loco_ctrl_output.clone(),
-            loco_ctrl_status_rpt: ds.loco_ctrl_status_rpt.clone(),
-            arm_ctrl_output: ds.arm_ctrl_output.clone(),
-            loco_params: ds.loco_params.clone(),
-            arm_params: ds.arm_params.clone(),
-
-            left_cam_frame: match ds.left_cam_image {
-                Some(ref i) => {
-                    let frame = i.to_cam_frame(ImageFormat::Jpeg(75)).unwrap();
-                    Some(frame)
-                },
-                None => None
-            },
-            right_cam_frame: match ds.right_cam_image {
-                Some(ref i) => {
-                    let frame = i.to_cam_frame(ImageFormat::Jpeg(75)).unwrap();
-                    Some(frame)
-                },
-                None => None
-            },
+    pub fn from_datastore(ds: &DataStore, vehicle_id: &str) -> Self {
+        let mut packet = Self::default();
+        packet.update_from_datastore(ds, vehicle_id);
+
+        packet
+    }
+
+    /// Update the packet from the data store, reusing the allocations of its strings and maps.
+    pub fn update_from_datastore(&mut self, ds: &DataStore, vehicle_id: &str) {
+        self.vehicle_id.clear();
+        self.vehicle_id.push_str(vehicle_id);
+        self.session_id.clone_from(&ds.hk.session_id);
+        self.build_id.clone_from(&ds.hk.build_id);
+        self.backfill = false;
+        self.sim_time_s = ds.hk.sim_time_s;
+        self.sensed = ds.hk.cycle_start_utc;
+        self.last_tc_stamps = ds.hk.last_tc_stamps;
+        self.num_tc_budget_overflows = ds.hk.num_tc_budget_overflows;
+        self.num_tc_oversized = ds.hk.num_tc_oversized;
+        self.safe = ds.safety.is_safe();
+        self.safe_cause.clear();
+        self.safe_cause.push_str(ds.safety.cause_string());
+        self.mode = ds.safety.mode_mgr.mode();
+        self.loco_ctrl_output.clone_from(&ds.loco.loco_ctrl_output);
+        self.loco_ctrl_status_rpt = ds.loco.loco_ctrl_status_rpt;
+        self.arm_ctrl_output.clone_from(&ds.mech.arm_ctrl_output);
+        self.loco_params.clone_from(&ds.loco.loco_params);
+        self.wheel_rate_ctrl_output.clone_from(&ds.loco.wheel_rate_ctrl_output);
+        self.wheel_rate_ctrl_status_rpt = ds.loco.wheel_rate_ctrl_status_rpt;
+        self.arm_params.clone_from(&ds.mech.arm_params);
+        self.mast_ctrl_output.clone_from(&ds.mech.mast_ctrl_output);
+        self.mast_ctrl_status_rpt = ds.mech.mast_ctrl_status_rpt;
+        self.drawbar_status_rpt = ds.checkout.drawbar_status_rpt;
+        self.mech_arm_fault = *ds.mech.arm_fault.get();
+        self.self_test_status_rpt = ds.checkout.self_test_status_rpt;
+        self.turn_cal_status_rpt = ds.checkout.turn_cal_status_rpt;
+    }
+}
+
+impl TmFilter {
+    /// Create the filter from the parameters, or `None` if they don't filter anything.
+    fn new(params: &TmParams) -> Result<Option<Self>, TmServerError> {
+        if params.include.is_empty() && params.exclude.is_empty() {
+            return Ok(None);
+        }
+
+        let fields = TmPacket::tm_fields();
+        let parse = |names: &[String]| -> Result<Vec<Vec<String>>, TmServerError> {
+            names
+                .iter()
+                .map(|name| {
+                    let path: Vec<String> = name.split('.').map(String::from).collect();
+                    match is_tm_field(&fields, &path) {
+                        true => Ok(path),
+                        false => Err(TmServerError::UnknownTmField(name.clone())),
+                    }
+                })
+                .collect()
+        };
+
+        let mut include = parse(&params.include)?;
+        if !include.is_empty() {
+            include.extend(TM_ALWAYS_SENT.iter().map(|f| vec![String::from(*f)]));
+        }
+
+        let exclude = parse(&params.exclude)?
+            .into_iter()
+            .filter(|p| !(p.len() == 1 && TM_ALWAYS_SENT.contains(&p[0].as_str())))
+            .collect();
+
+        Ok(Some(Self { include, exclude }))
+    }
+
+    /// Apply the filter to a packet.
+    fn apply(&self, packet: Value) -> Value {
+        let mut packet = match self.include.is_empty() {
+            true => packet,
+            false => {
+                let mut included = Value::Object(Map::new());
+                for path in self.include.iter() {
+                    if let Some(v) = query::lookup(&packet, &path.join(".")) {
+                        insert_at(&mut included, path, v.clone());
+                    }
+                }
+                included
+            }
+        };
+
+        for path in self.exclude.iter() {
+            let (last, parents) = path.split_last().expect("Empty TM field path");
+            let parent = parents
+                .iter()
+                .try_fold(&mut packet, |v, field| v.get_mut(field.as_str()));
+
+            if let Some(Value::Object(map)) = parent {
+                map.remove(last);
+            }
+        }
+
+        packet
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Send a message on a telemetry channel.
+///
+/// Telemetry sent while no ground station is listening is dropped, which isn't an error.
+fn publish(socket: &dyn Transport, msg: &[u8]) -> Result<(), TmServerError> {
+    match socket.send_bytes(msg) {
+        Ok(()) | Err(TransportError::NotSent) => Ok(()),
+        Err(e) => Err(TmServerError::SendError(e)),
+    }
+}
+
+/// Serialize a packet into the buffer, applying the filter if there is one.
+fn serialize(
+    packet: &TmPacket,
+    filter: Option<&TmFilter>,
+    buffer: &mut Vec<u8>
+) -> Result<(), TmServerError> {
+    match filter {
+        Some(f) => {
+            let value = serde_json::to_value(packet).map_err(TmServerError::SerializationError)?;
+            serde_json::to_writer(buffer, &f.apply(value))
+        }
+        None => serde_json::to_writer(buffer, packet),
+    }
+    .map_err(TmServerError::SerializationError)
+}
+
+/// Check that a field path names a field in the metadata.
+///
+/// Fields without metadata for their own fields can't be checked any deeper, so any path below
+/// them is accepted.
+fn is_tm_field(fields: &[TmFieldMeta], path: &[String]) -> bool {
+    match path.split_first() {
+        None => true,
+        Some((name, rest)) => match fields.iter().find(|f| f.name == name.as_str()) {
+            Some(f) if f.fields.is_empty() => true,
+            Some(f) => is_tm_field(&f.fields, rest),
+            None => false,
+        },
+    }
+}
+
+/// Insert a value into a JSON object at the given path, creating the parent objects as needed.
+fn insert_at(target: &mut Value, path: &[String], value: Value) {
+    match path.split_first() {
+        Some((name, [])) => {
+            if let Value::Object(map) = target {
+                map.insert(name.clone(), value);
+            }
+        }
+        Some((name, rest)) => {
+            if let Value::Object(map) = target {
+                let child = map
+                    .entry(name.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                insert_at(child, rest, value);
+            }
+        }
+        None => (),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{count_allocs, net_params};
+    use comms_if::{
+        eqpt::mech::ActId,
+        tm::img::{ImageChunk, ImageReassembler},
+    };
+    use image::{DynamicImage, RgbImage};
+    use std::time::{Duration, Instant};
+
+    /// Serialise and parse the packet, returning the JSON before and after.
+    fn round_trip(packet: &TmPacket) -> (Value, Value) {
+        let json = serde_json::to_string(packet).unwrap();
+        let parsed: TmPacket = serde_json::from_str(&json).unwrap();
+
+        (
+            serde_json::to_value(packet).unwrap(),
+            serde_json::to_value(&parsed).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_tm_packet_round_trip() {
+        let mut ds = DataStore::default();
+
+        let (before, after) = round_trip(&TmPacket::from_datastore(&ds, ""));
+        assert_eq!(before, after);
+
+        ds.hk.session_id = String::from("ünïcödé session");
+        ds.hk.sim_time_s = f64::MAX;
+        ds.hk.num_tc_budget_overflows = u64::MAX;
+        ds.hk.last_tc_stamps = Some(TcStamps::recvd(Some(Utc::now())));
+        ds.make_safe(crate::data_store::SafeModeCause::MakeSafeTc);
+        ds.loco.loco_ctrl_output.speed_rads.insert(ActId::DrvFL, -f64::MIN_POSITIVE);
+        ds.loco.loco_ctrl_output.pos_rad.insert(ActId::StrRR, 0.1);
+        ds.mech.arm_fault.set(Some(ArmFault {
+            act_id: ActId::ArmElbow,
+            current_a: 1.5e-300,
+            limit_a: f64::MAX,
+            pos_rad: -0.0,
+        }));
+
+        let packet = TmPacket::from_datastore(&ds, "phobos-1");
+        let (before, after) = round_trip(&packet);
+        assert_eq!(before, after);
+
+        // Fields the ground tools look up by name
+        for field in TM_ALWAYS_SENT {
+            assert!(before.get(field).is_some(), "{} missing from the packet", field);
+        }
+        assert_eq!(before["safe"], Value::Bool(true));
+    }
+
+    #[test]
+    fn test_steady_state_send_does_not_allocate() {
+        let ctx = zmq::Context::new();
+        let params = net_params(
+            "inproc://mech_dems",
+            "inproc://mech_sens",
+            "inproc://tm_alloc",
+            "inproc://img_tm_alloc",
+        );
+        let mut server = TmServer::new(&ctx, &params, &TmParams::default(), None).unwrap();
+
+        let mut ds = DataStore::default();
+        ds.hk.session_id = String::from("session");
+        ds.loco.loco_ctrl_output = MechDems::empty_loco();
+        ds.loco.wheel_rate_ctrl_output = MechDems::empty_loco();
+
+        // First send grows the packet and buffer
+        server.send(&ds, true).unwrap();
+
+        for i in 1..=10 {
+            ds.hk.sim_time_s = i as f64 * 0.1;
+            ds.loco.loco_ctrl_output.speed_rads.insert(ActId::DrvFL, i as f64);
+
+            assert_eq!(count_allocs(|| server.send(&ds, true).unwrap()), 0);
+        }
+    }
+
+    #[test]
+    fn test_send_image_does_not_wait_for_encoding() {
+        let ctx = zmq::Context::new();
+        let params = net_params(
+            "inproc://mech_dems",
+            "inproc://mech_sens",
+            "inproc://tm_img",
+            "inproc://img_tm_img",
+        );
+        let mut server = TmServer::new(&ctx, &params, &TmParams::default(), None).unwrap();
+
+        let ground = ctx.socket(zmq::SUB).unwrap();
+        ground.connect("inproc://img_tm_img").unwrap();
+        ground.set_subscribe(b"").unwrap();
+        ground.set_rcvtimeo(30_000).unwrap();
+
+        // A noisy image takes a while to recompress
+        let image = CamImage {
+            timestamp: Utc::now(),
+            image: DynamicImage::ImageRgb8(RgbImage::from_fn(640, 480, |x, y| {
+                let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)) as u8;
+                image::Rgb([v, v.wrapping_mul(3), v.wrapping_add(y as u8)])
+            })),
+        };
+
+        // Images beyond the queue are dropped rather than waited for
+        let start = Instant::now();
+        let results: Vec<_> = (0..IMG_QUEUE_LEN + 3)
+            .map(|_| server.send_image(CamId::LeftNav, &image))
+            .collect();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results.last().unwrap(),
+            Err(TmServerError::ImageQueueFull)
+        ));
+
+        // The worker still sends the queued images
+        let mut reassembler = ImageReassembler::new(4, 10_000);
+        loop {
+            let chunk: ImageChunk =
+                serde_json::from_slice(&ground.recv_bytes(0).unwrap()).unwrap();
+            if let Some((cam_id, frame)) = reassembler.push(chunk).unwrap() {
+                assert_eq!(cam_id, CamId::LeftNav);
+                assert_eq!(
+                    frame.timestamp.timestamp_millis(),
+                    image.timestamp.timestamp_millis()
+                );
+                break;
+            }
         }
     }
 }
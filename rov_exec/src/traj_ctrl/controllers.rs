@@ -1,123 +1,135 @@
 //! # Trajectory controllers module
 //!
-//! This module provides the PID controllers used for TrajCtrl, including their
-//! error calculations.
+//! This module provides the controllers used for TrajCtrl, including their
+//! error calculations. Two controllers are available, selected by the 
+//! `controller` parameter:
+//!
+//!  - `Pid` - a pair of PID controllers (`util::control::Pid`) on the
+//!    lateral and heading errors to the current path segment.
+//!  - `PurePursuit` - steers along the arc which passes through a lookahead
+//!    point on the path. The lookahead distance grows with speed, which makes
+//!    this controller less sensitive to how finely the path is discretised.
+//!
+//! Both are evaluated every cycle and their curvature demands are put in the
+//! status report, so that one can be tuned against the other.
 
 // ---------------------------------------------------------------------------
 // IMPORTS
 // ---------------------------------------------------------------------------
 
-// External
-use std::time::Instant;
-
 // Internal
-use util::maths::norm;
+use util::{control::{Pid, PidParams}, maths::{angle_diff_rad, norm}};
 use super::path::*;
+use super::params::ControllerType;
 use crate::loc::Pose;
-use comms_if::tc::loco_ctrl::MnvrCmd;
+use comms_if::{
+    tc::loco_ctrl::MnvrCmd,
+    units::{Curvature, MetersPerSec, Radians},
+};
 
 // ---------------------------------------------------------------------------
 // DATA STRUCTURES
 // ---------------------------------------------------------------------------
 
-/// A PID controller
-pub struct PidController {
-    /// Previous instant that the error was passed in 
-    prev_time: Option<Instant>,
-
-    /// Proportional gain
-    k_p: f64,
+/// A pure pursuit controller
+pub struct PurePursuitController {
+    /// Lookahead distance at zero speed
+    lookahead_min_m: f64,
 
-    /// Integral gain
-    k_i: f64,
+    /// Increase in lookahead distance per unit speed
+    lookahead_gain_s: f64,
 
-    /// Dervative gain
-    k_d: f64,
-
-    /// Previous error
-    prev_error: Option<f64>,
-
-    /// The integral accumulation
-    integral: f64
+    /// Maximum lookahead distance
+    lookahead_max_m: f64
 }
 
 /// The trajectory controllers
 pub struct TrajControllers {
     /// Lateral error controller
-    lat_ctrl: PidController,
+    lat_ctrl: Pid,
 
     /// Heading error controller
-    head_ctrl: PidController
+    head_ctrl: Pid,
+
+    /// Pure pursuit controller
+    pure_pursuit: PurePursuitController,
+
+    /// Speed demanded on the previous cycle, used to set the lookahead 
+    /// distance
+    prev_speed_dem_ms: f64
 }
 
 // ---------------------------------------------------------------------------
 // IMPLEMENTATIONS
 // ---------------------------------------------------------------------------
 
-impl PidController {
+impl PurePursuitController {
 
-    /// Create a new controller with the given gains.
-    pub fn new(k_p: f64, k_i: f64, k_d: f64) -> Self {
+    /// Create a new controller with the given lookahead parameters.
+    pub fn new(
+        lookahead_min_m: f64, 
+        lookahead_gain_s: f64, 
+        lookahead_max_m: f64
+    ) -> Self {
         Self {
-            k_p, k_i, k_d,
-            integral: 0f64,
-            prev_time: None,
-            prev_error: None
+            lookahead_min_m,
+            lookahead_gain_s,
+            lookahead_max_m
         }
     }
 
-    /// Get the value of the controller for the given error.
+    /// Get the lookahead distance for the given speed.
+    pub fn get_lookahead(&self, speed_ms: f64) -> f64 {
+        (self.lookahead_min_m + self.lookahead_gain_s * speed_ms.abs())
+            .min(self.lookahead_max_m)
+    }
+
+    /// Get the curvature demand to reach the lookahead point.
     ///
-    /// This function is time-aware so there is no need to pass in a delta-time
-    /// value.
-    pub fn get(&mut self, error: f64) -> f64 {
-        // Get current time
-        let curr_time = Instant::now();
-
-        // Calculate dt
-        let dt = match self.prev_time {
-            Some(t0) => Some((curr_time - t0).as_secs_f64()),
-            None => None
-        };
-
-        // Accumulate the integral term.
-        //
-        // If there's no time difference then we don't accumulate the integral
-        // The other option is to add on the error and that will produce a 
-        // large spike in integral compared to normal operation, so we don't do
-        // this.
-        self.integral += match dt {
-            Some(t) => error * t,
-            None => 0f64
-        };
-
-        // Calculate the derivative.
-        //
-        // If there's no time difference again we assume no derivative, for the
-        // same reasons as for integral.
-        let deriv = match self.prev_error {
-            Some(e) => match dt {
-                Some(t) => (error - e) / t,
-                None => 0f64
-            },
-            None => match dt {
-                Some(t) => error / t,
-                None => 0f64
+    /// The lookahead point is the first point on the path, starting from the
+    /// current target, which is at least the lookahead distance away from the
+    /// rover. If the whole remainder of the path is closer than that the last
+    /// point is used.
+    ///
+    /// Returns the curvature demand and the distance to the lookahead point,
+    /// or `None` if the target is beyond the end of the path.
+    pub fn get(
+        &self,
+        path: &Path,
+        target_index: usize,
+        pose: &Pose,
+        speed_ms: f64
+    ) -> Option<(f64, f64)> {
+        let lookahead_m = self.get_lookahead(speed_ms);
+
+        // Find the lookahead point
+        let mut point_m_lm = path.get_point(target_index)?;
+        for i in target_index..path.get_num_points() {
+            // Unwrap is safe since i is within the path
+            point_m_lm = path.get_point(i).unwrap();
+
+            if norm(&point_m_lm, &pose.position_m_lm[0..2]).unwrap() 
+                >= lookahead_m 
+            {
+                break;
             }
-        };
+        }
 
-        // Calculate the output
-        let out = 
-            self.k_p * error 
-            + self.k_i * self.integral 
-            + self.k_d * deriv;
-        
-        // Remember the previous error and time
-        self.prev_error = Some(error);
-        self.prev_time = Some(curr_time);
+        // Transform the point into the rover's frame
+        let dx_m = point_m_lm[0] - pose.position_m_lm[0];
+        let dy_m = point_m_lm[1] - pose.position_m_lm[1];
+        let head_rad = pose.get_heading();
+        let y_m_rb = -dx_m * head_rad.sin() + dy_m * head_rad.cos();
+        let dist_sq_m = dx_m.powi(2) + dy_m.powi(2);
+
+        // If we're on top of the point there's no arc to follow
+        if dist_sq_m == 0.0 {
+            return Some((0.0, 0.0));
+        }
 
-        // Return
-        out
+        // The arc through the rover's position, tangent to its heading, which
+        // passes through the point
+        Some((2.0 * y_m_rb / dist_sq_m, dist_sq_m.sqrt()))
     }
 }
 
@@ -126,32 +138,47 @@ impl TrajControllers {
     /// Create a new instance of the controllers from the parameters
     pub fn new(params: &super::Params) -> Self {
         Self {
-            lat_ctrl: PidController::new(
+            lat_ctrl: Pid::new(PidParams::new(
                 params.lat_k_p, params.lat_k_i, params.lat_k_d
-            ),
-            head_ctrl: PidController::new(
+            )),
+            head_ctrl: Pid::new(PidParams::new(
                 params.head_k_p, params.head_k_i, params.head_k_d
-            )
+            )),
+            pure_pursuit: PurePursuitController::new(
+                params.pp_lookahead_min_m,
+                params.pp_lookahead_gain_s,
+                params.pp_lookahead_max_m
+            ),
+            prev_speed_dem_ms: 0f64
         }
     }
 
-    /// Get the ackerman demand for the current path segment and pose.
+    /// Get the ackerman demand to follow the path from the current pose.
+    ///
+    /// The curvature demand comes from the controller selected in the 
+    /// parameters. The errors to the current segment, which are used to abort
+    /// the path, are always calculated.
     ///
     /// TODO: Add crab support
     pub fn get_ackerman_cmd(
         &mut self, 
-        segment: &PathSegment, 
+        path: &Path,
+        target_index: usize,
         pose: &Pose,
         report: &mut super::StatusReport,
         params: &super::Params
     ) -> MnvrCmd {
 
+        // Can safely unwrap here as target management has already been 
+        // performed by TrajCtrl
+        let segment = path.get_segment_to_target(target_index).unwrap();
+
         // Calculate lateral error
-        let lat_err_m = self.calc_lat_error(segment, pose);
+        let lat_err_m = self.calc_lat_error(&segment, pose);
         report.lat_error_m = lat_err_m;
 
         // Calcualte heading error
-        let head_err_rad = self.calc_head_error(segment, pose);
+        let head_err_rad = self.calc_head_error(&segment, pose);
         report.head_error_rad = head_err_rad;
 
         // Enforce limits on heading and lateral errors
@@ -165,16 +192,22 @@ impl TrajControllers {
         // Pass the errors through the controllers
         let lat_curv_dem_m = self.lat_ctrl.get(lat_err_m);
         let head_curv_dem_m = self.head_ctrl.get(head_err_rad);
-
-        // Sum the curvatures and apply limits
-        let mut curv_dem_m = lat_curv_dem_m + head_curv_dem_m;
-
-        if curv_dem_m > params.max_curv_dem_m {
-            curv_dem_m = params.max_curv_dem_m;
-        }
-        if curv_dem_m < params.min_curv_dem_m {
-            curv_dem_m = params.min_curv_dem_m;
-        }
+        report.pid_curv_dem_m = lat_curv_dem_m + head_curv_dem_m;
+
+        // Get the pure pursuit demand, falling back to the PID demand if there
+        // is no lookahead point (which shouldn't happen)
+        let (pp_curv_dem_m, pp_lookahead_m) = self.pure_pursuit
+            .get(path, target_index, pose, self.prev_speed_dem_ms)
+            .unwrap_or((report.pid_curv_dem_m, 0f64));
+        report.pp_curv_dem_m = pp_curv_dem_m;
+        report.pp_lookahead_m = pp_lookahead_m;
+
+        // Select the demand and apply limits
+        let curv_dem = Curvature(match params.controller {
+            ControllerType::Pid => report.pid_curv_dem_m,
+            ControllerType::PurePursuit => report.pp_curv_dem_m
+        })
+        .clamp(params.min_curv_dem_m, params.max_curv_dem_m);
 
         // Calculate speed demand
         let mut speed_dem_ms = 0f64;
@@ -183,24 +216,22 @@ impl TrajControllers {
             .enumerate() 
         {
             speed_dem_ms += 
-                curv_dem_m.powi(
+                curv_dem.0.powi(
                     (params.curv_speed_map_coeffs.len() - 1 - i) 
                     as i32)
                 * c;
         }
 
         // Apply speed limits
-        if speed_dem_ms > params.max_speed_dem_ms {
-            speed_dem_ms = params.max_speed_dem_ms
-        }
-        if speed_dem_ms < params.min_speed_dem_ms {
-            speed_dem_ms = params.min_speed_dem_ms
-        }
+        let speed_dem = MetersPerSec(speed_dem_ms)
+            .clamp(params.min_speed_dem_ms, params.max_speed_dem_ms);
+
+        self.prev_speed_dem_ms = speed_dem.0;
 
         MnvrCmd::Ackerman {
-            speed_ms: speed_dem_ms,
-            curv_m: curv_dem_m,
-            crab_rad: 0.0
+            speed_ms: speed_dem,
+            curv_m: curv_dem,
+            crab_rad: Radians(0.0)
         }
     }
 
@@ -228,7 +259,7 @@ impl TrajControllers {
         //
         // The unwrap here is safe as we are enforcing dimentions by taking
         // a slice of the position.
-        norm(&isect_m_lm, &pose.position_m_lm[0..1]).unwrap()
+        norm(&isect_m_lm, &pose.position_m_lm[0..2]).unwrap()
     }
 
     /// Calculate the heading error to the segment
@@ -243,7 +274,8 @@ impl TrajControllers {
         // To do this we simply get the arctan of the segment slope.
         let seg_head_rad = segment.slope_m.atan();
 
-        // Return the rover's heading - the segment heading
-        pose.get_heading() - seg_head_rad
+        // Return the rover's heading - the segment heading, wrapped so that
+        // the controller always turns the shortest way
+        angle_diff_rad(pose.get_heading(), seg_head_rad)
     }
 }
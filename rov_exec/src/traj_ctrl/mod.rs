@@ -16,6 +16,9 @@
 //! curvature demands which are then summed and saturated. Speed demands are 
 //! calculated based off of the curvature demand, the tighter the turn, the 
 //! slower the desired speed.
+//!
+//! Alternatively a pure pursuit controller can be selected with the 
+//! `controller` parameter, see the `controllers` module.
 
 // ---------------------------------------------------------------------------
 // MODULES
@@ -33,5 +36,5 @@ pub mod state;
 // Internal
 pub use path::*;
 pub use controllers::*;
-pub use params::Params;
+pub use params::{ControllerType, Params, PathParams};
 pub use state::*;
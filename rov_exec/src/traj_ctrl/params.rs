@@ -7,6 +7,9 @@
 // External
 use serde::Deserialize;
 
+// Internal
+use comms_if::units::{Curvature, MetersPerSec, RadPerSec};
+
 // ---------------------------------------------------------------------------
 // DATA STRUCTURES
 // ---------------------------------------------------------------------------
@@ -15,6 +18,9 @@ use serde::Deserialize;
 #[derive(Deserialize)]
 pub struct Params {
     
+    /// The controller used to follow the path.
+    pub controller: ControllerType,
+
     /// Lateral controller proportional gain
     pub lat_k_p: f64,
 
@@ -34,10 +40,10 @@ pub struct Params {
     pub head_k_d: f64,
 
     /// Curvature demand minimum limit
-    pub min_curv_dem_m: f64,
+    pub min_curv_dem_m: Curvature,
 
     /// Curvature demand minimum limit
-    pub max_curv_dem_m: f64,
+    pub max_curv_dem_m: Curvature,
 
     /// Curvature to speed map coefficients
     /// 
@@ -47,10 +53,10 @@ pub struct Params {
     pub curv_speed_map_coeffs: Vec<f64>,
 
     /// Minimum speed demand
-    pub min_speed_dem_ms: f64,
+    pub min_speed_dem_ms: MetersPerSec,
 
     /// Maximum speed demand
-    pub max_speed_dem_ms: f64,
+    pub max_speed_dem_ms: MetersPerSec,
 
     /// The limit on lateral error. Above this limit the path sequence will
     /// be aborted.
@@ -62,9 +68,64 @@ pub struct Params {
 
     /// The rate at which to turn the rover during a heading adjustment 
     /// manouvre.
-    pub head_adjust_rate_rads: f64,
+    pub head_adjust_rate_rads: RadPerSec,
 
     /// The threshold under which a heading adjustment will be considered 
     /// complete.
-    pub head_adjust_threshold_rad: f64
-}
\ No newline at end of file
+    pub head_adjust_threshold_rad: f64,
+
+    /// Pure pursuit lookahead distance at zero speed.
+    pub pp_lookahead_min_m: f64,
+
+    /// Pure pursuit lookahead distance gain on speed, i.e. the lookahead 
+    /// increases by this many meters for each m/s of speed.
+    pub pp_lookahead_gain_s: f64,
+
+    /// Pure pursuit maximum lookahead distance.
+    pub pp_lookahead_max_m: f64,
+
+    /// Limits on the paths that can be loaded into trajectory control.
+    pub path: PathParams
+}
+
+/// Limits applied to every path entering the system, see `Path::validate`.
+#[derive(Clone, Default, Deserialize)]
+pub struct PathParams {
+    /// Minimum number of points in a path. Paths always need at least 2.
+    pub min_num_points: usize,
+
+    /// Maximum distance between consecutive points in a path.
+    pub max_point_separation_m: f64,
+
+    /// Maximum total length of a path.
+    pub max_length_m: f64,
+
+    /// Maximum curvature between consecutive segments of a path.
+    pub max_curvature_m: Curvature,
+
+    /// Separation between points preferred by trajectory control. Paths are
+    /// resampled to this separation once validated.
+    pub preferred_separation_m: f64,
+
+    /// Largest change in heading between segments allowed after densifying a
+    /// validated path, see `Path::densify_by_curvature`.
+    pub max_heading_step_rad: f64
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// The controllers which can be used to follow the path.
+///
+/// Both controllers are run on every cycle so that their outputs can be 
+/// compared in the status report, but only the selected one drives the rover.
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub enum ControllerType {
+    /// Lateral and heading error PID controllers, see `TrajControllers`.
+    Pid,
+
+    /// Pure pursuit of a speed dependent lookahead point on the path, see 
+    /// `PurePursuitController`.
+    PurePursuit
+}
@@ -8,9 +8,29 @@
 
 // External
 use serde::{Serialize, Deserialize};
+use std::fs;
 
 // Internal
-use util::maths::norm;
+use super::params::PathParams;
+use util::maths::{
+    angle_diff_rad,
+    dist_to_segment,
+    norm,
+    point_in_polygon,
+    segment_intersection
+};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Change in heading above which a point is treated as a corner, and kept,
+/// during resampling.
+const RESAMPLE_CORNER_RAD: f64 = 0.1;
+
+/// Maximum number of times a spline piece is halved by `split_catmull_rom`,
+/// which bounds the number of points added to each segment.
+const DENSIFY_MAX_DEPTH: usize = 10;
 
 // ---------------------------------------------------------------------------
 // DATA STRUCTURES
@@ -42,6 +62,68 @@ pub struct PathSegment {
     pub intercept_m: f64
 }
 
+/// A point where a path crosses another path or a polygon edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathIntersection {
+
+    /// The position of the intersection
+    pub point_m_lm: [f64; 2],
+
+    /// The heading of the path at the intersection
+    pub heading_rad: f64,
+
+    /// The distance along the path to the intersection
+    pub dist_along_m: f64,
+
+    /// The index of the point at the start of the path segment which crosses
+    pub segment_index: usize,
+
+    /// The index of the start of the other path's segment, or of the polygon
+    /// edge, which is crossed
+    pub other_index: usize
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Errors that can occur while loading a path from a file.
+#[derive(Debug, thiserror::Error)]
+pub enum PathLoadError {
+    #[error("Could not read the path file: {0}")]
+    FileReadError(std::io::Error),
+
+    #[error("Path file has unsupported extension {0:?}, expected json or csv")]
+    UnsupportedFormat(Option<String>),
+
+    #[error("Could not parse the JSON path file: {0}")]
+    JsonError(serde_json::Error),
+
+    #[error("Could not parse line {0} of the CSV path file")]
+    CsvError(usize)
+}
+
+/// Reasons a path can be rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum PathError {
+    #[error("Path contains {0} points, at least 2 are required")]
+    NotEnoughPoints(usize),
+
+    #[error("Point {0} is not finite")]
+    NonFinitePoint(usize),
+
+    #[error(
+        "Points {0} and {1} are {2:.3} m apart, more than the maximum separation of {3:.3} m")]
+    PointsTooFar(usize, usize, f64, f64),
+
+    #[error("Path is {0:.3} m long, more than the maximum length of {1:.3} m")]
+    TooLong(f64, f64),
+
+    #[error(
+        "Curvature at point {0} is {1:.3} 1/m, more than the maximum of {2:.3} 1/m")]
+    CurvatureTooHigh(usize, f64, f64)
+}
+
 // ---------------------------------------------------------------------------
 // IMPLEMENTATIONS
 // ---------------------------------------------------------------------------
@@ -54,6 +136,267 @@ impl Path {
         }
     }
 
+    /// Create a path from the given points in the LocalMap frame.
+    pub fn from_points(points_m_lm: Vec<[f64; 2]>) -> Self {
+        Path {
+            points_m_lm
+        }
+    }
+
+    /// Load a path from a file.
+    ///
+    /// The format is chosen by the file's extension:
+    ///  - `json` - the serialised `Path`, i.e. `{"points_m_lm": [[x, y], ...]}`.
+    ///  - `csv` - one `x,y` point per line. Blank lines, lines starting with
+    ///    `#`, and a header line are ignored.
+    ///
+    /// The path is not checked, use `validate` before accepting it.
+    pub fn load(file_path: &std::path::Path) -> Result<Self, PathLoadError> {
+        let contents = fs::read_to_string(file_path)
+            .map_err(PathLoadError::FileReadError)?;
+
+        let ext = file_path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+
+        match ext.as_deref() {
+            Some("json") => serde_json::from_str(&contents)
+                .map_err(PathLoadError::JsonError),
+            Some("csv") => Self::from_csv(&contents),
+            _ => Err(PathLoadError::UnsupportedFormat(ext))
+        }
+    }
+
+    /// Parse a path from CSV text, with one `x,y` point per line.
+    pub fn from_csv(contents: &str) -> Result<Self, PathLoadError> {
+        let mut points_m_lm = vec![];
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<Option<f64>> = line.split(',')
+                .map(|f| f.trim().parse().ok())
+                .collect();
+
+            match fields.as_slice() {
+                [Some(x), Some(y)] => points_m_lm.push([*x, *y]),
+                // Allow a header line before any points
+                [None, None] if points_m_lm.is_empty() => continue,
+                // Line numbers start at 1 for humans
+                _ => return Err(PathLoadError::CsvError(i + 1))
+            }
+        }
+
+        Ok(Self::from_points(points_m_lm))
+    }
+
+    /// Validate the path against the given parameters, returning the path
+    /// densified around corners and resampled to the preferred point
+    /// separation.
+    ///
+    /// These checks are applied to every path entering the system:
+    ///  1. The path has at least `min_num_points` points (and never less than
+    ///     2).
+    ///  1. All points are finite.
+    ///  1. No two consecutive points are further apart than
+    ///     `max_point_separation_m`.
+    ///  1. The total length is no more than `max_length_m`.
+    ///  1. The curvature between consecutive segments is no more than
+    ///     `max_curvature_m`.
+    pub fn validate(&self, params: &PathParams) -> Result<Path, PathError> {
+
+        let num_points = self.points_m_lm.len();
+        if num_points < params.min_num_points.max(2) {
+            return Err(PathError::NotEnoughPoints(num_points));
+        }
+
+        for (i, point) in self.points_m_lm.iter().enumerate() {
+            if !point[0].is_finite() || !point[1].is_finite() {
+                return Err(PathError::NonFinitePoint(i));
+            }
+        }
+
+        let mut length_m = 0f64;
+
+        for i in 1..num_points {
+            // Unwrap is safe as all points are 2D
+            let sep_m = norm(
+                &self.points_m_lm[i - 1],
+                &self.points_m_lm[i])
+                .unwrap();
+
+            if sep_m > params.max_point_separation_m {
+                return Err(PathError::PointsTooFar(
+                    i - 1, i, sep_m, params.max_point_separation_m));
+            }
+
+            length_m += sep_m;
+        }
+
+        if length_m > params.max_length_m {
+            return Err(PathError::TooLong(length_m, params.max_length_m));
+        }
+
+        for i in 1..(num_points - 1) {
+            let curv_m = self.get_curvature_at(i);
+
+            if curv_m > params.max_curvature_m.0 {
+                return Err(PathError::CurvatureTooHigh(
+                    i, curv_m, params.max_curvature_m.0));
+            }
+        }
+
+        // Round off the corners before evening out the spacing, so that the
+        // resampled points follow the corners rather than cutting them.
+        Ok(self
+            .densify_by_curvature(params.max_heading_step_rad)
+            .resample(params.preferred_separation_m))
+    }
+
+    /// Resample the path so that consecutive points are `separation_m` apart,
+    /// measured along the path.
+    ///
+    /// The first and last points are always kept, as are any corners (points
+    /// where the heading changes by more than `RESAMPLE_CORNER_RAD`), so that
+    /// resampling never cuts a corner. Spacing restarts from each kept point,
+    /// so the segment before one may be shorter than `separation_m`.
+    pub fn resample(&self, separation_m: f64) -> Path {
+
+        if self.points_m_lm.len() < 2 || separation_m <= 0.0 {
+            return Path::from_points(self.points_m_lm.clone());
+        }
+
+        let num_points = self.points_m_lm.len();
+        let mut points_m_lm = vec![self.points_m_lm[0]];
+
+        // Distance along the path to the next point to be placed, measured
+        // from the start of the current segment.
+        let mut next_m = separation_m;
+
+        for i in 1..num_points {
+            let start = self.points_m_lm[i - 1];
+            let end = self.points_m_lm[i];
+            let seg_length_m = norm(&start, &end).unwrap();
+
+            // Don't place a point right on top of the end of the segment, it
+            // will either be kept as a corner or covered by the next segment.
+            while next_m < seg_length_m - separation_m * 1e-3 {
+                let frac = next_m / seg_length_m;
+                points_m_lm.push([
+                    start[0] + frac * (end[0] - start[0]),
+                    start[1] + frac * (end[1] - start[1])
+                ]);
+                next_m += separation_m;
+            }
+
+            let is_corner = i < num_points - 1
+                && self.get_turn_at(i).abs() > RESAMPLE_CORNER_RAD;
+
+            if i == num_points - 1 || is_corner {
+                points_m_lm.push(end);
+                next_m = separation_m;
+            }
+            else {
+                next_m -= seg_length_m;
+            }
+        }
+
+        Path::from_points(points_m_lm)
+    }
+
+    /// Add points where the path turns, so that the heading changes by at
+    /// most `max_heading_step_rad` from one segment to the next.
+    ///
+    /// Each segment is replaced by a Catmull-Rom spline through its end points,
+    /// split where the spline's direction changes by more than half of the
+    /// heading step, so that neighbouring pieces never turn by more than the
+    /// step. The spline passes through every original point, so corners are
+    /// rounded off smoothly rather than cut, and segments with no turn at
+    /// either end are left straight. Note that the spline bulges slightly
+    /// outwards on the segments either side of a sharp corner. Use `resample`
+    /// afterwards to even out the spacing.
+    pub fn densify_by_curvature(&self, max_heading_step_rad: f64) -> Path {
+
+        let num_points = self.points_m_lm.len();
+
+        if num_points < 3 || max_heading_step_rad <= 0.0 {
+            return Path::from_points(self.points_m_lm.clone());
+        }
+
+        let mut points_m_lm = vec![self.points_m_lm[0]];
+
+        for i in 1..num_points {
+            // Control points for the spline, mirroring the end points at the
+            // start and end of the path
+            let p1 = self.points_m_lm[i - 1];
+            let p2 = self.points_m_lm[i];
+            let p0 = match i {
+                1 => [2.0 * p1[0] - p2[0], 2.0 * p1[1] - p2[1]],
+                _ => self.points_m_lm[i - 2]
+            };
+            let p3 = match i {
+                i if i == num_points - 1 =>
+                    [2.0 * p2[0] - p1[0], 2.0 * p2[1] - p1[1]],
+                _ => self.points_m_lm[i + 1]
+            };
+
+            // The end of the last piece is the original point, which is
+            // pushed exactly rather than evaluated
+            let mut ends_t = vec![];
+            split_catmull_rom(
+                &[p0, p1, p2, p3],
+                0.0,
+                1.0,
+                0.5 * max_heading_step_rad,
+                0,
+                &mut ends_t);
+            ends_t.pop();
+
+            for t in ends_t {
+                points_m_lm.push(catmull_rom(&p0, &p1, &p2, &p3, t));
+            }
+
+            points_m_lm.push(p2);
+        }
+
+        Path::from_points(points_m_lm)
+    }
+
+    /// Get the curvature at the given (interior) point, i.e. the change in
+    /// heading between the segments either side of the point divided by their
+    /// mean length.
+    fn get_curvature_at(&self, index: usize) -> f64 {
+        let prev = self.points_m_lm[index - 1];
+        let curr = self.points_m_lm[index];
+        let next = self.points_m_lm[index + 1];
+
+        let mean_length_m = 0.5 * (
+            norm(&prev, &curr).unwrap() + norm(&curr, &next).unwrap());
+
+        // Repeated points have no defined heading, so treat them as straight
+        if mean_length_m == 0.0 {
+            return 0.0;
+        }
+
+        self.get_turn_at(index).abs() / mean_length_m
+    }
+
+    /// Get the change in heading at the given (interior) point, wrapped into
+    /// [-pi, pi). Positive turns are to the left.
+    fn get_turn_at(&self, index: usize) -> f64 {
+        let prev = self.points_m_lm[index - 1];
+        let curr = self.points_m_lm[index];
+        let next = self.points_m_lm[index + 1];
+
+        let head_in_rad = (curr[1] - prev[1]).atan2(curr[0] - prev[0]);
+        let head_out_rad = (next[1] - curr[1]).atan2(next[0] - curr[0]);
+
+        angle_diff_rad(head_out_rad, head_in_rad)
+    }
+
     /// Returns the path segment connecting the target point and the previous
     /// point.
     ///
@@ -70,7 +413,7 @@ impl Path {
         }
 
         // Catch invalid targets
-        if target_index == 0 || target_index > self.points_m_lm.len() {
+        if target_index == 0 || target_index >= self.points_m_lm.len() {
             return None;
         }
 
@@ -129,4 +472,594 @@ impl Path {
     pub fn get_num_points(&self) -> usize {
         self.points_m_lm.len()
     }
-}
\ No newline at end of file
+
+    /// Get the point at the given index, or `None` if it is beyond the end of
+    /// the path.
+    pub fn get_point(&self, index: usize) -> Option<[f64; 2]> {
+        self.points_m_lm.get(index).copied()
+    }
+
+    /// Get the shortest distance from the given point to any segment of the
+    /// path.
+    ///
+    /// If the path is empty (not enough points) then `None` is returned.
+    pub fn get_distance_to(&self, point_m_lm: &[f64; 2]) -> Option<f64> {
+        if self.points_m_lm.len() < 2 {
+            return None;
+        }
+
+        self.points_m_lm
+            .windows(2)
+            .map(|w| dist_to_segment(point_m_lm, &w[0], &w[1]))
+            .fold(None, |min: Option<f64>, d| Some(min.map_or(d, |m| m.min(d))))
+    }
+
+    /// Find every point where this path crosses the other path.
+    ///
+    /// Intersections are ordered by distance along this path. Segments which
+    /// are parallel and overlap are not reported. Every pair of segments is
+    /// checked, which is fast enough for paths of a few thousand points since
+    /// most pairs are rejected on their bounding boxes.
+    pub fn intersect(&self, other: &Path) -> Vec<PathIntersection> {
+        let other_segs: Vec<[[f64; 2]; 2]> = other.points_m_lm
+            .windows(2)
+            .map(|w| [w[0], w[1]])
+            .collect();
+
+        self.intersect_segments(&other_segs)
+    }
+
+    /// Find every point where this path crosses the edge of the polygon with
+    /// the given vertices.
+    ///
+    /// The polygon is closed automatically. Intersections are ordered by
+    /// distance along the path, and `other_index` is the index of the vertex
+    /// at the start of the crossed edge.
+    pub fn intersect_polygon(
+        &self,
+        vertices_m_lm: &[[f64; 2]]
+    ) -> Vec<PathIntersection> {
+        let edges: Vec<[[f64; 2]; 2]> = (0..vertices_m_lm.len())
+            .map(|i| [
+                vertices_m_lm[i],
+                vertices_m_lm[(i + 1) % vertices_m_lm.len()]
+            ])
+            .collect();
+
+        self.intersect_segments(&edges)
+    }
+
+    /// Return `true` if no part of the path is inside the polygon with the
+    /// given vertices, for example a keep-out zone.
+    pub fn is_clear_of_polygon(&self, vertices_m_lm: &[[f64; 2]]) -> bool {
+        !self.points_m_lm
+            .iter()
+            .any(|p| point_in_polygon(p, vertices_m_lm))
+        && self.intersect_polygon(vertices_m_lm).is_empty()
+    }
+
+    /// Find the intersections of the path with each of the given segments,
+    /// ordered by distance along the path.
+    fn intersect_segments(
+        &self,
+        segments: &[[[f64; 2]; 2]]
+    ) -> Vec<PathIntersection> {
+        let mut intersections = Vec::new();
+        let mut dist_m = 0.0;
+
+        for (i, w) in self.points_m_lm.windows(2).enumerate() {
+            let bbox = bounding_box(&w[0], &w[1]);
+
+            for (j, seg) in segments.iter().enumerate() {
+                if !boxes_overlap(&bbox, &bounding_box(&seg[0], &seg[1])) {
+                    continue;
+                }
+
+                if let Some(point) = segment_intersection(
+                    &w[0], &w[1], &seg[0], &seg[1]
+                ) {
+                    intersections.push(PathIntersection {
+                        point_m_lm: point,
+                        heading_rad: (w[1][1] - w[0][1])
+                            .atan2(w[1][0] - w[0][0]),
+                        dist_along_m: dist_m + norm(&w[0], &point).unwrap(),
+                        segment_index: i,
+                        other_index: j
+                    });
+                }
+            }
+
+            dist_m += norm(&w[0], &w[1]).unwrap();
+        }
+
+        intersections.sort_by(|a, b| a.dist_along_m
+            .partial_cmp(&b.dist_along_m)
+            .unwrap_or(std::cmp::Ordering::Equal)
+        );
+
+        // A crossing exactly at a path point is found on both of the segments
+        // that meet there, so only keep one
+        intersections.dedup_by(|a, b| a.other_index == b.other_index
+            && a.point_m_lm == b.point_m_lm
+        );
+
+        intersections
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Get the `[min_x, min_y, max_x, max_y]` bounding box of a segment.
+fn bounding_box(start: &[f64; 2], end: &[f64; 2]) -> [f64; 4] {
+    [
+        start[0].min(end[0]),
+        start[1].min(end[1]),
+        start[0].max(end[0]),
+        start[1].max(end[1])
+    ]
+}
+
+/// Return `true` if two bounding boxes overlap, including touching.
+fn boxes_overlap(a: &[f64; 4], b: &[f64; 4]) -> bool {
+    a[0] <= b[2] && b[0] <= a[2] && a[1] <= b[3] && b[1] <= a[3]
+}
+
+/// Evaluate the uniform Catmull-Rom spline between `p1` and `p2` at `t` in
+/// [0, 1].
+fn catmull_rom(
+    p0: &[f64; 2],
+    p1: &[f64; 2],
+    p2: &[f64; 2],
+    p3: &[f64; 2],
+    t: f64
+) -> [f64; 2] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    let interp = |k: usize| 0.5 * (
+        2.0 * p1[k]
+        + (p2[k] - p0[k]) * t
+        + (2.0 * p0[k] - 5.0 * p1[k] + 4.0 * p2[k] - p3[k]) * t2
+        + (3.0 * p1[k] - p0[k] - 3.0 * p2[k] + p3[k]) * t3
+    );
+
+    [interp(0), interp(1)]
+}
+
+/// Evaluate the direction of the uniform Catmull-Rom spline between `p1` and
+/// `p2` at `t` in [0, 1], i.e. the derivative of `catmull_rom`.
+fn catmull_rom_tangent(
+    p0: &[f64; 2],
+    p1: &[f64; 2],
+    p2: &[f64; 2],
+    p3: &[f64; 2],
+    t: f64
+) -> [f64; 2] {
+    let t2 = t * t;
+
+    let interp = |k: usize| 0.5 * (
+        (p2[k] - p0[k])
+        + 2.0 * (2.0 * p0[k] - 5.0 * p1[k] + 4.0 * p2[k] - p3[k]) * t
+        + 3.0 * (3.0 * p1[k] - p0[k] - 3.0 * p2[k] + p3[k]) * t2
+    );
+
+    [interp(0), interp(1)]
+}
+
+/// Split the Catmull-Rom spline with the given control points between `t0`
+/// and `t1` into pieces over which its direction changes by no more than
+/// `max_turn_rad`, pushing the end of each piece onto `ends_t` in order.
+///
+/// Pieces are halved until they meet the limit or have been halved
+/// `DENSIFY_MAX_DEPTH` times.
+fn split_catmull_rom(
+    ctrl: &[[f64; 2]; 4],
+    t0: f64,
+    t1: f64,
+    max_turn_rad: f64,
+    depth: usize,
+    ends_t: &mut Vec<f64>
+) {
+    let heading = |t: f64| {
+        let d = catmull_rom_tangent(&ctrl[0], &ctrl[1], &ctrl[2], &ctrl[3], t);
+        d[1].atan2(d[0])
+    };
+
+    // Check the middle as well as the ends so that a piece which turns one
+    // way then back isn't taken as straight
+    let t_mid = 0.5 * (t0 + t1);
+    let (h0, h_mid, h1) = (heading(t0), heading(t_mid), heading(t1));
+    let turn_rad = angle_diff_rad(h_mid, h0).abs()
+        + angle_diff_rad(h1, h_mid).abs();
+
+    if turn_rad > max_turn_rad && depth < DENSIFY_MAX_DEPTH {
+        split_catmull_rom(ctrl, t0, t_mid, max_turn_rad, depth + 1, ends_t);
+        split_catmull_rom(ctrl, t_mid, t1, max_turn_rad, depth + 1, ends_t);
+    }
+    else {
+        ends_t.push(t1);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comms_if::units::Curvature;
+
+    const EPS: f64 = 1e-9;
+
+    fn square_path() -> Path {
+        Path::from_points(vec![[0.0, 0.0], [3.0, 0.0], [3.0, 4.0], [0.0, 4.0]])
+    }
+
+    fn path_params() -> PathParams {
+        PathParams {
+            min_num_points: 2,
+            max_point_separation_m: 5.0,
+            max_length_m: 20.0,
+            max_curvature_m: Curvature(2.0),
+            preferred_separation_m: 0.5,
+            max_heading_step_rad: 0.1
+        }
+    }
+
+    /// Get the separation between each pair of consecutive points.
+    fn separations_m(path: &Path) -> Vec<f64> {
+        path.points_m_lm
+            .windows(2)
+            .map(|w| norm(&w[0], &w[1]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_and_single_point() {
+        for path in [Path::new_empty(), Path::from_points(vec![[1.0, 2.0]])] {
+            assert!(path.get_length().is_none());
+            assert!(path.get_segment_to_target(0).is_none());
+            assert!(path.get_segment_to_target(1).is_none());
+            assert!(path.get_distance_to(&[0.0, 0.0]).is_none());
+            assert!(path.intersect(&square_path()).is_empty());
+            assert!(square_path().intersect(&path).is_empty());
+        }
+
+        assert_eq!(Path::new_empty().get_num_points(), 0);
+        assert!(Path::new_empty().get_point(0).is_none());
+        assert_eq!(Path::from_points(vec![[1.0, 2.0]]).get_point(0), Some([1.0, 2.0]));
+    }
+
+    #[test]
+    fn test_length() {
+        assert!((square_path().get_length().unwrap() - 10.0).abs() < EPS);
+
+        let path = Path::from_points(vec![[0.0, 0.0], [3.0, 4.0]]);
+        assert!((path.get_length().unwrap() - 5.0).abs() < EPS);
+
+        // Repeated points add nothing
+        let path = Path::from_points(vec![[1.0, 1.0], [1.0, 1.0]]);
+        assert!(path.get_length().unwrap().abs() < EPS);
+    }
+
+    #[test]
+    fn test_segment_lookup() {
+        let path = square_path();
+
+        let seg = path.get_segment_to_target(2).unwrap();
+        assert_eq!(seg.start_m_lm, [3.0, 0.0]);
+        assert_eq!(seg.target_m_lm, [3.0, 4.0]);
+        assert!((seg.length_m - 4.0).abs() < EPS);
+
+        let seg = path.get_segment_to_target(1).unwrap();
+        assert!((seg.length_m - 3.0).abs() < EPS);
+        assert!(seg.slope_m.abs() < EPS);
+        assert!(seg.intercept_m.abs() < EPS);
+
+        // There's no segment to the first point or beyond the last one
+        assert!(path.get_segment_to_target(0).is_none());
+        assert!(path.get_segment_to_target(3).is_some());
+        assert!(path.get_segment_to_target(4).is_none());
+    }
+
+    #[test]
+    fn test_distance_to() {
+        let path = square_path();
+
+        assert!((path.get_distance_to(&[1.0, 1.0]).unwrap() - 1.0).abs() < EPS);
+        assert!((path.get_distance_to(&[5.0, 2.0]).unwrap() - 2.0).abs() < EPS);
+        assert!(path.get_distance_to(&[3.0, 2.0]).unwrap().abs() < EPS);
+    }
+
+    #[test]
+    fn test_intersect() {
+        let path = square_path();
+        let other = Path::from_points(vec![[1.0, -1.0], [1.0, 5.0]]);
+
+        // Crosses the first and last segments
+        let ints = path.intersect(&other);
+        assert_eq!(ints.len(), 2);
+
+        assert!((ints[0].point_m_lm[0] - 1.0).abs() < EPS);
+        assert!(ints[0].point_m_lm[1].abs() < EPS);
+        assert!((ints[0].dist_along_m - 1.0).abs() < EPS);
+        assert!(ints[0].heading_rad.abs() < EPS);
+        assert_eq!(ints[0].segment_index, 0);
+
+        assert!((ints[1].dist_along_m - 9.0).abs() < EPS);
+        assert_eq!(ints[1].segment_index, 2);
+
+        // A crossing at a path point is only reported once
+        let through_corner = Path::from_points(vec![[2.0, -1.0], [4.0, 1.0]]);
+        assert_eq!(path.intersect(&through_corner).len(), 1);
+
+        // Paths which don't meet
+        let apart = Path::from_points(vec![[10.0, 0.0], [10.0, 4.0]]);
+        assert!(path.intersect(&apart).is_empty());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let path = Path::from_points(vec![
+            [0.0, -0.0],
+            [0.1, f64::MIN_POSITIVE],
+            [f64::MAX, f64::MIN],
+            [-1.5e-300, 1e300]
+        ]);
+
+        let json = serde_json::to_string(&path).unwrap();
+        let parsed: Path = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.points_m_lm, path.points_m_lm);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+
+    #[test]
+    fn test_polygon() {
+        let keep_out = [[1.0, 1.0], [2.0, 1.0], [2.0, 2.0], [1.0, 2.0]];
+
+        // Passing straight through the polygon crosses two edges
+        let path = Path::from_points(vec![[0.0, 1.5], [3.0, 1.5]]);
+        let ints = path.intersect_polygon(&keep_out);
+        assert_eq!(ints.len(), 2);
+        assert_eq!(ints[0].other_index, 3);
+        assert_eq!(ints[1].other_index, 1);
+        assert!(!path.is_clear_of_polygon(&keep_out));
+
+        // Entirely inside crosses no edges but isn't clear
+        let inside = Path::from_points(vec![[1.2, 1.2], [1.8, 1.8]]);
+        assert!(inside.intersect_polygon(&keep_out).is_empty());
+        assert!(!inside.is_clear_of_polygon(&keep_out));
+
+        assert!(square_path().is_clear_of_polygon(&keep_out));
+    }
+
+    #[test]
+    fn test_validate_not_enough_points() {
+        let params = path_params();
+
+        for path in [Path::new_empty(), Path::from_points(vec![[1.0, 2.0]])] {
+            assert!(matches!(
+                path.validate(&params),
+                Err(PathError::NotEnoughPoints(n)) if n == path.get_num_points()
+            ));
+        }
+
+        // The minimum can be raised above 2
+        let params = PathParams { min_num_points: 5, ..path_params() };
+        assert!(matches!(
+            square_path().validate(&params),
+            Err(PathError::NotEnoughPoints(4))
+        ));
+    }
+
+    #[test]
+    fn test_validate_non_finite_point() {
+        for bad in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let path = Path::from_points(vec![[0.0, 0.0], [1.0, 0.0], [bad, 1.0]]);
+            assert!(matches!(
+                path.validate(&path_params()),
+                Err(PathError::NonFinitePoint(2))
+            ));
+
+            let path = Path::from_points(vec![[0.0, bad], [1.0, 0.0]]);
+            assert!(matches!(
+                path.validate(&path_params()),
+                Err(PathError::NonFinitePoint(0))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_validate_point_separation() {
+        let path = Path::from_points(vec![[0.0, 0.0], [1.0, 0.0], [7.0, 0.0]]);
+
+        match path.validate(&path_params()) {
+            Err(PathError::PointsTooFar(i, j, sep_m, max_m)) => {
+                assert_eq!((i, j), (1, 2));
+                assert!((sep_m - 6.0).abs() < EPS);
+                assert!((max_m - 5.0).abs() < EPS);
+            },
+            r => panic!("Expected PointsTooFar, got {:?}", r.err())
+        }
+    }
+
+    #[test]
+    fn test_validate_length() {
+        let points_m_lm = (0..7).map(|i| [4.0 * i as f64, 0.0]).collect();
+
+        match Path::from_points(points_m_lm).validate(&path_params()) {
+            Err(PathError::TooLong(length_m, max_m)) => {
+                assert!((length_m - 24.0).abs() < EPS);
+                assert!((max_m - 20.0).abs() < EPS);
+            },
+            r => panic!("Expected TooLong, got {:?}", r.err())
+        }
+    }
+
+    #[test]
+    fn test_validate_curvature() {
+        // A right angle with a mean segment length of 0.75 m
+        let path = Path::from_points(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 0.5]]);
+
+        match path.validate(&path_params()) {
+            Err(PathError::CurvatureTooHigh(i, curv_m, max_m)) => {
+                assert_eq!(i, 1);
+                assert!((curv_m - std::f64::consts::FRAC_PI_2 / 0.75).abs() < EPS);
+                assert!((max_m - 2.0).abs() < EPS);
+            },
+            r => panic!("Expected CurvatureTooHigh, got {:?}", r.err())
+        }
+
+        // Turns in either direction count
+        let path = Path::from_points(vec![[0.0, 0.0], [1.0, 0.0], [1.0, -0.5]]);
+        assert!(matches!(
+            path.validate(&path_params()),
+            Err(PathError::CurvatureTooHigh(1, _, _))
+        ));
+    }
+
+    #[test]
+    fn test_validate_resamples() {
+        let path = square_path();
+        let params = path_params();
+        let valid = path.validate(&params).unwrap();
+
+        // The ends are kept
+        assert_eq!(valid.get_point(0), path.get_point(0));
+        assert_eq!(
+            valid.get_point(valid.get_num_points() - 1),
+            path.get_point(path.get_num_points() - 1)
+        );
+
+        // No gap is larger than the preferred separation, and the corners are
+        // rounded off rather than cut
+        assert!(valid.get_num_points() > path.get_num_points());
+        assert!(separations_m(&valid)
+            .iter()
+            .all(|&s| s <= params.preferred_separation_m + EPS));
+        assert!(valid.get_length().unwrap() >= path.get_length().unwrap() - EPS);
+
+        // The corners are rounded off, so the resampled points stay close to
+        // them without passing through them
+        for i in 1..(path.get_num_points() - 1) {
+            let corner = path.get_point(i).unwrap();
+            assert!(valid.get_distance_to(&corner).unwrap() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_resample_spacing() {
+        // The collinear point at 1.5 m isn't a corner so isn't kept
+        let path = Path::from_points(vec![[0.0, 0.0], [1.5, 0.0], [4.0, 0.0]]);
+        let resampled = path.resample(1.0);
+
+        assert_eq!(resampled.get_num_points(), 5);
+        assert_eq!(resampled.get_point(0), Some([0.0, 0.0]));
+        assert_eq!(resampled.get_point(4), Some([4.0, 0.0]));
+        assert!(separations_m(&resampled)
+            .iter()
+            .all(|s| (s - 1.0).abs() < EPS));
+    }
+
+    #[test]
+    fn test_resample_keeps_corners() {
+        let path = Path::from_points(vec![[0.0, 0.0], [2.5, 0.0], [2.5, 2.5]]);
+        let resampled = path.resample(1.0);
+
+        // Spacing restarts from the corner, so the segment before it and the
+        // one before the end are short
+        let expected = [
+            [0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.5, 0.0],
+            [2.5, 1.0], [2.5, 2.0], [2.5, 2.5]
+        ];
+        assert_eq!(resampled.get_num_points(), expected.len());
+        for (i, point) in expected.iter().enumerate() {
+            assert!(norm(&resampled.get_point(i).unwrap(), point).unwrap() < EPS);
+        }
+    }
+
+    #[test]
+    fn test_resample_degenerate() {
+        let path = square_path();
+
+        assert_eq!(path.resample(0.0).points_m_lm, path.points_m_lm);
+        assert_eq!(path.resample(-1.0).points_m_lm, path.points_m_lm);
+
+        let single = Path::from_points(vec![[1.0, 2.0]]);
+        assert_eq!(single.resample(1.0).points_m_lm, single.points_m_lm);
+    }
+
+    #[test]
+    fn test_densify_heading_step() {
+        let path = square_path();
+        let max_step_rad = 0.1;
+        let dense = path.densify_by_curvature(max_step_rad);
+
+        assert!(dense.get_num_points() > path.get_num_points());
+
+        // Every original point is kept, including the ends
+        assert_eq!(dense.get_point(0), path.get_point(0));
+        assert_eq!(dense.get_point(dense.get_num_points() - 1), path.get_point(3));
+        for point in &path.points_m_lm {
+            assert!(dense.points_m_lm.contains(point));
+        }
+
+        for i in 1..(dense.get_num_points() - 1) {
+            assert!(
+                dense.get_turn_at(i).abs() <= max_step_rad + EPS,
+                "Heading step of {} rad at point {}", dense.get_turn_at(i), i
+            );
+        }
+    }
+
+    #[test]
+    fn test_densify_degenerate() {
+        // Straight paths have nothing to round off
+        let path = Path::from_points(vec![[0.0, 0.0], [5.0, 0.0], [10.0, 0.0]]);
+        assert_eq!(path.densify_by_curvature(0.1).points_m_lm, path.points_m_lm);
+
+        let path = square_path();
+        assert_eq!(path.densify_by_curvature(0.0).points_m_lm, path.points_m_lm);
+
+        let path = Path::from_points(vec![[0.0, 0.0], [1.0, 1.0]]);
+        assert_eq!(path.densify_by_curvature(0.1).points_m_lm, path.points_m_lm);
+    }
+
+    #[test]
+    fn test_from_csv() {
+        // A header line, comments and blank lines are skipped
+        let csv = "x, y\n# Start\n\n0, 0\n  # Indented\n1.5 , 2\n";
+        let path = Path::from_csv(csv).unwrap();
+        assert_eq!(path.points_m_lm, vec![[0.0, 0.0], [1.5, 2.0]]);
+
+        assert_eq!(Path::from_csv("").unwrap().get_num_points(), 0);
+    }
+
+    #[test]
+    fn test_from_csv_errors() {
+        // Line numbers start at 1 and include skipped lines
+        assert!(matches!(
+            Path::from_csv("x,y\n0,0\n\n1,oops\n"),
+            Err(PathLoadError::CsvError(4))
+        ));
+
+        // Only the first line with points can be a header
+        assert!(matches!(
+            Path::from_csv("0,0\nx,y\n"),
+            Err(PathLoadError::CsvError(2))
+        ));
+
+        assert!(matches!(
+            Path::from_csv("0,0,0\n"),
+            Err(PathLoadError::CsvError(1))
+        ));
+        assert!(matches!(
+            Path::from_csv("x,y\n0\n"),
+            Err(PathLoadError::CsvError(2))
+        ));
+    }
+}
@@ -4,6 +4,9 @@
 // IMPORTS
 // ---------------------------------------------------------------------------
 
+// External
+use log::warn;
+
 // Internal
 use super::*;
 use crate::loc::Pose;
@@ -11,7 +14,7 @@ use comms_if::tc::loco_ctrl::MnvrCmd;
 use util::{
     module::State,
     params,
-    maths::norm,
+    maths::{angle_diff_rad, norm},
     session::Session
 };
 
@@ -38,6 +41,10 @@ pub struct TrajCtrl {
     /// Index of the current target point within the path
     target_point_index: usize,
 
+    /// Half the width of the keep-in corridor around the path sequence, or
+    /// `None` if there is no corridor
+    corridor_half_width_m: Option<f64>,
+
     /// Controller objects used to calculate manouvre commands
     controllers: TrajControllers
 }
@@ -69,7 +76,25 @@ pub struct StatusReport {
     pub lat_error_limit_exceeded: bool,
 
     /// If true the limit on the heading error has been exceeded
-    pub head_error_limit_exceeded: bool
+    pub head_error_limit_exceeded: bool,
+
+    /// Distance from the rover to the nearest point on the current path,
+    /// which unlike the lateral error isn't limited to the current segment
+    pub corridor_error_m: f64,
+
+    /// If true the rover has left the keep-in corridor around the path
+    pub corridor_exceeded: bool,
+
+    /// Curvature demand from the lateral and heading PID controllers, before
+    /// limits are applied
+    pub pid_curv_dem_m: f64,
+
+    /// Curvature demand from the pure pursuit controller, before limits are
+    /// applied
+    pub pp_curv_dem_m: f64,
+
+    /// Distance to the pure pursuit lookahead point
+    pub pp_lookahead_m: f64
 }
 
 // ---------------------------------------------------------------------------
@@ -97,9 +122,10 @@ pub enum ProcError {
     AttemptEmptySeqLoad,
 
     /// Attempted to load a sequence containing invalid paths. The contained
-    /// vector provides the indices of the paths which were invalid.
-    #[error("Loaded sequence contains invalid paths at index(s) {0:?}")]
-    SequenceContainsInvalidPaths(Vec<usize>)
+    /// vector provides the indices of the paths which were invalid, and the
+    /// reason each was rejected.
+    #[error("Loaded sequence contains invalid paths (index, reason): {0:?}")]
+    SequenceContainsInvalidPaths(Vec<(usize, PathError)>)
 }
 
 /// The possible modes of execution of TrajCtrl. Each mode is handled by a 
@@ -185,11 +211,15 @@ impl<'a> TrajCtrl {
     /// In the next call to `proc` the module will be in `HeadingAdjust` mode,
     /// in order to line up the rover with the first segment of the path.
     ///
+    /// If `corridor_width_m` is given the sequence is aborted if the rover
+    /// ever gets further than half of it from the path, whatever the
+    /// controllers are doing.
+    ///
     /// Loading a new sequence before the current one has been finished will 
     /// result in an error. To stop a path sequence whilst it's executing you
     /// must call `abort_path_sequence`.
     pub fn begin_path_sequence(
-        &mut self, seq: Vec<Path>
+        &mut self, seq: Vec<Path>, corridor_width_m: Option<f64>
     ) -> Result<(), ProcError> {
 
         // Check to see if there's already a sequence loaded
@@ -202,25 +232,29 @@ impl<'a> TrajCtrl {
             return Err(ProcError::AttemptEmptySeqLoad)
         }
         
-        // Check that all paths in the sequence are valid
-        let mut invalid_path_indexes: Vec<usize> = vec![];
+        // Check that all paths in the sequence are valid, resampling them to
+        // the preferred point separation
+        let mut valid_paths: Vec<Path> = vec![];
+        let mut invalid_paths: Vec<(usize, PathError)> = vec![];
         for (i, path) in seq.iter().enumerate() {
-            if path.get_num_points() < 2 {
-                invalid_path_indexes.push(i);
+            match path.validate(&self.params.path) {
+                Ok(p) => valid_paths.push(p),
+                Err(e) => invalid_paths.push((i, e))
             }
         }
 
         // If there were invalid paths 
-        if invalid_path_indexes.len() > 0 {
+        if !invalid_paths.is_empty() {
             return Err(
-                ProcError::SequenceContainsInvalidPaths(invalid_path_indexes))
+                ProcError::SequenceContainsInvalidPaths(invalid_paths))
         }
 
         // Setup counters and sequence. The target must be 1 not 0 as a segment
         // is defined backwards, i.e. between the target and previous points.
-        self.path_sequence = seq;
+        self.path_sequence = valid_paths;
         self.path_index = 0;
         self.target_point_index = 1;
+        self.corridor_half_width_m = corridor_width_m.map(|w| 0.5 * w);
 
         // Switch into Heading Adjust mode.
         self.mode = Mode::HeadingAdjust;
@@ -284,21 +318,23 @@ impl<'a> TrajCtrl {
 
         // ---- COMMAND GENERATION ----
 
-        // Get the current path segment
-        //
-        // Can safely unwrap here as target management already performed
-        let segment = self.path_sequence[self.path_index]
-            .get_segment_to_target(self.target_point_index)
-            .unwrap();
-
         // Get the command
         let mnvr_cmd = self.controllers.get_ackerman_cmd(
-            &segment, &self.input_data.pose, &mut self.report, &self.params);
+            &self.path_sequence[self.path_index], 
+            self.target_point_index,
+            &self.input_data.pose, 
+            &mut self.report, 
+            &self.params);
+
+        // Check the rover is still in the corridor
+        self.check_corridor();
 
         // Check for error exceedance
         if self.report.lat_error_limit_exceeded 
             || 
             self.report.head_error_limit_exceeded 
+            ||
+            self.report.corridor_exceeded
         {
             // Switch to sequence exceeded mode immediately so that we are 
             // stopped as close to the path as possible.
@@ -327,8 +363,10 @@ impl<'a> TrajCtrl {
         let segment = self.path_sequence[self.path_index]
             .get_segment_to_target(self.target_point_index)
             .unwrap();
-        let head_err_rad = self.input_data.pose.get_heading() 
-            - segment.slope_m.atan();
+        let head_err_rad = angle_diff_rad(
+            self.input_data.pose.get_heading(),
+            segment.slope_m.atan()
+        );
         
         // If the error is less than the threshold the adjustment is complete
         if head_err_rad.abs() < self.params.head_adjust_threshold_rad {
@@ -342,7 +380,7 @@ impl<'a> TrajCtrl {
             // as that of the turn rate, therefore if there is a positive error
             // we need a negative turn rate to decrease that error.
             self.output_data.mnvr_cmd = Some(MnvrCmd::PointTurn {
-                rate_rads: -1f64 * head_err_rad.signum() * self.params.head_adjust_rate_rads
+                rate_rads: self.params.head_adjust_rate_rads * (-1f64 * head_err_rad.signum())
             });
         }
 
@@ -363,6 +401,7 @@ impl<'a> TrajCtrl {
         self.path_sequence = vec![];
         self.path_index = 0;
         self.target_point_index = 0;
+        self.corridor_half_width_m = None;
 
         // Switch to NotExecuting mode
         self.mode = Mode::NotExecuting;
@@ -371,6 +410,36 @@ impl<'a> TrajCtrl {
         Ok(())
     }
 
+    /// Check whether the rover is outside the keep-in corridor around the
+    /// current path, setting the corridor error in the status report.
+    ///
+    /// The lateral error limit only applies to the current segment, so this
+    /// catches the rover drifting away from the path in a way the target 
+    /// management doesn't notice.
+    fn check_corridor(&mut self) {
+        let dist_m = match self.path_sequence[self.path_index]
+            .get_distance_to(&[
+                self.input_data.pose.position_m_lm[0],
+                self.input_data.pose.position_m_lm[1]
+            ])
+        {
+            Some(d) => d,
+            None => return
+        };
+
+        self.report.corridor_error_m = dist_m;
+
+        if let Some(half_width_m) = self.corridor_half_width_m {
+            if dist_m > half_width_m {
+                warn!(
+                    "Rover is {:.2} m from the path, outside the {:.2} m keep-in corridor, aborting",
+                    dist_m, 2.0 * half_width_m
+                );
+                self.report.corridor_exceeded = true;
+            }
+        }
+    }
+
     /// Get the longitudonal error to the current path segment.
     ///
     /// Positive errors indiciate that the rover hasn't reached the target yet.
@@ -438,4 +507,127 @@ impl<'a> TrajCtrl {
 
         long_err_m
     }
-}
\ No newline at end of file
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comms_if::units::{Curvature, MetersPerSec, RadPerSec};
+    use std::f64::consts::FRAC_PI_4;
+
+    fn test_params() -> Params {
+        Params {
+            controller: ControllerType::Pid,
+            lat_k_p: 1.0,
+            lat_k_i: 0.0,
+            lat_k_d: 0.0,
+            head_k_p: 1.0,
+            head_k_i: 0.0,
+            head_k_d: 0.0,
+            min_curv_dem_m: Curvature(-1.0),
+            max_curv_dem_m: Curvature(1.0),
+            curv_speed_map_coeffs: vec![0.1],
+            min_speed_dem_ms: MetersPerSec(0.0),
+            max_speed_dem_ms: MetersPerSec(0.1),
+            // Large limits so only the corridor can abort the sequence
+            lat_error_limit_m: 100.0,
+            head_error_limit_rad: 100.0,
+            head_adjust_rate_rads: RadPerSec(0.1),
+            head_adjust_threshold_rad: 0.1,
+            pp_lookahead_min_m: 0.5,
+            pp_lookahead_gain_s: 1.0,
+            pp_lookahead_max_m: 2.0,
+            path: PathParams {
+                min_num_points: 2,
+                max_point_separation_m: 20.0,
+                max_length_m: 100.0,
+                max_curvature_m: Curvature(1.0),
+                preferred_separation_m: 1.0,
+                max_heading_step_rad: 0.1
+            }
+        }
+    }
+
+    fn traj_ctrl() -> TrajCtrl {
+        let params = test_params();
+
+        TrajCtrl {
+            controllers: TrajControllers::new(&params),
+            params,
+            mode: Mode::NotExecuting,
+            input_data: InputData { pose: pose_at(0.0, 0.0) },
+            output_data: OutputData::default(),
+            report: StatusReport::default(),
+            path_sequence: vec![],
+            path_index: 0,
+            target_point_index: 0,
+            corridor_half_width_m: None
+        }
+    }
+
+    /// A pose at the given position heading along the diagonal test path.
+    fn pose_at(x_m: f64, y_m: f64) -> Pose {
+        Pose {
+            position_m_lm: [x_m, y_m, 0.0],
+            attitude_q_lm: [0.0, 0.0, 0.0, (0.5 * FRAC_PI_4).cos()]
+        }
+    }
+
+    /// Start following the diagonal path from (0, 0) to (10, 10) with the
+    /// given corridor, then report after one cycle at the given offset from
+    /// (2, 2) perpendicular to the path.
+    fn follow_with_drift(
+        corridor_width_m: Option<f64>,
+        drift_m: f64
+    ) -> (TrajCtrl, OutputData, StatusReport) {
+        let mut traj = traj_ctrl();
+        let path = Path::from_points(vec![[0.0, 0.0], [10.0, 10.0]]);
+        traj.begin_path_sequence(vec![path], corridor_width_m).unwrap();
+
+        // Lined up with the path, so the heading adjustment finishes straight
+        // away
+        traj.proc(&InputData { pose: pose_at(0.0, 0.0) }).unwrap();
+        assert!(matches!(traj.mode, Mode::FollowingPath));
+
+        let offset_m = drift_m * FRAC_PI_4.cos();
+        let pose = pose_at(2.0 + offset_m, 2.0 - offset_m);
+        let (output, report) = traj.proc(&InputData { pose }).unwrap();
+
+        (traj, output, report)
+    }
+
+    #[test]
+    fn test_inside_corridor() {
+        let (traj, output, report) = follow_with_drift(Some(1.0), 0.4);
+
+        assert!((report.corridor_error_m - 0.4).abs() < 1e-6);
+        assert!(!report.corridor_exceeded);
+        assert!(matches!(output.mnvr_cmd, Some(MnvrCmd::Ackerman { .. })));
+        assert!(matches!(traj.mode, Mode::FollowingPath));
+    }
+
+    #[test]
+    fn test_corridor_exceeded_ends_sequence() {
+        let (traj, output, report) = follow_with_drift(Some(1.0), 0.6);
+
+        assert!((report.corridor_error_m - 0.6).abs() < 1e-6);
+        assert!(report.corridor_exceeded);
+        assert_eq!(output.mnvr_cmd, Some(MnvrCmd::Stop));
+        assert!(matches!(traj.mode, Mode::NotExecuting));
+        assert!(traj.path_sequence.is_empty());
+        assert!(traj.corridor_half_width_m.is_none());
+    }
+
+    #[test]
+    fn test_no_corridor() {
+        let (traj, _, report) = follow_with_drift(None, 5.0);
+
+        assert!((report.corridor_error_m - 5.0).abs() < 1e-6);
+        assert!(!report.corridor_exceeded);
+        assert!(matches!(traj.mode, Mode::FollowingPath));
+    }
+}
@@ -0,0 +1,724 @@
+//! # Turning Circle Calibration
+//!
+//! Validates the Ackerman steering geometry used by LocoCtrl against the turning circle the rover
+//! actually drives. Started with the `calibrate turn start` TC, the rover drives a series of
+//! constant curvature arcs, alternating left and right. After allowing the steering to settle the
+//! position of the rover is recorded through each arc, and a circle fitted to the positions gives
+//! the curvature actually driven.
+//!
+//! Once all arcs are complete scales on the wheelbase (the RB_X positions of the axes) and track
+//! (the RB_Y positions) are fitted so that the curvature predicted for each arc matches the
+//! measured one. The prediction finds the motion of the rover which best agrees with all six
+//! wheels, given the steer angles and drive rates LocoCtrl demands from its nominal geometry. The
+//! track mostly affects the tightest turns, so arcs near the maximum curvature are needed to
+//! estimate it well.
+//!
+//! The arcs, the fit and the suggested LocoCtrl parameters are saved to the session's `turn_cal`
+//! directory as `turn_cal_<n>.json`. The parameters are only suggested, `loco_ctrl.toml` must be
+//! updated by hand. Safe mode aborts the calibration.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Internal
+use crate::{
+    loc::Pose,
+    loco_ctrl::{self, NUM_DRV_AXES, NUM_STR_AXES},
+};
+use comms_if::{
+    tc::{calibrate::TurnCalCmd, loco_ctrl::MnvrCmd},
+    tm::TmMeta,
+    units::{Curvature, MetersPerSec, Radians},
+};
+use util::{
+    format::{self, FormatHeader},
+    module::State,
+    params,
+    session::{Session, SessionError},
+};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Name of the calibration report format.
+pub const REPORT_FORMAT_NAME: &str = "turn_cal_report";
+
+/// Version of the calibration report format.
+pub const REPORT_FORMAT_VERSION: u32 = 1;
+
+/// Minimum number of measured arcs needed to fit the geometry.
+const MIN_FIT_ARCS: usize = 2;
+
+/// Number of steps either side of the current best scale searched on each pass of the fit.
+const FIT_HALF_WIDTH: i32 = 10;
+
+/// Step between the scales searched on the first pass of the fit, which is divided by
+/// `FIT_HALF_WIDTH` on each following pass.
+const FIT_INITIAL_STEP: f64 = 0.05;
+
+/// Number of passes of the fit.
+const FIT_NUM_PASSES: usize = 4;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Turning circle calibration state
+#[derive(Default)]
+pub struct TurnCal {
+    params: Params,
+
+    /// Parameters of LocoCtrl, giving the nominal geometry
+    loco_params: loco_ctrl::Params,
+
+    /// The calibration in progress, if any
+    run: Option<Run>,
+
+    /// Number of calibrations started in this session
+    num_runs: u32,
+
+    /// Directory the reports are saved in
+    report_dir: PathBuf,
+}
+
+/// Parameters for the turning circle calibration.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Params {
+    /// Time allowed at the start of each arc for the steer axes to reach their demands before
+    /// positions are recorded.
+    ///
+    /// Units: seconds
+    pub settle_time_s: f64,
+
+    /// Minimum distance travelled while recording an arc for its curvature to be measured.
+    ///
+    /// Units: meters
+    pub min_arc_length_m: f64,
+
+    /// Minimum number of positions recorded on an arc for its curvature to be measured.
+    pub min_arc_poses: usize,
+}
+
+/// Input data to the turning circle calibration.
+#[derive(Default)]
+pub struct InputData {
+    /// The command to execute, or `None` if there is no new command on this cycle.
+    pub cmd: Option<TurnCalCmd>,
+
+    /// True if the rover is in safe mode, which aborts the calibration.
+    pub safe: bool,
+
+    /// Current time in seconds
+    pub time_s: f64,
+
+    /// Current pose of the rover, if known
+    pub pose: Option<Pose>,
+}
+
+/// Status report for the turning circle calibration.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, TmMeta)]
+pub struct StatusReport {
+    /// True while a calibration is in progress
+    pub running: bool,
+
+    /// Number of the current (or last) calibration
+    pub run: u32,
+
+    /// Number of the arc being driven, starting from 1
+    pub arc: u32,
+
+    /// Number of arcs in the calibration
+    pub num_arcs: u32,
+}
+
+/// The saved result of a calibration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    /// Number of the calibration in the session
+    pub run: u32,
+
+    /// Speed the arcs were driven at
+    pub speed_ms: MetersPerSec,
+
+    /// Results of each arc, in the order they were driven
+    pub arcs: Vec<ArcResult>,
+
+    /// The fitted geometry, or `None` if too few arcs were measured
+    pub fit: Option<GeometryFit>,
+}
+
+/// Result of driving a single arc.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArcResult {
+    /// Curvature demanded of LocoCtrl
+    pub dem_curv_m: Curvature,
+
+    /// Curvature measured by localisation, or `None` if the arc was too short or there weren't
+    /// enough poses to measure it
+    pub meas_curv_m: Option<Curvature>,
+
+    /// Distance travelled while recording the arc
+    pub length_m: f64,
+
+    /// Number of positions recorded on the arc
+    pub num_poses: usize,
+}
+
+/// Corrections to the Ackerman geometry fitted from the measured arcs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeometryFit {
+    /// Scale on the RB_X positions of the axes
+    pub wheelbase_scale: f64,
+
+    /// Scale on the RB_Y positions of the axes
+    pub track_scale: f64,
+
+    /// RMS difference between the predicted and measured curvatures with the nominal geometry,
+    /// in 1/meters
+    pub rms_error_nominal: f64,
+
+    /// RMS difference between the predicted and measured curvatures with the fitted geometry, in
+    /// 1/meters
+    pub rms_error_fitted: f64,
+
+    /// The LocoCtrl parameters for the fitted geometry
+    pub suggested_params: SuggestedParams,
+}
+
+/// LocoCtrl parameters suggested by the calibration, named as in `loco_ctrl.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestedParams {
+    pub str_axis_pos_m_rb: [[f64; 3]; NUM_STR_AXES],
+    pub drv_axis_pos_m_rb: [[f64; 3]; NUM_DRV_AXES],
+}
+
+/// A calibration in progress.
+struct Run {
+    speed: MetersPerSec,
+
+    /// Curvatures of each arc, in the order they're driven
+    curvs: Vec<Curvature>,
+
+    arc_duration_s: f64,
+
+    /// Time the current arc started
+    arc_start_s: f64,
+
+    /// Positions of the rover recorded on the current arc
+    positions: Vec<[f64; 2]>,
+
+    /// Results of the completed arcs
+    arcs: Vec<ArcResult>,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Possible errors that can occur in the turning circle calibration.
+#[derive(Debug, thiserror::Error)]
+pub enum TurnCalError {
+    #[error("Could not load the parameters: {0}")]
+    ParamsError(params::LoadError),
+
+    #[error("Could not create the calibration report directory: {0}")]
+    SessionError(SessionError),
+
+    #[error("Invalid turning circle calibration: {0}")]
+    InvalidRun(String),
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl State for TurnCal {
+    type InitData = (&'static str, &'static str);
+    type InitError = TurnCalError;
+
+    type InputData = InputData;
+    /// A manouvre for LocoCtrl, if one needs to be sent this cycle
+    type OutputData = Option<MnvrCmd>;
+    type StatusReport = StatusReport;
+    type ProcError = TurnCalError;
+
+    /// Initialise the turning circle calibration.
+    ///
+    /// Expected init data is the paths to the calibration's parameter file and to the LocoCtrl
+    /// parameter file.
+    fn init(
+        &mut self,
+        init_data: Self::InitData,
+        session: &Session,
+    ) -> Result<(), Self::InitError> {
+        self.params = params::load(init_data.0).map_err(TurnCalError::ParamsError)?;
+        self.loco_params = params::load(init_data.1).map_err(TurnCalError::ParamsError)?;
+        self.report_dir = session
+            .module_dir("turn_cal")
+            .map_err(TurnCalError::SessionError)?;
+
+        Ok(())
+    }
+
+    /// Start, stop or continue a calibration.
+    fn proc(
+        &mut self,
+        input_data: &Self::InputData,
+    ) -> Result<(Self::OutputData, Self::StatusReport), Self::ProcError> {
+        let mut output = None;
+
+        // Safe mode always ends the calibration, LocoCtrl will already have stopped
+        if input_data.safe && self.run.is_some() {
+            warn!(
+                "Turning circle calibration {} aborted by safe mode",
+                self.num_runs
+            );
+            self.run = None;
+        }
+
+        match input_data.cmd {
+            Some(TurnCalCmd::Start {
+                speed_ms,
+                max_curv_m,
+                num_curvs,
+                arc_duration_s,
+            }) => {
+                if input_data.safe {
+                    return Err(TurnCalError::InvalidRun(
+                        "cannot start a calibration in safe mode".into(),
+                    ));
+                }
+                self.validate_start(speed_ms, max_curv_m, num_curvs, arc_duration_s)?;
+
+                // Each curvature is driven to the left then to the right
+                let curvs: Vec<Curvature> = (1..=num_curvs)
+                    .map(|i| max_curv_m.abs() * (i as f64 / num_curvs as f64))
+                    .flat_map(|c| vec![c, -c])
+                    .collect();
+
+                self.num_runs += 1;
+                info!(
+                    "Starting turning circle calibration {}: {} arcs at {} of {} s each",
+                    self.num_runs,
+                    curvs.len(),
+                    speed_ms,
+                    arc_duration_s
+                );
+
+                output = Some(arc_cmd(speed_ms, curvs[0]));
+
+                self.run = Some(Run {
+                    speed: speed_ms,
+                    curvs,
+                    arc_duration_s,
+                    arc_start_s: input_data.time_s,
+                    positions: Vec::new(),
+                    arcs: Vec::new(),
+                });
+            }
+            Some(TurnCalCmd::Abort) if self.run.is_some() => {
+                info!("Turning circle calibration {} aborted", self.num_runs);
+                self.run = None;
+                output = Some(MnvrCmd::Stop);
+            }
+            Some(TurnCalCmd::Abort) | None => (),
+        }
+
+        // Record the current arc, moving on to the next once it's complete
+        let mut complete = false;
+        if let Some(ref mut run) = self.run {
+            let elapsed_s = input_data.time_s - run.arc_start_s;
+
+            if elapsed_s >= self.params.settle_time_s {
+                if let Some(pose) = input_data.pose {
+                    run.positions
+                        .push([pose.position_m_lm[0], pose.position_m_lm[1]]);
+                }
+            }
+
+            if elapsed_s >= self.params.settle_time_s + run.arc_duration_s {
+                let result = measure_arc(
+                    &self.params,
+                    run.speed,
+                    run.curvs[run.arcs.len()],
+                    &run.positions,
+                );
+                info!(
+                    "Turning circle calibration arc {}: demanded {}, measured {:?}",
+                    run.arcs.len() + 1,
+                    result.dem_curv_m,
+                    result.meas_curv_m.map(|c| c.0)
+                );
+                run.arcs.push(result);
+
+                match run.curvs.get(run.arcs.len()) {
+                    Some(&curv) => {
+                        run.arc_start_s = input_data.time_s;
+                        run.positions.clear();
+                        output = Some(arc_cmd(run.speed, curv));
+                    }
+                    None => {
+                        complete = true;
+                        output = Some(MnvrCmd::Stop);
+                    }
+                }
+            }
+        }
+
+        let mut report = StatusReport {
+            running: self.run.is_some(),
+            run: self.num_runs,
+            ..Default::default()
+        };
+        if let Some(ref run) = self.run {
+            report.arc = (run.arcs.len() + 1).min(run.curvs.len()) as u32;
+            report.num_arcs = run.curvs.len() as u32;
+        }
+
+        if complete {
+            if let Some(run) = self.run.take() {
+                self.finish(run);
+            }
+            report.running = false;
+        }
+
+        Ok((output, report))
+    }
+}
+
+impl TurnCal {
+    /// Check that a calibration can be started with the given settings.
+    fn validate_start(
+        &self,
+        speed: MetersPerSec,
+        max_curv: Curvature,
+        num_curvs: u32,
+        arc_duration_s: f64,
+    ) -> Result<(), TurnCalError> {
+        if !speed.is_finite() || speed.0 == 0.0 {
+            return Err(TurnCalError::InvalidRun(format!("speed {}", speed)));
+        }
+
+        // Arcs below the minimum curvature are driven straight, and those above the maximum are
+        // saturated by LocoCtrl, so neither would measure the demanded curvature.
+        let min_curv = self.loco_params.ackerman_min_curvature_m;
+        let max_ackerman_curv = self.loco_params.ackerman_max_curvature_m;
+        if !max_curv.is_finite() || max_curv.abs() > max_ackerman_curv {
+            return Err(TurnCalError::InvalidRun(format!(
+                "maximum curvature {} is above the Ackerman maximum of {}",
+                max_curv, max_ackerman_curv
+            )));
+        }
+        if num_curvs == 0 || max_curv.abs() / (num_curvs as f64) < min_curv {
+            return Err(TurnCalError::InvalidRun(format!(
+                "{} curvatures up to {} would drive below the Ackerman minimum of {}",
+                num_curvs, max_curv, min_curv
+            )));
+        }
+
+        if !arc_duration_s.is_finite() || arc_duration_s <= 0.0 {
+            return Err(TurnCalError::InvalidRun(format!(
+                "arc duration {} s",
+                arc_duration_s
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// End the calibration, fitting the geometry and saving the report.
+    fn finish(&mut self, run: Run) {
+        let fit = self.fit_geometry(run.speed, &run.arcs);
+
+        match fit {
+            Some(ref f) => info!(
+                "Turning circle calibration {} complete: wheelbase scale {:.4}, track scale \
+                 {:.4}, RMS curvature error {:.4} 1/m nominal, {:.4} 1/m fitted",
+                self.num_runs,
+                f.wheelbase_scale,
+                f.track_scale,
+                f.rms_error_nominal,
+                f.rms_error_fitted
+            ),
+            None => warn!(
+                "Turning circle calibration {} complete, but too few arcs were measured to fit \
+                 the geometry",
+                self.num_runs
+            ),
+        }
+
+        let report = Report {
+            run: self.num_runs,
+            speed_ms: run.speed,
+            arcs: run.arcs,
+            fit,
+        };
+
+        let path = self
+            .report_dir
+            .join(format!("turn_cal_{}.json", self.num_runs));
+        let result = format::save_json(
+            &path,
+            &FormatHeader::new(REPORT_FORMAT_NAME, REPORT_FORMAT_VERSION),
+            &report,
+        );
+
+        match result {
+            Ok(()) => info!("Suggested LocoCtrl parameters saved to {:?}", path),
+            Err(e) => warn!(
+                "Could not save the turning circle calibration report: {}",
+                e
+            ),
+        }
+    }
+
+    /// Fit the wheelbase and track scales to the measured arcs.
+    ///
+    /// The scales are found by a grid search around the best scales so far, which is refined on
+    /// each pass, starting from the nominal geometry.
+    fn fit_geometry(&self, speed: MetersPerSec, arcs: &[ArcResult]) -> Option<GeometryFit> {
+        let measured: Vec<(Curvature, Curvature)> = arcs
+            .iter()
+            .filter_map(|a| a.meas_curv_m.map(|m| (a.dem_curv_m, m)))
+            .collect();
+
+        if measured.len() < MIN_FIT_ARCS {
+            return None;
+        }
+
+        // Sum of the squared curvature errors for the given scales
+        let cost = |wheelbase_scale: f64, track_scale: f64| -> Option<f64> {
+            measured.iter().try_fold(0.0, |sum, &(dem, meas)| {
+                let pred = self.predict_curv(speed, dem, wheelbase_scale, track_scale)?;
+                Some(sum + (pred - meas).0.powi(2))
+            })
+        };
+
+        let nominal_cost = cost(1.0, 1.0)?;
+        let mut best = (1.0, 1.0, nominal_cost);
+        let mut step = FIT_INITIAL_STEP;
+
+        for _ in 0..FIT_NUM_PASSES {
+            let (centre_wb, centre_tr) = (best.0, best.1);
+
+            for i in -FIT_HALF_WIDTH..=FIT_HALF_WIDTH {
+                for j in -FIT_HALF_WIDTH..=FIT_HALF_WIDTH {
+                    let wheelbase_scale = centre_wb + i as f64 * step;
+                    let track_scale = centre_tr + j as f64 * step;
+                    if wheelbase_scale <= 0.0 || track_scale <= 0.0 {
+                        continue;
+                    }
+
+                    if let Some(c) = cost(wheelbase_scale, track_scale) {
+                        if c < best.2 {
+                            best = (wheelbase_scale, track_scale, c);
+                        }
+                    }
+                }
+            }
+
+            step /= FIT_HALF_WIDTH as f64;
+        }
+
+        let (wheelbase_scale, track_scale, fitted_cost) = best;
+        let scale = |pos: &[f64; 3]| [pos[0] * wheelbase_scale, pos[1] * track_scale, pos[2]];
+
+        let mut suggested_params = SuggestedParams {
+            str_axis_pos_m_rb: self.loco_params.str_axis_pos_m_rb,
+            drv_axis_pos_m_rb: self.loco_params.drv_axis_pos_m_rb,
+        };
+        for pos in suggested_params.str_axis_pos_m_rb.iter_mut() {
+            *pos = scale(pos);
+        }
+        for pos in suggested_params.drv_axis_pos_m_rb.iter_mut() {
+            *pos = scale(pos);
+        }
+
+        Some(GeometryFit {
+            wheelbase_scale,
+            track_scale,
+            rms_error_nominal: (nominal_cost / measured.len() as f64).sqrt(),
+            rms_error_fitted: (fitted_cost / measured.len() as f64).sqrt(),
+            suggested_params,
+        })
+    }
+
+    /// Predict the curvature driven for an arc if the rover's axes were at their nominal positions
+    /// scaled by the given wheelbase and track scales.
+    ///
+    /// The steer angles and wheel speeds are those LocoCtrl demands from the nominal geometry. On
+    /// the scaled rover no single motion satisfies all six wheels, so the motion of the body
+    /// `(v_x, v_y, omega)` is found which minimises the squared slip of every wheel, along and
+    /// across its rolling direction. With no scaling this is exactly the demanded curvature.
+    fn predict_curv(
+        &self,
+        speed: MetersPerSec,
+        dem_curv: Curvature,
+        wheelbase_scale: f64,
+        track_scale: f64,
+    ) -> Option<Curvature> {
+        let k = dem_curv.0;
+
+        // Normal equations of the least squares problem
+        let mut ata = [[0.0; 3]; 3];
+        let mut atb = [0.0; 3];
+
+        for pos in self.loco_params.str_axis_pos_m_rb.iter() {
+            // Demands from the nominal geometry, as calculated by LocoCtrl with no crab
+            let str_rad = (pos[0] * k / (1.0 - pos[1] * k)).atan();
+            let wheel_speed_ms = speed.0 * (1.0 - pos[1] * k).hypot(pos[0] * k);
+
+            // Where the wheel actually is
+            let x = pos[0] * wheelbase_scale;
+            let y = pos[1] * track_scale;
+
+            let (sin, cos) = str_rad.sin_cos();
+            let rows = [
+                // Along the wheel, matching the wheel's speed
+                ([cos, sin, sin * x - cos * y], wheel_speed_ms),
+                // Across the wheel, with no sideways slip
+                ([-sin, cos, cos * x + sin * y], 0.0),
+            ];
+
+            for (row, b) in rows.iter() {
+                for r in 0..3 {
+                    for c in 0..3 {
+                        ata[r][c] += row[r] * row[c];
+                    }
+                    atb[r] += row[r] * b;
+                }
+            }
+        }
+
+        let [v_x, v_y, omega] = solve_3x3(&ata, &atb)?;
+        let v = v_x.hypot(v_y);
+
+        match v > f64::EPSILON {
+            true => Some(Curvature(omega / v * v_x.signum())),
+            false => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// The manouvre for an arc of the calibration.
+fn arc_cmd(speed: MetersPerSec, curv: Curvature) -> MnvrCmd {
+    MnvrCmd::Ackerman {
+        speed_ms: speed,
+        curv_m: curv,
+        crab_rad: Radians(0.0),
+    }
+}
+
+/// Measure the curvature of an arc from the positions recorded on it.
+fn measure_arc(
+    params: &Params,
+    speed: MetersPerSec,
+    dem_curv: Curvature,
+    positions: &[[f64; 2]],
+) -> ArcResult {
+    let length_m: f64 = positions
+        .windows(2)
+        .map(|w| (w[1][0] - w[0][0]).hypot(w[1][1] - w[0][1]))
+        .sum();
+
+    let meas_curv_m =
+        match positions.len() >= params.min_arc_poses && length_m >= params.min_arc_length_m {
+            true => fit_circle(positions).map(|(radius_m, left)| {
+                // When reversing the path turns the opposite way to the demanded curvature
+                let sign = match left {
+                    true => speed.signum(),
+                    false => -speed.signum(),
+                };
+                Curvature(sign / radius_m)
+            }),
+            false => None,
+        };
+
+    ArcResult {
+        dem_curv_m: dem_curv,
+        meas_curv_m,
+        length_m,
+        num_poses: positions.len(),
+    }
+}
+
+/// Fit a circle to a set of points, returning its radius and whether the points turn to the left
+/// (anticlockwise).
+///
+/// Uses the algebraic fit of `x^2 + y^2 + D x + E y + F = 0`, which is linear in `D`, `E` and `F`.
+/// The points are moved to be around their mean first to keep the problem well conditioned.
+fn fit_circle(points: &[[f64; 2]]) -> Option<(f64, bool)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p[0]).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p[1]).sum::<f64>() / n;
+
+    let mut ata = [[0.0; 3]; 3];
+    let mut atb = [0.0; 3];
+    for p in points {
+        let (x, y) = (p[0] - mean_x, p[1] - mean_y);
+        let row = [x, y, 1.0];
+        let b = -(x * x + y * y);
+
+        for r in 0..3 {
+            for c in 0..3 {
+                ata[r][c] += row[r] * row[c];
+            }
+            atb[r] += row[r] * b;
+        }
+    }
+
+    let [d, e, f] = solve_3x3(&ata, &atb)?;
+    let radius_sq = (d * d + e * e) / 4.0 - f;
+    if radius_sq <= 0.0 {
+        return None;
+    }
+
+    // The direction of the turn from the cross products of successive steps
+    let turn: f64 = points
+        .windows(3)
+        .map(|w| {
+            let a = [w[1][0] - w[0][0], w[1][1] - w[0][1]];
+            let b = [w[2][0] - w[1][0], w[2][1] - w[1][1]];
+            a[0] * b[1] - a[1] * b[0]
+        })
+        .sum();
+
+    Some((radius_sq.sqrt(), turn > 0.0))
+}
+
+/// Solve the 3x3 linear system `a x = b` by Cramer's rule, or `None` if `a` is singular.
+fn solve_3x3(a: &[[f64; 3]; 3], b: &[f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+
+    let det_a = det(a);
+    let scale = a.iter().flatten().fold(0.0, |max: f64, v| max.max(v.abs()));
+    if det_a.abs() <= f64::EPSILON * scale.powi(3) {
+        return None;
+    }
+
+    let mut x = [0.0; 3];
+    for (col, x_col) in x.iter_mut().enumerate() {
+        let mut m = *a;
+        for row in 0..3 {
+            m[row][col] = b[row];
+        }
+        *x_col = det(&m) / det_a;
+    }
+
+    Some(x)
+}
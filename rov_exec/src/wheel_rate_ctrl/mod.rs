@@ -0,0 +1,29 @@
+//! Wheel rate control module
+//!
+//! LocoCtrl assumes that the servos achieve exactly the rate they are demanded, which isn't true
+//! on slopes or in soft soil. When measured wheel rates are available from the mechanisms server
+//! this module closes the loop, trimming each drive demand with a PI controller on the difference
+//! between the demanded and measured rate. Without sensor data the demands are passed through
+//! unchanged.
+
+// ---------------------------------------------------------------------------
+// MODULES
+// ---------------------------------------------------------------------------
+
+mod params;
+mod state;
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// Internal
+pub use params::*;
+pub use state::*;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// The number of drive axes on the rover.
+pub const NUM_DRV_AXES: usize = 6;
@@ -0,0 +1,56 @@
+//! Parameters structure for WheelRateCtrl
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use util::control::PidParams;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Parameters for wheel rate control.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Params {
+    /// If false the demands are always passed through unchanged.
+    pub enabled: bool,
+
+    /// Proportional gain on the rate error
+    pub k_p: f64,
+
+    /// Integral gain on the rate error
+    pub k_i: f64,
+
+    /// Anti-windup gain, which bleeds off the integral while the trim is limited so that a
+    /// stalled wheel doesn't wind up a large trim. See `PidParams::k_aw`.
+    pub k_aw: f64,
+
+    /// Limit on the magnitude of the trim added to each demand.
+    ///
+    /// Units: radians/second
+    pub max_trim_rads: f64,
+
+    /// Demands smaller than this are treated as a stop, and are not trimmed so that the rover
+    /// doesn't creep while stationary.
+    ///
+    /// Units: radians/second
+    pub min_dem_rads: f64,
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl Params {
+    /// Parameters of the trim controller on each drive axis.
+    pub fn pid_params(&self) -> PidParams {
+        PidParams {
+            out_min: -self.max_trim_rads,
+            out_max: self.max_trim_rads,
+            k_aw: self.k_aw,
+            ..PidParams::new(self.k_p, self.k_i, 0.0)
+        }
+    }
+}
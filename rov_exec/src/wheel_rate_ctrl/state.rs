@@ -0,0 +1,159 @@
+//! Implementations for the WheelRateCtrl state structure
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External
+use serde::{Deserialize, Serialize};
+
+// Internal
+use super::{Params, NUM_DRV_AXES};
+use comms_if::{
+    eqpt::mech::{ActId, MechDems, MechSensData},
+    tm::TmMeta,
+};
+use util::{control::Pid, module::State, params, session::Session};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Wheel rate control module state
+#[derive(Default)]
+pub struct WheelRateCtrl {
+    pub(crate) params: Params,
+
+    /// Trim controller of each drive axis, in `ActId::drv_ids` order
+    pub(crate) pids: Vec<Pid>,
+}
+
+/// Input data to wheel rate control.
+#[derive(Default)]
+pub struct InputData {
+    /// The demands from LocoCtrl
+    pub dems: MechDems,
+
+    /// Sensor data received on this cycle, or `None` if there was none.
+    pub sens: Option<MechSensData>,
+}
+
+/// Status report for WheelRateCtrl processing.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, TmMeta)]
+pub struct StatusReport {
+    /// True if the demands were trimmed using measured rates on this cycle
+    pub closed_loop: bool,
+
+    /// Demanded minus measured rate of each drive axis, in `ActId::drv_ids` order
+    ///
+    /// Units: radians/second
+    #[tm(unit = "rad/s")]
+    pub rate_error_rads: [f64; NUM_DRV_AXES],
+
+    /// True if the trim on any axis was limited by `max_trim_rads`
+    pub trim_limited: bool,
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl State for WheelRateCtrl {
+    type InitData = &'static str;
+    type InitError = params::LoadError;
+
+    type InputData = InputData;
+    type OutputData = MechDems;
+    type StatusReport = StatusReport;
+    type ProcError = std::convert::Infallible;
+
+    /// Initialise the WheelRateCtrl module.
+    ///
+    /// Expected init data is the path to the parameter file
+    fn init(
+        &mut self,
+        init_data: Self::InitData,
+        _session: &Session,
+    ) -> Result<(), Self::InitError> {
+        self.params = params::load(init_data)?;
+        self.pids = vec![Pid::new(self.params.pid_params()); NUM_DRV_AXES];
+
+        Ok(())
+    }
+
+    /// Perform cyclic processing of wheel rate control.
+    fn proc(
+        &mut self,
+        input_data: &Self::InputData,
+    ) -> Result<(Self::OutputData, Self::StatusReport), Self::ProcError> {
+        let mut report = StatusReport::default();
+        let mut dems = input_data.dems.clone();
+
+        // Without measurements (or if disabled) run open loop
+        let sens = match input_data.sens {
+            Some(ref s) if self.params.enabled => s,
+            _ => {
+                self.reset();
+                return Ok((dems, report));
+            }
+        };
+
+        let dt_s = crate::CYCLE_PERIOD_S;
+
+        for (i, (act_id, pid)) in ActId::drv_ids()
+            .iter()
+            .zip(self.pids.iter_mut())
+            .enumerate()
+        {
+            let dem_rads = match dems.speed_rads.get(act_id) {
+                Some(&d) => d,
+                None => {
+                    pid.reset();
+                    continue;
+                }
+            };
+
+            // Measurements may not include every axis, in which case that axis is open loop
+            let meas_rads = match sens.speed_rads.get(act_id) {
+                Some(&m) => m,
+                None => {
+                    pid.reset();
+                    continue;
+                }
+            };
+
+            let error_rads = dem_rads - meas_rads;
+            report.rate_error_rads[i] = error_rads;
+
+            // Don't trim a stopped wheel, and forget any accumulated error so the next move
+            // starts fresh
+            if dem_rads.abs() < self.params.min_dem_rads {
+                pid.reset();
+                continue;
+            }
+
+            let trim_rads = pid.update(error_rads, dt_s);
+            report.trim_limited |= pid.is_saturated();
+
+            dems.speed_rads.insert(*act_id, dem_rads + trim_rads);
+        }
+
+        report.closed_loop = true;
+
+        Ok((dems, report))
+    }
+}
+
+impl WheelRateCtrl {
+    /// Function called when entering safe mode.
+    ///
+    /// Clears the integrated errors so that no trim is carried over to the next move.
+    pub fn make_safe(&mut self) {
+        self.reset();
+    }
+
+    /// Clear the integrated errors.
+    fn reset(&mut self) {
+        self.pids.iter_mut().for_each(Pid::reset);
+    }
+}
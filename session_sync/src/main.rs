@@ -0,0 +1,121 @@
+//! # Session Sync
+//!
+//! Copies the artefacts of completed sessions (logs, archives, maps, reports and images) from the
+//! rover to an archive on the ground.
+//!
+//! `session_sync serve` runs on the rover and serves the sessions directory. A session is complete
+//! once none of its files have changed for `--settle-s`, so the session of a running executable
+//! isn't copied until it has stopped.
+//!
+//! `session_sync pull` runs on the ground. Files already in the archive are skipped and partly
+//! copied files are resumed, so only new data crosses the link. Files are copied in order of their
+//! kind (logs first, images last) then size, up to a budget for each run. If the rover's telemetry
+//! endpoint is given the copy is paused while the rover is driving.
+
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+/// Requests and responses between the ground and the rover.
+mod protocol;
+
+/// The ground side, which copies files into the archive.
+mod pull;
+
+/// The rover side, which serves the sessions directory.
+mod serve;
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use color_eyre::Result;
+use comms_if::net::TransportKind;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Options for the rover side.
+#[derive(StructOpt)]
+pub struct ServeOpts {
+    /// Endpoint to serve the sessions on.
+    #[structopt(long, default_value = "tcp://*:5040")]
+    endpoint: String,
+
+    /// Sessions directory to serve, by default `$SUSF_PHOBOS_SW_ROOT/sessions`.
+    #[structopt(long, parse(from_os_str))]
+    sessions_dir: Option<PathBuf>,
+
+    /// Time since a session's last change before it is complete and can be copied, in seconds.
+    #[structopt(long, default_value = "60")]
+    settle_s: u64,
+
+    /// Transport to use, either zmq or grpc.
+    #[structopt(long, default_value = "zmq", possible_values = &["zmq", "grpc"])]
+    transport: TransportKind,
+}
+
+/// Options for the ground side.
+#[derive(StructOpt)]
+pub struct PullOpts {
+    /// Endpoint of the rover's session_sync server.
+    #[structopt(long, default_value = "tcp://localhost:5040")]
+    endpoint: String,
+
+    /// Directory of the ground archive the sessions are copied into.
+    #[structopt(long, parse(from_os_str), default_value = "ground_archive")]
+    archive_dir: PathBuf,
+
+    /// Maximum amount of data to copy in each run, in megabytes.
+    #[structopt(long, default_value = "100")]
+    budget_mb: f64,
+
+    /// Size of each request for file data, in kilobytes.
+    #[structopt(long, default_value = "256")]
+    chunk_kb: u64,
+
+    /// Time to wait for the rover to respond to each request, in milliseconds.
+    #[structopt(long, default_value = "5000")]
+    response_timeout_ms: i32,
+
+    /// Endpoint of the rover's TmServer. If given copying is paused while the rover is driving.
+    #[structopt(long)]
+    tm_endpoint: Option<String>,
+
+    /// Keep running, starting a new run this many seconds after the last one finished.
+    #[structopt(long)]
+    repeat_s: Option<u64>,
+
+    /// Transport to use, either zmq or grpc. Must match the server's transport.
+    #[structopt(long, default_value = "zmq", possible_values = &["zmq", "grpc"])]
+    transport: TransportKind,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Command line options.
+#[derive(StructOpt)]
+#[structopt(name = "session_sync", about = "Copy completed session data from the rover to the ground")]
+enum Opts {
+    /// Serve the rover's completed sessions, run on the rover.
+    Serve(ServeOpts),
+
+    /// Copy completed sessions from the rover into the ground archive, run on the ground.
+    Pull(PullOpts),
+}
+
+// ------------------------------------------------------------------------------------------------
+// MAIN
+// ------------------------------------------------------------------------------------------------
+
+fn main() -> Result<()> {
+    match Opts::from_args() {
+        Opts::Serve(opts) => serve::run(&opts),
+        Opts::Pull(opts) => pull::run(&opts),
+    }
+}
@@ -0,0 +1,111 @@
+//! # Sync Protocol
+//!
+//! The ground sends a `SyncRequest` and the rover replies with a `SyncResponse`, both serialised as
+//! JSON. File data is base64 encoded, in the same way as downlinked images.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A file in a completed session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Path of the file relative to the sessions directory, with `/` separators
+    pub path: String,
+
+    /// Size of the file in bytes
+    pub size: u64,
+
+    pub kind: FileKind,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SyncRequest {
+    /// List the files in every completed session
+    List,
+
+    /// Read part of a file
+    Read { path: String, offset: u64, len: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SyncResponse {
+    Files(Vec<FileEntry>),
+
+    /// Part of a file, which is shorter than requested if the end of the file was reached
+    Data { offset: u64, b64_data: String },
+
+    /// The request could not be carried out, for the given reason
+    Error(String),
+}
+
+/// Kinds of session artefact, in the order they are synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum FileKind {
+    Log,
+
+    /// Reports and parameters, such as planner reports
+    Report,
+
+    /// Archived module data
+    Archive,
+
+    /// Terrain and cost maps
+    Map,
+
+    Image,
+
+    Other,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl FileKind {
+    /// Get the kind of a file from its extension.
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match ext.as_str() {
+            "log" => FileKind::Log,
+            "json" | "toml" | "txt" | "md" => FileKind::Report,
+            "csv" => FileKind::Archive,
+            "map" | "npy" | "bin" => FileKind::Map,
+            "png" | "jpg" | "jpeg" => FileKind::Image,
+            _ => FileKind::Other,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Join a path from a `FileEntry` onto a root directory.
+///
+/// Returns `None` if the path is absolute or leaves the root, so that a request can't reach files
+/// outside of the sessions directory.
+pub fn join_relative(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+
+    if path.components().all(|c| matches!(c, Component::Normal(_))) {
+        Some(root.join(path))
+    } else {
+        None
+    }
+}
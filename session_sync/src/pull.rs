@@ -0,0 +1,307 @@
+//! # Session Pull
+//!
+//! Copies the files of completed sessions from the rover into the ground archive.
+//!
+//! Files are first written to `<name>.part` and renamed once complete, so a run that is
+//! interrupted leaves only `.part` files, which the next run resumes.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use comms_if::net::{transport, zmq, SocketOptions, Transport, TransportExt};
+use serde_json::Value;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    protocol::{join_relative, FileEntry, SyncRequest, SyncResponse},
+    PullOpts,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Modes in which the rover isn't driving, so the link can be used for copying.
+const IDLE_MODES: [&str; 3] = ["Boot", "Standby", "Safe"];
+
+/// If no telemetry has arrived for this long the rover executable isn't running, so the rover is
+/// idle.
+const TM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time to wait before checking again if the rover is idle.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Tracks if the rover is idle from its telemetry.
+struct IdleMonitor {
+    socket: Box<dyn Transport>,
+
+    /// Mode in the last telemetry packet
+    mode: Option<String>,
+
+    /// Time the last telemetry packet arrived
+    last_tm: Option<Instant>,
+}
+
+/// A file to copy, and where to copy it to.
+struct Pending {
+    entry: FileEntry,
+
+    local_path: PathBuf,
+
+    part_path: PathBuf,
+
+    /// Number of bytes already in the `.part` file
+    offset: u64,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl IdleMonitor {
+    /// True if the rover isn't driving.
+    fn is_idle(&mut self) -> bool {
+        while let Ok(Some(packet)) = self.socket.recv_json::<Value>() {
+            self.last_tm = Some(Instant::now());
+            self.mode = packet
+                .get("mode")
+                .and_then(Value::as_str)
+                .map(|m| m.to_string());
+        }
+
+        match (self.last_tm, &self.mode) {
+            (Some(t), Some(mode)) if t.elapsed() < TM_TIMEOUT => IDLE_MODES.contains(&mode.as_str()),
+            _ => true,
+        }
+    }
+
+    /// Block until the rover is idle.
+    fn wait(&mut self) {
+        if self.is_idle() {
+            return;
+        }
+
+        println!("Rover is busy, waiting for it to be idle");
+        while !self.is_idle() {
+            thread::sleep(IDLE_POLL_INTERVAL);
+        }
+        println!("Rover is idle, resuming");
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+pub fn run(opts: &PullOpts) -> Result<()> {
+    let ctx = zmq::Context::new();
+
+    // Relaxed so that a request which times out doesn't leave the socket stuck
+    let socket = transport::open(
+        opts.transport,
+        &ctx,
+        zmq::REQ,
+        SocketOptions {
+            block_on_first_connect: false,
+            req_correlate: true,
+            req_relaxed: true,
+            recv_timeout: opts.response_timeout_ms,
+            send_timeout: 1000,
+            ..Default::default()
+        },
+        &opts.endpoint,
+    )
+    .wrap_err("Failed to create the session sync client")?;
+
+    let mut idle = match opts.tm_endpoint {
+        Some(ref ep) => Some(IdleMonitor {
+            socket: transport::open(
+                opts.transport,
+                &ctx,
+                zmq::SUB,
+                SocketOptions {
+                    block_on_first_connect: false,
+                    recv_timeout: 0,
+                    ..Default::default()
+                },
+                ep,
+            )
+            .wrap_err("Failed to subscribe to the rover's telemetry")?,
+            mode: None,
+            last_tm: None,
+        }),
+        None => None,
+    };
+
+    fs::create_dir_all(&opts.archive_dir)
+        .wrap_err_with(|| format!("Could not create the archive {:?}", opts.archive_dir))?;
+
+    loop {
+        let result = sync(opts, &*socket, &mut idle);
+
+        match opts.repeat_s {
+            Some(s) => {
+                if let Err(e) = result {
+                    println!("Sync failed: {:#}", e);
+                }
+                thread::sleep(Duration::from_secs(s));
+            }
+            None => return result,
+        }
+    }
+}
+
+/// Copy as many files as fit in the budget.
+fn sync(opts: &PullOpts, socket: &dyn Transport, idle: &mut Option<IdleMonitor>) -> Result<()> {
+    let files = match request(socket, &SyncRequest::List)? {
+        SyncResponse::Files(f) => f,
+        r => return Err(eyre!("Unexpected response to the file list request: {:?}", r)),
+    };
+
+    // Find the files which aren't in the archive yet
+    let mut pending = Vec::new();
+    let mut num_synced = 0;
+    for entry in files {
+        let local_path = join_relative(&opts.archive_dir, &entry.path)
+            .ok_or_else(|| eyre!("The rover listed an invalid path {:?}", entry.path))?;
+
+        if fs::metadata(&local_path).is_ok_and(|m| m.len() == entry.size) {
+            num_synced += 1;
+            continue;
+        }
+
+        let part_path = local_path.with_file_name(format!(
+            "{}.part",
+            local_path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+
+        // Resume partly copied files, unless the file on the rover is now shorter
+        let offset = match fs::metadata(&part_path) {
+            Ok(m) if m.len() <= entry.size => m.len(),
+            _ => 0,
+        };
+
+        pending.push(Pending {
+            entry,
+            local_path,
+            part_path,
+            offset,
+        });
+    }
+
+    pending.sort_by_key(|p| (p.entry.kind, p.entry.size));
+
+    // Copy in priority order, skipping files which don't fit in what's left of the budget
+    let mut budget = (opts.budget_mb.max(0.0) * 1e6) as u64;
+    let mut num_copied = 0;
+    let mut num_over_budget = 0;
+    let mut num_bytes = 0;
+
+    for p in pending.iter_mut() {
+        let remaining = p.entry.size - p.offset;
+        if remaining > budget {
+            num_over_budget += 1;
+            continue;
+        }
+
+        copy_file(opts, socket, idle, p)?;
+
+        budget -= remaining;
+        num_bytes += remaining;
+        num_copied += 1;
+    }
+
+    println!(
+        "Copied {} files ({:.2} MB), {} over budget, {} already in the archive",
+        num_copied,
+        num_bytes as f64 / 1e6,
+        num_over_budget,
+        num_synced
+    );
+
+    Ok(())
+}
+
+/// Copy a single file into the archive.
+fn copy_file(
+    opts: &PullOpts,
+    socket: &dyn Transport,
+    idle: &mut Option<IdleMonitor>,
+    p: &mut Pending,
+) -> Result<()> {
+    if let Some(dir) = p.local_path.parent() {
+        fs::create_dir_all(dir).wrap_err_with(|| format!("Could not create {:?}", dir))?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&p.part_path)
+        .wrap_err_with(|| format!("Could not open {:?}", p.part_path))?;
+    file.set_len(p.offset)?;
+
+    while p.offset < p.entry.size {
+        if let Some(ref mut i) = idle {
+            i.wait();
+        }
+
+        let req = SyncRequest::Read {
+            path: p.entry.path.clone(),
+            offset: p.offset,
+            len: (opts.chunk_kb * 1024).max(1).min(p.entry.size - p.offset),
+        };
+
+        let data = match request(socket, &req)? {
+            SyncResponse::Data { offset, b64_data } if offset == p.offset => {
+                base64::decode(&b64_data).wrap_err("The rover sent invalid file data")?
+            }
+            r => return Err(eyre!("Unexpected response reading {}: {:?}", p.entry.path, r)),
+        };
+
+        if data.is_empty() {
+            return Err(eyre!("{} ended before its listed size", p.entry.path));
+        }
+
+        file.write_all(&data)
+            .wrap_err_with(|| format!("Could not write {:?}", p.part_path))?;
+        p.offset += data.len() as u64;
+    }
+
+    fs::rename(&p.part_path, &p.local_path)
+        .wrap_err_with(|| format!("Could not rename {:?}", p.part_path))?;
+
+    println!("Copied {} ({} bytes)", p.entry.path, p.entry.size);
+
+    Ok(())
+}
+
+/// Send a request to the rover and wait for the response.
+fn request(socket: &dyn Transport, req: &SyncRequest) -> Result<SyncResponse> {
+    socket
+        .send_json(req)
+        .wrap_err("Could not send the request to the rover")?;
+
+    match socket
+        .recv_json()
+        .wrap_err("Could not recieve the rover's response")?
+    {
+        Some(SyncResponse::Error(e)) => Err(eyre!("The rover responded with an error: {}", e)),
+        Some(r) => Ok(r),
+        None => Err(eyre!("The rover did not respond")),
+    }
+}
@@ -0,0 +1,163 @@
+//! # Session Server
+//!
+//! Answers the ground's requests, listing the files of completed sessions and reading parts of
+//! them.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use color_eyre::{eyre::WrapErr, Result};
+use comms_if::net::{transport, zmq, SocketOptions, TransportError, TransportExt};
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    protocol::{join_relative, FileEntry, FileKind, SyncRequest, SyncResponse},
+    ServeOpts,
+};
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Largest amount of file data sent in one response, whatever the ground asks for.
+const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+pub fn run(opts: &ServeOpts) -> Result<()> {
+    let sessions_dir = match opts.sessions_dir {
+        Some(ref d) => d.clone(),
+        None => util::host::get_phobos_sw_root()
+            .wrap_err("SUSF_PHOBOS_SW_ROOT is not set, pass --sessions-dir instead")?
+            .join("sessions"),
+    };
+    let settle = Duration::from_secs(opts.settle_s);
+
+    let ctx = zmq::Context::new();
+    let socket = transport::open(
+        opts.transport,
+        &ctx,
+        zmq::REP,
+        SocketOptions {
+            bind: true,
+            block_on_first_connect: false,
+            recv_timeout: -1,
+            send_timeout: 1000,
+            ..Default::default()
+        },
+        &opts.endpoint,
+    )
+    .wrap_err("Failed to create the session server")?;
+
+    println!("Serving completed sessions in {:?} on {}", sessions_dir, opts.endpoint);
+
+    loop {
+        let response = match socket.recv_json::<SyncRequest>() {
+            Ok(Some(SyncRequest::List)) => match list_completed(&sessions_dir, settle) {
+                Ok(files) => SyncResponse::Files(files),
+                Err(e) => SyncResponse::Error(format!("Could not list the sessions: {}", e)),
+            },
+            Ok(Some(SyncRequest::Read { path, offset, len })) => {
+                match read_part(&sessions_dir, &path, offset, len.min(MAX_READ_BYTES)) {
+                    Ok(data) => SyncResponse::Data {
+                        offset,
+                        b64_data: base64::encode(&data),
+                    },
+                    Err(e) => SyncResponse::Error(format!("Could not read {}: {}", path, e)),
+                }
+            }
+            Ok(None) => continue,
+            Err(TransportError::DeserializationError(e)) => {
+                SyncResponse::Error(format!("Invalid request: {}", e))
+            }
+            Err(e) => return Err(e).wrap_err("Could not recieve a request"),
+        };
+
+        if let Err(e) = socket.send_json(&response) {
+            println!("Could not respond to the ground: {}", e);
+        }
+    }
+}
+
+/// List the files of every session which hasn't changed for at least `settle`.
+fn list_completed(sessions_dir: &Path, settle: Duration) -> io::Result<Vec<FileEntry>> {
+    let now = SystemTime::now();
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(sessions_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let mut session_files = Vec::new();
+        let mut last_modified = SystemTime::UNIX_EPOCH;
+        walk(sessions_dir, &entry.path(), &mut session_files, &mut last_modified)?;
+
+        let age = now.duration_since(last_modified).unwrap_or_default();
+        if age >= settle {
+            files.append(&mut session_files);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Add the files under `dir` to `files`, keeping track of the latest modification time.
+fn walk(
+    root: &Path,
+    dir: &Path,
+    files: &mut Vec<FileEntry>,
+    last_modified: &mut SystemTime,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let meta = entry.metadata()?;
+
+        if let Ok(m) = meta.modified() {
+            *last_modified = (*last_modified).max(m);
+        }
+
+        if meta.is_dir() {
+            walk(root, &path, files, last_modified)?;
+        } else if meta.is_file() {
+            let rel: Vec<String> = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+
+            files.push(FileEntry {
+                path: rel.join("/"),
+                size: meta.len(),
+                kind: FileKind::from_path(&path),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Read up to `len` bytes of a file in the sessions directory, starting at `offset`.
+fn read_part(sessions_dir: &Path, path: &str, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let full_path = join_relative(sessions_dir, path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path leaves the sessions"))?;
+
+    let mut file = fs::File::open(full_path)?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut data = Vec::new();
+    file.take(len).read_to_end(&mut data)?;
+
+    Ok(data)
+}
@@ -0,0 +1,309 @@
+//! # TC Relay
+//!
+//! A store-and-forward relay between the ground console and a rover which is only in contact some
+//! of the time, so that operations can be practised with a mission-like commanding cadence.
+//!
+//! TCs sent from the console are answered immediately with `TcResponse::Queued` and held by the
+//! relay. While the rover is in contact the queued TCs are delivered one at a time, in the order
+//! they were sent, and the rover's responses are printed. TCs which have been queued for longer
+//! than `--expiry-s` are discarded rather than delivered.
+//!
+//! The rover is in contact while its telemetry is arriving, so telemetry and images are passed
+//! through the relay to the ground. Telemetry the rover stored while out of contact is backfilled
+//! through the same channel once contact is regained, see `tm_backfill_max_packets` in net.toml.
+//!
+//! A TC is sent again if the rover doesn't respond to it, so a TC whose response was lost may be
+//! executed twice. Each TC's number is sent to the rover in the envelope, which the rover's REP
+//! socket returns with the response, so a late response is always matched to the TC it answers.
+//!
+//! The ground tools and the rover are pointed at the relay instead of each other:
+//!
+//! ```text
+//! command_line_rover (tcp://*:5020) <- tc_relay -> (tcp://*:6020) <- rov_exec tc_endpoint
+//! tm_gateway -> (tcp://*:6030, 6031) tc_relay -> (tcp://localhost:5030, 5031) rov_exec
+//! ```
+//!
+//! Only the zmq transport is supported.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use color_eyre::{eyre::WrapErr, Result};
+use comms_if::{
+    net::{zmq, MonitoredSocket, SocketOptions},
+    tc::{TcPacket, TcResponse},
+};
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Command line options for the relay.
+#[derive(StructOpt)]
+#[structopt(name = "tc_relay", about = "Store and forward TCs to a rover with an intermittent link")]
+struct Opts {
+    /// Endpoint of the ground's TcServer (command_line_rover --endpoint).
+    #[structopt(long, default_value = "tcp://localhost:5020")]
+    ground_tc_endpoint: String,
+
+    /// Endpoint for the rover's TcClient to connect to, set as tc_endpoint in the rover's net.toml.
+    #[structopt(long, default_value = "tcp://*:6020")]
+    rover_tc_endpoint: String,
+
+    /// Endpoint of the rover's TmServer.
+    #[structopt(long, default_value = "tcp://localhost:5030")]
+    rover_tm_endpoint: String,
+
+    /// Endpoint for tm_gateway to subscribe to telemetry on (tm_gateway --tm-endpoint).
+    #[structopt(long, default_value = "tcp://*:6030")]
+    ground_tm_endpoint: String,
+
+    /// Endpoint of the rover's image telemetry channel.
+    #[structopt(long, default_value = "tcp://localhost:5031")]
+    rover_img_endpoint: String,
+
+    /// Endpoint for tm_gateway to subscribe to images on (tm_gateway --img-endpoint).
+    #[structopt(long, default_value = "tcp://*:6031")]
+    ground_img_endpoint: String,
+
+    /// Time a TC may wait in the queue before it is discarded, in seconds.
+    #[structopt(long, default_value = "600")]
+    expiry_s: u64,
+
+    /// The rover is out of contact if no telemetry has arrived for this long, in seconds.
+    #[structopt(long, default_value = "2")]
+    contact_timeout_s: f64,
+
+    /// Time to wait for the rover to respond to a TC before sending it again, in milliseconds.
+    #[structopt(long, default_value = "1000")]
+    response_timeout_ms: u64,
+}
+
+/// A TC waiting to be delivered.
+struct Queued {
+    /// Number of the TC, counting from 1 since the relay started
+    num: u64,
+
+    /// Time the TC was recieved from the console
+    recvd: Instant,
+
+    /// The serialized `TcPacket`
+    payload: Vec<u8>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Longest time to wait for a message before checking the queue.
+const MAX_POLL_MS: i64 = 100;
+
+// Indexes of the sockets
+const GROUND_TC: usize = 0;
+const ROVER_TC: usize = 1;
+const ROVER_TM: usize = 2;
+const GROUND_TM: usize = 3;
+const ROVER_IMG: usize = 4;
+const GROUND_IMG: usize = 5;
+
+// ------------------------------------------------------------------------------------------------
+// MAIN
+// ------------------------------------------------------------------------------------------------
+
+fn main() -> Result<()> {
+    let opts = Opts::from_args();
+
+    let ctx = zmq::Context::new();
+
+    let socket = |socket_type, bind, endpoint: &str| {
+        MonitoredSocket::new(
+            &ctx,
+            socket_type,
+            SocketOptions {
+                bind,
+                block_on_first_connect: false,
+                linger: 0,
+                ..Default::default()
+            },
+            endpoint,
+        )
+        .wrap_err_with(|| format!("Could not open {}", endpoint))
+    };
+
+    // TCs use DEALER sockets so that the console can be answered while the rover is out of
+    // contact. The REQ and REP envelopes are kept so the console and rover see normal messages.
+    let sockets = [
+        socket(zmq::DEALER, false, &opts.ground_tc_endpoint)?,
+        socket(zmq::DEALER, true, &opts.rover_tc_endpoint)?,
+        socket(zmq::SUB, false, &opts.rover_tm_endpoint)?,
+        socket(zmq::PUB, true, &opts.ground_tm_endpoint)?,
+        socket(zmq::SUB, false, &opts.rover_img_endpoint)?,
+        socket(zmq::PUB, true, &opts.ground_img_endpoint)?,
+    ];
+
+    let expiry = Duration::from_secs(opts.expiry_s);
+    let contact_timeout = Duration::from_secs_f64(opts.contact_timeout_s.max(0.0));
+    let response_timeout = Duration::from_millis(opts.response_timeout_ms);
+
+    let mut queue: VecDeque<Queued> = VecDeque::new();
+    let mut next_num = 1;
+
+    // Time the TC at the front of the queue was sent to the rover, if it has been
+    let mut sent_at: Option<Instant> = None;
+
+    let mut last_tm: Option<Instant> = None;
+    let mut in_contact = false;
+
+    println!("TC relay started, TCs expire after {} s", opts.expiry_s);
+
+    loop {
+        let mut items = [
+            sockets[GROUND_TC].as_poll_item(zmq::POLLIN),
+            sockets[ROVER_TC].as_poll_item(zmq::POLLIN),
+            sockets[ROVER_TM].as_poll_item(zmq::POLLIN),
+            sockets[ROVER_IMG].as_poll_item(zmq::POLLIN),
+        ];
+        zmq::poll(&mut items, MAX_POLL_MS).wrap_err("Could not poll the sockets")?;
+        let (ground_tc_readable, rover_tc_readable) = (items[0].is_readable(), items[1].is_readable());
+
+        // ---- TELEMETRY ----
+
+        for &(src, dest) in [(ROVER_TM, GROUND_TM), (ROVER_IMG, GROUND_IMG)].iter() {
+            while let Ok(frames) = sockets[src].recv_multipart(zmq::DONTWAIT) {
+                if src == ROVER_TM {
+                    last_tm = Some(Instant::now());
+                }
+
+                // Dropped if tm_gateway isn't connected, as it would be without the relay
+                sockets[dest].send_multipart(frames, zmq::DONTWAIT).ok();
+            }
+        }
+
+        let now_in_contact = last_tm.is_some_and(|t| t.elapsed() < contact_timeout);
+        if now_in_contact != in_contact {
+            in_contact = now_in_contact;
+            match in_contact {
+                true => println!("Rover in contact, {} TCs queued", queue.len()),
+                false => println!("Rover out of contact"),
+            }
+        }
+
+        // ---- TCS FROM THE CONSOLE ----
+
+        if ground_tc_readable {
+            while let Ok(mut frames) = sockets[GROUND_TC].recv_multipart(zmq::DONTWAIT) {
+                let payload = frames.pop().unwrap_or_default();
+
+                let response = match serde_json::from_slice::<TcPacket>(&payload) {
+                    Ok(packet) => {
+                        println!("TC {} queued: {:?}", next_num, packet.tc);
+                        queue.push_back(Queued {
+                            num: next_num,
+                            recvd: Instant::now(),
+                            payload,
+                        });
+                        next_num += 1;
+                        TcResponse::Queued
+                    }
+                    Err(e) => {
+                        println!("Rejected an invalid TC from the console: {}", e);
+                        TcResponse::Invalid
+                    }
+                };
+
+                // Reply inside the console's REQ envelope
+                frames.push(serde_json::to_vec(&response)?);
+                if let Err(e) = sockets[GROUND_TC].send_multipart(frames, zmq::DONTWAIT) {
+                    println!("Could not respond to the console: {}", e);
+                }
+            }
+        }
+
+        // ---- RESPONSES FROM THE ROVER ----
+
+        if rover_tc_readable {
+            while let Ok(frames) = sockets[ROVER_TC].recv_multipart(zmq::DONTWAIT) {
+                let response = frames
+                    .last()
+                    .and_then(|f| serde_json::from_slice::<TcResponse>(f).ok());
+                let num = frames.first().and_then(|f| decode_num(f));
+
+                // Only the front TC is ever sent, so a response to any other is a duplicate from
+                // a TC that was sent again, and its first response has already been handled
+                match queue.front() {
+                    Some(q) if Some(q.num) == num => {
+                        println!(
+                            "TC {} delivered after {:.1} s, rover responded {:?}",
+                            q.num,
+                            q.recvd.elapsed().as_secs_f64(),
+                            response
+                        );
+                        queue.pop_front();
+                        sent_at = None;
+                    }
+                    _ => match num {
+                        Some(n) => println!("Duplicate response to TC {}: {:?}", n, response),
+                        None => println!("Unexpected response from the rover: {:?}", response),
+                    },
+                }
+            }
+        }
+
+        // ---- DELIVERY ----
+
+        // Discard expired TCs, unless the front one is waiting for a response
+        let first = match sent_at {
+            Some(_) => 1,
+            None => 0,
+        };
+        while queue.len() > first && queue[first].recvd.elapsed() >= expiry {
+            let q = queue.remove(first).unwrap();
+            println!("TC {} expired after {} s without being delivered", q.num, opts.expiry_s);
+        }
+
+        if sent_at.is_some_and(|t| t.elapsed() >= response_timeout) {
+            sent_at = None;
+            if let Some(q) = queue.front() {
+                println!("No response to TC {} from the rover", q.num);
+
+                if q.recvd.elapsed() >= expiry {
+                    println!("TC {} expired after {} s without a response", q.num, opts.expiry_s);
+                    queue.pop_front();
+                }
+            }
+        }
+
+        if in_contact && sent_at.is_none() {
+            if let Some(q) = queue.front() {
+                // Wrap in the REP socket's envelope, which is returned with the response
+                let frames = vec![encode_num(q.num), vec![], q.payload.clone()];
+                match sockets[ROVER_TC].send_multipart(frames, zmq::DONTWAIT) {
+                    Ok(()) => sent_at = Some(Instant::now()),
+                    Err(zmq::Error::EAGAIN) => (),
+                    Err(e) => println!("Could not send TC {} to the rover: {}", q.num, e),
+                }
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Encode a TC number as the envelope frame sent to the rover.
+fn encode_num(num: u64) -> Vec<u8> {
+    num.to_be_bytes().to_vec()
+}
+
+/// Decode the TC number from the envelope frame of a response, if it is one.
+fn decode_num(frame: &[u8]) -> Option<u64> {
+    frame.try_into().ok().map(u64::from_be_bytes)
+}
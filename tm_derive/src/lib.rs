@@ -0,0 +1,174 @@
+//! # Telemetry metadata derive
+//!
+//! Provides `#[derive(TmMeta)]`, which implements `comms_if::tm::TmMeta` for a struct with named
+//! fields. Metadata is given to each field with the `tm` attribute:
+//!
+//! - `#[tm(unit = "m/s")]` - engineering unit of the field.
+//! - `#[tm(desc = "...")]` - description of the field, which otherwise is the first paragraph of
+//!   the field's doc comment.
+//! - `#[tm(nested)]` - the field's type also implements `TmMeta`, so its fields are included.
+//! - `#[tm(skip)]` - leave the field out.
+//!
+//! Fields marked `#[serde(skip)]` or `#[serde(skip_serializing)]` aren't in the telemetry, so are
+//! left out automatically.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Field, Fields, Lit, LitStr, Meta};
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Metadata attributes of a single field.
+#[derive(Default)]
+struct FieldAttrs {
+    unit: Option<String>,
+    desc: Option<String>,
+    nested: bool,
+    skip: bool,
+}
+
+// ------------------------------------------------------------------------------------------------
+// DERIVES
+// ------------------------------------------------------------------------------------------------
+
+#[proc_macro_derive(TmMeta, attributes(tm))]
+pub fn derive_tm_meta(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref s) => match s.fields {
+            Fields::Named(ref f) => Some(&f.named),
+            _ => None,
+        },
+        _ => None,
+    }
+    .ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            "TmMeta can only be derived for structs with named fields",
+        )
+    })?;
+
+    let mut entries = Vec::new();
+
+    for field in fields.iter() {
+        let attrs = FieldAttrs::parse(field)?;
+        if attrs.skip {
+            continue;
+        }
+
+        let name = field.ident.as_ref().unwrap().to_string();
+        let unit = match attrs.unit {
+            Some(u) => quote!(Some(#u)),
+            None => quote!(None),
+        };
+        let desc = attrs.desc.unwrap_or_else(|| doc_summary(field));
+        let ty = &field.ty;
+        let nested = match attrs.nested {
+            true => quote!(<#ty as ::comms_if::tm::TmMeta>::tm_fields()),
+            false => quote!(Vec::new()),
+        };
+
+        entries.push(quote! {
+            ::comms_if::tm::TmFieldMeta {
+                name: #name,
+                unit: #unit,
+                desc: #desc,
+                fields: #nested,
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::comms_if::tm::TmMeta for #ident #ty_generics #where_clause {
+            fn tm_fields() -> Vec<::comms_if::tm::TmFieldMeta> {
+                vec![#(#entries),*]
+            }
+        }
+    })
+}
+
+/// Get the first paragraph of a field's doc comment as a single line.
+fn doc_summary(field: &Field) -> String {
+    let mut lines = Vec::new();
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("doc")) {
+        let line = match attr.meta {
+            Meta::NameValue(ref nv) => match nv.value {
+                Expr::Lit(ref l) => match l.lit {
+                    Lit::Str(ref s) => s.value(),
+                    _ => continue,
+                },
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        let line = line.trim().to_string();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+
+    lines.join(" ")
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut attrs = Self::default();
+
+        for attr in field.attrs.iter() {
+            if attr.path().is_ident("tm") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("unit") {
+                        attrs.unit = Some(meta.value()?.parse::<LitStr>()?.value());
+                    } else if meta.path.is_ident("desc") {
+                        attrs.desc = Some(meta.value()?.parse::<LitStr>()?.value());
+                    } else if meta.path.is_ident("nested") {
+                        attrs.nested = true;
+                    } else if meta.path.is_ident("skip") {
+                        attrs.skip = true;
+                    } else {
+                        return Err(meta.error("unknown tm attribute"));
+                    }
+                    Ok(())
+                })?;
+            } else if attr.path().is_ident("serde") {
+                // Other serde attributes are of no interest, so errors parsing them are ignored
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing") {
+                        attrs.skip = true;
+                    } else if meta.input.peek(syn::Token![=]) {
+                        meta.value()?.parse::<Expr>()?;
+                    }
+                    Ok(())
+                })
+                .ok();
+            }
+        }
+
+        Ok(attrs)
+    }
+}
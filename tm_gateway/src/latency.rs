@@ -0,0 +1,172 @@
+//! # Latency Report
+//!
+//! Distributions of the end-to-end latencies, measured from the timestamps in the telemetry:
+//!
+//! - Command to actuation, from the console sending a TC to the rover sending the resulting
+//!   demands to the mechanisms, split into the uplink, execution and actuation stages.
+//! - Sensation to display, from the rover reading its sensors to the gateway publishing the
+//!   telemetry, and from a camera acquiring an image to the gateway publishing it.
+//!
+//! Stamps are taken on the ground and on the rover, so their clocks must be synchronised.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use chrono::{DateTime, Utc};
+use comms_if::tm::latency::{ms_between, TcStamps};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Maximum number of samples kept in each distribution, the oldest are dropped first.
+const MAX_SAMPLES: usize = 100_000;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Latency distributions measured from the telemetry.
+pub struct LatencyReport {
+    uplink: Distribution,
+    execution: Distribution,
+    actuation: Distribution,
+    command_to_actuation: Distribution,
+    tm_sensation_to_display: Distribution,
+    img_sensation_to_display: Distribution,
+
+    /// Reciept time of the last TC measured, as every packet carries the stamps of the last TC
+    last_tc_recvd: Option<DateTime<Utc>>,
+}
+
+/// The distribution of a single latency.
+struct Distribution {
+    name: &'static str,
+
+    samples_ms: VecDeque<f64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl LatencyReport {
+    pub fn new() -> Self {
+        Self {
+            uplink: Distribution::new("Uplink (console to rover)"),
+            execution: Distribution::new("Execution (reciept to execution)"),
+            actuation: Distribution::new("Actuation (execution to demands)"),
+            command_to_actuation: Distribution::new("Command to actuation"),
+            tm_sensation_to_display: Distribution::new("TM sensation to display"),
+            img_sensation_to_display: Distribution::new("Image sensation to display"),
+            last_tc_recvd: None,
+        }
+    }
+
+    /// Record the latencies of a telemetry packet which was displayed at the given time.
+    pub fn add_packet(&mut self, fields: &Map<String, Value>, displayed: DateTime<Utc>) {
+        if let Some(sensed) = fields
+            .get("sensed")
+            .and_then(|v| serde_json::from_value::<DateTime<Utc>>(v.clone()).ok())
+        {
+            self.tm_sensation_to_display.add(ms_between(sensed, displayed));
+        }
+
+        let stamps = match fields
+            .get("last_tc_stamps")
+            .and_then(|v| serde_json::from_value::<Option<TcStamps>>(v.clone()).ok())
+            .flatten()
+        {
+            Some(s) => s,
+            None => return,
+        };
+
+        // Wait for the TC to be actuated, and only measure each TC once
+        if stamps.actuated.is_none() || self.last_tc_recvd == Some(stamps.recvd) {
+            return;
+        }
+        self.last_tc_recvd = Some(stamps.recvd);
+
+        if let Some(sent) = stamps.sent {
+            self.uplink.add(ms_between(sent, stamps.recvd));
+        }
+        if let Some(executed) = stamps.executed {
+            self.execution.add(ms_between(stamps.recvd, executed));
+
+            if let Some(actuated) = stamps.actuated {
+                self.actuation.add(ms_between(executed, actuated));
+            }
+        }
+        if let Some(ms) = stamps.command_to_actuation_ms() {
+            self.command_to_actuation.add(ms);
+        }
+    }
+
+    /// Record the latency of an image acquired and displayed at the given times.
+    pub fn add_image(&mut self, acquired: DateTime<Utc>, displayed: DateTime<Utc>) {
+        self.img_sensation_to_display.add(ms_between(acquired, displayed));
+    }
+
+    /// Print the distributions.
+    pub fn print(&self) {
+        println!("Latency report (ms):");
+        for d in [
+            &self.uplink,
+            &self.execution,
+            &self.actuation,
+            &self.command_to_actuation,
+            &self.tm_sensation_to_display,
+            &self.img_sensation_to_display,
+        ]
+        .iter()
+        {
+            d.print();
+        }
+    }
+}
+
+impl Distribution {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            samples_ms: VecDeque::new(),
+        }
+    }
+
+    fn add(&mut self, ms: f64) {
+        if !ms.is_finite() {
+            return;
+        }
+
+        if self.samples_ms.len() >= MAX_SAMPLES {
+            self.samples_ms.pop_front();
+        }
+        self.samples_ms.push_back(ms);
+    }
+
+    fn print(&self) {
+        if self.samples_ms.is_empty() {
+            println!("    {:<36} no samples", self.name);
+            return;
+        }
+
+        let mut sorted: Vec<f64> = self.samples_ms.iter().copied().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+
+        println!(
+            "    {:<36} n {:>6}, min {:>8.1}, median {:>8.1}, p95 {:>8.1}, p99 {:>8.1}, max {:>8.1}",
+            self.name,
+            sorted.len(),
+            sorted[0],
+            percentile(0.5),
+            percentile(0.95),
+            percentile(0.99),
+            sorted[sorted.len() - 1]
+        );
+    }
+}
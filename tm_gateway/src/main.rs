@@ -0,0 +1,453 @@
+//! # Telemetry Gateway
+//!
+//! Subscribes to the rover's telemetry and republishes it as JSON over websockets or to an MQTT
+//! broker, so that browser dashboards can display telemetry without exposing zmq to the public
+//! network.
+//!
+//! Each top level field of the telemetry packet is published as its own channel. A message on a
+//! channel has the form:
+//!
+//! ```json
+//! { "vehicle_id": "phobos_1", "sim_time_s": 12.3, "channel": "safe", "value": false }
+//! ```
+//!
+//! Clients pick a channel using the websocket path, e.g. `ws://<host>:8030/safe`, or connect to
+//! `ws://<host>:8030/` to receive every channel. With `--output mqtt` each channel is instead
+//! published to the topic `<--mqtt-topic-prefix>/<channel>` on the broker given by `--mqtt-broker`.
+//!
+//! Images from the rover's image telemetry channel are reassembled and published on the `image`
+//! channel, with the complete `CamFrame` as the value. They can also be saved to a directory with
+//! `--img-dir`.
+//!
+//! Packets the rover stored while out of contact and sent once contact was regained are marked as
+//! backfill. So that they aren't mistaken for live values they are published whole on the
+//! `backfill` channel, rather than split into channels.
+//!
+//! If the telemetry is recieved over a lossy transport, such as UDP, the link's loss statistics are
+//! published on the `link_stats` channel once a second, and printed every 10 seconds.
+//!
+//! With `--latency-report-s` the gateway measures the command to actuation and sensation to display
+//! latencies from the timestamps in the telemetry, and prints their distributions.
+
+// ------------------------------------------------------------------------------------------------
+// MODULES
+// ------------------------------------------------------------------------------------------------
+
+/// Distributions of the end-to-end latencies.
+mod latency;
+
+/// Publishing to an MQTT broker.
+mod mqtt;
+
+/// Common interface of the websocket and MQTT outputs.
+mod publish;
+
+/// Push-only websocket server.
+mod ws;
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use chrono::Utc;
+use color_eyre::{eyre::WrapErr, Result};
+use comms_if::{
+    eqpt::cam::ImageFormat,
+    net::{transport, zmq, SocketOptions, Transport, TransportError, TransportExt, TransportKind},
+    tm::img::{ImageChunk, ImageReassembler},
+};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+use latency::LatencyReport;
+use mqtt::MqttPublisher;
+use publish::Publisher;
+use ws::WsServer;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Command line options for the gateway.
+#[derive(StructOpt)]
+#[structopt(
+    name = "tm_gateway",
+    about = "Republish rover telemetry over websockets or MQTT"
+)]
+struct Opts {
+    /// Endpoint of the rover's TmServer.
+    #[structopt(long, default_value = "tcp://localhost:5030")]
+    tm_endpoint: String,
+
+    /// Where to republish the telemetry, either ws to serve websockets or mqtt to publish to a
+    /// broker.
+    #[structopt(long, default_value = "ws", possible_values = &["ws", "mqtt"])]
+    output: Output,
+
+    /// Address to serve websockets on.
+    #[structopt(long, default_value = "0.0.0.0:8030")]
+    bind: String,
+
+    /// Address of the MQTT broker, as <host>:<port>.
+    #[structopt(long, default_value = "localhost:1883")]
+    mqtt_broker: String,
+
+    /// Prefix of the MQTT topics, each channel is published to <prefix>/<channel>.
+    #[structopt(long, default_value = "phobos/tm")]
+    mqtt_topic_prefix: String,
+
+    /// MQTT client ID, which must be unique on the broker.
+    #[structopt(long, default_value = "tm_gateway")]
+    mqtt_client_id: String,
+
+    /// Top level telemetry fields which should not be republished, for example large camera
+    /// frames which would swamp a browser. May be given more than once.
+    #[structopt(long)]
+    exclude: Vec<String>,
+
+    /// Endpoint of the rover's image telemetry channel.
+    #[structopt(long, default_value = "tcp://localhost:5031")]
+    img_endpoint: String,
+
+    /// Directory to save reassembled images into. Images are not saved if not given.
+    #[structopt(long, parse(from_os_str))]
+    img_dir: Option<PathBuf>,
+
+    /// Largest number of chunks accepted for one image, images claiming more are rejected. Must
+    /// be at least img_downlink_max_bytes / img_downlink_chunk_bytes from the rover's net.toml.
+    #[structopt(long, default_value = "256")]
+    img_max_chunks: u32,
+
+    /// Transport to use, either zmq or grpc. Must match ground_transport in the rover's net.toml.
+    #[structopt(long, default_value = "zmq", possible_values = &["zmq", "grpc"])]
+    transport: TransportKind,
+
+    /// Transport to use for the TM channel only, overriding --transport. Must match tm_transport
+    /// in the rover's net.toml. With udp the TM endpoint is bound, for example udp://*:5030.
+    #[structopt(long, possible_values = &["zmq", "grpc", "udp"])]
+    tm_transport: Option<TransportKind>,
+
+    /// Measure the end-to-end latencies and print their distributions every this many seconds.
+    /// The clocks of the rover and the ground must be synchronised.
+    #[structopt(long)]
+    latency_report_s: Option<u64>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+/// Where the gateway republishes telemetry.
+enum Output {
+    /// Serve websockets
+    Ws,
+
+    /// Publish to an MQTT broker
+    Mqtt,
+}
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Number of incomplete images to keep while waiting for their remaining chunks.
+const MAX_PENDING_IMAGES: usize = 8;
+
+/// Interval between publishing the link statistics.
+const LINK_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of link statistics intervals between printing the statistics.
+const LINK_STATS_PRINT_INTERVALS: u32 = 10;
+
+// ------------------------------------------------------------------------------------------------
+// MAIN
+// ------------------------------------------------------------------------------------------------
+
+fn main() -> Result<()> {
+    let opts = Opts::from_args();
+
+    // Create the zmq context
+    let ctx = zmq::Context::new();
+
+    // Subscribe to all telemetry
+    let socket_options = SocketOptions {
+        block_on_first_connect: false,
+        recv_timeout: 200,
+        ..Default::default()
+    };
+
+    let socket = transport::open(
+        opts.tm_transport.unwrap_or(opts.transport),
+        &ctx,
+        zmq::SUB,
+        socket_options,
+        &opts.tm_endpoint,
+    )
+    .wrap_err("Failed to create the TM subscriber")?;
+
+    println!("Subscribed to telemetry at {}", opts.tm_endpoint);
+
+    // Start the output
+    let server: Arc<dyn Publisher> = match opts.output {
+        Output::Ws => {
+            let server =
+                WsServer::bind(&opts.bind).wrap_err("Failed to start the websocket server")?;
+            println!("Serving websockets on {}", opts.bind);
+            Arc::new(server)
+        }
+        Output::Mqtt => {
+            let publisher = MqttPublisher::connect(
+                &opts.mqtt_broker,
+                &opts.mqtt_client_id,
+                &opts.mqtt_topic_prefix,
+            )
+            .wrap_err("Failed to start the MQTT publisher")?;
+            println!(
+                "Publishing to the MQTT broker at {} under {}",
+                opts.mqtt_broker,
+                publisher.topic("<channel>")
+            );
+            Arc::new(publisher)
+        }
+    };
+
+    // Subscribe to the image channel, which is handled in its own thread so that large images
+    // don't delay the rest of the telemetry
+    let img_socket = transport::open(
+        opts.transport,
+        &ctx,
+        zmq::SUB,
+        SocketOptions {
+            block_on_first_connect: false,
+            recv_timeout: 200,
+            ..Default::default()
+        },
+        &opts.img_endpoint,
+    )
+    .wrap_err("Failed to create the image TM subscriber")?;
+
+    println!("Subscribed to images at {}", opts.img_endpoint);
+
+    if let Some(ref dir) = opts.img_dir {
+        fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("Could not create the image directory {:?}", dir))?;
+    }
+
+    let latency = opts
+        .latency_report_s
+        .map(|_| Arc::new(Mutex::new(LatencyReport::new())));
+
+    {
+        let server = server.clone();
+        let img_dir = opts.img_dir.clone();
+        let latency = latency.clone();
+        let max_chunks = opts.img_max_chunks;
+        thread::spawn(move || image_thread(img_socket, server, img_dir, latency, max_chunks));
+    }
+
+    let mut last_stats = Instant::now();
+    let mut num_stats = 0;
+    let mut last_latency_report = Instant::now();
+
+    loop {
+        if let (Some(ref report), Some(ivl_s)) = (&latency, opts.latency_report_s) {
+            if last_latency_report.elapsed() >= Duration::from_secs(ivl_s) {
+                last_latency_report = Instant::now();
+                report.lock().unwrap().print();
+            }
+        }
+
+        // Publish the link statistics, if the transport can lose messages
+        if last_stats.elapsed() >= LINK_STATS_INTERVAL {
+            last_stats = Instant::now();
+
+            if let Some(stats) = socket.link_stats() {
+                num_stats += 1;
+                if num_stats % LINK_STATS_PRINT_INTERVALS == 0 {
+                    println!(
+                        "Link: {} datagrams recieved, {} lost, {} packets recieved ({} with FEC), \
+                        {} lost",
+                        stats.datagrams_recvd,
+                        stats.datagrams_lost,
+                        stats.msgs_recvd,
+                        stats.msgs_recovered,
+                        stats.msgs_lost
+                    );
+                }
+
+                let msg = json!({ "channel": "link_stats", "value": stats });
+                server.send("link_stats", &msg.to_string());
+            }
+        }
+
+        // Get the next packet, waiting for one to arrive if needed
+        let packet: Value = match socket.recv_json() {
+            Ok(Some(p)) => p,
+            Ok(None) => continue,
+            Err(TransportError::DeserializationError(e)) => {
+                println!("Could not parse telemetry packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e).wrap_err("Could not recieve telemetry"),
+        };
+
+        let fields = match packet.as_object() {
+            Some(f) => f,
+            None => {
+                println!("Telemetry packet was not a JSON object");
+                continue;
+            }
+        };
+
+        let backfill = fields.get("backfill").and_then(Value::as_bool) == Some(true);
+
+        // Backfilled packets are old so would skew the latencies
+        if let Some(ref report) = latency {
+            if !backfill {
+                report.lock().unwrap().add_packet(fields, Utc::now());
+            }
+        }
+
+        // No point doing the work if no one is listening
+        if !server.has_subscribers() {
+            continue;
+        }
+
+        if backfill {
+            let msg = json!({
+                "vehicle_id": fields.get("vehicle_id"),
+                "sim_time_s": fields.get("sim_time_s"),
+                "channel": "backfill",
+                "value": packet
+            });
+
+            server.send("backfill", &msg.to_string());
+            continue;
+        }
+
+        // Split the packet into channels, one per field
+        for (channel, value) in fields {
+            if opts.exclude.contains(channel) {
+                continue;
+            }
+
+            let msg = json!({
+                "vehicle_id": fields.get("vehicle_id"),
+                "sim_time_s": fields.get("sim_time_s"),
+                "channel": channel,
+                "value": value
+            });
+
+            server.send(channel, &msg.to_string());
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ws" => Ok(Output::Ws),
+            "mqtt" => Ok(Output::Mqtt),
+            _ => Err(format!("Unknown output {:?}, expected ws or mqtt", s)),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Reassemble images from the image channel, publishing and optionally saving each one.
+fn image_thread(
+    socket: Box<dyn Transport>,
+    server: Arc<dyn Publisher>,
+    img_dir: Option<PathBuf>,
+    latency: Option<Arc<Mutex<LatencyReport>>>,
+    max_chunks: u32,
+) {
+    let mut reassembler = ImageReassembler::new(MAX_PENDING_IMAGES, max_chunks);
+    let mut num_dropped = 0;
+
+    loop {
+        let chunk: ImageChunk = match socket.recv_json() {
+            Ok(Some(c)) => c,
+            Ok(None) => continue,
+            Err(TransportError::DeserializationError(e)) => {
+                println!("Could not parse image chunk: {}", e);
+                continue;
+            }
+            Err(e) => {
+                println!("Could not recieve image chunk, image channel closed: {}", e);
+                return;
+            }
+        };
+
+        let (cam_id, frame) = match reassembler.push(chunk) {
+            Ok(Some(f)) => f,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("Could not reassemble image: {}", e);
+                continue;
+            }
+        };
+
+        if let Some(ref report) = latency {
+            report.lock().unwrap().add_image(frame.timestamp, Utc::now());
+        }
+
+        if reassembler.num_dropped() != num_dropped {
+            num_dropped = reassembler.num_dropped();
+            println!("{} images have been dropped due to missing chunks", num_dropped);
+        }
+
+        // Save the image
+        if let Some(ref dir) = img_dir {
+            let ext = match frame.format {
+                ImageFormat::Png => "png",
+                ImageFormat::Jpeg(_) => "jpg",
+            };
+
+            let mut path = dir.clone();
+            path.push(format!(
+                "{:?}_{}.{}",
+                cam_id,
+                frame.timestamp.timestamp_millis(),
+                ext
+            ));
+
+            match base64::decode(&frame.b64_data) {
+                Ok(data) => {
+                    if let Err(e) = fs::write(&path, data) {
+                        println!("Could not save image to {:?}: {}", path, e);
+                    }
+                }
+                Err(e) => println!("Could not decode image: {}", e),
+            }
+        }
+
+        // Publish the image
+        if server.has_subscribers() {
+            let msg = json!({
+                "channel": "image",
+                "cam_id": cam_id,
+                "value": frame
+            });
+
+            server.send("image", &msg.to_string());
+        }
+    }
+}
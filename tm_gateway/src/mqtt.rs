@@ -0,0 +1,244 @@
+//! # MQTT Publisher
+//!
+//! Publishes each telemetry channel to its own topic on an MQTT broker, `<prefix>/<channel>`, for
+//! dashboards which already speak MQTT (many do over the broker's own websocket listener).
+//!
+//! Messages are published at QoS 0 without blocking. If the broker is unreachable or the outgoing
+//! queue is full messages are dropped, the connection is retried in the background.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS};
+
+use crate::publish::Publisher;
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Number of messages which may be waiting to be sent to the broker.
+const QUEUE_LEN: usize = 256;
+
+/// MQTT keep alive interval.
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+/// Time to wait before reconnecting to the broker after an error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// Publishes telemetry channels to an MQTT broker.
+pub struct MqttPublisher {
+    client: Client,
+
+    /// Prefix of every topic, without a trailing `/`
+    topic_prefix: String,
+
+    /// Whether the broker has accepted the connection
+    connected: Arc<AtomicBool>,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum MqttError {
+    #[error("The broker address must be <host>:<port>, got {0:?}")]
+    InvalidBrokerAddress(String),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl MqttPublisher {
+    /// Connect to the broker at the given `<host>:<port>` address.
+    ///
+    /// The connection is made, and remade after any error, in a background thread.
+    pub fn connect(broker: &str, client_id: &str, topic_prefix: &str) -> Result<Self, MqttError> {
+        let (host, port) = broker
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h, p.parse::<u16>().ok()?)))
+            .filter(|(h, _)| !h.is_empty())
+            .ok_or_else(|| MqttError::InvalidBrokerAddress(broker.into()))?;
+
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(KEEP_ALIVE);
+
+        let (client, connection) = Client::new(options, QUEUE_LEN);
+
+        let connected = Arc::new(AtomicBool::new(false));
+        {
+            let connected = connected.clone();
+            let broker = broker.to_string();
+            thread::spawn(move || connection_thread(connection, connected, broker));
+        }
+
+        Ok(Self {
+            client,
+            topic_prefix: topic_prefix.trim_end_matches('/').to_string(),
+            connected,
+        })
+    }
+
+    /// Get the topic a channel is published on.
+    pub fn topic(&self, channel: &str) -> String {
+        if self.topic_prefix.is_empty() {
+            channel.to_string()
+        } else {
+            format!("{}/{}", self.topic_prefix, channel)
+        }
+    }
+}
+
+impl Publisher for MqttPublisher {
+    /// Subscribers are invisible to a publisher, so assume there are some while the broker is
+    /// connected.
+    fn has_subscribers(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    fn send(&self, channel: &str, msg: &str) {
+        // A full queue means the broker can't keep up, drop the message rather than stall the
+        // telemetry
+        self.client
+            .try_publish(self.topic(channel), QoS::AtMostOnce, false, msg.as_bytes())
+            .ok();
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Drive the MQTT connection, reconnecting after errors.
+fn connection_thread(mut connection: Connection, connected: Arc<AtomicBool>, broker: String) {
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                println!("Connected to the MQTT broker at {}", broker);
+                connected.store(true, Ordering::Relaxed);
+            }
+            Ok(_) => (),
+            Err(e) => {
+                if connected.swap(false, Ordering::Relaxed) {
+                    println!("Lost connection to the MQTT broker at {}: {}", broker, e);
+                }
+                thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        time::Instant,
+    };
+
+    #[test]
+    fn test_topics() {
+        let p = MqttPublisher::connect("localhost:1", "test", "phobos/tm/").unwrap();
+        assert_eq!(p.topic("safe"), "phobos/tm/safe");
+
+        let p = MqttPublisher::connect("localhost:1", "test", "").unwrap();
+        assert_eq!(p.topic("safe"), "safe");
+    }
+
+    #[test]
+    fn test_invalid_broker_address() {
+        assert!(MqttPublisher::connect("localhost", "test", "tm").is_err());
+        assert!(MqttPublisher::connect(":1883", "test", "tm").is_err());
+        assert!(MqttPublisher::connect("localhost:mqtt", "test", "tm").is_err());
+    }
+
+    #[test]
+    fn test_send_without_broker_does_not_block() {
+        let p = MqttPublisher::connect("127.0.0.1:1", "test", "tm").unwrap();
+        assert!(!p.has_subscribers());
+
+        let start = Instant::now();
+        for _ in 0..(QUEUE_LEN * 4) {
+            p.send("safe", "false");
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Read one MQTT packet from the stream, returning its type nibble and body.
+    fn read_packet(stream: &mut impl Read) -> (u8, Vec<u8>) {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).unwrap();
+        let kind = byte[0] >> 4;
+
+        // Variable length remaining length field
+        let mut len = 0usize;
+        let mut shift = 0;
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            len |= ((byte[0] & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).unwrap();
+        (kind, body)
+    }
+
+    #[test]
+    fn test_publish_to_broker() {
+        // A fake broker which accepts the connection and records the first publish
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let p = MqttPublisher::connect(&addr, "test", "phobos/tm").unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+
+        // CONNECT, answered with an accepting CONNACK
+        let (kind, _) = read_packet(&mut stream);
+        assert_eq!(kind, 1);
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+        let start = Instant::now();
+        while !p.has_subscribers() {
+            assert!(start.elapsed() < Duration::from_secs(2), "never connected");
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        p.send("safe", r#"{"value":false}"#);
+
+        // PUBLISH at QoS 0, which is the topic length, topic and payload
+        let (kind, body) = read_packet(&mut stream);
+        assert_eq!(kind, 3);
+        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+        assert_eq!(&body[2..2 + topic_len], b"phobos/tm/safe");
+        assert_eq!(&body[2 + topic_len..], br#"{"value":false}"#);
+    }
+}
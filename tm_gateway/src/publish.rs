@@ -0,0 +1,18 @@
+//! # Publisher
+//!
+//! Common interface of the gateway's outputs, so the telemetry loop doesn't need to know whether
+//! it is serving websockets or publishing to an MQTT broker.
+
+// ------------------------------------------------------------------------------------------------
+// TRAITS
+// ------------------------------------------------------------------------------------------------
+
+/// An output which republishes telemetry channels.
+pub trait Publisher: Send + Sync {
+    /// Whether anyone may receive published messages. When this is false the gateway skips the
+    /// work of splitting and serialising the telemetry.
+    fn has_subscribers(&self) -> bool;
+
+    /// Publish a JSON message on the given channel. Must not block on slow receivers.
+    fn send(&self, channel: &str, msg: &str);
+}
@@ -0,0 +1,393 @@
+//! # Websocket Server
+//!
+//! A push-only websocket server built on `tungstenite`. Messages sent by clients are never read,
+//! the gateway is one way.
+//!
+//! Clients choose which telemetry channel they receive using the request path, for example
+//! `ws://rover:8030/safe` receives only the `safe` channel. Connecting to `/` receives all channels.
+//!
+//! Each connection gets its own thread, which performs the handshake and then writes the client's
+//! messages from a bounded queue. A slow or malicious client therefore can't hold up the accept
+//! loop or the other clients, and messages are dropped for a client whose queue is full.
+
+// ------------------------------------------------------------------------------------------------
+// IMPORTS
+// ------------------------------------------------------------------------------------------------
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tungstenite::{
+    handshake::server::{Request, Response},
+    Message, WebSocket,
+};
+
+use crate::publish::Publisher;
+
+// ------------------------------------------------------------------------------------------------
+// CONSTANTS
+// ------------------------------------------------------------------------------------------------
+
+/// Maximum time a single write to a client may take before the client is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Maximum time a client has to complete the whole handshake request, however slowly it trickles
+/// in.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Maximum number of handshakes in progress at once, further connections are closed immediately.
+const MAX_PENDING_HANDSHAKES: usize = 16;
+
+/// Number of messages queued for a client before further messages to it are dropped.
+const CLIENT_QUEUE_LEN: usize = 64;
+
+// ------------------------------------------------------------------------------------------------
+// STRUCTS
+// ------------------------------------------------------------------------------------------------
+
+/// A websocket server which pushes messages to all connected clients.
+pub struct WsServer {
+    clients: Arc<Mutex<Vec<WsClient>>>,
+}
+
+/// A connected websocket client, as seen by the server.
+struct WsClient {
+    /// Queue of messages for the client's writer thread
+    tx: SyncSender<Arc<str>>,
+
+    /// The channel requested by the client, or `None` for all channels
+    channel: Option<String>,
+}
+
+/// A stream which fails reads once its deadline has passed, used to bound the total time of the
+/// handshake rather than the time of each read.
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+// ------------------------------------------------------------------------------------------------
+// ENUMS
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum WsError {
+    #[error("Could not bind the websocket server: {0}")]
+    BindError(std::io::Error),
+
+    #[error("IO error during handshake: {0}")]
+    HandshakeIoError(std::io::Error),
+
+    #[error("The client did not complete a valid websocket handshake: {0}")]
+    InvalidHandshake(String),
+}
+
+// ------------------------------------------------------------------------------------------------
+// IMPLS
+// ------------------------------------------------------------------------------------------------
+
+impl WsServer {
+    /// Bind a new server to the given address.
+    ///
+    /// Clients are accepted in a background thread.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self, WsError> {
+        let listener = TcpListener::bind(addr).map_err(WsError::BindError)?;
+
+        Ok(Self::from_listener(listener))
+    }
+
+    /// Serve websockets on an already bound listener.
+    fn from_listener(listener: TcpListener) -> Self {
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let clients_clone = clients.clone();
+
+        thread::spawn(move || accept_thread(listener, clients_clone));
+
+        Self { clients }
+    }
+
+    /// Get the number of connected clients.
+    pub fn num_clients(&self) -> usize {
+        self.clients
+            .lock()
+            .expect("WsServer: clients mutex poisoned")
+            .len()
+    }
+}
+
+impl Publisher for WsServer {
+    fn has_subscribers(&self) -> bool {
+        self.num_clients() > 0
+    }
+
+    /// Send a text message on the given channel.
+    ///
+    /// The message is queued for all clients subscribed to this channel, or to all channels, and
+    /// written by their own threads so the lock is never held across a write. Clients whose
+    /// threads have exited are removed.
+    fn send(&self, channel: &str, msg: &str) {
+        let msg: Arc<str> = Arc::from(msg);
+
+        let mut clients = self
+            .clients
+            .lock()
+            .expect("WsServer: clients mutex poisoned");
+
+        clients.retain(|c| match c.channel {
+            Some(ref ch) if ch != channel => true,
+            _ => match c.tx.try_send(msg.clone()) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        });
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::ZERO {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// FUNCTIONS
+// ------------------------------------------------------------------------------------------------
+
+/// Background thread which accepts new clients, handing each one to its own thread.
+fn accept_thread(listener: TcpListener, clients: Arc<Mutex<Vec<WsClient>>>) {
+    let pending = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                println!("Could not accept websocket client: {}", e);
+                continue;
+            }
+        };
+
+        if pending.fetch_add(1, Ordering::SeqCst) >= MAX_PENDING_HANDSHAKES {
+            pending.fetch_sub(1, Ordering::SeqCst);
+            println!("Too many websocket handshakes in progress, closing new connection");
+            continue;
+        }
+
+        let clients = clients.clone();
+        let pending = pending.clone();
+        thread::spawn(move || client_thread(stream, clients, pending));
+    }
+}
+
+/// Thread serving one client, which performs the handshake then writes the client's messages until
+/// the connection fails.
+fn client_thread(stream: TcpStream, clients: Arc<Mutex<Vec<WsClient>>>, pending: Arc<AtomicUsize>) {
+    let peer = stream
+        .peer_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_else(|_| "unknown".into());
+
+    let result = handshake(stream);
+    pending.fetch_sub(1, Ordering::SeqCst);
+
+    let (mut socket, channel) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Websocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+
+    println!(
+        "Websocket client {} connected to channel {}",
+        peer,
+        channel.as_deref().unwrap_or("*")
+    );
+
+    let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE_LEN);
+    clients
+        .lock()
+        .expect("WsServer: clients mutex poisoned")
+        .push(WsClient { tx, channel });
+
+    write_loop(&mut socket, rx);
+
+    println!("Websocket client {} disconnected", peer);
+}
+
+/// Perform the server side of the websocket opening handshake, returning the socket and the
+/// requested channel.
+///
+/// The request's size is limited by `tungstenite` and its duration by `HANDSHAKE_TIMEOUT`.
+// The callback's error type is set by tungstenite
+#[allow(clippy::result_large_err)]
+fn handshake(stream: TcpStream) -> Result<(WebSocket<DeadlineStream>, Option<String>), WsError> {
+    stream
+        .set_write_timeout(Some(WRITE_TIMEOUT))
+        .map_err(WsError::HandshakeIoError)?;
+
+    let stream = DeadlineStream {
+        stream,
+        deadline: Instant::now() + HANDSHAKE_TIMEOUT,
+    };
+
+    let mut path = String::new();
+    let socket = tungstenite::accept_hdr(stream, |req: &Request, resp: Response| {
+        path = req.uri().path().to_string();
+        Ok(resp)
+    })
+    .map_err(|e| WsError::InvalidHandshake(e.to_string()))?;
+
+    // Get the channel from the path
+    let channel = match path.trim_matches('/') {
+        "" => None,
+        c => Some(c.to_string()),
+    };
+
+    Ok((socket, channel))
+}
+
+/// Write queued messages to the client until the server is dropped or a write fails.
+fn write_loop(socket: &mut WebSocket<DeadlineStream>, rx: Receiver<Arc<str>>) {
+    while let Ok(msg) = rx.recv() {
+        if socket.send(Message::Text(msg.to_string())).is_err() {
+            break;
+        }
+    }
+
+    socket.close(None).ok();
+}
+
+// ------------------------------------------------------------------------------------------------
+// TESTS
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start a server on a free local port, returning it and its address.
+    fn server() -> (WsServer, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (WsServer::from_listener(listener), addr)
+    }
+
+    /// Connect a client to the given path, waiting until the server has registered it.
+    fn connect(server: &WsServer, addr: &str, path: &str) -> WebSocket<impl Read + Write> {
+        let before = server.num_clients();
+        let (socket, _) = tungstenite::connect(format!("ws://{}{}", addr, path)).unwrap();
+
+        let start = Instant::now();
+        while server.num_clients() == before {
+            assert!(
+                start.elapsed() < Duration::from_secs(2),
+                "client was not registered"
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        socket
+    }
+
+    fn read_text(socket: &mut WebSocket<impl Read + Write>) -> String {
+        match socket.read().unwrap() {
+            Message::Text(t) => t,
+            m => panic!("Expected a text message, got {:?}", m),
+        }
+    }
+
+    #[test]
+    fn test_channel_routing() {
+        let (server, addr) = server();
+
+        let mut all = connect(&server, &addr, "/");
+        let mut safe = connect(&server, &addr, "/safe");
+        assert_eq!(server.num_clients(), 2);
+        assert!(server.has_subscribers());
+
+        server.send("mode", "m1");
+        server.send("safe", "s1");
+
+        assert_eq!(read_text(&mut all), "m1");
+        assert_eq!(read_text(&mut all), "s1");
+        assert_eq!(read_text(&mut safe), "s1");
+    }
+
+    #[test]
+    fn test_disconnected_client_is_removed() {
+        let (server, addr) = server();
+
+        let socket = connect(&server, &addr, "/");
+        drop(socket);
+
+        // The writer thread only notices the closed connection when it writes, so keep sending
+        let start = Instant::now();
+        while server.num_clients() > 0 {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "client was not removed"
+            );
+            server.send("safe", "false");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_slow_handshake_does_not_block_accept() {
+        let (server, addr) = server();
+
+        // A client which opens a connection but never finishes its request
+        let mut slow = TcpStream::connect(&addr).unwrap();
+        slow.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+
+        let mut fast = connect(&server, &addr, "/");
+        server.send("safe", "true");
+        assert_eq!(read_text(&mut fast), "true");
+
+        // The slow client is dropped once the handshake times out
+        let mut buf = [0u8; 16];
+        slow.set_read_timeout(Some(HANDSHAKE_TIMEOUT * 2)).unwrap();
+        assert!(matches!(slow.read(&mut buf), Ok(0) | Err(_)));
+    }
+
+    #[test]
+    fn test_send_does_not_block_on_slow_client() {
+        let (server, addr) = server();
+
+        let _socket = connect(&server, &addr, "/");
+
+        // The client never reads, but sending must not block even once its queue and the socket
+        // buffers are full. The client is dropped when a write times out.
+        let big = "x".repeat(64 * 1024);
+        let start = Instant::now();
+        for _ in 0..(CLIENT_QUEUE_LEN * 8) {
+            server.send("img", &big);
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,44 @@
+//! Build script for the utility library.
+//!
+//! Records the git commit and build profile for `util::build_info`. If git isn't available, for
+//! example when building from a source archive, the commit is recorded as `unknown`.
+
+use std::{path::PathBuf, process::Command};
+
+fn main() {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+
+    let git = |args: &[&str]| -> Option<String> {
+        Command::new("git")
+            .args(args)
+            .current_dir(&manifest_dir)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+
+    let hash = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".into());
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .map(|s| !s.is_empty())
+        .unwrap_or(false);
+
+    println!("cargo:rustc-env=PHOBOS_GIT_HASH={}", hash);
+    println!("cargo:rustc-env=PHOBOS_GIT_DIRTY={}", dirty);
+    println!(
+        "cargo:rustc-env=PHOBOS_BUILD_PROFILE={}",
+        std::env::var("PROFILE").unwrap_or_default()
+    );
+
+    // Rerun when the commit changes, which moves the current branch's ref, or the index changes
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        let git_dir = PathBuf::from(git_dir);
+        println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+        println!("cargo:rerun-if-changed={}", git_dir.join("index").display());
+
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}", git_dir.join(head_ref).display());
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
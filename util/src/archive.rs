@@ -1,20 +1,46 @@
 //! Struct archiving functionality
 //!
 //! To add archiving functionality to a struct implement the `Archive` trait.
+//!
+//! The archive root of each session contains a `format.json` header giving the version of the
+//! archive format, see [`crate::format`].
+//!
+//! Archives are read back with an [`ArchiveReader`], which deserialises each row into the type
+//! that wrote it, can select rows by time, and can convert the numeric columns into a matrix for
+//! analysis with [`ArchiveReader::into_columns`].
 
 // ---------------------------------------------------------------------------
 // IMPORTS
 // ---------------------------------------------------------------------------
 
 // External imports
+use std::marker::PhantomData;
 use std::path::Path;
 use std::fs::{File, OpenOptions};
-use csv::WriterBuilder;
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
 pub use csv::Writer;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
 
 // Internal imports
+use crate::format::{FormatError, FormatHeader};
 use crate::session::Session;
+use comms_if::tm::TmMeta;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Name of the archive format in its header.
+pub const FORMAT_NAME: &str = "archive";
+
+/// Version of the archive format, which shall be incremented when the layout
+/// of the CSV files changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Name of the column which rows are selected by in
+/// [`ArchiveReader::records_between`].
+pub const TIME_COLUMN: &str = "time_s";
 
 // ---------------------------------------------------------------------------
 // DATA STRUCTURES
@@ -32,6 +58,68 @@ struct Record<T: Serialize> {
     data: T
 }
 
+/// An object used to read CSV archive files into records of type `T`.
+///
+/// `T` is usually the type which was archived, with `Deserialize` derived as
+/// well as `Serialize`.
+pub struct ArchiveReader<T> {
+    reader: csv::Reader<File>,
+    columns: Vec<Column>,
+    _record: PhantomData<T>
+}
+
+/// A column of an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// Name of the column, which is the name of the archived field
+    pub name: String,
+
+    /// Unit of the column, if the archive was written with
+    /// `Archiver::from_path_with_meta`
+    pub unit: Option<String>
+}
+
+/// The numeric columns of an archive as a row-major matrix.
+///
+/// Booleans are stored as 0 or 1 and empty values (`None`) as NaN. The data
+/// can be used directly by other crates, for example with ndarray:
+///
+/// ```ignore
+/// let (rows, cols) = columns.shape();
+/// let matrix = Array2::from_shape_vec((rows, cols), columns.data)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Columns {
+    /// The columns, in the order they appear in each row of `data`
+    pub columns: Vec<Column>,
+
+    /// Number of rows
+    pub num_rows: usize,
+
+    /// The values, row by row
+    pub data: Vec<f64>
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Errors which can occur when reading an archive.
+#[derive(Debug, Error)]
+pub enum ArchiveReadError {
+    #[error("Cannot read the archive: {0}")]
+    CsvError(csv::Error),
+
+    #[error("The session's archive format is not supported: {0}")]
+    FormatError(FormatError),
+
+    #[error("The archive has no {0:?} column to select rows by time")]
+    NoTimeColumn(&'static str),
+
+    #[error("Invalid time {0:?} in the archive")]
+    InvalidTime(String)
+}
+
 // ---------------------------------------------------------------------------
 // TRAITS
 // ---------------------------------------------------------------------------
@@ -56,23 +144,36 @@ impl Archiver {
     pub fn from_path<P: AsRef<Path>>(
         session: &Session, path: P
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut session_path = session.arch_root.clone();
-        session_path.push(path);
-        
-        // Create the file if it does not exist
-        std::fs::File::create(session_path.clone())?;
-
-        // Open the file in append mode
-        let file = match OpenOptions::new()
-            .append(true).open(session_path)
-        {
-            Ok(f) => f,
-            Err(e) => return Err(Box::new(e))
-        };
-
         let w = WriterBuilder::new()
             .has_headers(true)
-            .from_writer(file);
+            .from_writer(open(session, path)?);
+
+        Ok(Self {
+            writer: Some(w)
+        })
+    }
+
+    /// Create a new archiver for records of type `T`, whose header includes the unit of each
+    /// column from the TM metadata of `T`, for example `speed_ms [m/s]`.
+    ///
+    /// `T` must be a flat struct, as are all records that can be written to CSV.
+    pub fn from_path_with_meta<T: TmMeta, P: AsRef<Path>>(
+        session: &Session, path: P
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let header: Vec<String> = T::tm_fields()
+            .iter()
+            .map(|f| match f.unit {
+                Some(u) => format!("{} [{}]", f.name, u),
+                None => f.name.to_string()
+            })
+            .collect();
+
+        // The header is written here, so the automatic one from the first record isn't needed
+        let mut w = WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(open(session, path)?);
+        w.write_record(&header)?;
+        w.flush()?;
 
         Ok(Self {
             writer: Some(w)
@@ -98,3 +199,220 @@ impl Archiver {
         Ok(())
     }
 }
+
+impl<T: DeserializeOwned> ArchiveReader<T> {
+    /// Open an archive file.
+    ///
+    /// Units in the header, such as `time_s [s]`, are separated from the
+    /// column names so that the columns match the fields of `T`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ArchiveReadError> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(path)
+            .map_err(ArchiveReadError::CsvError)?;
+
+        let columns: Vec<Column> = reader
+            .headers()
+            .map_err(ArchiveReadError::CsvError)?
+            .iter()
+            .map(Column::parse)
+            .collect();
+
+        reader.set_headers(columns.iter().map(|c| c.name.as_str()).collect());
+
+        Ok(Self {
+            reader,
+            columns,
+            _record: PhantomData
+        })
+    }
+
+    /// Open an archive file from a path relative to the archive root of the
+    /// session in `session_dir`, checking that the session's archive format
+    /// is supported by this build.
+    pub fn open_in_session<S: AsRef<Path>, P: AsRef<Path>>(
+        session_dir: S, path: P
+    ) -> Result<Self, ArchiveReadError> {
+        let arch_root = session_dir.as_ref().join("arch");
+
+        format_header()
+            .check(arch_root.join("format.json"))
+            .map_err(ArchiveReadError::FormatError)?;
+
+        Self::open(arch_root.join(path))
+    }
+
+    /// Get the columns of the archive.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Iterate over the remaining records in the archive.
+    pub fn records(
+        &mut self
+    ) -> impl Iterator<Item = Result<T, ArchiveReadError>> + '_ {
+        self.reader
+            .deserialize()
+            .map(|r| r.map_err(ArchiveReadError::CsvError))
+    }
+
+    /// Iterate over the remaining records whose time is at least `start_s`
+    /// and less than `end_s`.
+    ///
+    /// The archive must have a `time_s` column.
+    pub fn records_between(
+        &mut self, start_s: f64, end_s: f64
+    ) -> Result<
+        impl Iterator<Item = Result<T, ArchiveReadError>> + '_,
+        ArchiveReadError
+    > {
+        let time_col = self.columns
+            .iter()
+            .position(|c| c.name == TIME_COLUMN)
+            .ok_or(ArchiveReadError::NoTimeColumn(TIME_COLUMN))?;
+
+        let header = self.reader
+            .headers()
+            .map_err(ArchiveReadError::CsvError)?
+            .clone();
+
+        Ok(self.reader.records().filter_map(move |r| {
+            let row = match r {
+                Ok(row) => row,
+                Err(e) => return Some(Err(ArchiveReadError::CsvError(e)))
+            };
+
+            let time = row.get(time_col).unwrap_or("");
+            let time_s: f64 = match time.parse() {
+                Ok(t) => t,
+                Err(_) => return Some(Err(
+                    ArchiveReadError::InvalidTime(String::from(time))
+                ))
+            };
+
+            match time_s >= start_s && time_s < end_s {
+                true => Some(
+                    row.deserialize(Some(&header))
+                        .map_err(ArchiveReadError::CsvError)
+                ),
+                false => None
+            }
+        }))
+    }
+
+    /// Read the remaining rows into a matrix of the numeric columns.
+    ///
+    /// Columns holding any value which isn't a number, boolean or empty,
+    /// such as an enum, are left out.
+    pub fn into_columns(mut self) -> Result<Columns, ArchiveReadError> {
+        let rows = self.reader
+            .records()
+            .collect::<Result<Vec<StringRecord>, _>>()
+            .map_err(ArchiveReadError::CsvError)?;
+
+        // Convert each column, keeping only those which are entirely numeric
+        let numeric: Vec<(usize, Vec<f64>)> = (0..self.columns.len())
+            .filter_map(|i| {
+                rows.iter()
+                    .map(|row| parse_value(row.get(i).unwrap_or("")))
+                    .collect::<Option<Vec<f64>>>()
+                    .map(|values| (i, values))
+            })
+            .collect();
+
+        let mut data = Vec::with_capacity(rows.len() * numeric.len());
+        for row in 0..rows.len() {
+            data.extend(numeric.iter().map(|(_, values)| values[row]));
+        }
+
+        Ok(Columns {
+            columns: numeric
+                .iter()
+                .map(|(i, _)| self.columns[*i].clone())
+                .collect(),
+            num_rows: rows.len(),
+            data
+        })
+    }
+}
+
+impl Column {
+    /// Parse a column from its header, such as `speed_ms [m/s]`.
+    pub fn parse(header: &str) -> Self {
+        match header.strip_suffix(']').and_then(|h| h.rsplit_once(" [")) {
+            Some((name, unit)) => Self {
+                name: String::from(name),
+                unit: Some(String::from(unit))
+            },
+            None => Self {
+                name: String::from(header),
+                unit: None
+            }
+        }
+    }
+}
+
+impl Columns {
+    /// Get the shape of the matrix as (rows, columns).
+    pub fn shape(&self) -> (usize, usize) {
+        (self.num_rows, self.columns.len())
+    }
+
+    /// Get the values of a single column.
+    pub fn column(&self, name: &str) -> Option<Vec<f64>> {
+        let idx = self.columns.iter().position(|c| c.name == name)?;
+
+        Some(
+            self.data
+                .iter()
+                .skip(idx)
+                .step_by(self.columns.len())
+                .cloned()
+                .collect()
+        )
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Get the header of the archive format written by this build.
+pub fn format_header() -> FormatHeader {
+    FormatHeader::new(FORMAT_NAME, FORMAT_VERSION)
+}
+
+/// Read the numeric columns of an archive file, see
+/// [`ArchiveReader::into_columns`].
+pub fn read_columns<P: AsRef<Path>>(path: P) -> Result<Columns, ArchiveReadError> {
+    ArchiveReader::<()>::open(path)?.into_columns()
+}
+
+/// Parse a single archived value as a number.
+fn parse_value(value: &str) -> Option<f64> {
+    match value {
+        "" => Some(f64::NAN),
+        "true" => Some(1.0),
+        "false" => Some(0.0),
+        v => v.parse().ok()
+    }
+}
+
+/// Create and open an archive file from a path relative to the session's archive root.
+fn open<P: AsRef<Path>>(
+    session: &Session, path: P
+) -> Result<File, Box<dyn std::error::Error>> {
+    let mut session_path = session.arch_root.clone();
+    session_path.push(path);
+    
+    // Create the file if it does not exist
+    std::fs::File::create(session_path.clone())?;
+
+    // Open the file in append mode
+    match OpenOptions::new()
+        .append(true).open(session_path)
+    {
+        Ok(f) => Ok(f),
+        Err(e) => Err(Box::new(e))
+    }
+}
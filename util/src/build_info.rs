@@ -0,0 +1,100 @@
+//! Build information
+//!
+//! Identifies the build of the software which produced some data, so that data from different
+//! builds used during a test campaign can be told apart. The git commit and build profile are
+//! recorded at compile time by the build script. Cargo features belong to each executable, so are
+//! given by the executable when it creates its `BuildInfo`.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+// Internal imports
+use crate::format::{self, FormatError, FormatHeader};
+use crate::session::Session;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Short hash of the git commit the software was built from, or `unknown`.
+pub const GIT_HASH: &str = env!("PHOBOS_GIT_HASH");
+
+/// Cargo profile the software was built with, such as `debug` or `release`.
+pub const PROFILE: &str = env!("PHOBOS_BUILD_PROFILE");
+
+/// Name of the build information file format.
+pub const FORMAT_NAME: &str = "build_info";
+
+/// Version of the build information file format.
+pub const FORMAT_VERSION: u32 = 1;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Information on the build of an executable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// Name of the executable
+    pub exec_name: String,
+
+    /// Short hash of the git commit, see `GIT_HASH`
+    pub git_hash: String,
+
+    /// True if there were uncommitted changes to tracked files when the software was built
+    pub git_dirty: bool,
+
+    /// Cargo profile, see `PROFILE`
+    pub profile: String,
+
+    /// Cargo features enabled in the executable
+    pub features: Vec<String>,
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl BuildInfo {
+    /// Get the information on this build, given the names of the executable's enabled features.
+    pub fn new(exec_name: &str, features: &[&str]) -> Self {
+        Self {
+            exec_name: String::from(exec_name),
+            git_hash: String::from(GIT_HASH),
+            git_dirty: git_dirty(),
+            profile: String::from(PROFILE),
+            features: features.iter().map(|f| String::from(*f)).collect(),
+        }
+    }
+
+    /// Save the build information as `build_info.json` in the session root.
+    pub fn save(&self, session: &Session) -> Result<(), FormatError> {
+        format::save_json(
+            session.session_root.join("build_info.json"),
+            &FormatHeader::new(FORMAT_NAME, FORMAT_VERSION),
+            self,
+        )
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// True if there were uncommitted changes to tracked files when the software was built.
+pub fn git_dirty() -> bool {
+    env!("PHOBOS_GIT_DIRTY") == "true"
+}
+
+/// Get a short ID for this build, the git hash with `-dirty` appended if there were uncommitted
+/// changes.
+pub fn build_id() -> String {
+    match git_dirty() {
+        true => format!("{}-dirty", GIT_HASH),
+        false => String::from(GIT_HASH),
+    }
+}
@@ -0,0 +1,371 @@
+//! Control utilities
+//!
+//! Provides a PID controller for use by the rover's control modules. On top
+//! of the textbook controller it provides:
+//!
+//!  - Output limits, with flags reporting when the output is saturated.
+//!  - Back-calculation anti-windup, which bleeds off the integral while the
+//!    output is saturated so that the controller recovers quickly once the
+//!    error reduces.
+//!  - A first order low-pass filter on the derivative, to stop noise on the
+//!    error being amplified.
+//!  - Bumpless gain changes, so that retuning a running controller doesn't
+//!    cause a step in its output.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Gains and limits of a PID controller.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PidParams {
+    /// Proportional gain
+    pub k_p: f64,
+
+    /// Integral gain
+    pub k_i: f64,
+
+    /// Derivative gain
+    pub k_d: f64,
+
+    /// Minimum output, the output is saturated below this
+    #[serde(default = "neg_infinity")]
+    pub out_min: f64,
+
+    /// Maximum output, the output is saturated above this
+    #[serde(default = "infinity")]
+    pub out_max: f64,
+
+    /// Anti-windup tracking gain. Each second the integral is moved towards
+    /// the saturated output by this fraction of the amount the output was
+    /// saturated by. 0 disables anti-windup.
+    #[serde(default)]
+    pub k_aw: f64,
+
+    /// Time constant of the low-pass filter on the derivative, 0 for no
+    /// filtering.
+    #[serde(default)]
+    pub deriv_filter_tc_s: f64,
+}
+
+/// A PID controller.
+#[derive(Debug, Clone)]
+pub struct Pid {
+    params: PidParams,
+
+    /// The integral term, which already includes the integral gain so that
+    /// the gain can be changed without a step in the output
+    integral: f64,
+
+    /// The filtered derivative of the error
+    deriv: f64,
+
+    /// Error on the previous update
+    prev_error: Option<f64>,
+
+    /// Time of the previous update, used by `get`
+    prev_time: Option<Instant>,
+
+    /// Saturation of the last output
+    saturation: Saturation,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Saturation of a controller's output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Saturation {
+    #[default]
+    None,
+
+    /// The output was limited to `out_max`
+    Upper,
+
+    /// The output was limited to `out_min`
+    Lower,
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl PidParams {
+    /// Parameters with the given gains, no output limits, no anti-windup
+    /// and no derivative filtering.
+    pub fn new(k_p: f64, k_i: f64, k_d: f64) -> Self {
+        Self {
+            k_p,
+            k_i,
+            k_d,
+            out_min: f64::NEG_INFINITY,
+            out_max: f64::INFINITY,
+            k_aw: 0.0,
+            deriv_filter_tc_s: 0.0,
+        }
+    }
+}
+
+impl Pid {
+    /// Create a new controller with the given parameters.
+    pub fn new(params: PidParams) -> Self {
+        Self {
+            params,
+            integral: 0.0,
+            deriv: 0.0,
+            prev_error: None,
+            prev_time: None,
+            saturation: Saturation::None,
+        }
+    }
+
+    /// Get the output of the controller for the given error.
+    ///
+    /// This function is time-aware so there is no need to pass in a
+    /// delta-time value. On the first call there is no time difference, so
+    /// only the proportional term is used.
+    pub fn get(&mut self, error: f64) -> f64 {
+        let curr_time = Instant::now();
+
+        let dt_s = match self.prev_time {
+            Some(t0) => (curr_time - t0).as_secs_f64(),
+            None => 0.0,
+        };
+        self.prev_time = Some(curr_time);
+
+        self.update(error, dt_s)
+    }
+
+    /// Get the output of the controller for the given error, `dt_s` seconds
+    /// after the previous update.
+    ///
+    /// If `dt_s` isn't positive the integral and derivative aren't updated,
+    /// as dividing by a zero time step would produce a large spike.
+    pub fn update(&mut self, error: f64, dt_s: f64) -> f64 {
+        let p = &self.params;
+        let dt_valid = dt_s > 0.0 && dt_s.is_finite();
+
+        // Low-pass filter the derivative
+        if let (Some(e0), true) = (self.prev_error, dt_valid) {
+            let raw = (error - e0) / dt_s;
+            let alpha = dt_s / (p.deriv_filter_tc_s.max(0.0) + dt_s);
+            self.deriv += alpha * (raw - self.deriv);
+        }
+
+        let unsat = p.k_p * error + self.integral + p.k_d * self.deriv;
+        let out = unsat.max(p.out_min).min(p.out_max);
+
+        self.saturation = if unsat > p.out_max {
+            Saturation::Upper
+        } else if unsat < p.out_min {
+            Saturation::Lower
+        } else {
+            Saturation::None
+        };
+
+        // Accumulate the integral for the next update, with back-calculation
+        // pulling it back while the output is saturated
+        if dt_valid {
+            self.integral += (p.k_i * error + p.k_aw * (out - unsat)) * dt_s;
+        }
+
+        self.prev_error = Some(error);
+
+        out
+    }
+
+    /// Change the controller's parameters without a step in its output.
+    ///
+    /// The integral is adjusted to cancel the change in the proportional
+    /// term for the last error and in the derivative term for the current
+    /// derivative. A change in the integral gain only affects the error
+    /// accumulated from now on.
+    pub fn set_params(&mut self, params: PidParams) {
+        if let Some(e) = self.prev_error {
+            self.integral += (self.params.k_p - params.k_p) * e;
+        }
+        self.integral += (self.params.k_d - params.k_d) * self.deriv;
+
+        self.params = params;
+    }
+
+    /// Get the controller's parameters.
+    pub fn params(&self) -> &PidParams {
+        &self.params
+    }
+
+    /// Clear the integral, derivative and timing, as if the controller had
+    /// just been created.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.params);
+    }
+
+    /// Get the saturation of the last output.
+    pub fn saturation(&self) -> Saturation {
+        self.saturation
+    }
+
+    /// True if the last output was limited.
+    pub fn is_saturated(&self) -> bool {
+        self.saturation != Saturation::None
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+fn infinity() -> f64 {
+    f64::INFINITY
+}
+
+fn neg_infinity() -> f64 {
+    f64::NEG_INFINITY
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-9;
+
+    #[test]
+    fn test_proportional() {
+        let mut pid = Pid::new(PidParams::new(2.0, 0.0, 0.0));
+
+        assert!((pid.update(1.5, 0.1) - 3.0).abs() < EPS);
+        assert!((pid.update(-0.5, 0.1) + 1.0).abs() < EPS);
+        assert!(!pid.is_saturated());
+    }
+
+    #[test]
+    fn test_integral() {
+        let mut pid = Pid::new(PidParams::new(0.0, 1.0, 0.0));
+
+        // The integral is accumulated after the output, so lags one update
+        assert!(pid.update(2.0, 0.5).abs() < EPS);
+        assert!((pid.update(2.0, 0.5) - 1.0).abs() < EPS);
+        assert!((pid.update(2.0, 0.5) - 2.0).abs() < EPS);
+    }
+
+    #[test]
+    fn test_zero_dt_does_not_integrate() {
+        let mut pid = Pid::new(PidParams::new(0.0, 1.0, 1.0));
+
+        pid.update(1.0, 0.0);
+        pid.update(5.0, 0.0);
+        assert!(pid.update(5.0, -1.0).abs() < EPS);
+        assert!(pid.update(5.0, f64::NAN).abs() < EPS);
+    }
+
+    #[test]
+    fn test_derivative_filter() {
+        // Unfiltered the derivative follows the error's rate of change
+        let mut pid = Pid::new(PidParams::new(0.0, 0.0, 1.0));
+        pid.update(0.0, 0.1);
+        assert!((pid.update(1.0, 0.1) - 10.0).abs() < EPS);
+
+        // Filtered with a time constant equal to the time step it moves half
+        // way towards the raw derivative each update
+        let mut pid = Pid::new(PidParams {
+            deriv_filter_tc_s: 0.1,
+            ..PidParams::new(0.0, 0.0, 1.0)
+        });
+        pid.update(0.0, 0.1);
+        assert!((pid.update(1.0, 0.1) - 5.0).abs() < EPS);
+        assert!((pid.update(1.0, 0.1) - 2.5).abs() < EPS);
+    }
+
+    #[test]
+    fn test_saturation_flags() {
+        let mut pid = Pid::new(PidParams {
+            out_min: -1.0,
+            out_max: 2.0,
+            ..PidParams::new(1.0, 0.0, 0.0)
+        });
+
+        assert_eq!(pid.update(5.0, 0.1), 2.0);
+        assert_eq!(pid.saturation(), Saturation::Upper);
+
+        assert_eq!(pid.update(-5.0, 0.1), -1.0);
+        assert_eq!(pid.saturation(), Saturation::Lower);
+
+        assert!((pid.update(0.5, 0.1) - 0.5).abs() < EPS);
+        assert_eq!(pid.saturation(), Saturation::None);
+        assert!(!pid.is_saturated());
+    }
+
+    #[test]
+    fn test_anti_windup() {
+        let params = PidParams {
+            out_min: -1.0,
+            out_max: 1.0,
+            ..PidParams::new(1.0, 1.0, 0.0)
+        };
+        let mut windup = Pid::new(params);
+        let mut no_windup = Pid::new(PidParams { k_aw: 1.0, ..params });
+
+        // Hold a large error so that both outputs saturate
+        for _ in 0..100 {
+            windup.update(5.0, 0.1);
+            no_windup.update(5.0, 0.1);
+        }
+
+        // Once the error reverses the controller with anti-windup leaves
+        // saturation straight away, the other is still unwinding
+        assert_eq!(windup.update(-0.5, 0.1), 1.0);
+        assert!(no_windup.update(-0.5, 0.1) < 1.0);
+        assert!(!no_windup.is_saturated());
+    }
+
+    #[test]
+    fn test_bumpless_gain_change() {
+        let mut pid = Pid::new(PidParams::new(1.0, 0.5, 0.0));
+        pid.update(2.0, 0.1);
+        let before = pid.update(2.0, 0.0);
+
+        pid.set_params(PidParams::new(3.0, 0.5, 0.0));
+
+        // For the same error the output is unchanged by the new gain
+        assert!((pid.update(2.0, 0.0) - before).abs() < EPS);
+        assert_eq!(pid.params().k_p, 3.0);
+    }
+
+    #[test]
+    fn test_bumpless_derivative_gain_change() {
+        let mut pid = Pid::new(PidParams::new(1.0, 0.0, 0.5));
+        pid.update(0.0, 0.1);
+        let before = pid.update(2.0, 0.1);
+
+        pid.set_params(PidParams::new(1.0, 0.0, 2.0));
+
+        // The derivative is unchanged when dt is zero, so the output must be
+        // too
+        assert!((pid.update(2.0, 0.0) - before).abs() < EPS);
+        assert_eq!(pid.params().k_d, 2.0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut pid = Pid::new(PidParams::new(1.0, 1.0, 1.0));
+        pid.update(1.0, 0.1);
+        pid.update(2.0, 0.1);
+
+        pid.reset();
+
+        // Only the proportional term remains
+        assert!((pid.update(3.0, 0.1) - 3.0).abs() < EPS);
+    }
+}
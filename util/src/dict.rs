@@ -0,0 +1,365 @@
+//! # Command and Telemetry Dictionary
+//!
+//! Generates a dictionary of every telecommand and every telemetry field, which mission
+//! operations use to write procedures and configure ground displays.
+//!
+//! TCs are read from the structopt definition of [`Tc`], so their descriptions are the doc
+//! comments on the TC enums. TM fields are found by walking a serialised sample of the telemetry
+//! packet, and are described by the packet's [`TmMeta`]. Units which aren't given explicitly are
+//! inferred from the unit suffix naming convention used throughout the software, for example
+//! `speed_ms` is in metres/second and `pos_x_m_lm` in metres.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use structopt::{
+    clap::{App, ArgSettings},
+    StructOpt,
+};
+
+use comms_if::{
+    tc::Tc,
+    tm::{TmFieldMeta, TmMeta},
+};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Dictionary of all TCs and TM fields.
+#[derive(Debug, Clone, Serialize)]
+pub struct Dictionary {
+    pub tc: Vec<TcEntry>,
+
+    pub tm: Vec<TmEntry>,
+}
+
+/// A single TC, as it would be typed into the command line.
+#[derive(Debug, Clone, Serialize)]
+pub struct TcEntry {
+    /// The full command, for example `mnvr ack`
+    pub command: String,
+
+    pub desc: String,
+
+    pub args: Vec<TcArg>,
+}
+
+/// An argument of a TC.
+#[derive(Debug, Clone, Serialize)]
+pub struct TcArg {
+    /// Name of the argument, which for flags and options includes the leading `--`
+    pub name: String,
+
+    pub kind: ArgKind,
+
+    pub required: bool,
+
+    pub unit: Option<String>,
+
+    /// Allowed values of the argument, if it's restricted
+    pub values: Vec<String>,
+
+    pub desc: String,
+}
+
+/// A single field of the TM packet.
+#[derive(Debug, Clone, Serialize)]
+pub struct TmEntry {
+    /// Path to the field in the packet, for example `loco_ctrl_output.pos_rad`
+    pub field: String,
+
+    #[serde(rename = "type")]
+    pub ty: String,
+
+    pub unit: Option<String>,
+
+    pub desc: String,
+}
+
+/// One row of the TC dictionary CSV, which has one row per argument.
+#[derive(Serialize)]
+struct TcRow<'a> {
+    command: &'a str,
+    arg: &'a str,
+    kind: Option<ArgKind>,
+    required: Option<bool>,
+    unit: Option<&'a str>,
+    values: String,
+    desc: &'a str,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// How an argument is given on the command line.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum ArgKind {
+    Positional,
+    Flag,
+    Option,
+}
+
+/// Errors that can occur while generating or writing the dictionary.
+#[derive(Debug, thiserror::Error)]
+pub enum DictError {
+    #[error("Could not serialise the TM sample: {0}")]
+    TmSampleError(serde_json::Error),
+
+    #[error("Could not serialise the dictionary: {0}")]
+    JsonError(serde_json::Error),
+
+    #[error("Could not write the CSV dictionary: {0}")]
+    CsvError(csv::Error),
+
+    #[error("Could not write the dictionary: {0}")]
+    WriteError(std::io::Error),
+}
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Unit suffixes of field names, longest first so that `_rads` isn't taken as `_s`.
+const UNIT_SUFFIXES: [(&str, &str); 7] = [
+    ("_rads", "rad/s"),
+    ("_rad", "rad"),
+    ("_ms", "m/s"),
+    ("_hz", "Hz"),
+    ("_m", "m"),
+    ("_s", "s"),
+    ("_a", "A"),
+];
+
+/// Reference frame suffixes, which follow the unit suffix.
+const FRAME_SUFFIXES: [&str; 2] = ["_lm", "_rb"];
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl Dictionary {
+    /// Generate the dictionary, using the given TM packet as a sample of the telemetry.
+    ///
+    /// Collections in the sample that are empty can't be described beyond their type, so the
+    /// sample should be as fully populated as possible.
+    pub fn new<T: Serialize + TmMeta>(tm_sample: &T) -> Result<Self, DictError> {
+        let mut tc = Vec::new();
+        walk_tc(&Tc::clap(), "", &mut tc);
+
+        let sample = serde_json::to_value(tm_sample).map_err(DictError::TmSampleError)?;
+        let mut tm = Vec::new();
+        walk_tm(&sample, "", None, &T::tm_fields(), &mut tm);
+
+        Ok(Self { tc, tm })
+    }
+
+    /// Write the dictionary as a single JSON file.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<(), DictError> {
+        let json = serde_json::to_string_pretty(self).map_err(DictError::JsonError)?;
+        std::fs::write(path, json).map_err(DictError::WriteError)
+    }
+
+    /// Write the dictionary as `tc_dictionary.csv` and `tm_dictionary.csv` in the given
+    /// directory.
+    pub fn write_csv<P: AsRef<Path>>(&self, dir: P) -> Result<(), DictError> {
+        let dir = dir.as_ref();
+
+        let mut w = csv::Writer::from_path(dir.join("tc_dictionary.csv"))
+            .map_err(DictError::CsvError)?;
+        for entry in self.tc.iter() {
+            // Commands without arguments still get a row
+            if entry.args.is_empty() {
+                w.serialize(TcRow {
+                    command: &entry.command,
+                    arg: "",
+                    kind: None,
+                    required: None,
+                    unit: None,
+                    values: String::new(),
+                    desc: &entry.desc,
+                })
+                .map_err(DictError::CsvError)?;
+            }
+
+            for arg in entry.args.iter() {
+                w.serialize(TcRow {
+                    command: &entry.command,
+                    arg: &arg.name,
+                    kind: Some(arg.kind),
+                    required: Some(arg.required),
+                    unit: arg.unit.as_deref(),
+                    values: arg.values.join("|"),
+                    desc: &arg.desc,
+                })
+                .map_err(DictError::CsvError)?;
+            }
+        }
+        w.flush().map_err(DictError::WriteError)?;
+
+        let mut w = csv::Writer::from_path(dir.join("tm_dictionary.csv"))
+            .map_err(DictError::CsvError)?;
+        for entry in self.tm.iter() {
+            w.serialize(entry).map_err(DictError::CsvError)?;
+        }
+        w.flush().map_err(DictError::WriteError)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Infer the unit of a field or argument from the suffix of its name.
+pub fn unit_from_name(name: &str) -> Option<&'static str> {
+    let name = name.trim_start_matches('-').replace('-', "_");
+
+    // Curvatures use the suffix of the length they're the inverse of
+    if name.starts_with("curv") {
+        return Some("1/m");
+    }
+
+    let name = FRAME_SUFFIXES
+        .iter()
+        .find_map(|f| name.strip_suffix(f))
+        .unwrap_or(&name);
+
+    UNIT_SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|(_, unit)| *unit)
+}
+
+/// Add an entry for each command without subcommands in the given app.
+///
+/// clap 2 has no public API for inspecting an app, so this reads the parser's fields directly.
+fn walk_tc(app: &App, prefix: &str, entries: &mut Vec<TcEntry>) {
+    let p = &app.p;
+
+    for sub in p.subcommands.iter() {
+        let command = match prefix.is_empty() {
+            true => sub.p.meta.name.clone(),
+            false => format!("{} {}", prefix, sub.p.meta.name),
+        };
+
+        if !sub.p.subcommands.is_empty() {
+            walk_tc(sub, &command, entries);
+            continue;
+        }
+
+        let sp = &sub.p;
+        let mut args = Vec::new();
+
+        for pos in sp.positionals.values() {
+            args.push(TcArg {
+                name: pos.b.name.to_string(),
+                kind: ArgKind::Positional,
+                required: pos.b.settings.is_set(ArgSettings::Required),
+                unit: unit_from_name(pos.b.name).map(String::from),
+                values: possible_values(&pos.v.possible_vals),
+                desc: arg_desc(pos.b.long_help, pos.b.help),
+            });
+        }
+
+        for opt in sp.opts.iter() {
+            let name = format!("--{}", opt.s.long.unwrap_or(opt.b.name));
+            args.push(TcArg {
+                unit: unit_from_name(&name).map(String::from),
+                name,
+                kind: ArgKind::Option,
+                required: opt.b.settings.is_set(ArgSettings::Required),
+                values: possible_values(&opt.v.possible_vals),
+                desc: arg_desc(opt.b.long_help, opt.b.help),
+            });
+        }
+
+        for flag in sp.flags.iter().filter(|f| !["help", "version"].contains(&f.b.name)) {
+            args.push(TcArg {
+                name: format!("--{}", flag.s.long.unwrap_or(flag.b.name)),
+                kind: ArgKind::Flag,
+                required: false,
+                unit: None,
+                values: Vec::new(),
+                desc: arg_desc(flag.b.long_help, flag.b.help),
+            });
+        }
+
+        entries.push(TcEntry {
+            command,
+            desc: arg_desc(sp.meta.long_about, sp.meta.about),
+            args,
+        });
+    }
+}
+
+/// Add an entry for each leaf field of the given TM value.
+///
+/// `meta` is the metadata of the value itself, and `fields` that of its fields, if they're known.
+fn walk_tm(
+    value: &Value,
+    path: &str,
+    meta: Option<&TmFieldMeta>,
+    fields: &[TmFieldMeta],
+    entries: &mut Vec<TmEntry>,
+) {
+    let ty = match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, v) in map.iter() {
+                let child = match path.is_empty() {
+                    true => key.clone(),
+                    false => format!("{}.{}", path, key),
+                };
+                let child_meta = fields.iter().find(|f| f.name == key);
+                let child_fields = child_meta.map(|m| m.fields.as_slice()).unwrap_or_default();
+                walk_tm(v, &child, child_meta, child_fields, entries);
+            }
+            return;
+        }
+        Value::Object(_) => "map".to_string(),
+        Value::Array(items) => match items.first() {
+            Some(v) => format!("array<{}>", json_type(v)),
+            None => "array".to_string(),
+        },
+        v => json_type(v).to_string(),
+    };
+
+    // Without metadata the unit comes from the name of the field itself
+    let name = path.rsplit('.').next().unwrap_or(path);
+
+    entries.push(TmEntry {
+        field: path.to_string(),
+        ty,
+        unit: meta
+            .and_then(|m| m.unit)
+            .or_else(|| unit_from_name(name))
+            .map(String::from),
+        desc: meta.map(|m| m.desc.to_string()).unwrap_or_default(),
+    });
+}
+
+/// Get the name of the type of a JSON value.
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "optional",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Get the description of an argument or command, preferring the long help.
+fn arg_desc(long: Option<&str>, short: Option<&str>) -> String {
+    long.or(short).unwrap_or_default().to_string()
+}
+
+fn possible_values(vals: &Option<Vec<&str>>) -> Vec<String> {
+    vals.iter().flatten().map(|v| v.to_string()).collect()
+}
@@ -0,0 +1,208 @@
+//! Versioned on-disk formats
+//!
+//! Artefacts saved during a session carry a header naming their format and its version, so that
+//! post-processing tools can reject data saved by an incompatible build rather than silently
+//! misinterpreting it. JSON artefacts are wrapped as `{"format": .., "version": .., "data": ..}`.
+//! Formats which can't hold a header, such as CSV archives, have a `format.json` file containing
+//! only the header saved alongside them. Headers also record the build and session which saved
+//! the artefact, see [`crate::build_info`].
+//!
+//! When the layout of an artefact changes its version constant shall be incremented. Loading
+//! data of an older version may be supported by passing a migration to [`load_json_migrate`].
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External imports
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+// Internal imports
+use crate::{build_info, session};
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Identifies the format of a saved artefact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatHeader {
+    /// Name of the format, for example `self_test_report`
+    pub format: String,
+
+    /// Version of the format, incremented whenever its layout changes
+    pub version: u32,
+
+    /// ID of the build which saved the artefact, see `build_info::build_id`
+    #[serde(default)]
+    pub build_id: Option<String>,
+
+    /// ID of the session the artefact was saved in, if any
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// An artefact wrapped with its format header.
+#[derive(Serialize)]
+struct VersionedRef<'a, T: Serialize> {
+    #[serde(flatten)]
+    header: &'a FormatHeader,
+    data: &'a T,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Errors which can occur when saving or loading a versioned artefact.
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("Cannot read the file: {0}")]
+    ReadError(std::io::Error),
+
+    #[error("Cannot write the file: {0}")]
+    WriteError(std::io::Error),
+
+    #[error("Cannot serialize or deserialize the data: {0}")]
+    JsonError(serde_json::Error),
+
+    #[error(
+        "The file has no format header, it was probably saved by an older build which did not \
+         version its artefacts")]
+    MissingHeader,
+
+    #[error("Expected a {expected:?} file but found a {found:?} file")]
+    WrongFormat { expected: String, found: String },
+
+    #[error(
+        "Version {found} of the {format:?} format is not supported, this build supports version \
+         {supported}")]
+    UnsupportedVersion { format: String, found: u32, supported: u32 },
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl FormatHeader {
+    /// Create a new header for an artefact saved by this build in the current session.
+    pub fn new(format: &str, version: u32) -> Self {
+        Self {
+            format: String::from(format),
+            version,
+            build_id: Some(build_info::build_id()),
+            session_id: session::get_session_id().map(String::from),
+        }
+    }
+
+    /// Save only the header to the given path, for artefacts which can't contain it.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FormatError> {
+        let json = serde_json::to_string_pretty(self).map_err(FormatError::JsonError)?;
+        fs::write(path, json).map_err(FormatError::WriteError)
+    }
+
+    /// Load a header saved with `save`, checking that it matches this one.
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> Result<(), FormatError> {
+        let json = fs::read_to_string(path).map_err(FormatError::ReadError)?;
+        let value: Value = serde_json::from_str(&json).map_err(FormatError::JsonError)?;
+
+        let found = read_header(&value)?;
+        self.check_format(&found)?;
+
+        match found.version == self.version {
+            true => Ok(()),
+            false => Err(self.unsupported(found.version)),
+        }
+    }
+
+    fn check_format(&self, found: &FormatHeader) -> Result<(), FormatError> {
+        match found.format == self.format {
+            true => Ok(()),
+            false => Err(FormatError::WrongFormat {
+                expected: self.format.clone(),
+                found: found.format.clone(),
+            }),
+        }
+    }
+
+    fn unsupported(&self, found: u32) -> FormatError {
+        FormatError::UnsupportedVersion {
+            format: self.format.clone(),
+            found,
+            supported: self.version,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Save the data as JSON with the given format header.
+pub fn save_json<T: Serialize, P: AsRef<Path>>(
+    path: P,
+    header: &FormatHeader,
+    data: &T,
+) -> Result<(), FormatError> {
+    let json = serde_json::to_string_pretty(&VersionedRef { header, data })
+        .map_err(FormatError::JsonError)?;
+
+    fs::write(path, json).map_err(FormatError::WriteError)
+}
+
+/// Load JSON data saved with `save_json`, rejecting it if its header doesn't match.
+pub fn load_json<T: DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    header: &FormatHeader,
+) -> Result<T, FormatError> {
+    load_json_migrate(path, header, |_, _| None)
+}
+
+/// Load JSON data saved with `save_json`, migrating older versions to the current one.
+///
+/// `migrate` is given the version of the file and its data, and shall return the data converted
+/// to the current version, or `None` if that version can't be migrated.
+pub fn load_json_migrate<T, P, F>(
+    path: P,
+    header: &FormatHeader,
+    migrate: F,
+) -> Result<T, FormatError>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+    F: FnOnce(u32, Value) -> Option<Value>,
+{
+    let json = fs::read_to_string(path).map_err(FormatError::ReadError)?;
+    let mut value: Value = serde_json::from_str(&json).map_err(FormatError::JsonError)?;
+
+    let found = read_header(&value)?;
+    header.check_format(&found)?;
+
+    let data = value
+        .get_mut("data")
+        .map(Value::take)
+        .ok_or(FormatError::MissingHeader)?;
+
+    let data = if found.version == header.version {
+        data
+    } else if found.version < header.version {
+        migrate(found.version, data).ok_or_else(|| header.unsupported(found.version))?
+    } else {
+        return Err(header.unsupported(found.version));
+    };
+
+    serde_json::from_value(data).map_err(FormatError::JsonError)
+}
+
+// ---------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Read the format header fields from a JSON value.
+fn read_header(value: &Value) -> Result<FormatHeader, FormatError> {
+    FormatHeader::deserialize(value).map_err(|_| FormatError::MissingHeader)
+}
@@ -15,4 +15,82 @@ pub fn get_phobos_sw_root() -> Result<PathBuf, std::env::VarError> {
         Ok(s) => Ok(s.into()),
         Err(e) => Err(e)
     }
-}
\ No newline at end of file
+}
+
+/// Errors that can occur when changing how the current thread is scheduled.
+#[derive(Debug, thiserror::Error)]
+pub enum SchedError {
+    #[error("Real-time scheduling is only supported on Linux")]
+    Unsupported,
+
+    #[error("Priority {0} is outside the allowed SCHED_FIFO range of {1} to {2}")]
+    InvalidPriority(i32, i32, i32),
+
+    #[error("Core {0} does not exist, this host has {1} cores")]
+    InvalidCore(usize, usize),
+
+    #[error("The OS refused the request: {0}")]
+    OsError(std::io::Error),
+}
+
+/// Switch the calling thread to the `SCHED_FIFO` real-time policy with the given priority.
+///
+/// This usually requires root or the `CAP_SYS_NICE` capability, if it is not permitted an
+/// `OsError` is returned and the thread's scheduling is left unchanged.
+#[cfg(target_os = "linux")]
+pub fn set_fifo_priority(priority: i32) -> Result<(), SchedError> {
+    // Safety: these functions have no preconditions, and the param struct is fully initialised
+    // before being passed by pointer.
+    unsafe {
+        let min = libc::sched_get_priority_min(libc::SCHED_FIFO);
+        let max = libc::sched_get_priority_max(libc::SCHED_FIFO);
+        if priority < min || priority > max {
+            return Err(SchedError::InvalidPriority(priority, min, max));
+        }
+
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        match libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) {
+            0 => Ok(()),
+            _ => Err(SchedError::OsError(std::io::Error::last_os_error())),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_fifo_priority(_priority: i32) -> Result<(), SchedError> {
+    Err(SchedError::Unsupported)
+}
+
+/// Pin the calling thread to a single CPU core.
+///
+/// Threads spawned by the calling thread afterwards inherit this affinity, so call this after any
+/// worker threads which should be free to run on other cores have been started.
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(core: usize) -> Result<(), SchedError> {
+    let num_cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    if core >= num_cores {
+        return Err(SchedError::InvalidCore(core, num_cores));
+    }
+
+    // Safety: the cpu set is zero initialised before use and only modified through the libc
+    // macros, and the core index has been checked above.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(core, &mut set);
+
+        match libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) {
+            0 => Ok(()),
+            _ => Err(SchedError::OsError(std::io::Error::last_os_error())),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(_core: usize) -> Result<(), SchedError> {
+    Err(SchedError::Unsupported)
+}
@@ -5,6 +5,10 @@
 // ---------------------------------------------------------------------------
 
 pub mod archive;
+pub mod build_info;
+pub mod control;
+pub mod dict;
+pub mod format;
 pub mod host;
 #[macro_use]
 pub mod logger;
@@ -12,6 +16,7 @@ pub mod maths;
 pub mod module;
 pub mod params;
 pub mod session;
+pub mod startup;
 pub mod script_interpreter;
 pub mod time;
 
@@ -10,7 +10,7 @@ use fern;
 use colored::{ColoredString, Colorize};
 
 // Internal imports
-use crate::session;
+use crate::{build_info, session};
 
 // Re-exports
 pub use log::LevelFilter;
@@ -79,6 +79,8 @@ pub fn logger_init(
     
     info!("Logging initialised");
     info!("    Session epoch: {}", session::get_epoch());
+    info!("    Session ID: {}", session.session_id);
+    info!("    Build: {} ({})", build_info::build_id(), build_info::PROFILE);
     info!("    Log level: {:?}", min_level);
     info!("    Log file path: {:?}", session.log_file_path);
 
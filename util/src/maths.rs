@@ -69,4 +69,297 @@ where
     }
 
     ret
-}
\ No newline at end of file
+}
+
+/// Wrap an angle into the range [-pi, pi).
+pub fn wrap_angle_rad<T>(angle_rad: T) -> T
+where
+    T: Float
+{
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let two_pi = pi + pi;
+
+    let wrapped = (angle_rad + pi) % two_pi;
+
+    // The remainder takes the sign of the dividend, so shift negative results
+    // back into range
+    if wrapped < T::zero() {
+        wrapped + pi
+    } else {
+        wrapped - pi
+    }
+}
+
+/// Return the shortest signed difference `to - from` between two angles,
+/// in the range [-pi, pi).
+pub fn angle_diff_rad<T>(to_rad: T, from_rad: T) -> T
+where
+    T: Float
+{
+    wrap_angle_rad(to_rad - from_rad)
+}
+
+/// Return the distance from a point to the segment between `start` and
+/// `end`.
+pub fn dist_to_segment(point: &[f64; 2], start: &[f64; 2], end: &[f64; 2]) -> f64 {
+    let seg = [end[0] - start[0], end[1] - start[1]];
+    let len_sq = seg[0] * seg[0] + seg[1] * seg[1];
+
+    // Fraction along the segment of the closest point, which is the start
+    // for zero length segments
+    let t = match len_sq > 0.0 {
+        true => (((point[0] - start[0]) * seg[0] + (point[1] - start[1]) * seg[1])
+            / len_sq).clamp(0.0, 1.0),
+        false => 0.0
+    };
+
+    let closest = [start[0] + t * seg[0], start[1] + t * seg[1]];
+
+    (point[0] - closest[0]).hypot(point[1] - closest[1])
+}
+
+/// Return the point at which the segments `a_0 -> a_1` and `b_0 -> b_1`
+/// intersect, or `None` if they don't.
+///
+/// Parallel segments are treated as not intersecting, even if they overlap.
+pub fn segment_intersection(
+    a_0: &[f64; 2],
+    a_1: &[f64; 2],
+    b_0: &[f64; 2],
+    b_1: &[f64; 2]
+) -> Option<[f64; 2]> {
+    let a = [a_1[0] - a_0[0], a_1[1] - a_0[1]];
+    let b = [b_1[0] - b_0[0], b_1[1] - b_0[1]];
+
+    let denom = a[0] * b[1] - a[1] * b[0];
+    if denom == 0.0 {
+        return None;
+    }
+
+    // Fractions along each segment of the intersection of the lines
+    let d = [b_0[0] - a_0[0], b_0[1] - a_0[1]];
+    let t = (d[0] * b[1] - d[1] * b[0]) / denom;
+    let u = (d[0] * a[1] - d[1] * a[0]) / denom;
+
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some([a_0[0] + t * a[0], a_0[1] + t * a[1]])
+    } else {
+        None
+    }
+}
+
+/// Return `true` if the point is inside the polygon with the given
+/// vertices.
+///
+/// The polygon is closed automatically, and may be concave. Points exactly on
+/// an edge may be reported as inside or outside.
+pub fn point_in_polygon(point: &[f64; 2], vertices: &[[f64; 2]]) -> bool {
+    let mut inside = false;
+
+    // Count the edges crossed by a ray from the point in the +x direction
+    for (i, v_i) in vertices.iter().enumerate() {
+        let v_j = &vertices[(i + vertices.len() - 1) % vertices.len()];
+
+        if (v_i[1] > point[1]) != (v_j[1] > point[1]) {
+            let x_cross = v_i[0]
+                + (point[1] - v_i[1]) * (v_j[0] - v_i[0]) / (v_j[1] - v_i[1]);
+
+            if point[0] < x_cross {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+/// Running mean and variance of a series of values, calculated with
+/// Welford's algorithm so that the values don't need to be stored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+
+    /// Sum of the squared differences from the mean
+    m2: f64,
+
+    min: f64,
+    max: f64,
+}
+
+impl RunningStats {
+    /// Create an empty set of statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a value.
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+
+        self.count += 1;
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Number of values added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Mean of the values, or `None` if there are none.
+    pub fn mean(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            _ => Some(self.mean)
+        }
+    }
+
+    /// Sample variance of the values, or `None` if there are fewer than two.
+    pub fn variance(&self) -> Option<f64> {
+        match self.count {
+            0 | 1 => None,
+            n => Some(self.m2 / (n - 1) as f64)
+        }
+    }
+
+    /// Sample standard deviation of the values, or `None` if there are fewer
+    /// than two.
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// Smallest value, or `None` if there are none.
+    pub fn min(&self) -> Option<f64> {
+        self.mean().map(|_| self.min)
+    }
+
+    /// Largest value, or `None` if there are none.
+    pub fn max(&self) -> Option<f64> {
+        self.mean().map(|_| self.max)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const EPS: f64 = 1e-9;
+
+    #[test]
+    fn test_wrap_angle() {
+        assert!(wrap_angle_rad(0.0f64).abs() < EPS);
+        assert!((wrap_angle_rad(1.0f64) - 1.0).abs() < EPS);
+        assert!((wrap_angle_rad(-1.0f64) + 1.0).abs() < EPS);
+
+        // The range is [-pi, pi), so pi wraps to -pi
+        assert!((wrap_angle_rad(PI) + PI).abs() < EPS);
+        assert!((wrap_angle_rad(-PI) + PI).abs() < EPS);
+
+        // Multiple turns in either direction
+        assert!((wrap_angle_rad(2.0 * PI + 0.5) - 0.5).abs() < EPS);
+        assert!((wrap_angle_rad(-4.0 * PI - 0.5) + 0.5).abs() < EPS);
+        assert!((wrap_angle_rad(1.5 * PI) + 0.5 * PI).abs() < EPS);
+        assert!((wrap_angle_rad(-1.5 * PI) - 0.5 * PI).abs() < EPS);
+
+        // Also works for f32
+        assert!((wrap_angle_rad(3.0 * std::f32::consts::PI) + std::f32::consts::PI).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_angle_diff() {
+        assert!(angle_diff_rad(1.0f64, 1.0).abs() < EPS);
+        assert!((angle_diff_rad(1.0f64, 0.5) - 0.5).abs() < EPS);
+        assert!((angle_diff_rad(0.5f64, 1.0) + 0.5).abs() < EPS);
+
+        // The shortest way round crosses +/-pi
+        assert!((angle_diff_rad(-PI + 0.1, PI - 0.1) - 0.2).abs() < EPS);
+        assert!((angle_diff_rad(PI - 0.1, -PI + 0.1) + 0.2).abs() < EPS);
+    }
+
+    #[test]
+    fn test_dist_to_segment() {
+        let (start, end) = ([0.0, 0.0], [2.0, 0.0]);
+
+        // Beside, beyond each end, and on the segment
+        assert!((dist_to_segment(&[1.0, 1.0], &start, &end) - 1.0).abs() < EPS);
+        assert!((dist_to_segment(&[-3.0, 4.0], &start, &end) - 5.0).abs() < EPS);
+        assert!((dist_to_segment(&[3.0, -1.0], &start, &end) - 2f64.sqrt()).abs() < EPS);
+        assert!(dist_to_segment(&[1.5, 0.0], &start, &end).abs() < EPS);
+
+        // Zero length segment is a point
+        assert!((dist_to_segment(&[3.0, 4.0], &start, &start) - 5.0).abs() < EPS);
+    }
+
+    #[test]
+    fn test_segment_intersection() {
+        let p = segment_intersection(&[0.0, 0.0], &[2.0, 2.0], &[0.0, 2.0], &[2.0, 0.0]).unwrap();
+        assert!((p[0] - 1.0).abs() < EPS && (p[1] - 1.0).abs() < EPS);
+
+        // Touching at an end counts
+        let p = segment_intersection(&[0.0, 0.0], &[1.0, 0.0], &[1.0, -1.0], &[1.0, 1.0]).unwrap();
+        assert!((p[0] - 1.0).abs() < EPS && p[1].abs() < EPS);
+
+        // The lines cross but the segments don't
+        assert!(segment_intersection(&[0.0, 0.0], &[1.0, 0.0], &[2.0, -1.0], &[2.0, 1.0]).is_none());
+
+        // Parallel and collinear segments
+        assert!(segment_intersection(&[0.0, 0.0], &[1.0, 0.0], &[0.0, 1.0], &[1.0, 1.0]).is_none());
+        assert!(segment_intersection(&[0.0, 0.0], &[2.0, 0.0], &[1.0, 0.0], &[3.0, 0.0]).is_none());
+    }
+
+    #[test]
+    fn test_point_in_polygon() {
+        // Concave "L" shape
+        let poly = [[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 2.0], [0.0, 2.0]];
+
+        assert!(point_in_polygon(&[0.5, 0.5], &poly));
+        assert!(point_in_polygon(&[0.5, 1.5], &poly));
+        assert!(point_in_polygon(&[1.5, 0.5], &poly));
+        assert!(!point_in_polygon(&[1.5, 1.5], &poly));
+        assert!(!point_in_polygon(&[-0.5, 0.5], &poly));
+        assert!(!point_in_polygon(&[0.5, -0.5], &poly));
+
+        // Degenerate polygons contain nothing
+        assert!(!point_in_polygon(&[0.0, 0.0], &[]));
+        assert!(!point_in_polygon(&[0.0, 0.0], &[[0.0, 0.0]]));
+    }
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::new();
+        assert_eq!(stats.count(), 0);
+        assert!(stats.mean().is_none());
+        assert!(stats.variance().is_none());
+        assert!(stats.min().is_none());
+
+        stats.push(-2.0);
+        assert_eq!(stats.mean(), Some(-2.0));
+        assert!(stats.variance().is_none());
+        assert_eq!(stats.min(), Some(-2.0));
+        assert_eq!(stats.max(), Some(-2.0));
+
+        for v in [4.0, 0.0, 6.0] {
+            stats.push(v);
+        }
+        assert_eq!(stats.count(), 4);
+        assert!((stats.mean().unwrap() - 2.0).abs() < EPS);
+        assert!((stats.variance().unwrap() - 40.0 / 3.0).abs() < EPS);
+        assert!((stats.std_dev().unwrap() - (40.0f64 / 3.0).sqrt()).abs() < EPS);
+        assert_eq!(stats.min(), Some(-2.0));
+        assert_eq!(stats.max(), Some(6.0));
+    }
+}
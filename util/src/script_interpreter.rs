@@ -11,10 +11,9 @@
 use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
 use std::fs;
-use regex::RegexBuilder;
 
 // Internal
-use comms_if::tc::{Tc, TcParseError};
+use comms_if::tc::{script::{self, ScriptParseError}, Tc};
 use crate::session::get_elapsed_seconds;
 
 // ---------------------------------------------------------------------------
@@ -51,16 +50,8 @@ pub enum ScriptError {
     #[error("Could not load the script: {0}")]
     ScriptLoadError(std::io::Error),
 
-    #[error("The script is empty (or is so bad it can't be read)")]
-    ScriptEmpty,
-
-    #[error(
-        "Script contains an invalid timestamp: {0}. \
-        Should be a float (like 1.0)")]
-    InvalidTimestamp(String),
-
-    #[error("Script contains an invalid TC at {0} s: {1}")]
-    InvalidTc(f64, TcParseError)
+    #[error(transparent)]
+    ParseError(ScriptParseError)
 }
 
 pub enum PendingTcs {
@@ -88,51 +79,20 @@ impl ScriptInterpreter {
         }
 
         // Load the script into a string
-        let script = match fs::read_to_string(script_path) {
+        let script_str = match fs::read_to_string(script_path) {
             Ok(s) => s,
             Err(e) => return Err(ScriptError::ScriptLoadError(e))
         };
 
-        // Empty queue of commands
-        let mut tc_queue: VecDeque<Command> = VecDeque::new();
-
-        // Go through the script executing __the magic regex__.
-        let re = RegexBuilder::
-            new(r"^\s*(\d+(\.\d+)?)\s*:\s*([^;]*);")
-            .multi_line(true)
-            .build()
-            .unwrap();
-
-        let mut num_caps = 0;
-
-        for cap in re.captures_iter(&script) {
-            // Parse the exec time
-            let exec_time_s: f64 = match cap.get(1).unwrap().as_str().parse() {
-                Ok(t) => t,
-                Err(e) => return Err(
-                    ScriptError::InvalidTimestamp(format!("{}", e)))
-            };
-
-            // Parse the TC from the payload. The scripts contain JSON only.
-            let tc = match Tc::from_json(
-                cap.get(3).unwrap().as_str()) 
-            {
-                Ok(c) => c,
-                Err(e) => return Err(ScriptError::InvalidTc(exec_time_s, e))
-            };
-
-            // Build command from the match
-            tc_queue.push_back(Command {
-                exec_time_s,
-                tc
-            });
-
-            num_caps += 1;
-        }
-
-        if num_caps == 0 {
-            return Err(ScriptError::ScriptEmpty)
-        }
+        // Parse the commands, which are already in order
+        let tc_queue: VecDeque<Command> = script::parse(&script_str)
+            .map_err(ScriptError::ParseError)?
+            .into_iter()
+            .map(|s| Command {
+                exec_time_s: s.exec_time_s,
+                tc: s.tc
+            })
+            .collect();
 
         Ok(ScriptInterpreter {
             _script_path: path,
@@ -7,12 +7,15 @@
 // External imports
 use chrono::{DateTime, Utc};
 use conquer_once::OnceCell;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Component, Path, PathBuf};
 use std::fs;
 use thiserror::Error;
 
 // Internal imports
-use crate::time;
+use crate::{archive, format::{self, FormatError, FormatHeader}, time};
 
 // ---------------------------------------------------------------------------
 // STATICS
@@ -20,6 +23,8 @@ use crate::time;
 
 static SESSION_EPOCH: OnceCell<DateTime<Utc>> = OnceCell::uninit();
 
+static SESSION_ID: OnceCell<String> = OnceCell::uninit();
+
 // ---------------------------------------------------------------------------
 // CONSTANTS
 // ---------------------------------------------------------------------------
@@ -35,6 +40,9 @@ const TIMESTAMP_FORMAT: &'static str = "%Y%m%d_%H%M%S";
 
 /// A struct storing information about the current session
 pub struct Session {
+    /// Random ID which is unique to this session, see `get_session_id`
+    pub session_id: String,
+
     /// The root directory for this session
     pub session_root: PathBuf,
 
@@ -64,7 +72,16 @@ pub enum SessionError {
     CannotInitEpoch(conquer_once::TryInitError),
 
     #[error("Cannot get the epoch time, did you forget to initialise the session?")]
-    CannotGetEpoch
+    CannotGetEpoch,
+
+    #[error("Cannot save the archive format header: {0}")]
+    CannotSaveFormat(FormatError),
+
+    #[error("{0:?} is not a plain file or directory name within the session")]
+    InvalidName(String),
+
+    #[error("Cannot save the file: {0}")]
+    CannotSave(FormatError)
 }
 
 // ---------------------------------------------------------------------------
@@ -88,6 +105,9 @@ impl Session {
             Err(e) => return Err(SessionError::CannotInitEpoch(e))
         };
 
+        // Give the session its ID, before anything is saved which records it
+        let session_id = SESSION_ID.get_or_init(new_session_id).clone();
+
         // Format the session epoch as a timestamp
         let timestamp = match SESSION_EPOCH.get() {
             Some(e) => e.format(TIMESTAMP_FORMAT),
@@ -117,17 +137,63 @@ impl Session {
             Err(e) => return Err(SessionError::CannotCreateDir(e))
         };
 
+        // Record the archive format so tools can check they understand it
+        archive::format_header()
+            .save(arch_path.join("format.json"))
+            .map_err(SessionError::CannotSaveFormat)?;
+
         // Create the log file path
         let mut log_file_path = path.clone();
         log_file_path.push(format!("{}.log", exec_name));
 
         // Build the session struct
         Ok(Session {
+            session_id,
             session_root: path,
             arch_root: arch_path,
             log_file_path
         })
     }
+
+    /// Get the directory within the session for the given module, creating
+    /// it if it doesn't exist.
+    ///
+    /// Modules should save their files here rather than in the session root,
+    /// so that the outputs of different modules cannot clash.
+    pub fn module_dir(&self, module_name: &str) -> Result<PathBuf, SessionError> {
+        check_name(module_name)?;
+
+        let path = self.session_root.join(module_name);
+        fs::create_dir_all(&path).map_err(SessionError::CannotCreateDir)?;
+
+        Ok(path)
+    }
+
+    /// Save the data as versioned JSON (see [`crate::format`]) into the
+    /// module's directory, in a file named `{file_stem}_{elapsed}.json` where
+    /// `elapsed` is the number of seconds since the start of the session.
+    ///
+    /// Neither name may contain a path separator or `..`, so files can only
+    /// be written inside the session. On success the path to the file is
+    /// returned.
+    pub fn save_with_timestamp<T: Serialize>(
+        &self,
+        module_name: &str,
+        file_stem: &str,
+        header: &FormatHeader,
+        data: &T
+    ) -> Result<PathBuf, SessionError> {
+        check_name(file_stem)?;
+
+        let path = self.module_dir(module_name)?.join(format!(
+            "{}_{:.3}.json", file_stem, get_elapsed_seconds()
+        ));
+
+        format::save_json(&path, header, data)
+            .map_err(SessionError::CannotSave)?;
+
+        Ok(path)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -162,4 +228,55 @@ pub fn get_epoch() -> &'static DateTime<Utc> {
         Some(e) => e,
         None => panic!("Cannot get the session epoch!")
     }
-}
\ No newline at end of file
+}
+
+/// Return the random ID of the session, or `None` if the session has not
+/// been initialised.
+///
+/// The ID tells apart sessions of different rovers, or of the same rover
+/// after its clock has been reset, which the session timestamp cannot.
+pub fn get_session_id() -> Option<&'static str> {
+    SESSION_ID.get().map(|s| s.as_str())
+}
+
+/// Find the directory of a previous session, given either its path or its
+/// name within the `sessions` directory of the software root.
+///
+/// Returns `None` if no such directory exists.
+pub fn find_session_dir(session: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(session);
+    if path.is_dir() {
+        return Some(path);
+    }
+
+    let path = crate::host::get_phobos_sw_root()
+        .ok()?
+        .join("sessions")
+        .join(session);
+
+    match path.is_dir() {
+        true => Some(path),
+        false => None
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// ---------------------------------------------------------------------------
+
+/// Generate a new random session ID.
+fn new_session_id() -> String {
+    // RandomState is seeded from the OS's random number generator
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+/// Check that the name is a single normal path component, so that joining it
+/// onto a session path cannot escape the session.
+fn check_name(name: &str) -> Result<(), SessionError> {
+    let mut components = Path::new(name).components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(SessionError::InvalidName(String::from(name)))
+    }
+}
@@ -0,0 +1,204 @@
+//! Start-up sequencing
+//!
+//! Executables start up in named stages, each of which declares the stages
+//! it depends on. A stage which fails because of something outside the
+//! executable, such as an equipment server which hasn't started yet, can be
+//! retried with an increasing backoff for a configured period. If start-up
+//! fails the error names the stage which blocked it, and how many attempts
+//! were made.
+//!
+//! The session and logger are created before the sequence starts, since the
+//! sequence logs its progress into the session.
+
+// ---------------------------------------------------------------------------
+// IMPORTS
+// ---------------------------------------------------------------------------
+
+// External imports
+use log::{info, warn};
+use serde::Deserialize;
+use std::error::Error as StdError;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+// ---------------------------------------------------------------------------
+// DATA STRUCTURES
+// ---------------------------------------------------------------------------
+
+/// Parameters controlling the retries of start-up stages.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StartupParams {
+    /// Time to keep retrying a failing stage for before giving up
+    pub retry_period_s: f64,
+
+    /// Wait before the first retry
+    pub initial_backoff_s: f64,
+
+    /// Longest wait between retries, the wait doubles after each failure up
+    /// to this
+    pub max_backoff_s: f64,
+}
+
+/// Runs the start-up stages in order.
+pub struct Startup {
+    params: StartupParams,
+
+    /// Names of the completed stages, and how long each took
+    completed: Vec<(String, Duration)>,
+
+    start: Instant,
+}
+
+// ---------------------------------------------------------------------------
+// ENUMERATIONS
+// ---------------------------------------------------------------------------
+
+/// Errors which stop start-up.
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error(
+        "Start-up stage {stage:?} depends on {dependency:?}, which has not \
+         completed")]
+    MissingDependency { stage: String, dependency: String },
+
+    #[error(
+        "Start-up blocked by {stage:?}, which failed after {attempts} \
+         attempt(s): {source}")]
+    StageFailed {
+        stage: String,
+        attempts: u32,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+}
+
+/// Whether a failing stage may be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// The failure can't be fixed by waiting, such as an invalid parameter
+    /// file
+    Never,
+
+    /// The failure may be transient, such as a server which hasn't started
+    Transient,
+}
+
+// ---------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// ---------------------------------------------------------------------------
+
+impl Default for StartupParams {
+    fn default() -> Self {
+        Self {
+            retry_period_s: 10.0,
+            initial_backoff_s: 0.25,
+            max_backoff_s: 2.0,
+        }
+    }
+}
+
+impl Startup {
+    /// Start a new sequence with the default parameters.
+    pub fn new() -> Self {
+        Self {
+            params: StartupParams::default(),
+            completed: Vec::new(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Set the retry parameters, usually once the parameter files have been
+    /// loaded by an earlier stage.
+    pub fn set_params(&mut self, params: StartupParams) {
+        self.params = params;
+    }
+
+    /// Return true if the given stage has completed.
+    pub fn is_complete(&self, stage: &str) -> bool {
+        self.completed.iter().any(|(s, _)| s == stage)
+    }
+
+    /// Run a stage, retrying it if it fails and `retry` allows it.
+    ///
+    /// All of the stage's dependencies must already have completed.
+    pub fn stage<T, E, F>(
+        &mut self,
+        name: &str,
+        dependencies: &[&str],
+        retry: Retry,
+        mut f: F,
+    ) -> Result<T, StartupError>
+    where
+        E: StdError + Send + Sync + 'static,
+        F: FnMut() -> Result<T, E>,
+    {
+        if let Some(dep) = dependencies.iter().find(|d| !self.is_complete(d)) {
+            return Err(StartupError::MissingDependency {
+                stage: String::from(name),
+                dependency: String::from(*dep),
+            });
+        }
+
+        let stage_start = Instant::now();
+        let mut backoff_s = self.params.initial_backoff_s;
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let err = match f() {
+                Ok(t) => {
+                    let elapsed = stage_start.elapsed();
+                    info!(
+                        "Start-up stage {:?} complete in {:.2} s",
+                        name,
+                        elapsed.as_secs_f64()
+                    );
+                    self.completed.push((String::from(name), elapsed));
+                    return Ok(t);
+                }
+                Err(e) => e,
+            };
+
+            let can_retry = retry == Retry::Transient
+                && stage_start.elapsed().as_secs_f64() + backoff_s
+                    <= self.params.retry_period_s;
+
+            if !can_retry {
+                return Err(StartupError::StageFailed {
+                    stage: String::from(name),
+                    attempts,
+                    source: Box::new(err),
+                });
+            }
+
+            warn!(
+                "Start-up stage {:?} failed (attempt {}), retrying in {:.2} s: \
+                 {}",
+                name, attempts, backoff_s, err
+            );
+
+            thread::sleep(Duration::from_secs_f64(backoff_s));
+            backoff_s = (backoff_s * 2.0).min(self.params.max_backoff_s);
+        }
+    }
+
+    /// Log a summary of the completed stages.
+    pub fn finish(self) {
+        info!(
+            "Start-up complete in {:.2} s",
+            self.start.elapsed().as_secs_f64()
+        );
+
+        for (stage, elapsed) in self.completed.iter() {
+            info!("    {:<24} {:.2} s", stage, elapsed.as_secs_f64());
+        }
+    }
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Self::new()
+    }
+}